@@ -1,18 +1,135 @@
 //! Module containing code for the 'arena' that stores AST nodes.
+//!
+//! An arena can also be saved to a file and loaded again (see [`Arena::save`]), which is much
+//! faster than parsing a large document and keeps any sharing between trees.  An arena file is
+//! made of:
+//! - the bytes `SAPARENA`, followed by the version of the file layout ([`FILE_VERSION`])
+//! - the [name](Persist::TYPE_NAME) and [version](Persist::VERSION) of the type of node stored
+//! - the number of nodes, and the index of the root
+//! - every node in the order it was allocated: its [tag and text](Persist::encode), and the
+//!   indices of its children
+//!
+//! All numbers are little-endian `u32`s, and text is stored as its length followed by its UTF-8
+//! bytes.
 
+use crate::ast::Ast;
+use std::cell::{RefCell, UnsafeCell};
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::Path;
 use typed_arena::Arena as TyArena;
 
+/// The bytes at the start of every arena file
+const MAGIC: &[u8; 8] = b"SAPARENA";
+/// The version of the layout of arena files.  This must be increased whenever the layout changes.
+pub const FILE_VERSION: u32 = 2;
+
+/// Node types which can be saved in an arena file (see [`Arena::save`]).  Every reference that a
+/// node holds must be one of its [`children`](Ast::children), because only the children are
+/// saved.
+pub trait Persist<'arena>: Ast<'arena> {
+    /// The name of this type of node, which is stored in arena files so that they can't be
+    /// loaded as a different type
+    const TYPE_NAME: &'static str;
+    /// The version of [`encode`](Persist::encode).  This must be increased whenever the encoding
+    /// changes (e.g. when a new variant is added), so that older files are rejected instead of
+    /// being misread.
+    const VERSION: u32;
+
+    /// Returns a number identifying what kind of node this is (e.g. which variant it is), and any
+    /// text which it contains.  The children are saved separately.
+    fn encode(&self) -> (u8, &str);
+
+    /// The inverse of [`encode`](Persist::encode): builds a node from its tag, text and children.
+    /// Returns [`None`] if they don't make a valid node.
+    fn decode(tag: u8, text: &str, children: Vec<&'arena Self>) -> Option<Self>;
+}
+
+/// The ways that saving or loading an arena file can fail
+#[derive(Debug)]
+pub enum ArenaFileError {
+    /// The file couldn't be read or written
+    Io(std::io::Error),
+    /// The root given to [`Arena::save`] isn't a node in that arena
+    RootNotInArena,
+    /// A node in the arena given to [`Arena::save`] has a child which is in a different arena
+    ChildNotInArena,
+    /// The file doesn't start with the arena file header
+    NotAnArenaFile,
+    /// The file was written by a version of Sapling with a different layout or node encoding
+    UnsupportedVersion { type_name: String, version: u32 },
+    /// The file stores a different type of node
+    WrongNodeType {
+        expected: &'static str,
+        found: String,
+    },
+    /// The contents of the file don't make valid nodes
+    Corrupt(String),
+}
+
+impl std::fmt::Display for ArenaFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArenaFileError::Io(e) => write!(f, "Couldn't access arena file: {}", e),
+            ArenaFileError::RootNotInArena => write!(f, "The root isn't a node in this arena."),
+            ArenaFileError::ChildNotInArena => {
+                write!(f, "A node in this arena has a child from another arena.")
+            }
+            ArenaFileError::NotAnArenaFile => write!(f, "This isn't an arena file."),
+            ArenaFileError::UnsupportedVersion { type_name, version } => write!(
+                f,
+                "Arena files of version {} of '{}' nodes aren't supported.",
+                version, type_name
+            ),
+            ArenaFileError::WrongNodeType { expected, found } => write!(
+                f,
+                "Expected an arena file of '{}' nodes, found '{}' nodes.",
+                expected, found
+            ),
+            ArenaFileError::Corrupt(message) => write!(f, "Corrupt arena file: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ArenaFileError {}
+
+impl From<std::io::Error> for ArenaFileError {
+    fn from(e: std::io::Error) -> Self {
+        ArenaFileError::Io(e)
+    }
+}
+
 /// An item that is stored in the [`Arena`].  This allows the [`Arena`] to build on
-/// [`typed_arena::Arena`] by storing extra detail about the nodes stored in the arena.
-#[derive(Debug, Clone)]
+/// [`typed_arena::Arena`] by storing extra detail about the nodes stored in the arena.  The node
+/// is in an [`UnsafeCell`] so that [`Arena::gc`] can free it and reuse its slot for another node.
+#[derive(Debug)]
 struct Item<T> {
-    node: T,
+    node: UnsafeCell<T>,
 }
 
 impl<T> Item<T> {
     /// Constructs a new `Item` that contains a given node
     pub fn new(node: T) -> Self {
-        Item { node }
+        Item {
+            node: UnsafeCell::new(node),
+        }
+    }
+}
+
+/// A summary of how much memory an [`Arena`] is using, as returned by [`Arena::stats`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ArenaStats {
+    /// The number of nodes in the [`Arena`] which haven't been freed by [`Arena::gc`]
+    pub node_count: usize,
+    /// An estimate of the number of bytes used by those nodes.  This doesn't include any heap
+    /// memory owned by the nodes themselves (like the contents of strings), nor any unused space
+    /// at the end of the arena's current chunk.
+    pub bytes: usize,
+}
+
+impl std::fmt::Display for ArenaStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} nodes using ~{} bytes", self.node_count, self.bytes)
     }
 }
 
@@ -25,11 +142,23 @@ impl<T> Item<T> {
 /// This also differs from standard arena allocators in the following ways:
 /// - Nodes added to an [`Arena`] are **always immutable**.  Once they are added they can be cloned
 ///   but not changed.
-/// - This does not merge syntax tree nodes (where rustc does).  Sapling relies on the fact that
-///   within a given tree in the arena, all the nodes in that tree must have unique references.
-///   Nodes **can** exist inside multiple trees at once.
+/// - This does not merge syntax tree nodes by default (where rustc does).  Sapling relies on the
+///   fact that within a given tree in the arena, all the nodes in that tree must have unique
+///   references.  Nodes **can** exist inside multiple trees at once, and an [`Interner`] can be
+///   used to share identical subtrees between different trees.
+///
+/// Nodes which are no longer reachable from any tree can be freed by [`Arena::gc`], which puts
+/// their slots on a free list so that later nodes are allocated in their place.  The memory of the
+/// slots themselves is only returned when the whole `Arena` is dropped.
 pub struct Arena<T> {
     base_arena: TyArena<Item<T>>,
+    /// Every live node in `base_arena`, in the order they were allocated.  [`typed_arena::Arena`]
+    /// can only be iterated through a mutable reference, which is never available whilst trees are
+    /// borrowing the nodes, so [`Arena::iter`] uses this instead.
+    nodes: RefCell<Vec<*mut T>>,
+    /// The slots of the nodes freed by [`Arena::gc`], which are reused before any new slots are
+    /// taken from `base_arena`.  Each one holds a [`Default`] node in place of the freed one.
+    free_slots: RefCell<Vec<*mut T>>,
 }
 
 impl<T> Arena<T> {
@@ -37,17 +166,585 @@ impl<T> Arena<T> {
     pub fn new() -> Arena<T> {
         Arena {
             base_arena: TyArena::new(),
+            nodes: RefCell::new(Vec::new()),
+            free_slots: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Creates an empty `Arena` with space for at least `capacity` nodes, so that no more memory
+    /// has to be allocated until more than `capacity` nodes have been added.  Unlike a [`Vec`], an
+    /// `Arena` never moves its nodes when it grows (it allocates a new chunk instead), so this is
+    /// only an optimisation.
+    pub fn with_capacity(capacity: usize) -> Arena<T> {
+        Arena {
+            base_arena: TyArena::with_capacity(capacity),
+            nodes: RefCell::new(Vec::new()),
+            free_slots: RefCell::new(Vec::new()),
         }
     }
 
+    /// Returns the number of nodes that this `Arena` can hold before it has to allocate more
+    /// memory.
+    pub fn capacity(&self) -> usize {
+        // All the chunks before the current one are full (because nodes are only allocated one at
+        // a time), so the only spare space is what's left of the current chunk and any freed slots.
+        self.base_arena.len() + self.base_arena.uninitialized_array().len()
+    }
+
     /// Add a new node to the `Arena`, and returns an immutable reference to its final location.
     pub fn alloc(&self, node: T) -> &T {
-        &self.base_arena.alloc(Item::new(node)).node
+        let slot = match self.free_slots.borrow_mut().pop() {
+            Some(slot) => {
+                // SAFETY: free slots are no longer referenced by anything (see `Arena::gc`), and
+                // still hold a valid node, which is dropped here
+                unsafe { *slot = node };
+                slot
+            }
+            None => self.base_arena.alloc(Item::new(node)).node.get(),
+        };
+        self.nodes.borrow_mut().push(slot);
+        // SAFETY: `slot` points into `base_arena`, which never moves its items
+        unsafe { &*slot }
+    }
+
+    /// Returns an iterator over every node in this `Arena` (whether or not it is still reachable),
+    /// in the order they were allocated.  Nodes which are allocated whilst iterating aren't
+    /// visited.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        (0..self.len()).map(move |i| {
+            let node = self.nodes.borrow()[i];
+            // SAFETY: `node` points into `base_arena`, which never moves its items, and it hasn't
+            // been freed because it is still in `nodes`
+            unsafe { &*node }
+        })
+    }
+
+    /// Returns the number of nodes in this `Arena` which haven't been freed by [`Arena::gc`],
+    /// whether or not they are still reachable.
+    pub fn len(&self) -> usize {
+        self.nodes.borrow().len()
+    }
+
+    /// Returns `true` if this `Arena` contains no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns statistics about how much memory this `Arena` is using.
+    pub fn stats(&self) -> ArenaStats {
+        let node_count = self.len();
+        ArenaStats {
+            node_count,
+            bytes: node_count * std::mem::size_of::<Item<T>>(),
+        }
+    }
+
+    /// Frees every node which can't be reached from any of the `roots`, returning how many were
+    /// freed.  The freed nodes are dropped (so any memory they own, like the contents of strings,
+    /// is returned straight away), and their slots are reused by the next nodes to be allocated.
+    ///
+    /// # Safety
+    ///
+    /// Nodes are handed out as references which live as long as the `Arena`, so the borrow checker
+    /// can't tell which of them are still in use.  Every node which is used after this call (e.g.
+    /// by a tree's undo history, the editor's yank register or an [`Interner`]) must be reachable
+    /// from one of the `roots`; any other reference into this `Arena` is left dangling.
+    pub unsafe fn gc<'arena>(&'arena self, roots: &[&'arena T]) -> usize
+    where
+        T: Ast<'arena>,
+    {
+        // Mark every node which is reachable from the roots
+        let mut reachable: HashSet<*const T> = HashSet::new();
+        let mut stack = roots.to_vec();
+        while let Some(node) = stack.pop() {
+            // Nodes can be shared, so only visit each one once
+            if reachable.insert(node) {
+                stack.extend(node.children().iter().copied());
+            }
+        }
+        // Sweep the rest onto the free list.  Replacing each node with a default one drops it
+        // without leaving its slot uninitialised, because `base_arena` will drop every slot.
+        let mut free_slots = self.free_slots.borrow_mut();
+        let num_nodes = self.len();
+        self.nodes.borrow_mut().retain(|&node| {
+            if reachable.contains(&(node as *const T)) {
+                return true;
+            }
+            *node = T::default();
+            free_slots.push(node);
+            false
+        });
+        num_nodes - self.len()
     }
 }
 
+/// Allocates nodes in an [`Arena`], reusing an existing node whenever an identical one has
+/// already been interned.  Two nodes are identical if they have the same
+/// [tag and text](Persist::encode) and exactly the same children (i.e. references to the same
+/// nodes), so only the node itself is hashed and never the whole subtree under it.  This means
+/// that subtrees are only shared if they are interned bottom-up, children before parents.
+///
+/// Every node in a tree must have a unique reference, so the [`DAG`] never interns the nodes it
+/// edits.  An `Interner` is only for sharing subtrees between different trees (e.g. when loading
+/// many similar documents); using it to build a tree with repeated substructures would make those
+/// substructures indistinguishable to the editor.
+///
+/// [`DAG`]: crate::editable_tree::DAG
+pub struct Interner<'arena, T> {
+    arena: &'arena Arena<T>,
+    /// The interned nodes, grouped by the hashes of their tags, text and children
+    nodes: HashMap<u64, Vec<&'arena T>>,
+}
+
+impl<'arena, T: Persist<'arena>> Interner<'arena, T> {
+    /// Creates an `Interner` which allocates nodes in `arena`, and which hasn't interned any
+    /// nodes yet.
+    pub fn new(arena: &'arena Arena<T>) -> Self {
+        Interner {
+            arena,
+            nodes: HashMap::new(),
+        }
+    }
+
+    /// Add a node to the [`Arena`], unless an identical node has already been interned, in which
+    /// case a reference to that node is returned instead.  Nodes which were allocated without
+    /// this `Interner` are never reused.
+    pub fn alloc(&mut self, node: T) -> &'arena T {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        node.encode().hash(&mut hasher);
+        for child in node.children() {
+            std::ptr::hash(*child, &mut hasher);
+        }
+        let bucket = self.nodes.entry(hasher.finish()).or_default();
+        let existing_node = bucket.iter().find(|existing_node| {
+            existing_node.encode() == node.encode()
+                && existing_node.children().len() == node.children().len()
+                && (existing_node.children().iter())
+                    .zip(node.children())
+                    .all(|(a, b)| std::ptr::eq(*a, *b))
+        });
+        if let Some(existing_node) = existing_node {
+            return existing_node;
+        }
+        let new_node = self.arena.alloc(node);
+        bucket.push(new_node);
+        new_node
+    }
+}
+
+impl<T> Arena<T> {
+    /// Writes every node in this `Arena` to a file at `path`, along with which one is the `root`.
+    /// Nodes which are shared between trees are only written once, so they will still be shared
+    /// when the file is [loaded](Arena::load).
+    pub fn save<'arena>(&'arena self, root: &'arena T, path: &Path) -> Result<(), ArenaFileError>
+    where
+        T: Persist<'arena>,
+    {
+        std::fs::write(path, self.to_bytes(root)?)?;
+        Ok(())
+    }
+
+    /// Adds every node in an arena file (see [`Arena::save`]) to this `Arena`, and returns the
+    /// root that was saved with them.  Nodes which were shared are still shared.  If the file turns
+    /// out to be corrupt, the nodes read before the problem was found stay in this `Arena` (like
+    /// any other unreachable nodes).
+    pub fn load<'arena>(&'arena self, path: &Path) -> Result<&'arena T, ArenaFileError>
+    where
+        T: Persist<'arena>,
+    {
+        self.load_bytes(&std::fs::read(path)?)
+    }
+
+    /// Encodes the contents of an arena file (see [`Arena::save`])
+    fn to_bytes<'arena>(&'arena self, root: &'arena T) -> Result<Vec<u8>, ArenaFileError>
+    where
+        T: Persist<'arena>,
+    {
+        let mut indices: HashMap<*const T, u32> = HashMap::new();
+        let mut bytes = MAGIC.to_vec();
+        write_u32(&mut bytes, FILE_VERSION);
+        write_str(&mut bytes, T::TYPE_NAME);
+        write_u32(&mut bytes, T::VERSION);
+        write_u32(&mut bytes, self.len() as u32);
+        // The root's index isn't known yet, so fill it in once every node has been numbered
+        let root_offset = bytes.len();
+        write_u32(&mut bytes, 0);
+        for (index, node) in self.iter().enumerate() {
+            indices.insert(node, index as u32);
+            let (tag, text) = node.encode();
+            bytes.push(tag);
+            write_str(&mut bytes, text);
+            write_u32(&mut bytes, node.children().len() as u32);
+            for child in node.children() {
+                // Nodes can only refer to nodes which already exist, so every child comes before
+                // its parent (unless it isn't in this arena at all)
+                let child_index = indices
+                    .get(&(*child as *const T))
+                    .ok_or(ArenaFileError::ChildNotInArena)?;
+                write_u32(&mut bytes, *child_index);
+            }
+        }
+        let root_index = indices
+            .get(&(root as *const T))
+            .ok_or(ArenaFileError::RootNotInArena)?;
+        bytes[root_offset..root_offset + 4].copy_from_slice(&root_index.to_le_bytes());
+        Ok(bytes)
+    }
+
+    /// Decodes the contents of an arena file (see [`Arena::load`])
+    fn load_bytes<'arena>(&'arena self, bytes: &[u8]) -> Result<&'arena T, ArenaFileError>
+    where
+        T: Persist<'arena>,
+    {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(ArenaFileError::NotAnArenaFile);
+        }
+        let file_version = reader.read_u32()?;
+        if file_version != FILE_VERSION {
+            return Err(ArenaFileError::UnsupportedVersion {
+                type_name: "arena".to_string(),
+                version: file_version,
+            });
+        }
+        let type_name = reader.read_str()?;
+        if type_name != T::TYPE_NAME {
+            return Err(ArenaFileError::WrongNodeType {
+                expected: T::TYPE_NAME,
+                found: type_name.to_string(),
+            });
+        }
+        let version = reader.read_u32()?;
+        if version != T::VERSION {
+            return Err(ArenaFileError::UnsupportedVersion {
+                type_name: type_name.to_string(),
+                version,
+            });
+        }
+        let num_nodes = reader.read_u32()? as usize;
+        let root_index = reader.read_u32()? as usize;
+        // Children always come before their parents, so every child has been loaded by the time
+        // its parent is read
+        let mut loaded: Vec<&'arena T> = Vec::with_capacity(num_nodes.min(reader.bytes.len()));
+        for index in 0..num_nodes {
+            let tag = reader.read_u8()?;
+            let text = reader.read_str()?;
+            let num_children = reader.read_u32()? as usize;
+            let mut children = Vec::with_capacity(num_children.min(reader.bytes.len()));
+            for _ in 0..num_children {
+                let child_index = reader.read_u32()? as usize;
+                let child = loaded.get(child_index).ok_or_else(|| {
+                    ArenaFileError::Corrupt(format!(
+                        "node {} refers to node {}, which comes after it.",
+                        index, child_index
+                    ))
+                })?;
+                children.push(*child);
+            }
+            let node = T::decode(tag, text, children)
+                .ok_or_else(|| ArenaFileError::Corrupt(format!("node {} is invalid.", index)))?;
+            loaded.push(self.alloc(node));
+        }
+        if !reader.bytes.is_empty() {
+            return Err(ArenaFileError::Corrupt(
+                "unexpected bytes at the end.".to_string(),
+            ));
+        }
+        loaded.get(root_index).copied().ok_or_else(|| {
+            ArenaFileError::Corrupt(format!(
+                "the root is node {}, which doesn't exist.",
+                root_index
+            ))
+        })
+    }
+}
+
+/// Reads the numbers and text of an arena file (see the [module-level documentation](self))
+struct Reader<'b> {
+    /// The bytes which haven't been read yet
+    bytes: &'b [u8],
+}
+
+impl<'b> Reader<'b> {
+    /// Reads the next `len` bytes
+    fn take(&mut self, len: usize) -> Result<&'b [u8], ArenaFileError> {
+        if self.bytes.len() < len {
+            return Err(ArenaFileError::Corrupt(
+                "unexpected end of file.".to_string(),
+            ));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, ArenaFileError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn read_u32(&mut self) -> Result<u32, ArenaFileError> {
+        let mut le_bytes = [0; 4];
+        le_bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(le_bytes))
+    }
+
+    fn read_str(&mut self) -> Result<&'b str, ArenaFileError> {
+        let len = self.read_u32()? as usize;
+        std::str::from_utf8(self.take(len)?)
+            .map_err(|_| ArenaFileError::Corrupt("text isn't valid UTF-8.".to_string()))
+    }
+}
+
+fn write_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_str(bytes: &mut Vec<u8>, text: &str) {
+    write_u32(bytes, text.len() as u32);
+    bytes.extend_from_slice(text.as_bytes());
+}
+
 impl<T> Default for Arena<T> {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Arena, ArenaFileError, ArenaStats, Interner, FILE_VERSION};
+    use crate::ast::{
+        json::{JSONFormat, JSON},
+        test_json::TestJSON,
+        Ast,
+    };
+
+    #[test]
+    fn with_capacity() {
+        let arena = Arena::with_capacity(100);
+        assert!(arena.capacity() >= 100);
+        let capacity = arena.capacity();
+        // Adding fewer nodes than the capacity shouldn't allocate any more memory
+        for _ in 0..50 {
+            arena.alloc(JSON::True);
+        }
+        assert_eq!(arena.len(), 50);
+        assert_eq!(arena.capacity(), capacity);
+        // Going over the capacity makes the arena grow
+        for _ in 0..capacity {
+            arena.alloc(JSON::True);
+        }
+        assert_eq!(arena.len(), 50 + capacity);
+        assert!(arena.capacity() >= arena.len());
+    }
+
+    #[test]
+    fn interner() {
+        let arena = Arena::new();
+        let mut interner = Interner::new(&arena);
+        let mut array = |first, second| {
+            let children = vec![interner.alloc(first), interner.alloc(second)];
+            interner.alloc(JSON::Array(children))
+        };
+        let a = array(JSON::True, JSON::False);
+        let b = array(JSON::True, JSON::False);
+        assert!(std::ptr::eq(a, b));
+        // Only `true`, `false` and the array have been allocated
+        assert_eq!(arena.len(), 3);
+        // Distinct trees get distinct nodes
+        let c = array(JSON::False, JSON::True);
+        assert!(!std::ptr::eq(a, c));
+        assert!(std::ptr::eq(a.children()[0], c.children()[1]));
+        assert_eq!(arena.len(), 4);
+        // Nodes are only reused if their children are the same nodes, not just equal ones
+        let d = arena.alloc(JSON::Array(vec![
+            arena.alloc(JSON::True),
+            arena.alloc(JSON::False),
+        ]));
+        assert_eq!(d, a);
+        let e = interner.alloc(d.clone());
+        assert!(!std::ptr::eq(a, e));
+        assert!(std::ptr::eq(e, interner.alloc(d.clone())));
+        // Nodes allocated without the interner are never reused
+        assert!(!std::ptr::eq(d, e));
+        // Nodes with different text are different nodes
+        let x = interner.alloc(JSON::Str("x".to_string()));
+        assert!(std::ptr::eq(x, interner.alloc(JSON::Str("x".to_string()))));
+        assert!(!std::ptr::eq(x, interner.alloc(JSON::Str("y".to_string()))));
+    }
+
+    #[test]
+    fn iter() {
+        let arena = Arena::new();
+        assert_eq!(arena.iter().count(), 0);
+        let mut interner = Interner::new(&arena);
+        let nodes = [
+            arena.alloc(JSON::True),
+            interner.alloc(JSON::Null),
+            interner.alloc(JSON::Null),
+            arena.alloc(JSON::True),
+        ];
+        // Interned nodes are only allocated once
+        assert_eq!(arena.len(), 3);
+        let visited: Vec<&JSON> = arena.iter().collect();
+        assert_eq!(visited.len(), arena.len());
+        // Every node is visited exactly once, in the order they were allocated
+        for node in &nodes {
+            let times_visited = visited.iter().filter(|n| std::ptr::eq(**n, *node)).count();
+            assert_eq!(times_visited, 1);
+        }
+        assert!(std::ptr::eq(visited[0], nodes[0]));
+        assert!(std::ptr::eq(visited[1], nodes[1]));
+        assert!(std::ptr::eq(visited[2], nodes[3]));
+        // Allocating whilst iterating is fine, but the new nodes aren't visited
+        let mut num_visited = 0;
+        for node in arena.iter() {
+            arena.alloc(node.clone());
+            num_visited += 1;
+        }
+        assert_eq!((num_visited, arena.len()), (3, 6));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let arena = Arena::new();
+        // Two versions of a document, which share their first element
+        let shared =
+            TestJSON::Object(vec![("value".to_string(), TestJSON::True)]).add_to_arena(&arena);
+        let version =
+            |last: TestJSON| arena.alloc(JSON::Array(vec![shared, last.add_to_arena(&arena)]));
+        let old = version(TestJSON::Str("line\nbreak".to_string()));
+        let new = version(TestJSON::Null);
+        let root = arena.alloc(JSON::Array(vec![old, new]));
+        let path = std::env::temp_dir().join(format!("sapling-arena-{}.bin", std::process::id()));
+        arena.save(root, &path).unwrap();
+        let loaded_arena = Arena::new();
+        let loaded: &JSON = loaded_arena.load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // The whole arena is loaded, including nodes which aren't part of the root
+        assert_eq!(loaded_arena.len(), arena.len());
+        assert_eq!(loaded, root);
+        assert_eq!(
+            loaded.to_text(&JSONFormat::COMPACT),
+            root.to_text(&JSONFormat::COMPACT)
+        );
+        // Shared nodes are still shared
+        let (old, new) = (loaded.children()[0], loaded.children()[1]);
+        assert!(std::ptr::eq(old.children()[0], new.children()[0]));
+    }
+
+    #[test]
+    fn load_errors() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![TestJSON::True]).add_to_arena(&arena);
+        let bytes = arena.to_bytes(root).unwrap();
+        fn load(bytes: &[u8]) -> Result<(), ArenaFileError> {
+            let arena = Arena::<JSON>::new();
+            arena.load_bytes(bytes).map(|_| ())
+        }
+        assert!(load(&bytes).is_ok());
+        assert!(matches!(
+            load(b"[true]"),
+            Err(ArenaFileError::NotAnArenaFile)
+        ));
+        // Files from other versions are rejected rather than misread
+        let mut future_file = bytes.clone();
+        future_file[8..12].copy_from_slice(&(FILE_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            load(&future_file),
+            Err(ArenaFileError::UnsupportedVersion { ref type_name, .. }) if type_name == "arena"
+        ));
+        // The version of the JSON encoding comes after the type name ("json")
+        let mut future_json = bytes.clone();
+        future_json[20] += 1;
+        assert_eq!(
+            load(&future_json).unwrap_err().to_string(),
+            "Arena files of version 2 of 'json' nodes aren't supported."
+        );
+        assert_eq!(
+            load(&bytes[..bytes.len() - 1]).unwrap_err().to_string(),
+            "Corrupt arena file: unexpected end of file."
+        );
+        // A root from another arena can't be saved
+        let other_arena = Arena::new();
+        let other_root = other_arena.alloc(JSON::Null);
+        assert!(matches!(
+            arena.to_bytes(other_root),
+            Err(ArenaFileError::RootNotInArena)
+        ));
+        // Neither can an arena with a node whose child is in another arena
+        let mixed_arena = Arena::new();
+        let mixed_root = mixed_arena.alloc(JSON::Array(vec![other_root]));
+        assert!(matches!(
+            mixed_arena.to_bytes(mixed_root),
+            Err(ArenaFileError::ChildNotInArena)
+        ));
+        assert_eq!(
+            mixed_arena.to_bytes(mixed_root).unwrap_err().to_string(),
+            "A node in this arena has a child from another arena."
+        );
+    }
+
+    #[test]
+    fn gc() {
+        let arena = Arena::new();
+        // [true, "x", [null]]
+        let old_root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::Str("x".to_string()),
+            TestJSON::Array(vec![TestJSON::Null]),
+        ])
+        .add_to_arena(&arena);
+        // Replace the root with [true, false], which shares `true` with the old root
+        let new_root = arena.alloc(JSON::Array(vec![
+            old_root.children()[0],
+            arena.alloc(JSON::False),
+        ]));
+        assert_eq!(arena.len(), 7);
+        let capacity = arena.capacity();
+        // The old root, "x", [null] and `null` can only be reached from the old root
+        assert_eq!(unsafe { arena.gc(&[new_root]) }, 4);
+        assert_eq!(arena.len(), 3);
+        assert_eq!(new_root.to_text(&JSONFormat::COMPACT), "[true, false]");
+        let remaining: Vec<&JSON> = arena.iter().collect();
+        assert_eq!(remaining, vec![&JSON::True, &JSON::False, new_root]);
+        // Everything left is reachable, so collecting again frees nothing
+        assert_eq!(unsafe { arena.gc(&[new_root]) }, 0);
+        // The freed slots are reused before the arena grows
+        for _ in 0..4 {
+            arena.alloc(JSON::Null);
+        }
+        assert_eq!(arena.len(), 7);
+        assert_eq!(arena.capacity(), capacity);
+        // With no roots, every node is freed
+        assert_eq!(unsafe { arena.gc(&[]) }, 7);
+        assert!(arena.is_empty());
+    }
+
+    #[test]
+    fn stats() {
+        let arena = Arena::new();
+        assert_eq!(
+            arena.stats(),
+            ArenaStats {
+                node_count: 0,
+                bytes: 0
+            }
+        );
+        // [true, false, {"value": true}] is made of 7 nodes: the array, `true`, `false`, the
+        // object, the field, the key and the field's value
+        TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::False,
+            TestJSON::Object(vec![("value".to_string(), TestJSON::True)]),
+        ])
+        .add_to_arena(&arena);
+        assert_eq!(
+            arena.stats(),
+            ArenaStats {
+                node_count: 7,
+                bytes: 7 * std::mem::size_of::<JSON>()
+            }
+        );
+    }
+}
@@ -90,7 +90,7 @@ impl CursorPath {
     where
         Node: Ast<'arena>,
     {
-        NodeIter::new(root, &self)
+        NodeIter::new(root, self)
     }
 }
 
@@ -1,7 +1,64 @@
+use super::cursor_path::CursorPath;
 use super::Ast;
+use std::collections::{HashMap, HashSet};
+use unicode_width::UnicodeWidthStr;
 
-/// How many spaces corespond to one indentation level
-const INDENT_WIDTH: usize = 4;
+/// How many columns a tab character is assumed to occupy when it is drawn on the screen
+const TAB_WIDTH: usize = 4;
+
+/// The whitespace that is added for each level of indentation
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Indent {
+    /// Indent by a given number of spaces
+    Spaces(usize),
+    /// Indent by a single tab character
+    Tab,
+}
+
+impl Indent {
+    /// The default indentation, which is four spaces
+    pub const DEFAULT: Indent = Indent::Spaces(4);
+
+    /// Returns the text of one level of this indentation
+    pub fn unit(&self) -> String {
+        match self {
+            Indent::Spaces(n) => " ".repeat(*n),
+            Indent::Tab => "\t".to_string(),
+        }
+    }
+
+    /// Returns how many columns one level of this indentation occupies on the screen
+    pub fn width(&self) -> usize {
+        match self {
+            Indent::Spaces(n) => *n,
+            Indent::Tab => TAB_WIDTH,
+        }
+    }
+}
+
+impl std::str::FromStr for Indent {
+    type Err = String;
+
+    /// Parses an [`Indent`] typed by the user, which is either a number of spaces or `tab`
+    fn from_str(s: &str) -> Result<Indent, String> {
+        match s {
+            "tab" => Ok(Indent::Tab),
+            _ => s
+                .parse()
+                .map(Indent::Spaces)
+                .map_err(|_| format!("Expected a number of spaces or 'tab', found '{}'.", s)),
+        }
+    }
+}
+
+/// Returns the column that the cursor ends up in after drawing `text` from `column`.  If `text`
+/// contains a newline, the column is counted from the start of its last line.
+fn column_after(column: usize, text: &str) -> usize {
+    match text.rsplit_once('\n') {
+        Some((_, last_line)) => last_line.width(),
+        None => column + text.width(),
+    }
+}
 
 /// A single piece of a node that can be rendered to the screen
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -24,47 +81,323 @@ pub enum DisplayToken {
 pub enum RecTok<'arena, Node> {
     Tok(DisplayToken),
     Child(&'arena Node),
+    /// The tokens of a child, generated by its parent instead of by the child itself (e.g. so that
+    /// padding can be added which depends on the child's siblings).  These tokens still belong to
+    /// the child.
+    ChildTokens(&'arena Node, Vec<RecTok<'arena, Node>>),
 }
 
-/// Write a stream of display tokens to a string
-pub fn write_tokens<'arena, Node: Ast<'arena>>(
-    root: &'arena Node,
-    string: &mut String,
-    format_style: &Node::FormatStyle,
-) {
-    let mut indentation_string = String::new();
-
-    // Process the token string
-    for (_id, tok) in root.display_tokens(format_style) {
-        match tok {
-            DisplayToken::Text(s) => {
-                // Push the string we've been given
-                string.push_str(&s);
-            }
-            DisplayToken::Whitespace(n) => {
-                // Push 'n' many spaces
-                for _ in 0..n {
-                    string.push(' ');
+/// An iterator over the display tokens of a tree, along with the node that generated each one (as
+/// returned by [`Ast::display_token_iter`]).  Nodes are only expanded when their tokens are
+/// reached, so stopping part way through a large tree avoids generating the rest of it.
+pub struct DisplayTokenIter<'arena, 'f, Node: Ast<'arena>> {
+    format_style: &'f Node::FormatStyle,
+    /// The nodes which are part way through being expanded (innermost last), along with their
+    /// remaining tokens
+    stack: Vec<(&'arena Node, std::vec::IntoIter<RecTok<'arena, Node>>)>,
+    /// The index of each node in `stack` (apart from the root) within its parent, and the number
+    /// of children that each node in `stack` has expanded so far
+    path: Vec<usize>,
+    num_expanded: Vec<usize>,
+    /// For each node in `stack` whose children have been expanded out of order, the indices of
+    /// the children which haven't been expanded yet (smallest last), keyed by their addresses
+    unexpanded: Vec<Option<HashMap<*const Node, Vec<usize>>>>,
+    /// The width of one level of indentation, and how many levels the next line will be indented
+    indent_width: usize,
+    indent_depth: usize,
+    /// The column that the next token will be drawn in, which is passed to
+    /// [`Ast::display_tokens_at`] when a child is expanded
+    column: usize,
+    /// The nodes whose tokens are replaced by a placeholder (see
+    /// [`with_folded`](DisplayTokenIter::with_folded))
+    folded: Option<&'f HashSet<*const Node>>,
+    /// The line that the next token will be drawn on, and the first line whose tokens are needed
+    /// (see [`skip_to_line`](DisplayTokenIter::skip_to_line))
+    line: usize,
+    first_line: usize,
+}
+
+impl<'arena, 'f, Node: Ast<'arena>> DisplayTokenIter<'arena, 'f, Node> {
+    /// Creates an iterator over the display tokens of the tree under `root`
+    pub fn new(root: &'arena Node, format_style: &'f Node::FormatStyle) -> Self {
+        DisplayTokenIter {
+            format_style,
+            stack: vec![(root, root.display_tokens_at(format_style, 0).into_iter())],
+            path: Vec::new(),
+            num_expanded: vec![0],
+            unexpanded: vec![None],
+            indent_width: Node::indent(format_style).width(),
+            indent_depth: 0,
+            column: 0,
+            folded: None,
+            line: 0,
+            first_line: 0,
+        }
+    }
+
+    /// Skips over the nodes which end before `first_line`, without generating their text.  Each
+    /// skipped node only produces the [`Newline`](DisplayToken::Newline)s and
+    /// [`Whitespace`](DisplayToken::Whitespace) that move past it, so the tokens after it are
+    /// still drawn in the right place.  Nodes are only skipped if their size is
+    /// [cheap](Ast::cheap_size_at) to calculate.
+    pub fn skip_to_line(mut self, first_line: usize) -> Self {
+        self.first_line = first_line;
+        self
+    }
+
+    /// Replaces the tokens of every node in `folded` (and so all of its descendants) with a single
+    /// placeholder, like `object (+3)`, which says how many children have been hidden
+    pub fn with_folded(mut self, folded: &'f HashSet<*const Node>) -> Self {
+        self.folded = Some(folded);
+        // The root has already been expanded, so its tokens are replaced here
+        let root = self.stack[0].0;
+        if let Some(toks) = self.folded_tokens(root) {
+            self.stack[0].1 = toks;
+        }
+        self
+    }
+
+    /// Returns the tokens which move past `node` (which is about to be expanded) without drawing
+    /// it, if it ends before the [first line](DisplayTokenIter::skip_to_line) that is needed
+    fn skipped_tokens(
+        &self,
+        node: &'arena Node,
+    ) -> Option<std::vec::IntoIter<RecTok<'arena, Node>>> {
+        if self.line >= self.first_line {
+            return None;
+        }
+        let indentation = self.indent_depth * self.indent_width;
+        let size = node.cheap_size_at(self.format_style, indentation, self.column)?;
+        if self.line + size.lines() >= self.first_line {
+            return None;
+        }
+        let mut toks = vec![RecTok::Tok(DisplayToken::Newline); size.lines()];
+        toks.push(RecTok::Tok(DisplayToken::Whitespace(
+            size.last_line_length(),
+        )));
+        Some(toks.into_iter())
+    }
+
+    /// Returns the placeholder tokens of `node` if it is folded, or [`None`] if it isn't
+    fn folded_tokens(
+        &self,
+        node: &'arena Node,
+    ) -> Option<std::vec::IntoIter<RecTok<'arena, Node>>> {
+        if !self.folded?.contains(&(node as *const Node)) {
+            return None;
+        }
+        let text = format!("{} (+{})", node.display_name(), node.children().len());
+        Some(vec![RecTok::Tok(DisplayToken::Text(text))].into_iter())
+    }
+
+    /// Returns the parent of the node which generated the most recent token, or [`None`] if that
+    /// node is the root
+    pub fn parent(&self) -> Option<&'arena Node> {
+        let len = self.stack.len();
+        len.checked_sub(2).map(|i| self.stack[i].0)
+    }
+
+    /// Returns the path from the root to the node which generated the most recent token
+    pub fn path(&self) -> CursorPath {
+        CursorPath::from_vec(self.path.clone())
+    }
+
+    /// Starts expanding `child`, which is a child of the innermost node being expanded
+    fn push(&mut self, child: &'arena Node, toks: std::vec::IntoIter<RecTok<'arena, Node>>) {
+        // We can unwrap here because `push` is only called whilst a node is being expanded
+        let (parent, _) = self.stack.last().unwrap();
+        let num_expanded = self.num_expanded.last_mut().unwrap();
+        let unexpanded = self.unexpanded.last_mut().unwrap();
+        // Children are almost always expanded in order, but can be reordered (e.g. by sorting the
+        // keys of an object).  Once that happens, the remaining children are looked up by
+        // address.  Nodes can be shared, so each address keeps the indices of every copy of that
+        // node, and each copy is used once.
+        let siblings = parent.children();
+        let index = match (unexpanded.as_mut(), siblings.get(*num_expanded)) {
+            (None, Some(c)) if std::ptr::eq(*c, child) => Some(*num_expanded),
+            (None, _) => {
+                // Every child before `num_expanded` was expanded in order
+                let mut indices: HashMap<*const Node, Vec<usize>> = HashMap::new();
+                for (i, c) in siblings.iter().enumerate().skip(*num_expanded).rev() {
+                    indices.entry(*c as *const Node).or_default().push(i);
                 }
+                unexpanded
+                    .insert(indices)
+                    .get_mut(&(child as *const Node))
+                    .and_then(Vec::pop)
             }
-            DisplayToken::Newline => {
-                // Push a newline and keep indentation
-                string.push('\n');
-                string.push_str(&indentation_string);
-            }
-            DisplayToken::Indent => {
-                // Add `INDENT_WIDTH` spaces to the indentation_string
-                for _ in 0..INDENT_WIDTH {
-                    indentation_string.push(' ');
+            (Some(indices), _) => indices.get_mut(&(child as *const Node)).and_then(Vec::pop),
+        };
+        let index = index.unwrap_or(*num_expanded);
+        *num_expanded += 1;
+        self.stack.push((child, toks));
+        self.path.push(index);
+        self.num_expanded.push(0);
+        self.unexpanded.push(None);
+    }
+}
+
+impl<'arena, 'f, Node: Ast<'arena>> Iterator for DisplayTokenIter<'arena, 'f, Node> {
+    type Item = (&'arena Node, DisplayToken);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, toks) = self.stack.last_mut()?;
+            match toks.next() {
+                Some(RecTok::Tok(t)) => {
+                    match &t {
+                        DisplayToken::Text(s) => self.column = column_after(self.column, s),
+                        DisplayToken::Whitespace(n) => self.column += n,
+                        DisplayToken::Newline => {
+                            self.line += 1;
+                            self.column = self.indent_depth * self.indent_width;
+                        }
+                        DisplayToken::Indent => self.indent_depth += 1,
+                        DisplayToken::Dedent => {
+                            self.indent_depth = self.indent_depth.saturating_sub(1);
+                        }
+                    }
+                    return Some((*node, t));
+                }
+                Some(RecTok::Child(c)) => {
+                    let child_toks = self
+                        .folded_tokens(c)
+                        .or_else(|| self.skipped_tokens(c))
+                        .unwrap_or_else(|| {
+                            c.display_tokens_at(self.format_style, self.column)
+                                .into_iter()
+                        });
+                    self.push(c, child_toks);
+                }
+                Some(RecTok::ChildTokens(c, child_toks)) => {
+                    let child_toks = self
+                        .folded_tokens(c)
+                        .unwrap_or_else(|| child_toks.into_iter());
+                    self.push(c, child_toks);
+                }
+                // This node has no tokens left, so carry on with its parent
+                None => {
+                    self.stack.pop();
+                    self.path.pop();
+                    self.num_expanded.pop();
+                    self.unexpanded.pop();
                 }
             }
-            DisplayToken::Dedent => {
-                // Remove `INDENT_WIDTH` spaces to the indentation_string
-                for _ in 0..INDENT_WIDTH {
-                    let popped_char = indentation_string.pop();
-                    debug_assert_eq!(popped_char, Some(' '));
+        }
+    }
+}
+
+/// Write the text generated by the display tokens of a tree to any [`std::fmt::Write`] sink
+pub fn write_tokens<'arena, Node: Ast<'arena> + 'arena>(
+    root: &Node,
+    out: &mut dyn std::fmt::Write,
+    format_style: &Node::FormatStyle,
+) -> std::fmt::Result {
+    let indent = Node::indent(format_style);
+    let mut writer = TokenWriter {
+        out,
+        format_style,
+        indent_unit: indent.unit(),
+        indent_width: indent.width(),
+        indent_depth: 0,
+        column: 0,
+    };
+    writer.write_node(root)
+}
+
+/// Write the text generated by the display tokens of a tree to any [`std::io::Write`] sink, such
+/// as a buffered file.  Like [`write_tokens`], the text is streamed to the sink one token at a
+/// time.
+pub fn write_tokens_io<'arena, Node: Ast<'arena> + 'arena>(
+    root: &Node,
+    out: &mut dyn std::io::Write,
+    format_style: &Node::FormatStyle,
+) -> std::io::Result<()> {
+    let mut adapter = IoAdapter { out, error: None };
+    match write_tokens(root, &mut adapter, format_style) {
+        Ok(()) => Ok(()),
+        // `fmt::Error` doesn't carry any information, so we return the `io::Error` that caused it
+        Err(std::fmt::Error) => Err(adapter
+            .error
+            .unwrap_or_else(|| std::io::Error::other("formatting error"))),
+    }
+}
+
+/// An adapter which lets an [`std::io::Write`] sink be used as a [`std::fmt::Write`] sink, storing
+/// the last [`std::io::Error`] so that it isn't lost
+struct IoAdapter<'w> {
+    out: &'w mut dyn std::io::Write,
+    error: Option<std::io::Error>,
+}
+
+impl std::fmt::Write for IoAdapter<'_> {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        self.out.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            std::fmt::Error
+        })
+    }
+}
+
+/// Writes the display tokens of a tree to a sink, keeping track of the indentation and of which
+/// column the next token will be written in
+struct TokenWriter<'w, 's, Node: Ast<'s>> {
+    out: &'w mut dyn std::fmt::Write,
+    format_style: &'w Node::FormatStyle,
+    indent_unit: String,
+    indent_width: usize,
+    indent_depth: usize,
+    column: usize,
+}
+
+impl<'w, 'arena, Node: Ast<'arena> + 'arena> TokenWriter<'w, 'arena, Node> {
+    /// Recursively write the display tokens of a node and all its descendants
+    fn write_node(&mut self, node: &Node) -> std::fmt::Result {
+        let toks = node.display_tokens_at(self.format_style, self.column);
+        self.write_rec_toks(toks)
+    }
+
+    /// Write a list of display tokens (and the tokens of any children they contain)
+    fn write_rec_toks(&mut self, toks: Vec<RecTok<'arena, Node>>) -> std::fmt::Result {
+        // Process the token string
+        for tok in toks {
+            match tok {
+                RecTok::Tok(DisplayToken::Text(s)) => {
+                    // Push the string we've been given
+                    self.out.write_str(&s)?;
+                    self.column = column_after(self.column, &s);
+                }
+                RecTok::Tok(DisplayToken::Whitespace(n)) => {
+                    // Push 'n' many spaces
+                    for _ in 0..n {
+                        self.out.write_char(' ')?;
+                    }
+                    self.column += n;
+                }
+                RecTok::Tok(DisplayToken::Newline) => {
+                    // Push a newline and keep indentation
+                    self.out.write_char('\n')?;
+                    for _ in 0..self.indent_depth {
+                        self.out.write_str(&self.indent_unit)?;
+                    }
+                    self.column = self.indent_depth * self.indent_width;
+                }
+                RecTok::Tok(DisplayToken::Indent) => {
+                    // Add one unit of indentation
+                    self.indent_depth += 1;
+                }
+                RecTok::Tok(DisplayToken::Dedent) => {
+                    // Remove one unit of indentation
+                    debug_assert!(self.indent_depth > 0);
+                    self.indent_depth = self.indent_depth.saturating_sub(1);
+                }
+                RecTok::Child(child) => {
+                    // Recursively write the child's tokens
+                    self.write_node(child)?;
                 }
+                RecTok::ChildTokens(_, child_toks) => self.write_rec_toks(child_toks)?,
             }
         }
+        Ok(())
     }
 }
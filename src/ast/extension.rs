@@ -0,0 +1,27 @@
+//! Language extensions, which give the editor features that only some languages have.
+//!
+//! The [`Ast`](super::Ast) trait only has what every language needs.  Anything which only makes
+//! sense for some languages (like checking a JSON tree against a JSON Schema) is a method of
+//! [`LanguageExtension`] instead, which the editor looks for with
+//! [`Ast::extension`](super::Ast::extension).
+
+use super::cursor_path::CursorPath;
+
+/// The features that the trees of some languages have on top of [`Ast`](super::Ast).  This is
+/// implemented by the node type of an AST, and returned from
+/// [`Ast::extension`](super::Ast::extension).
+pub trait LanguageExtension<'arena> {
+    /// Checks this node against a schema, given as the text of a schema file.  Returns the path
+    /// (relative to this node) and a description of every part of this node which doesn't match
+    /// the schema, in document order.  This returns an error message if the schema isn't valid.
+    fn validate(&'arena self, schema: &str) -> Result<Vec<(CursorPath, String)>, String>;
+
+    /// Returns an expression in this language's path syntax (like the JSONPath `$.foo[2]`) for
+    /// the node at `path`, relative to this node
+    fn path_expr(&'arena self, path: &CursorPath) -> String;
+
+    /// Returns the path (relative to this node) to the node addressed by an expression in the
+    /// syntax of [`path_expr`](LanguageExtension::path_expr).  Returns an error message if the
+    /// expression isn't valid or doesn't lead to a node.
+    fn resolve_path_expr(&'arena self, expr: &str) -> Result<CursorPath, String>;
+}
@@ -0,0 +1,34 @@
+//! Formatters, which turn trees into text.
+//!
+//! Every AST's [`FormatStyle`](Ast::FormatStyle) is a formatter, which writes the text that the
+//! editor displays and saves.  A format style can choose between several formatters, like
+//! [`JSONFormat`](super::json::JSONFormat), which holds either a
+//! [`CompactFormat`](super::json::CompactFormat) or a [`PrettyFormat`](super::json::PrettyFormat).
+//! Other formatters can write a tree in a completely different language (like
+//! [`YAMLFormat`](super::json::YAMLFormat), which writes JSON trees as YAML), without adding
+//! anything to the AST's format style.
+
+use super::{display_token::write_tokens, Ast};
+
+/// A way of writing a tree of `Node`s as text
+pub trait Formatter<'arena, Node: Ast<'arena>> {
+    /// Appends the text of the tree under `node` to `out`
+    fn write(&self, node: &Node, out: &mut String);
+
+    /// Builds the text of the tree under `node`.  This is the same as
+    /// [`write`](Formatter::write), except that it returns a new [`String`] rather than appending
+    /// to an existing one.
+    fn format(&self, node: &Node) -> String {
+        let mut s = String::new();
+        self.write(node, &mut s);
+        s
+    }
+}
+
+/// Every AST's format style writes the same text as [`write_text`](Ast::write_text)
+impl<'arena, Node: Ast<'arena> + 'arena> Formatter<'arena, Node> for Node::FormatStyle {
+    fn write(&self, node: &Node, out: &mut String) {
+        // Writing to a `String` can never fail
+        write_tokens(node, out, self).unwrap();
+    }
+}
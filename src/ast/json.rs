@@ -1,15 +1,172 @@
-use super::display_token::{DisplayToken, RecTok};
+use super::cursor_path::CursorPath;
+use super::display_token::{write_tokens, DisplayToken, Indent, RecTok};
+use super::extension::LanguageExtension;
+use super::formatter::Formatter;
 use super::size::Size;
-use super::Ast;
+use super::{Ast, SyntaxClass};
+use crate::arena::{Arena, Persist};
 
-/// An enum to hold the different ways that a JSON AST can be formatted
-#[derive(Eq, PartialEq, Copy, Clone)]
+/// The compact formatter, which writes a JSON tree on one line with minimal whitespace.  E.g.
+/// `[{"foo": true, "bar": false}, true]`
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct CompactFormat {
+    /// If `true`, the spaces after colons and commas are left out as well, so that the text is
+    /// as small as possible.  E.g. `[{"foo":true,"bar":false},true]`
+    pub minified: bool,
+    /// If `true`, the fields of every object are written in order of their keys rather than the
+    /// order they are stored in.  This is a stable sort, so fields with duplicate keys keep their
+    /// relative order.
+    pub sort_keys: bool,
+}
+
+impl CompactFormat {
+    /// The default compact format
+    pub const DEFAULT: CompactFormat = CompactFormat {
+        minified: false,
+        sort_keys: false,
+    };
+    /// The default [`minified`](CompactFormat::minified) format
+    pub const MINIFIED: CompactFormat = CompactFormat {
+        minified: true,
+        sort_keys: false,
+    };
+}
+
+/// The pretty formatter, which writes a JSON tree with pretty indenting and every element on a
+/// newline
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct PrettyFormat {
+    /// If `true`, the fields of every object are written in order of their keys rather than the
+    /// order they are stored in.  This is a stable sort, so fields with duplicate keys keep their
+    /// relative order.
+    pub sort_keys: bool,
+    /// The whitespace added for each level of nesting
+    pub indent: Indent,
+    /// If `true`, a comma is put after the last element of every non-empty array and object.
+    /// This isn't standard JSON, but many tools (including [`JSON::from_text`](Ast::from_text))
+    /// accept it.
+    pub trailing_commas: bool,
+    /// If set, any array or object is written on a single line if that line would still be at
+    /// most this many columns wide (like `rustfmt` and `prettier`).  This includes the
+    /// indentation and key in front of the container, but not the comma after it.  Each
+    /// container is checked separately, so an object which is too wide can still have children
+    /// which are written on one line.
+    pub max_width: Option<usize>,
+    /// If `true`, the keys of each object are padded so that all its values start in the same
+    /// column, e.g. `"foo":    true` above `"longer": false`.  Every object is aligned
+    /// separately.  This has no effect on objects which are written on one line.
+    pub align_values: bool,
+    /// Which small containers are written on one line
+    pub options: PrettyOptions,
+}
+
+impl PrettyFormat {
+    /// The default pretty format
+    pub const DEFAULT: PrettyFormat = PrettyFormat {
+        sort_keys: false,
+        indent: Indent::DEFAULT,
+        trailing_commas: false,
+        max_width: None,
+        align_values: false,
+        options: PrettyOptions::DEFAULT,
+    };
+
+    /// The default pretty format, but with any array or object that fits within `max_width`
+    /// columns written on one line (see [`max_width`](PrettyFormat::max_width))
+    pub const fn fill(max_width: usize) -> PrettyFormat {
+        PrettyFormat {
+            max_width: Some(max_width),
+            ..PrettyFormat::DEFAULT
+        }
+    }
+}
+
+/// Options for writing containers with a single child in the [`PrettyFormat`].  Empty containers
+/// are always written as `[]` or `{}`.  A container which is written on one line hugs its child,
+/// so a child which is spread over several lines starts on the same line as the container's
+/// opening bracket (e.g. `[{` and `}]`).
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Default)]
+pub struct PrettyOptions {
+    /// If `true`, arrays with exactly one element are written on one line, like `[true]`
+    pub collapse_single_element_arrays: bool,
+    /// If `true`, objects with exactly one field are written on one line, like `{"a": true}`
+    pub inline_single_field_objects: bool,
+}
+
+impl PrettyOptions {
+    /// The default options, where every non-empty container is spread over several lines (unless
+    /// it fits within [`PrettyFormat::max_width`])
+    pub const DEFAULT: PrettyOptions = PrettyOptions {
+        collapse_single_element_arrays: false,
+        inline_single_field_objects: false,
+    };
+}
+
+/// The formatter that the editor uses to display and save JSON trees, which is one of the JSON
+/// formatters
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum JSONFormat {
-    /// The most compact representation, has minimal whitespace.
-    /// E.g. `[{"foo": true, "bar": false}, true]`
-    Compact,
-    /// A prettified representation, with pretty indenting and every element on a newline.
-    Pretty,
+    /// Write the tree with a [`CompactFormat`]
+    Compact(CompactFormat),
+    /// Write the tree with a [`PrettyFormat`]
+    Pretty(PrettyFormat),
+}
+
+impl JSONFormat {
+    /// The default [`CompactFormat`]
+    pub const COMPACT: JSONFormat = JSONFormat::Compact(CompactFormat::DEFAULT);
+    /// The default [`minified`](CompactFormat::minified) format
+    pub const MINIFIED: JSONFormat = JSONFormat::Compact(CompactFormat::MINIFIED);
+    /// The default [`PrettyFormat`]
+    pub const PRETTY: JSONFormat = JSONFormat::Pretty(PrettyFormat::DEFAULT);
+
+    /// The [`PrettyFormat`], but with any array or object that fits within `max_width` columns
+    /// written on one line (see [`PrettyFormat::fill`])
+    pub const fn pretty_fill(max_width: usize) -> JSONFormat {
+        JSONFormat::Pretty(PrettyFormat::fill(max_width))
+    }
+
+    /// Returns `true` if the fields of every object are written in order of their keys
+    fn sort_keys(&self) -> bool {
+        match self {
+            JSONFormat::Compact(format) => format.sort_keys,
+            JSONFormat::Pretty(format) => format.sort_keys,
+        }
+    }
+
+    /// Returns the [`PrettyFormat`] if this is one
+    fn pretty(&self) -> Option<&PrettyFormat> {
+        match self {
+            JSONFormat::Compact(_) => None,
+            JSONFormat::Pretty(format) => Some(format),
+        }
+    }
+
+    /// Returns `true` if the spaces after colons and commas are left out
+    fn is_minified(&self) -> bool {
+        matches!(
+            self,
+            JSONFormat::Compact(CompactFormat { minified: true, .. })
+        )
+    }
+}
+
+impl Default for JSONFormat {
+    fn default() -> JSONFormat {
+        JSONFormat::PRETTY
+    }
+}
+
+impl From<CompactFormat> for JSONFormat {
+    fn from(format: CompactFormat) -> JSONFormat {
+        JSONFormat::Compact(format)
+    }
+}
+
+impl From<PrettyFormat> for JSONFormat {
+    fn from(format: PrettyFormat) -> JSONFormat {
+        JSONFormat::Pretty(format)
+    }
 }
 
 const CHAR_TRUE: char = 't';
@@ -19,6 +176,10 @@ const CHAR_ARRAY: char = 'a';
 const CHAR_OBJECT: char = 'o';
 const CHAR_FIELD: char = 'i';
 const CHAR_STRING: char = 's';
+/// Replace sequences which look like the empty values they create, as well as their `char`s
+const SEQ_EMPTY_ARRAY: &str = "[]";
+const SEQ_EMPTY_OBJECT: &str = "{}";
+const SEQ_EMPTY_STRING: &str = "\"\"";
 
 /// Error produced when inserting a child into a JSON node fails
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
@@ -30,6 +191,10 @@ pub enum InsertError {
     /// children.  The second argument is the number of children that this node has to have.  This
     /// is used by nodes such as `field`, which is required to have 2 children.
     FixedChildCount(String, usize),
+    /// A child was attempted to be inserted into a node which can't contain children of that
+    /// type.  The first argument is the parent node, and the second is the child.  This is used
+    /// because objects can only contain fields, and fields can only be contained in objects.
+    InvalidChild(String, String),
 }
 
 impl std::fmt::Display for InsertError {
@@ -41,14 +206,182 @@ impl std::fmt::Display for InsertError {
             InsertError::FixedChildCount(node, num_children) => {
                 write!(f, "Node {} can only have {} children.", node, num_children)
             }
+            InsertError::InvalidChild(node, child) => {
+                write!(f, "Node {} cannot contain node {}.", node, child)
+            }
         }
     }
 }
 
 impl std::error::Error for InsertError {}
 
-/// The sapling representation of the AST for a subset of JSON (where all values are either 'true'
-/// or 'false', and keys only contain ASCII).
+/// The different ways that parsing a JSON string can fail
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub enum ParseErrorKind {
+    /// The parser found a char that can't start or continue any valid JSON value
+    UnexpectedChar(char),
+    /// The text ended whilst the parser was still expecting a value
+    UnexpectedEnd,
+    /// An array was opened with `[` but never closed
+    UnterminatedArray,
+    /// An object was opened with `{` but never closed
+    UnterminatedObject,
+    /// A string was opened with `"` but never closed
+    UnterminatedString,
+    /// A string contained a `\` escape sequence that isn't valid JSON
+    InvalidEscape,
+    /// A complete JSON value was parsed, but it was followed by more non-whitespace text
+    TrailingChars,
+}
+
+/// Error produced when parsing a JSON string fails
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct ParseError {
+    /// The byte offset into the source text where the error was found
+    pub offset: usize,
+    /// The line on which the error was found (starting from 1)
+    pub line: usize,
+    /// The column at which the error was found, counted in chars (starting from 1)
+    pub column: usize,
+    /// What went wrong
+    pub kind: ParseErrorKind,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ParseErrorKind::UnexpectedChar(c) => write!(f, "Unexpected char {:?}", c),
+            ParseErrorKind::UnexpectedEnd => write!(f, "Unexpected end of text"),
+            ParseErrorKind::UnterminatedArray => write!(f, "Unterminated array"),
+            ParseErrorKind::UnterminatedObject => write!(f, "Unterminated object"),
+            ParseErrorKind::UnterminatedString => write!(f, "Unterminated string"),
+            ParseErrorKind::InvalidEscape => write!(f, "Invalid escape sequence"),
+            ParseErrorKind::TrailingChars => {
+                write!(f, "Unexpected text after the end of the value")
+            }
+        }?;
+        write!(f, " at line {}, column {}.", self.line, self.column)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl ParseError {
+    /// Builds a [`ParseError`] of a given kind at a byte `offset` into `text`, working out the line
+    /// and column of that offset
+    fn new(text: &str, offset: usize, kind: ParseErrorKind) -> ParseError {
+        let before = &text[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        ParseError {
+            offset,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            kind,
+        }
+    }
+}
+
+/// The different ways that a JSON tree can be structurally invalid
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub enum ValidationErrorKind {
+    /// An object contains more than one field with this key
+    DuplicateKey(String),
+    /// An object contains a node which isn't a [`Field`](JSON::Field).  The argument is the
+    /// display name of that node.
+    NonFieldInObject(String),
+    /// A [`Field`](JSON::Field) appears somewhere other than directly inside an object
+    FieldOutsideObject,
+    /// The key of a [`Field`](JSON::Field) isn't a [`Str`](JSON::Str).  The argument is the
+    /// display name of the key node.
+    NonStringKey(String),
+}
+
+/// One difference between two JSON trees, as found by [`JSON::diff`].  Every `path` is a
+/// [JSON Pointer](https://tools.ietf.org/html/rfc6901) to the node that changed, and every name is
+/// the [display name](Ast::display_name) of a node.
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub enum Change {
+    /// A node only exists in the new tree
+    Added { path: String, name: String },
+    /// A node only exists in the old tree
+    Removed { path: String, name: String },
+    /// A node has been replaced by a different value, or a different kind of node
+    Replaced {
+        path: String,
+        from_name: String,
+        to_name: String,
+    },
+}
+
+/// A problem found when validating a JSON tree
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ValidationError {
+    /// The path from the root to the offending node.  For duplicate keys, this points to the
+    /// second (or later) field with that key.
+    pub path: CursorPath,
+    /// What is wrong with the node
+    pub kind: ValidationErrorKind,
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ValidationErrorKind::DuplicateKey(key) => write!(f, "Duplicate key {:?}", key),
+            ValidationErrorKind::NonFieldInObject(node) => {
+                write!(f, "Object cannot contain node {}", node)
+            }
+            ValidationErrorKind::FieldOutsideObject => write!(f, "Field outside of an object"),
+            ValidationErrorKind::NonStringKey(node) => {
+                write!(f, "Field has non-string key {}", node)
+            }
+        }?;
+        let indices: Vec<String> = self.path.iter().map(usize::to_string).collect();
+        write!(f, " at [{}].", indices.join(", "))
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// The ways that converting between a [`JSON`] tree and a [`serde_json::Value`] can fail
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ConversionError {
+    /// The [`serde_json::Value`] contains a number, which Sapling's JSON can't represent
+    UnsupportedNumber(serde_json::Number),
+    /// The tree isn't valid JSON (see [`JSON::validate`]), so there is no equivalent
+    /// [`serde_json::Value`].  This includes objects with duplicate keys, because a
+    /// [`serde_json::Map`] can only hold each key once.
+    Invalid(Vec<ValidationError>),
+}
+
+#[cfg(feature = "serde")]
+impl std::fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConversionError::UnsupportedNumber(n) => {
+                write!(f, "Numbers like {} aren't supported.", n)
+            }
+            ConversionError::Invalid(errors) => {
+                let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+                write!(f, "{}", messages.join(" "))
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl std::error::Error for ConversionError {}
+
+/// Parses the value of a boolean format option
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("Expected 'true' or 'false', found '{}'.", value)),
+    }
+}
+
+/// The sapling representation of the AST for a subset of JSON (where there are no numbers).
 #[derive(Debug, Eq, PartialEq, Clone, Hash)]
 pub enum JSON<'arena> {
     /// The JSON value for 'true'.  Corresponds to the string `true`.
@@ -68,11 +401,13 @@ pub enum JSON<'arena> {
     /// A JSON object field.  The first `Ref` must be a [`Str`](JSON::Str), and the second is any
     /// JSON object
     Field([&'arena JSON<'arena>; 2]),
-    /// A JSON string
+    /// A JSON string, which stores the unescaped contents of the string.  Any quotes, backslashes
+    /// and control characters are escaped when it is written, so these can be used in both string
+    /// values and object keys.
     Str(String),
 }
 
-impl JSON<'_> {
+impl<'arena> JSON<'arena> {
     /// Return an iterator over all the possible chars that could represent JSON nodes
     fn all_object_chars() -> Box<dyn Iterator<Item = char>> {
         Box::new(
@@ -88,6 +423,626 @@ impl JSON<'_> {
             .copied(),
         )
     }
+
+    /// Returns `true` if this node should be written on a single line in a given format, starting
+    /// `column` columns into the line.  This is always the case for the [`CompactFormat`].  In
+    /// the [`PrettyFormat`], only arrays and objects which end within the
+    /// [`max_width`](PrettyFormat::max_width), or which have a single child and are collapsed by
+    /// the [`PrettyOptions`], are written on one line.
+    fn is_single_line(&self, format_style: &JSONFormat, column: usize) -> bool {
+        let format = match format_style {
+            JSONFormat::Compact(_) => return true,
+            JSONFormat::Pretty(format) => format,
+        };
+        match (format.max_width, self) {
+            (_, JSON::Array(children))
+                if children.len() == 1 && format.options.collapse_single_element_arrays =>
+            {
+                true
+            }
+            (_, JSON::Object(fields))
+                if fields.len() == 1 && format.options.inline_single_field_objects =>
+            {
+                true
+            }
+            (Some(max_width), JSON::Array(_)) | (Some(max_width), JSON::Object(_)) => max_width
+                .checked_sub(column)
+                .and_then(|limit| self.single_line_width(limit))
+                .is_some(),
+            _ => false,
+        }
+    }
+
+    /// Returns how many columns wide this node is when written on one line in the
+    /// [`CompactFormat`], or [`None`] if that is more than `limit`.  The
+    /// measuring stops as soon as the width goes over `limit`, so only as many nodes are visited
+    /// as can fit within `limit` columns, however big the tree under this node is.
+    fn single_line_width(&self, limit: usize) -> Option<usize> {
+        let width = match self {
+            JSON::True => 4,
+            JSON::False => 5,
+            JSON::Null => 4,
+            JSON::Str(string) => {
+                let size = Size::from(quote_string(string).as_str());
+                if size.lines() > 0 {
+                    return None;
+                }
+                size.last_line_length()
+            }
+            JSON::Field([key, value]) => {
+                // The key, followed by ": "
+                let key_width = key.single_line_width(limit)? + 2;
+                key_width + value.single_line_width(limit.checked_sub(key_width)?)?
+            }
+            JSON::Array(children) | JSON::Object(children) => {
+                // The brackets, and ", " between every pair of children
+                let mut width = 2 + 2 * children.len().saturating_sub(1);
+                for c in children {
+                    width += c.single_line_width(limit.checked_sub(width)?)?;
+                }
+                width
+            }
+        };
+        (width <= limit).then_some(width)
+    }
+
+    /// Returns the [`Size`] of this node when it is written in a given format, starting `column`
+    /// columns into a line whose indentation is `indentation` columns wide.  Whether a container
+    /// is written on one line can depend on how far into the line it starts (see
+    /// [`is_single_line`](JSON::is_single_line)), so the same node can have different sizes in
+    /// different places.
+    fn size_at(&self, format_style: &JSONFormat, indentation: usize, column: usize) -> Size {
+        // The widths of the text between a key and its value, and between two children (see
+        // `display_tokens_rec`)
+        let (colon_width, comma_width) = if format_style.is_minified() {
+            (1, 1)
+        } else {
+            (2, 2)
+        };
+        // The column just after some text of a given size which starts at `column`
+        let column_after = |size: Size| {
+            if size.lines() == 0 {
+                column + size.last_line_length()
+            } else {
+                indentation + size.last_line_length()
+            }
+        };
+        match self {
+            JSON::True => Size::new(0, 4),  // same as Size::from("true")
+            JSON::False => Size::new(0, 5), // same as Size::from("false")
+            JSON::Null => Size::new(0, 4),  // same as Size::from("null")
+            JSON::Str(string) => Size::from(quote_string(string).as_str()),
+            JSON::Field([key, value]) => {
+                let size =
+                    key.size_at(format_style, indentation, column) + Size::new(0, colon_width);
+                size + value.size_at(format_style, indentation, column_after(size))
+            }
+            // Special case: empty containers are rendered as "[]" or "{}", which only take up one
+            // line
+            JSON::Array(children) | JSON::Object(children) if children.is_empty() => {
+                Size::new(0, 2)
+            }
+            JSON::Array(children) | JSON::Object(children)
+                if !self.is_single_line(format_style, column) =>
+            {
+                /* For a container spread over several lines, we are only interested in how many
+                 * lines are occupied - the last line will always just be "]" or "}" */
+                let child_indentation = indentation + JSON::indent(format_style).width();
+                // The width of the widest key, if the values of an object are being aligned
+                let key_width = |f: &JSON| match f {
+                    JSON::Field([key, _]) => key.size(format_style).last_line_length(),
+                    _ => 0,
+                };
+                let align_width = match self {
+                    JSON::Object(_) if format_style.pretty().is_some_and(|f| f.align_values) => {
+                        children.iter().map(|f| key_width(f)).max()
+                    }
+                    _ => None,
+                };
+                // We initialise this to 1 because the opening bracket occupies its own line.
+                let mut number_of_lines = 1;
+                for c in children {
+                    let size = match (align_width, c) {
+                        // Aligned values start after the widest key, a ':' and a space
+                        (Some(width), JSON::Field([_, value])) => value.size_at(
+                            format_style,
+                            child_indentation,
+                            child_indentation + width + 2,
+                        ),
+                        _ => c.size_at(format_style, child_indentation, child_indentation),
+                    };
+                    // The `+ 1` accounts for the extra newline char generated between every
+                    // child.
+                    number_of_lines += size.lines() + 1;
+                }
+                Size::new(number_of_lines, 1)
+            }
+            JSON::Array(children) | JSON::Object(children) => {
+                // The children are measured in the order that `display_tokens_rec` writes them,
+                // because a child spread over several lines changes where the next one starts
+                let mut children: Vec<&&JSON> = children.iter().collect();
+                if format_style.sort_keys() {
+                    children.sort_by_key(|f| f.key());
+                }
+                // Size accumulator - starts with just the size of "[" or "{"
+                let mut size = Size::new(0, 1);
+                // Append all the children, and put ", " between all of them
+                let mut is_first_child = true;
+                for c in children {
+                    // If we're not on the first child, add a ", "
+                    if !is_first_child {
+                        size += Size::new(0, comma_width);
+                    }
+                    is_first_child = false;
+                    size += c.size_at(format_style, indentation, column_after(size));
+                }
+                // Append one more char for "]" or "}" to the end, and return
+                size + Size::new(0, 1)
+            }
+        }
+    }
+
+    /// Checks the structural invariants of this tree (e.g. that no object contains the same key
+    /// twice), returning every problem that was found.  This never modifies the tree.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        self.validate_rec(&mut CursorPath::root(), false, &mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Recursive helper for [`JSON::validate`].  `path` is the path to `self`, and `in_object` is
+    /// `true` if `self`'s parent is an object.
+    fn validate_rec(
+        &self,
+        path: &mut CursorPath,
+        in_object: bool,
+        errors: &mut Vec<ValidationError>,
+    ) {
+        let mut error = |path: &CursorPath, kind: ValidationErrorKind| {
+            errors.push(ValidationError {
+                path: path.clone(),
+                kind,
+            })
+        };
+        match self {
+            JSON::Field([key, _]) => {
+                if !in_object {
+                    error(path, ValidationErrorKind::FieldOutsideObject);
+                }
+                if !matches!(key, JSON::Str(_)) {
+                    path.push(0);
+                    error(path, ValidationErrorKind::NonStringKey(key.display_name()));
+                    path.pop();
+                }
+            }
+            JSON::Object(fields) => {
+                let mut seen_keys: Vec<&str> = Vec::new();
+                for (i, field) in fields.iter().enumerate() {
+                    path.push(i);
+                    match field {
+                        JSON::Field(_) => {
+                            if let Some(key) = field.key() {
+                                if seen_keys.contains(&key) {
+                                    error(path, ValidationErrorKind::DuplicateKey(key.to_string()));
+                                } else {
+                                    seen_keys.push(key);
+                                }
+                            }
+                        }
+                        _ => error(
+                            path,
+                            ValidationErrorKind::NonFieldInObject(field.display_name()),
+                        ),
+                    }
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+        let is_object = matches!(self, JSON::Object(_));
+        for (i, child) in self.children().iter().enumerate() {
+            path.push(i);
+            child.validate_rec(path, is_object, errors);
+            path.pop();
+        }
+    }
+
+    /// Returns the node addressed by a [JSON Pointer](https://tools.ietf.org/html/rfc6901) (e.g.
+    /// `/2/value`), or [`None`] if the pointer is invalid or doesn't point to a node.  The empty
+    /// pointer refers to this node.
+    pub fn get_pointer(&self, pointer: &str) -> Option<&JSON<'arena>> {
+        let mut node = self;
+        for index in self.pointer_path(pointer)?.iter() {
+            node = node.children()[*index];
+        }
+        Some(node)
+    }
+
+    /// Returns the path to the node addressed by a JSON Pointer (see
+    /// [`get_pointer`](JSON::get_pointer)), or [`None`] if there is no such node.  Nodes are
+    /// immutable, so this is how a node found by a pointer can be edited (by moving a
+    /// [`DAG`](crate::editable_tree::DAG)'s cursor to this path).  Note that the path to an object
+    /// member goes through its [`Field`](JSON::Field).
+    pub fn pointer_path(&self, pointer: &str) -> Option<CursorPath> {
+        let mut path = CursorPath::root();
+        if pointer.is_empty() {
+            return Some(path);
+        }
+        let mut node = self;
+        // Every reference token is preceded by a '/'
+        for token in pointer.strip_prefix('/')?.split('/') {
+            let token = unescape_pointer_token(token)?;
+            match node {
+                JSON::Array(children) => {
+                    // Indices can't have leading zeros, and `-` (the element after the last one)
+                    // never exists
+                    if token.is_empty()
+                        || (token.starts_with('0') && token != "0")
+                        || !token.bytes().all(|b| b.is_ascii_digit())
+                    {
+                        return None;
+                    }
+                    let index: usize = token.parse().ok()?;
+                    node = children.get(index)?;
+                    path.push(index);
+                }
+                JSON::Object(fields) => {
+                    // If a key is duplicated, the first field with that key is used
+                    let index = fields.iter().position(|f| f.key() == Some(&token))?;
+                    node = fields[index].children()[1];
+                    path.push(index);
+                    path.push(1);
+                }
+                _ => return None,
+            }
+        }
+        Some(path)
+    }
+
+    /// Returns the differences between this tree and `other`, in the order that they appear in
+    /// the trees.  Arrays are compared element by element, so inserting an element at the start
+    /// of an array replaces every element after it.  Objects are compared by key, where any keys
+    /// which are only in `other` are added after the changes to the keys in `self`.  This isn't
+    /// the smallest possible set of changes, but it is always the same for the same two trees.
+    pub fn diff(&self, other: &JSON) -> Vec<Change> {
+        let mut changes = Vec::new();
+        diff_rec(self, other, &mut String::new(), &mut changes);
+        changes
+    }
+
+    /// Writes this node as a block-style YAML document, with two spaces of indentation for every
+    /// level of nesting.  Empty arrays and objects are written inline as `[]` and `{}`, and strings
+    /// are only quoted if YAML would otherwise read them as something else.
+    pub fn write_yaml(&self, out: &mut String) {
+        self.write_yaml_rec(out, 0);
+    }
+
+    /// Builds a block-style YAML document of this node.  This is the same as
+    /// [`write_yaml`](JSON::write_yaml), except that it returns a [`String`] rather than appending
+    /// to an existing [`String`].
+    pub fn to_yaml(&self) -> String {
+        let mut s = String::new();
+        self.write_yaml(&mut s);
+        s
+    }
+
+    /// Writes the YAML of this node, assuming that the first line has already been indented to
+    /// column `indent`.  Any following lines are indented by `indent` spaces.
+    fn write_yaml_rec(&self, out: &mut String, indent: usize) {
+        match self {
+            JSON::True => out.push_str("true"),
+            JSON::False => out.push_str("false"),
+            JSON::Null => out.push_str("null"),
+            JSON::Str(string) => write_yaml_string(string, out),
+            JSON::Array(children) if children.is_empty() => out.push_str("[]"),
+            JSON::Object(fields) if fields.is_empty() => out.push_str("{}"),
+            JSON::Array(children) | JSON::Object(children) => {
+                for (i, child) in children.iter().enumerate() {
+                    if i > 0 {
+                        out.push('\n');
+                        out.push_str(&" ".repeat(indent));
+                    }
+                    // Array elements are items of a sequence, whereas fields write their own keys
+                    if let JSON::Array(_) = self {
+                        out.push_str("- ");
+                        child.write_yaml_rec(out, indent + 2);
+                    } else {
+                        child.write_yaml_rec(out, indent);
+                    }
+                }
+            }
+            JSON::Field([key, value]) => {
+                key.write_yaml_rec(out, indent);
+                out.push(':');
+                // Non-empty containers start on the next line, one level deeper than the key
+                if value.children().is_empty() {
+                    out.push(' ');
+                    value.write_yaml_rec(out, indent);
+                } else {
+                    out.push('\n');
+                    out.push_str(&" ".repeat(indent + 2));
+                    value.write_yaml_rec(out, indent + 2);
+                }
+            }
+        }
+    }
+}
+
+/// Writes a string as a YAML scalar.  Plain (unquoted) scalars are used where possible, but
+/// strings which would be read as another type (like `true`, `~` or `12`), or which contain
+/// characters with special meanings, are written as double-quoted scalars.
+fn write_yaml_string(string: &str, out: &mut String) {
+    let looks_like_other_type = matches!(
+        string.to_lowercase().as_str(),
+        "true" | "false" | "null" | "~" | "yes" | "no" | "on" | "off" | "y" | "n"
+    ) || string
+        .starts_with(|c: char| c.is_ascii_digit() || c == '.' || c == '+');
+    let needs_quotes = string.is_empty()
+        || looks_like_other_type
+        || string.starts_with(|c: char| c.is_whitespace() || "-?:,[]{}#&*!|>'\"%@`".contains(c))
+        || string.ends_with(|c: char| c.is_whitespace() || c == ':')
+        || string.contains(": ")
+        || string.contains(" #")
+        || string.contains(|c: char| c.is_control());
+    if !needs_quotes {
+        out.push_str(string);
+        return;
+    }
+    // Every JSON string literal is also a valid double-quoted YAML scalar
+    out.push_str(&quote_string(string));
+}
+
+/// Returns a string as a JSON string literal, with quotes around it and any quotes, backslashes
+/// and control characters escaped.  This is used for both string values and object keys.
+fn quote_string(string: &str) -> String {
+    let mut quoted = String::with_capacity(string.len() + 2);
+    quoted.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            '\u{8}' => quoted.push_str("\\b"),
+            '\u{c}' => quoted.push_str("\\f"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(feature = "serde")]
+impl<'arena> JSON<'arena> {
+    /// Builds a tree from a [`serde_json::Value`], adding its nodes to `arena`.  Field order is
+    /// kept.  This fails if the value contains a number, since Sapling's JSON doesn't support
+    /// them.
+    ///
+    /// This can't be a [`From`] impl, because the nodes have to be allocated in an arena.
+    pub fn from_serde(
+        value: &serde_json::Value,
+        arena: &'arena Arena<JSON<'arena>>,
+    ) -> Result<&'arena JSON<'arena>, ConversionError> {
+        let node = match value {
+            serde_json::Value::Null => JSON::Null,
+            serde_json::Value::Bool(true) => JSON::True,
+            serde_json::Value::Bool(false) => JSON::False,
+            serde_json::Value::Number(n) => {
+                return Err(ConversionError::UnsupportedNumber(n.clone()))
+            }
+            serde_json::Value::String(s) => JSON::Str(s.clone()),
+            serde_json::Value::Array(values) => JSON::Array(
+                values
+                    .iter()
+                    .map(|v| JSON::from_serde(v, arena))
+                    .collect::<Result<_, _>>()?,
+            ),
+            serde_json::Value::Object(map) => {
+                let mut fields: Vec<&JSON> = Vec::with_capacity(map.len());
+                for (key, v) in map {
+                    let key = arena.alloc(JSON::Str(key.clone()));
+                    let value = JSON::from_serde(v, arena)?;
+                    fields.push(arena.alloc(JSON::Field([key, value])));
+                }
+                JSON::Object(fields)
+            }
+        };
+        Ok(arena.alloc(node))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<&JSON<'_>> for serde_json::Value {
+    type Error = ConversionError;
+
+    /// Converts a tree into a [`serde_json::Value`], failing if the tree isn't
+    /// [valid](JSON::validate) JSON
+    fn try_from(json: &JSON<'_>) -> Result<Self, Self::Error> {
+        json.validate().map_err(ConversionError::Invalid)?;
+        Ok(to_serde(json))
+    }
+}
+
+/// Converts a [valid](JSON::validate) tree into a [`serde_json::Value`]
+#[cfg(feature = "serde")]
+fn to_serde(json: &JSON<'_>) -> serde_json::Value {
+    match json {
+        JSON::True => serde_json::Value::Bool(true),
+        JSON::False => serde_json::Value::Bool(false),
+        JSON::Null => serde_json::Value::Null,
+        JSON::Str(s) => serde_json::Value::String(s.clone()),
+        JSON::Array(children) => {
+            serde_json::Value::Array(children.iter().map(|c| to_serde(c)).collect())
+        }
+        JSON::Object(fields) => serde_json::Value::Object(
+            fields
+                .iter()
+                .map(|f| match f {
+                    JSON::Field([JSON::Str(key), value]) => (key.clone(), to_serde(value)),
+                    _ => unreachable!("Validated objects only contain fields with string keys"),
+                })
+                .collect(),
+        ),
+        // A valid tree never has a field outside an object
+        JSON::Field(_) => unreachable!("Validated trees only contain fields inside objects"),
+    }
+}
+
+/// Finds the differences between `old` and `new`, which are both at `path`, and adds them to
+/// `changes`.  `path` is left unchanged.
+fn diff_rec(old: &JSON, new: &JSON, path: &mut String, changes: &mut Vec<Change>) {
+    // Adds a reference token to the end of `path`, returning the previous length of `path` so
+    // that the token can be removed again
+    fn push_token(path: &mut String, token: &str) -> usize {
+        let len = path.len();
+        path.push('/');
+        path.push_str(&escape_pointer_token(token));
+        len
+    }
+
+    match (old, new) {
+        (JSON::Array(old_children), JSON::Array(new_children)) => {
+            let len = old_children.len().max(new_children.len());
+            for i in 0..len {
+                let path_len = push_token(path, &i.to_string());
+                match (old_children.get(i), new_children.get(i)) {
+                    (Some(o), Some(n)) => diff_rec(o, n, path, changes),
+                    (Some(o), None) => changes.push(Change::Removed {
+                        path: path.clone(),
+                        name: o.display_name(),
+                    }),
+                    (None, Some(n)) => changes.push(Change::Added {
+                        path: path.clone(),
+                        name: n.display_name(),
+                    }),
+                    (None, None) => unreachable!(),
+                }
+                path.truncate(path_len);
+            }
+        }
+        (JSON::Object(old_fields), JSON::Object(new_fields)) => {
+            // Only fields with string keys can be addressed by a pointer, so any other members of
+            // an (invalid) object are ignored
+            fn members<'a, 'arena>(
+                fields: &'a [&'arena JSON<'arena>],
+            ) -> impl Iterator<Item = (&'a str, &'arena JSON<'arena>)> + Clone {
+                fields.iter().filter_map(|f| match f {
+                    JSON::Field([JSON::Str(key), value]) => Some((key.as_str(), *value)),
+                    _ => None,
+                })
+            }
+            let (old_members, new_members) = (members(old_fields), members(new_fields));
+            // If a key is duplicated, only the first field with that key is compared
+            for (key, old_value) in old_members.clone() {
+                let path_len = push_token(path, key);
+                match new_members.clone().find(|(k, _)| *k == key) {
+                    Some((_, new_value)) => diff_rec(old_value, new_value, path, changes),
+                    None => changes.push(Change::Removed {
+                        path: path.clone(),
+                        name: old_value.display_name(),
+                    }),
+                }
+                path.truncate(path_len);
+            }
+            for (key, new_value) in new_members {
+                if old_members.clone().all(|(k, _)| k != key) {
+                    let path_len = push_token(path, key);
+                    changes.push(Change::Added {
+                        path: path.clone(),
+                        name: new_value.display_name(),
+                    });
+                    path.truncate(path_len);
+                }
+            }
+        }
+        _ => {
+            if old != new {
+                changes.push(Change::Replaced {
+                    path: path.clone(),
+                    from_name: old.display_name(),
+                    to_name: new.display_name(),
+                });
+            }
+        }
+    }
+}
+
+/// Escapes a key so that it can be used as a JSON Pointer reference token (the opposite of
+/// [`unescape_pointer_token`])
+fn escape_pointer_token(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+/// Undoes the escaping of a JSON Pointer reference token, where `~1` stands for `/` and `~0`
+/// stands for `~`.  Returns [`None`] if `~` is followed by anything else.
+fn unescape_pointer_token(token: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(token.len());
+    let mut chars = token.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '~' => match chars.next() {
+                Some('0') => unescaped.push('~'),
+                Some('1') => unescaped.push('/'),
+                _ => return None,
+            },
+            _ => unescaped.push(c),
+        }
+    }
+    Some(unescaped)
+}
+
+/// JSON trees can be checked against a JSON Schema (see [`json_schema`](super::json_schema)), and
+/// their nodes are addressed by JSONPath expressions (see [`json_path`](super::json_path))
+impl<'arena> LanguageExtension<'arena> for JSON<'arena> {
+    fn validate(&'arena self, schema: &str) -> Result<Vec<(CursorPath, String)>, String> {
+        super::json_schema::validate(self, schema)
+    }
+
+    fn path_expr(&'arena self, path: &CursorPath) -> String {
+        super::json_path::format(self, path)
+    }
+
+    fn resolve_path_expr(&'arena self, expr: &str) -> Result<CursorPath, String> {
+        super::json_path::resolve(self, expr)
+    }
+}
+
+impl std::fmt::Display for JSON<'_> {
+    /// Writes the [`CompactFormat`] representation of this JSON tree
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_tokens(self, f, &JSONFormat::COMPACT)
+    }
+}
+
+impl<'arena> Formatter<'arena, JSON<'arena>> for CompactFormat {
+    fn write(&self, node: &JSON<'arena>, out: &mut String) {
+        // Writing to a `String` can never fail
+        write_tokens(node, out, &JSONFormat::Compact(*self)).unwrap();
+    }
+}
+
+impl<'arena> Formatter<'arena, JSON<'arena>> for PrettyFormat {
+    fn write(&self, node: &JSON<'arena>, out: &mut String) {
+        // Writing to a `String` can never fail
+        write_tokens(node, out, &JSONFormat::Pretty(*self)).unwrap();
+    }
+}
+
+/// A [`Formatter`] which writes JSON trees as block-style YAML documents (see
+/// [`JSON::write_yaml`])
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Default)]
+pub struct YAMLFormat;
+
+impl<'arena> Formatter<'arena, JSON<'arena>> for YAMLFormat {
+    fn write(&self, node: &JSON<'arena>, out: &mut String) {
+        node.write_yaml(out);
+    }
 }
 
 impl Default for JSON<'_> {
@@ -99,23 +1054,36 @@ impl Default for JSON<'_> {
 impl<'arena> Ast<'arena> for JSON<'arena> {
     type FormatStyle = JSONFormat;
     type InsertError = InsertError;
+    type ParseError = ParseError;
 
     /* FORMATTING FUNCTIONS */
 
-    fn display_tokens_rec(
-        &'arena self,
+    fn display_tokens_rec(&self, format_style: &Self::FormatStyle) -> Vec<RecTok<'arena, Self>> {
+        self.display_tokens_at(format_style, 0)
+    }
+
+    fn display_tokens_at(
+        &self,
         format_style: &Self::FormatStyle,
+        column: usize,
     ) -> Vec<RecTok<'arena, Self>> {
-        let is_pretty = format_style == &JSONFormat::Pretty;
+        let is_pretty = !self.is_single_line(format_style, column);
+        let trailing_comma = is_pretty && format_style.pretty().is_some_and(|f| f.trailing_commas);
+        // The text between a key and its value, and the space after the commas between children
+        let (colon, comma_space) = if format_style.is_minified() {
+            (":", 0)
+        } else {
+            (": ", 1)
+        };
         match self {
             JSON::True => vec![RecTok::Tok(DisplayToken::Text("true".to_string()))],
             JSON::False => vec![RecTok::Tok(DisplayToken::Text("false".to_string()))],
             JSON::Null => vec![RecTok::Tok(DisplayToken::Text("null".to_string()))],
-            JSON::Str(string) => vec![RecTok::Tok(DisplayToken::Text(format!(r#""{}""#, string)))],
+            JSON::Str(string) => vec![RecTok::Tok(DisplayToken::Text(quote_string(string)))],
             JSON::Field([key, value]) => vec![
-                RecTok::Child(key),
-                RecTok::Tok(DisplayToken::Text(": ".to_string())),
-                RecTok::Child(value),
+                RecTok::Child(*key),
+                RecTok::Tok(DisplayToken::Text(colon.to_string())),
+                RecTok::Child(*value),
             ],
             JSON::Array(children) => {
                 // Special case: if this array is empty, render it as '[]'
@@ -138,13 +1106,16 @@ impl<'arena> Ast<'arena> for JSON<'arena> {
                         tokens.push(RecTok::Tok(DisplayToken::Text(",".to_string())));
                         if is_pretty {
                             tokens.push(RecTok::Tok(DisplayToken::Newline));
-                        } else {
-                            tokens.push(RecTok::Tok(DisplayToken::Whitespace(1)));
+                        } else if comma_space > 0 {
+                            tokens.push(RecTok::Tok(DisplayToken::Whitespace(comma_space)));
                         }
                     }
                     is_first_child = false;
                     // Push the single child
-                    tokens.push(RecTok::Child(c));
+                    tokens.push(RecTok::Child(*c));
+                }
+                if trailing_comma {
+                    tokens.push(RecTok::Tok(DisplayToken::Text(",".to_string())));
                 }
                 // Push the closing bracket
                 if is_pretty {
@@ -168,6 +1139,23 @@ impl<'arena> Ast<'arena> for JSON<'arena> {
                     tokens.push(RecTok::Tok(DisplayToken::Indent));
                     tokens.push(RecTok::Tok(DisplayToken::Newline));
                 }
+                // Sort the fields by key if needed.  `sort_by_key` is stable, so fields with
+                // duplicate keys will keep their relative order.
+                let mut fields: Vec<&&JSON> = fields.iter().collect();
+                if format_style.sort_keys() {
+                    fields.sort_by_key(|f| f.key());
+                }
+                // The width of the widest key, if the values are being aligned
+                let key_width = |f: &JSON| match f {
+                    JSON::Field([key, _]) => key.size(format_style).last_line_length(),
+                    _ => 0,
+                };
+                let align_width =
+                    if is_pretty && format_style.pretty().is_some_and(|f| f.align_values) {
+                        fields.iter().map(|f| key_width(f)).max()
+                    } else {
+                        None
+                    };
                 // Push the children, delimited by commas
                 let mut is_first_child = true;
                 for f in fields {
@@ -176,13 +1164,30 @@ impl<'arena> Ast<'arena> for JSON<'arena> {
                         tokens.push(RecTok::Tok(DisplayToken::Text(",".to_string())));
                         if is_pretty {
                             tokens.push(RecTok::Tok(DisplayToken::Newline));
-                        } else {
-                            tokens.push(RecTok::Tok(DisplayToken::Whitespace(1)));
+                        } else if comma_space > 0 {
+                            tokens.push(RecTok::Tok(DisplayToken::Whitespace(comma_space)));
                         }
                     }
                     is_first_child = false;
-                    // Push the single child
-                    tokens.push(RecTok::Child(f));
+                    // Push the single child, padding its key if the values are aligned
+                    match (align_width, f) {
+                        (Some(width), JSON::Field([key, value])) => {
+                            let padding = width - key_width(f);
+                            tokens.push(RecTok::ChildTokens(
+                                *f,
+                                vec![
+                                    RecTok::Child(*key),
+                                    RecTok::Tok(DisplayToken::Text(":".to_string())),
+                                    RecTok::Tok(DisplayToken::Whitespace(padding + 1)),
+                                    RecTok::Child(*value),
+                                ],
+                            ));
+                        }
+                        _ => tokens.push(RecTok::Child(*f)),
+                    }
+                }
+                if trailing_comma {
+                    tokens.push(RecTok::Tok(DisplayToken::Text(",".to_string())));
                 }
                 // Push the closing bracket
                 if is_pretty {
@@ -196,101 +1201,130 @@ impl<'arena> Ast<'arena> for JSON<'arena> {
         }
     }
 
-    fn size(&self, format_style: &Self::FormatStyle) -> Size {
+    fn indent(format_style: &Self::FormatStyle) -> Indent {
+        format_style.pretty().map_or(Indent::DEFAULT, |f| f.indent)
+    }
+
+    fn format_style_name(format_style: &Self::FormatStyle) -> String {
         match format_style {
-            JSONFormat::Pretty => {
-                match self {
-                    JSON::True => Size::new(0, 4),  // same as Size::from("true")
-                    JSON::False => Size::new(0, 5), // same as Size::from("false")
-                    JSON::Null => Size::new(0, 4),  // same as Size::from("null")
-                    JSON::Str(string) => {
-                        Size::new(0, 1) + Size::from(string.as_str()) + Size::new(0, 1)
-                    }
-                    JSON::Field([key, value]) => {
-                        key.size(format_style) + Size::new(0, 2) + value.size(format_style)
-                    }
-                    JSON::Object(fields) => {
-                        // Special case: if the object is empty, then it will be rendered as "{}",
-                        // which only takes up one line
-                        if fields.is_empty() {
-                            return Size::new(0, 2); // same as Size::from("{}")
-                        }
-                        /* For an object, we are only interested in how many lines are occupied -
-                         * the last line will always just be "}" */
-                        // We initialise this to 1 because the opening '{' occupies its own line.
-                        let mut number_of_lines = 1;
-                        for f in fields {
-                            // The `+ 1` accounts for the extra newline char generated between
-                            // every field.
-                            number_of_lines += f.size(format_style).lines() + 1;
-                        }
-                        Size::new(number_of_lines, 1)
-                    }
-                    JSON::Array(children) => {
-                        // Special case: if the array is empty, then it will be rendered as "[]",
-                        // which only takes up one line
-                        if children.is_empty() {
-                            return Size::new(0, 2); // same as Size::from("[]");
-                        }
-                        /* For an array, we are only interested in how many lines are occupied -
-                         * the last line will always just be "]" */
-                        // We initialise this to 1 because the opening '[' occupies its own line.
-                        let mut number_of_lines = 1;
-                        for c in children {
-                            // The `+ 1` accounts for the extra newline char generated between
-                            // every child.
-                            number_of_lines += c.size(format_style).lines() + 1;
-                        }
-                        Size::new(number_of_lines, 1)
-                    }
-                }
+            JSONFormat::Compact(CompactFormat {
+                minified: false, ..
+            }) => "compact".to_string(),
+            JSONFormat::Compact(CompactFormat { minified: true, .. }) => "minified".to_string(),
+            JSONFormat::Pretty(_) => "pretty".to_string(),
+        }
+    }
+
+    /// Switches between the [`CompactFormat`] and the [`PrettyFormat`].  Only
+    /// [`sort_keys`](PrettyFormat::sort_keys) is kept, because the other options belong to just
+    /// one of the formatters.
+    fn cycle_format_style(format_style: &mut Self::FormatStyle) {
+        let sort_keys = format_style.sort_keys();
+        *format_style = match format_style {
+            JSONFormat::Compact(_) => JSONFormat::Pretty(PrettyFormat {
+                sort_keys,
+                ..PrettyFormat::DEFAULT
+            }),
+            JSONFormat::Pretty(_) => JSONFormat::Compact(CompactFormat {
+                sort_keys,
+                ..CompactFormat::DEFAULT
+            }),
+        };
+    }
+
+    fn set_format_option(
+        format_style: &mut Self::FormatStyle,
+        option: &str,
+        value: &str,
+    ) -> Result<(), String> {
+        let sort_keys = format_style.sort_keys();
+        match (option, value, format_style) {
+            ("format", "compact", format_style) | ("format", "minified", format_style) => {
+                *format_style = JSONFormat::Compact(CompactFormat {
+                    minified: value == "minified",
+                    sort_keys,
+                })
             }
-            JSONFormat::Compact => {
-                match self {
-                    JSON::True => Size::new(0, 4),  // same as Size::from("true")
-                    JSON::False => Size::new(0, 5), // same as Size::from("false")
-                    JSON::Null => Size::new(0, 4),  // same as Size::from("false")
-                    JSON::Str(string) => {
-                        Size::new(0, 1) + Size::from(string.as_str()) + Size::new(0, 1)
-                    }
-                    JSON::Field([key, value]) => {
-                        key.size(format_style) + Size::new(0, 2) + value.size(format_style)
-                    }
-                    JSON::Object(fields) => {
-                        // Size accumulator - starts with just the size of "{"
-                        let mut size = Size::new(0, 1);
-                        // Append all the children, and put ", " between all of them
-                        let mut is_first_child = true;
-                        for f in fields {
-                            // If we're not on the first child, add a ", "
-                            if !is_first_child {
-                                size += Size::new(0, 2);
-                            }
-                            is_first_child = false;
-                            size += f.size(format_style);
-                        }
-                        // Append one more char for "}" to the end, and return
-                        size + Size::new(0, 1)
-                    }
-                    JSON::Array(children) => {
-                        // Size accumulator - starts with just the size of "["
-                        let mut size = Size::new(0, 1);
-                        // Append all the children, and put ", " between all of them
-                        let mut is_first_child = true;
-                        for c in children {
-                            // If we're not on the first child, add a ", "
-                            if !is_first_child {
-                                size += Size::new(0, 2);
-                            }
-                            is_first_child = false;
-                            size += c.size(format_style);
-                        }
-                        // Append one more char for "]" to the end, and return
-                        size + Size::new(0, 1)
-                    }
-                }
+            ("format", "pretty", JSONFormat::Pretty(_)) => {}
+            ("format", "pretty", format_style) => {
+                *format_style = JSONFormat::Pretty(PrettyFormat {
+                    sort_keys,
+                    ..PrettyFormat::DEFAULT
+                })
+            }
+            ("format", _, _) => {
+                return Err(format!(
+                    "Expected 'compact', 'minified' or 'pretty', found '{}'.",
+                    value
+                ))
+            }
+            ("sort_keys", _, JSONFormat::Compact(format)) => format.sort_keys = parse_bool(value)?,
+            ("sort_keys", _, JSONFormat::Pretty(format)) => format.sort_keys = parse_bool(value)?,
+            ("indent", _, JSONFormat::Pretty(format)) => format.indent = value.parse()?,
+            ("trailing_commas", _, JSONFormat::Pretty(format)) => {
+                format.trailing_commas = parse_bool(value)?
+            }
+            ("align_values", _, JSONFormat::Pretty(format)) => {
+                format.align_values = parse_bool(value)?
             }
+            ("collapse_single_element_arrays", _, JSONFormat::Pretty(format)) => {
+                format.options.collapse_single_element_arrays = parse_bool(value)?
+            }
+            ("inline_single_field_objects", _, JSONFormat::Pretty(format)) => {
+                format.options.inline_single_field_objects = parse_bool(value)?
+            }
+            ("max_width", "none", JSONFormat::Pretty(format)) => format.max_width = None,
+            ("max_width", _, JSONFormat::Pretty(format)) => {
+                format.max_width = Some(value.parse().map_err(|_| {
+                    format!("Expected a number of columns or 'none', found '{}'.", value)
+                })?)
+            }
+            (
+                "indent"
+                | "trailing_commas"
+                | "align_values"
+                | "collapse_single_element_arrays"
+                | "inline_single_field_objects"
+                | "max_width",
+                _,
+                JSONFormat::Compact(_),
+            ) => return Err(format!("'{}' only applies to the pretty format.", option)),
+            _ => return Err(format!("Unknown option '{}'.", option)),
         }
+        Ok(())
+    }
+
+    fn size(&self, format_style: &Self::FormatStyle) -> Size {
+        self.size_at(format_style, 0, 0)
+    }
+
+    fn cheap_size_at(
+        &self,
+        format_style: &Self::FormatStyle,
+        indentation: usize,
+        column: usize,
+    ) -> Option<Size> {
+        Some(self.size_at(format_style, indentation, column))
+    }
+
+    /* PARSING FUNCTIONS */
+
+    fn from_text(
+        text: &str,
+        arena: &'arena Arena<JSON<'arena>>,
+    ) -> Result<&'arena JSON<'arena>, ParseError> {
+        let mut parser = Parser {
+            text,
+            offset: 0,
+            arena,
+        };
+        let root = parser.parse_value()?;
+        // Make sure that the value we parsed used up the whole string
+        parser.skip_whitespace();
+        if parser.offset < text.len() {
+            return Err(parser.error(ParseErrorKind::TrailingChars));
+        }
+        Ok(root)
     }
 
     /* DEBUG VIEW FUNCTIONS */
@@ -298,8 +1332,8 @@ impl<'arena> Ast<'arena> for JSON<'arena> {
     fn children<'s>(&'s self) -> &'s [&'arena JSON<'arena>] {
         match self {
             JSON::True | JSON::False | JSON::Null | JSON::Str(_) => &[],
-            JSON::Array(children) => &children,
-            JSON::Object(fields) => &fields,
+            JSON::Array(children) => children,
+            JSON::Object(fields) => fields,
             JSON::Field(key_value) => &key_value[..],
         }
     }
@@ -320,11 +1354,38 @@ impl<'arena> Ast<'arena> for JSON<'arena> {
             }
             JSON::Field(_) => Err(InsertError::FixedChildCount(self.display_name(), 2)),
             JSON::Object(fields) => {
-                fields.insert(index, new_node);
-                Ok(())
+                if let JSON::Field(_) = new_node {
+                    fields.insert(index, new_node);
+                    Ok(())
+                } else {
+                    Err(InsertError::InvalidChild(
+                        self.display_name(),
+                        new_node.display_name(),
+                    ))
+                }
             }
             JSON::Array(children) => {
-                children.insert(index, new_node);
+                if let JSON::Field(_) = new_node {
+                    Err(InsertError::InvalidChild(
+                        self.display_name(),
+                        new_node.display_name(),
+                    ))
+                } else {
+                    children.insert(index, new_node);
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    fn remove_child(&mut self, index: usize) -> Result<(), InsertError> {
+        match self {
+            JSON::True | JSON::False | JSON::Null | JSON::Str(_) => {
+                Err(InsertError::NoPossibleChildren(self.display_name()))
+            }
+            JSON::Field(_) => Err(InsertError::FixedChildCount(self.display_name(), 2)),
+            JSON::Object(children) | JSON::Array(children) => {
+                children.remove(index);
                 Ok(())
             }
         }
@@ -338,17 +1399,62 @@ impl<'arena> Ast<'arena> for JSON<'arena> {
             JSON::Array(_) => "array".to_string(),
             JSON::Object(_) => "object".to_string(),
             JSON::Field(_) => "field".to_string(),
-            JSON::Str(content) => format!(r#""{}""#, content),
+            JSON::Str(content) => quote_string(content),
+        }
+    }
+
+    fn key(&self) -> Option<&str> {
+        match self {
+            JSON::Field([JSON::Str(key), _]) => Some(key),
+            _ => None,
+        }
+    }
+
+    fn syntax_class(&self, parent: Option<&Self>) -> SyntaxClass {
+        match (self, parent) {
+            // Keys are the strings which are the first child of a field
+            (JSON::Str(_), Some(JSON::Field([key, _]))) if std::ptr::eq(*key, self) => {
+                SyntaxClass::Key
+            }
+            (JSON::Str(_), _) => SyntaxClass::String,
+            (JSON::True, _) | (JSON::False, _) => SyntaxClass::Boolean,
+            (JSON::Null, _) => SyntaxClass::Null,
+            (JSON::Array(_), _) | (JSON::Object(_), _) => SyntaxClass::Container,
+            (JSON::Field(_), _) => SyntaxClass::Punctuation,
         }
     }
 
+    fn extension(&'arena self) -> Option<&'arena dyn LanguageExtension<'arena>> {
+        Some(self)
+    }
+
     /* AST EDITING FUNCTIONS */
 
-    fn replace_chars(&self) -> Box<dyn Iterator<Item = char>> {
-        Self::all_object_chars()
+    fn replace_seqs(&self) -> Box<dyn Iterator<Item = String>> {
+        let seqs = vec![SEQ_EMPTY_ARRAY, SEQ_EMPTY_OBJECT, SEQ_EMPTY_STRING];
+        Box::new(
+            Self::all_object_chars()
+                .map(String::from)
+                .chain(seqs.into_iter().map(String::from)),
+        )
+    }
+
+    fn node_for_replace_seq(&self, seq: &str) -> Option<Self> {
+        match seq {
+            SEQ_EMPTY_ARRAY => Some(JSON::Array(vec![])),
+            SEQ_EMPTY_OBJECT => Some(JSON::Object(vec![])),
+            SEQ_EMPTY_STRING => Some(JSON::Str("".to_string())),
+            _ => {
+                let mut chars = seq.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => self.node_for_char(c),
+                    _ => None,
+                }
+            }
+        }
     }
 
-    fn from_char(&self, c: char) -> Option<Self> {
+    fn node_for_char(&self, c: char) -> Option<Self> {
         match c {
             CHAR_TRUE => Some(JSON::True),
             CHAR_FALSE => Some(JSON::False),
@@ -360,6 +1466,31 @@ impl<'arena> Ast<'arena> for JSON<'arena> {
         }
     }
 
+    fn convert(&self, seq: &str, arena: &'arena Arena<Self>) -> Option<Self> {
+        match (self, self.node_for_replace_seq(seq)?) {
+            // Array elements become fields, keyed by their index in the array
+            (JSON::Array(children), JSON::Object(_)) => Some(JSON::Object(
+                children
+                    .iter()
+                    .enumerate()
+                    .map(|(i, child)| {
+                        arena.alloc(JSON::Field([arena.alloc(JSON::Str(i.to_string())), child]))
+                            as &JSON
+                    })
+                    .collect(),
+            )),
+            // Fields are replaced by their values, dropping the keys
+            (JSON::Object(fields), JSON::Array(_)) => Some(JSON::Array(
+                fields.iter().map(|f| f.children()[1]).collect(),
+            )),
+            // Converting a container to its own type keeps everything
+            (JSON::Array(_), JSON::Array(_)) | (JSON::Object(_), JSON::Object(_)) => {
+                Some(self.clone())
+            }
+            (_, new_node) => Some(new_node),
+        }
+    }
+
     fn insert_chars(&self) -> Box<dyn Iterator<Item = char>> {
         match self {
             JSON::True | JSON::False | JSON::Null | JSON::Field(_) | JSON::Str(_) => {
@@ -369,15 +1500,405 @@ impl<'arena> Ast<'arena> for JSON<'arena> {
             JSON::Array(_) => Self::all_object_chars(),
         }
     }
+
+    fn explode(&self, arena: &'arena Arena<Self>) -> Option<Self> {
+        match self {
+            // Move every field into its own object.  Because the old object won't be part of the
+            // new tree, the fields can be moved without being cloned.
+            JSON::Object(fields) => Some(JSON::Array(
+                fields
+                    .iter()
+                    .map(|f| arena.alloc(JSON::Object(vec![*f])) as &JSON)
+                    .collect(),
+            )),
+            _ => None,
+        }
+    }
+
+    fn merge(&self, _arena: &'arena Arena<Self>) -> Option<Self> {
+        match self {
+            // Only arrays which contain nothing but objects can be merged
+            JSON::Array(children) => {
+                let mut fields = Vec::new();
+                for c in children {
+                    match c {
+                        JSON::Object(child_fields) => fields.extend(child_fields.iter().copied()),
+                        _ => return None,
+                    }
+                }
+                Some(JSON::Object(fields))
+            }
+            _ => None,
+        }
+    }
+
+    fn child_sort_keys(&self) -> Result<Vec<String>, String> {
+        match self {
+            // Objects are sorted by their keys, which only makes sense if the keys are unique
+            JSON::Object(fields) => {
+                let keys: Vec<String> = fields
+                    .iter()
+                    .map(|f| f.key().unwrap_or_default().to_string())
+                    .collect();
+                for (i, key) in keys.iter().enumerate() {
+                    if keys[..i].contains(key) {
+                        return Err(format!(
+                            "Cannot sort an object with duplicate key {:?}.",
+                            key
+                        ));
+                    }
+                }
+                Ok(keys)
+            }
+            // Arrays can only be sorted if their elements are leaves of the same type.  The display
+            // names of booleans already sort with `false` before `true`.
+            JSON::Array(children) => {
+                let type_of = |c: &JSON| match c {
+                    JSON::True | JSON::False => Some("boolean"),
+                    JSON::Null => Some("null"),
+                    JSON::Str(_) => Some("string"),
+                    _ => None,
+                };
+                let first_type = children.first().and_then(|c| type_of(c));
+                for c in children.iter() {
+                    if type_of(c).is_none() {
+                        return Err(format!(
+                            "Cannot sort an array containing {}.",
+                            c.display_name()
+                        ));
+                    } else if type_of(c) != first_type {
+                        return Err("Cannot sort an array of mixed types.".to_string());
+                    }
+                }
+                Ok(children
+                    .iter()
+                    .map(|c| match c {
+                        JSON::Str(content) => content.clone(),
+                        _ => c.display_name(),
+                    })
+                    .collect())
+            }
+            _ => Err(format!(
+                "Cannot sort the children of {}.",
+                self.display_name()
+            )),
+        }
+    }
+
+    fn new_keyed_child(&self, key: String, arena: &'arena Arena<Self>) -> Option<Self> {
+        match self {
+            JSON::Object(_) => Some(JSON::Field([
+                arena.alloc(JSON::Str(key)),
+                arena.alloc(JSON::default()),
+            ])),
+            _ => None,
+        }
+    }
+
+    fn with_key(&self, key: String, arena: &'arena Arena<Self>) -> Option<Self> {
+        match self {
+            JSON::Field([_, value]) => Some(JSON::Field([arena.alloc(JSON::Str(key)), value])),
+            _ => None,
+        }
+    }
+
+    fn is_empty_container(&self) -> bool {
+        match self {
+            JSON::Array(children) | JSON::Object(children) => children.is_empty(),
+            // A field's value can't be removed on its own, so a field holding an empty container
+            // counts as empty (and is removed from its object instead)
+            JSON::Field([_, value]) => value.is_empty_container(),
+            _ => false,
+        }
+    }
+}
+
+impl<'arena> Persist<'arena> for JSON<'arena> {
+    const TYPE_NAME: &'static str = "json";
+    const VERSION: u32 = 1;
+
+    fn encode(&self) -> (u8, &str) {
+        match self {
+            JSON::True => (0, ""),
+            JSON::False => (1, ""),
+            JSON::Null => (2, ""),
+            JSON::Array(_) => (3, ""),
+            JSON::Object(_) => (4, ""),
+            JSON::Field(_) => (5, ""),
+            JSON::Str(content) => (6, content),
+        }
+    }
+
+    fn decode(tag: u8, text: &str, children: Vec<&'arena Self>) -> Option<Self> {
+        match (tag, text, children.as_slice()) {
+            (0, "", []) => Some(JSON::True),
+            (1, "", []) => Some(JSON::False),
+            (2, "", []) => Some(JSON::Null),
+            (3, "", _) => Some(JSON::Array(children)),
+            (4, "", fields) if fields.iter().all(|f| matches!(f, JSON::Field(_))) => {
+                Some(JSON::Object(children))
+            }
+            (5, "", [key @ JSON::Str(_), value]) => Some(JSON::Field([key, value])),
+            (6, _, []) => Some(JSON::Str(text.to_string())),
+            _ => None,
+        }
+    }
+}
+
+/// A simple recursive-descent parser which reads JSON text into nodes in an [`Arena`]
+struct Parser<'t, 'arena> {
+    /// The full text being parsed
+    text: &'t str,
+    /// The byte offset of the next unread char in `text`
+    offset: usize,
+    /// The arena that new nodes are added to
+    arena: &'arena Arena<JSON<'arena>>,
+}
+
+impl<'t, 'arena> Parser<'t, 'arena> {
+    /// Build a [`ParseError`] of a given kind at the current location
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        self.error_at(self.offset, kind)
+    }
+
+    /// Build a [`ParseError`] of a given kind at a given byte offset
+    fn error_at(&self, offset: usize, kind: ParseErrorKind) -> ParseError {
+        ParseError::new(self.text, offset, kind)
+    }
+
+    /// Returns the next unread char, without consuming it
+    fn peek(&self) -> Option<char> {
+        self.text[self.offset..].chars().next()
+    }
+
+    /// Consumes and returns the next unread char
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.offset += c.len_utf8();
+        Some(c)
+    }
+
+    /// Consume chars until the next char isn't whitespace.  Only the four whitespace chars that
+    /// JSON allows between tokens are skipped, so e.g. a non-breaking space is an unexpected char.
+    fn skip_whitespace(&mut self) {
+        while let Some(c @ (' ' | '\t' | '\n' | '\r')) = self.peek() {
+            self.offset += c.len_utf8();
+        }
+    }
+
+    /// Consume a keyword (such as `true`), assuming that the first char has already been checked
+    fn parse_keyword(
+        &mut self,
+        keyword: &str,
+        node: JSON<'arena>,
+    ) -> Result<&'arena JSON<'arena>, ParseError> {
+        for expected_char in keyword.chars() {
+            match self.peek() {
+                Some(c) if c == expected_char => self.offset += c.len_utf8(),
+                Some(c) => return Err(self.error(ParseErrorKind::UnexpectedChar(c))),
+                None => return Err(self.error(ParseErrorKind::UnexpectedEnd)),
+            }
+        }
+        Ok(self.arena.alloc(node))
+    }
+
+    /// Parse any JSON value, skipping any whitespace before it
+    fn parse_value(&mut self) -> Result<&'arena JSON<'arena>, ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('t') => self.parse_keyword("true", JSON::True),
+            Some('f') => self.parse_keyword("false", JSON::False),
+            Some('n') => self.parse_keyword("null", JSON::Null),
+            Some('"') => {
+                let string = self.parse_string()?;
+                Ok(self.arena.alloc(JSON::Str(string)))
+            }
+            Some('[') => self.parse_array(),
+            Some('{') => self.parse_object(),
+            Some(c) => Err(self.error(ParseErrorKind::UnexpectedChar(c))),
+            None => Err(self.error(ParseErrorKind::UnexpectedEnd)),
+        }
+    }
+
+    /// Parse the contents of a string literal (including the quotes), returning the unescaped
+    /// contents
+    fn parse_string(&mut self) -> Result<String, ParseError> {
+        let start_offset = self.offset;
+        // Consume the opening '"'
+        debug_assert_eq!(self.peek(), Some('"'));
+        self.offset += 1;
+
+        let mut string = String::new();
+        loop {
+            match self.next_char() {
+                Some('"') => return Ok(string),
+                Some('\\') => {
+                    let escaped_char = match self.next_char() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.parse_unicode_escape()?,
+                        Some(_) => return Err(self.error(ParseErrorKind::InvalidEscape)),
+                        None => break,
+                    };
+                    string.push(escaped_char);
+                }
+                Some(c) => string.push(c),
+                None => break,
+            }
+        }
+        // If we ran out of chars before finding the closing '"', then the string is unterminated
+        Err(self.error_at(start_offset, ParseErrorKind::UnterminatedString))
+    }
+
+    /// Parse the 4 hex digits after a `\u` escape.  A high surrogate must be followed by a
+    /// second `\u` escape of a low surrogate, and the pair is decoded into a single char (e.g.
+    /// `\ud83d\ude00` is `😀`).
+    fn parse_unicode_escape(&mut self) -> Result<char, ParseError> {
+        let start_offset = self.offset;
+        let invalid = |p: &Self| p.error_at(start_offset, ParseErrorKind::InvalidEscape);
+        let code_unit = self.parse_hex_digits().ok_or_else(|| invalid(self))?;
+        let code_point = match code_unit {
+            0xd800..=0xdbff => {
+                if !self.text[self.offset..].starts_with("\\u") {
+                    return Err(invalid(self));
+                }
+                self.offset += 2;
+                match self.parse_hex_digits() {
+                    Some(low @ 0xdc00..=0xdfff) => {
+                        0x10000 + ((code_unit - 0xd800) << 10) + (low - 0xdc00)
+                    }
+                    _ => return Err(invalid(self)),
+                }
+            }
+            _ => code_unit,
+        };
+        // This fails for a low surrogate which isn't part of a pair
+        std::char::from_u32(code_point).ok_or_else(|| invalid(self))
+    }
+
+    /// Consume exactly 4 ASCII hex digits, returning the number that they spell out
+    fn parse_hex_digits(&mut self) -> Option<u32> {
+        let hex_digits = self.text.get(self.offset..self.offset + 4)?;
+        if !hex_digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        self.offset += 4;
+        u32::from_str_radix(hex_digits, 16).ok()
+    }
+
+    /// Parse an array, starting at its opening `[`
+    fn parse_array(&mut self) -> Result<&'arena JSON<'arena>, ParseError> {
+        // Building an error finds its line and column, so it is only done if the array turns out
+        // to be unterminated
+        let start_offset = self.offset;
+        let unterminated = |p: &Self| p.error_at(start_offset, ParseErrorKind::UnterminatedArray);
+        // Consume the opening '['
+        self.offset += 1;
+
+        let mut children = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.offset += 1;
+            return Ok(self.arena.alloc(JSON::Array(children)));
+        }
+        loop {
+            children.push(self.parse_value()?);
+            // After every value, there should be either a ',' or the closing ']'.  We also allow
+            // a trailing comma before the ']', so that `trailing_commas` output can be read back.
+            self.skip_whitespace();
+            match self.next_char() {
+                Some(',') => {
+                    self.skip_whitespace();
+                    if self.peek() == Some(']') {
+                        self.offset += 1;
+                        return Ok(self.arena.alloc(JSON::Array(children)));
+                    }
+                }
+                Some(']') => return Ok(self.arena.alloc(JSON::Array(children))),
+                Some(c) => {
+                    self.offset -= c.len_utf8();
+                    return Err(self.error(ParseErrorKind::UnexpectedChar(c)));
+                }
+                None => return Err(unterminated(self)),
+            }
+        }
+    }
+
+    /// Parse an object, starting at its opening `{`
+    fn parse_object(&mut self) -> Result<&'arena JSON<'arena>, ParseError> {
+        // Like in `parse_array`, the error is only built if it is needed
+        let start_offset = self.offset;
+        let unterminated = |p: &Self| p.error_at(start_offset, ParseErrorKind::UnterminatedObject);
+        // Consume the opening '{'
+        self.offset += 1;
+
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.offset += 1;
+            return Ok(self.arena.alloc(JSON::Object(fields)));
+        }
+        loop {
+            // Parse the key, which has to be a string
+            self.skip_whitespace();
+            let key = match self.peek() {
+                Some('"') => self.parse_string()?,
+                Some(c) => return Err(self.error(ParseErrorKind::UnexpectedChar(c))),
+                None => return Err(unterminated(self)),
+            };
+            // Parse the ':' between the key and value
+            self.skip_whitespace();
+            match self.peek() {
+                Some(':') => self.offset += 1,
+                Some(c) => return Err(self.error(ParseErrorKind::UnexpectedChar(c))),
+                None => return Err(unterminated(self)),
+            }
+            // Parse the value, and combine it with the key to make a field
+            let value = self.parse_value()?;
+            let key = self.arena.alloc(JSON::Str(key));
+            fields.push(self.arena.alloc(JSON::Field([key, value])));
+            // After every field, there should be either a ',' or the closing '}' (which, like in
+            // arrays, can come after a trailing comma)
+            self.skip_whitespace();
+            match self.next_char() {
+                Some(',') => {
+                    self.skip_whitespace();
+                    if self.peek() == Some('}') {
+                        self.offset += 1;
+                        return Ok(self.arena.alloc(JSON::Object(fields)));
+                    }
+                }
+                Some('}') => return Ok(self.arena.alloc(JSON::Object(fields))),
+                Some(c) => {
+                    self.offset -= c.len_utf8();
+                    return Err(self.error(ParseErrorKind::UnexpectedChar(c)));
+                }
+                None => return Err(unterminated(self)),
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::super::display_token::Indent;
     use super::super::size::Size;
     use super::super::test_json::TestJSON;
-    use super::JSONFormat;
+    use super::{
+        Change, CompactFormat, JSONFormat, ParseError, ParseErrorKind, PrettyFormat, PrettyOptions,
+        ValidationError, ValidationErrorKind, YAMLFormat, JSON,
+    };
     use crate::arena::Arena;
+    use crate::ast::cursor_path::CursorPath;
+    use crate::ast::formatter::Formatter;
     use crate::ast::Ast;
+    use std::collections::HashSet;
 
     #[test]
     fn to_text() {
@@ -457,17 +1978,17 @@ mod tests {
             let arena = Arena::new();
             let root = tree.add_to_arena(&arena);
             // Test compact string
-            let compact_string = root.to_text(&JSONFormat::Compact);
+            let compact_string = root.to_text(&JSONFormat::COMPACT);
             assert_eq!(compact_string, *expected_compact_string);
             assert_eq!(
-                root.size(&JSONFormat::Compact),
+                root.size(&JSONFormat::COMPACT),
                 Size::from(*expected_compact_string)
             );
             // Test pretty string
-            let pretty_string = root.to_text(&JSONFormat::Pretty);
+            let pretty_string = root.to_text(&JSONFormat::PRETTY);
             assert_eq!(pretty_string, *expected_pretty_string);
             assert_eq!(
-                root.size(&JSONFormat::Pretty),
+                root.size(&JSONFormat::PRETTY),
                 Size::from(*expected_pretty_string)
             );
             // Test debug tree view
@@ -476,4 +1997,1244 @@ mod tests {
             assert_eq!(s, *tree_string);
         }
     }
+
+    #[test]
+    fn validate() {
+        let arena = Arena::new();
+        // {"a": true, "b": [{"x": null, "y": null}], "a": false}
+        let duplicated = TestJSON::Object(vec![
+            ("a".to_string(), TestJSON::True),
+            (
+                "b".to_string(),
+                TestJSON::Array(vec![TestJSON::Object(vec![
+                    ("x".to_string(), TestJSON::Null),
+                    ("y".to_string(), TestJSON::Null),
+                ])]),
+            ),
+            ("a".to_string(), TestJSON::False),
+        ])
+        .add_to_arena(&arena);
+        assert_eq!(
+            duplicated.validate(),
+            Err(vec![ValidationError {
+                path: CursorPath::from_vec(vec![2]),
+                kind: ValidationErrorKind::DuplicateKey("a".to_string()),
+            }])
+        );
+        assert_eq!(
+            duplicated.validate().unwrap_err()[0].to_string(),
+            r#"Duplicate key "a" at [2]."#
+        );
+        // A nested tree with the same keys in different objects is fine
+        let clean = TestJSON::Array(vec![
+            TestJSON::Object(vec![
+                (
+                    "x".to_string(),
+                    TestJSON::Object(vec![("x".to_string(), TestJSON::True)]),
+                ),
+                ("y".to_string(), TestJSON::Array(vec![TestJSON::Null])),
+            ]),
+            TestJSON::Object(vec![("x".to_string(), TestJSON::False)]),
+        ])
+        .add_to_arena(&arena);
+        assert_eq!(clean.validate(), Ok(()));
+        // Every error is reported, not just the first
+        let field = arena.alloc(JSON::Field([
+            arena.alloc(JSON::Null),
+            arena.alloc(JSON::True),
+        ]));
+        let bad = arena.alloc(JSON::Array(vec![
+            field,
+            arena.alloc(JSON::Object(vec![field])),
+        ]));
+        assert_eq!(
+            bad.validate()
+                .unwrap_err()
+                .into_iter()
+                .map(|e| e.kind)
+                .collect::<Vec<_>>(),
+            vec![
+                ValidationErrorKind::FieldOutsideObject,
+                ValidationErrorKind::NonStringKey("null".to_string()),
+                ValidationErrorKind::NonStringKey("null".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn diff() {
+        let arena = Arena::new();
+        let old = TestJSON::Object(vec![
+            ("name".to_string(), TestJSON::Str("sapling".to_string())),
+            (
+                "tags".to_string(),
+                TestJSON::Array(vec![TestJSON::True, TestJSON::Null]),
+            ),
+            ("a/b".to_string(), TestJSON::True),
+            ("removed".to_string(), TestJSON::Object(vec![])),
+        ])
+        .add_to_arena(&arena);
+        let new = TestJSON::Object(vec![
+            ("added".to_string(), TestJSON::False),
+            (
+                "tags".to_string(),
+                TestJSON::Array(vec![
+                    TestJSON::True,
+                    TestJSON::False,
+                    TestJSON::Array(vec![]),
+                ]),
+            ),
+            ("name".to_string(), TestJSON::Str("tree".to_string())),
+            ("a/b".to_string(), TestJSON::True),
+        ])
+        .add_to_arena(&arena);
+        assert_eq!(
+            old.diff(new),
+            vec![
+                // A changed scalar
+                Change::Replaced {
+                    path: "/name".to_string(),
+                    from_name: r#""sapling""#.to_string(),
+                    to_name: r#""tree""#.to_string(),
+                },
+                Change::Replaced {
+                    path: "/tags/1".to_string(),
+                    from_name: "null".to_string(),
+                    to_name: "false".to_string(),
+                },
+                // An added array element
+                Change::Added {
+                    path: "/tags/2".to_string(),
+                    name: "array".to_string(),
+                },
+                // A removed object key
+                Change::Removed {
+                    path: "/removed".to_string(),
+                    name: "object".to_string(),
+                },
+                Change::Added {
+                    path: "/added".to_string(),
+                    name: "false".to_string(),
+                },
+            ]
+        );
+        // The diff in the other direction swaps additions and removals
+        assert_eq!(
+            new.diff(old)[..3],
+            [
+                Change::Removed {
+                    path: "/added".to_string(),
+                    name: "false".to_string(),
+                },
+                Change::Replaced {
+                    path: "/tags/1".to_string(),
+                    from_name: "false".to_string(),
+                    to_name: "null".to_string(),
+                },
+                Change::Removed {
+                    path: "/tags/2".to_string(),
+                    name: "array".to_string(),
+                },
+            ]
+        );
+        // Keys are escaped, and identical trees have no differences
+        let key = "a/b~".to_string();
+        let before = TestJSON::Object(vec![(key.clone(), TestJSON::False)]).add_to_arena(&arena);
+        let after = TestJSON::Object(vec![(key, TestJSON::True)]).add_to_arena(&arena);
+        assert_eq!(
+            before.diff(after),
+            vec![Change::Replaced {
+                path: "/a~1b~0".to_string(),
+                from_name: "false".to_string(),
+                to_name: "true".to_string(),
+            }]
+        );
+        assert!(old.diff(old).is_empty());
+        // Different kinds of node replace each other instead of being compared
+        let array = TestJSON::Array(vec![]).add_to_arena(&arena);
+        assert_eq!(
+            old.diff(array),
+            vec![Change::Replaced {
+                path: "".to_string(),
+                from_name: "object".to_string(),
+                to_name: "array".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn json_pointer() {
+        let arena = Arena::new();
+        let text = r#"[true, {"value": [null, false], "a/b": true, "m~n": null, "": false}, "x"]"#;
+        let root = JSON::from_text(text, &arena).unwrap();
+        let pointer = |p: &str| root.get_pointer(p).map(|n| n.to_text(&JSONFormat::COMPACT));
+        for (p, expected) in &[
+            // The empty pointer is the whole document
+            ("", Some(text)),
+            // Array indices and object keys
+            ("/0", Some("true")),
+            ("/1/value", Some("[null, false]")),
+            ("/1/value/1", Some("false")),
+            ("/2", Some(r#""x""#)),
+            // Escaped keys, and the empty key
+            ("/1/a~1b", Some("true")),
+            ("/1/m~0n", Some("null")),
+            ("/1/", Some("false")),
+            // Not found
+            ("/3", None),
+            ("/-", None),
+            ("/01", None),
+            ("/1/missing", None),
+            ("/1/a/b", None),
+            ("/0/0", None),
+            ("/1/value/1/0", None),
+            // Invalid pointers
+            ("0", None),
+            ("/1/m~2n", None),
+            ("/1/m~", None),
+        ] {
+            assert_eq!(pointer(p).as_deref(), *expected, "Pointer {:?}", p);
+        }
+        // The path to an object member goes through its field
+        assert_eq!(
+            root.pointer_path("/1/value/0"),
+            Some(CursorPath::from_vec(vec![1, 0, 1, 0]))
+        );
+        assert_eq!(root.pointer_path(""), Some(CursorPath::root()));
+    }
+
+    #[test]
+    fn escaped_keys() {
+        let arena = Arena::new();
+        let root = TestJSON::Object(vec![
+            (r#"he said "hi""#.to_string(), TestJSON::True),
+            (
+                r"back\slash".to_string(),
+                TestJSON::Str("tab\tbell\u{7}".to_string()),
+            ),
+            ("new\nline".to_string(), TestJSON::Null),
+        ])
+        .add_to_arena(&arena);
+        let expected_text =
+            r#"{"he said \"hi\"": true, "back\\slash": "tab\tbell\u0007", "new\nline": null}"#;
+        for format in &[JSONFormat::COMPACT, JSONFormat::PRETTY] {
+            let text = root.to_text(format);
+            assert_eq!(root.size(format), Size::from(text.as_str()));
+            // Keys and values are escaped in the same way, so they all round-trip
+            assert_eq!(JSON::from_text(&text, &arena).unwrap(), root);
+        }
+        assert_eq!(root.to_text(&JSONFormat::COMPACT), expected_text);
+        // Escaped strings never span multiple lines in the tree view
+        let key = root.children()[2].children()[0];
+        assert_eq!(key.display_name(), r#""new\nline""#);
+    }
+
+    #[test]
+    fn yaml() {
+        let arena = Arena::new();
+        let text = r#"{
+            "name": "sapling",
+            "tags": ["editor", "true", "", "a: b", "line\nbreak"],
+            "nested": [[true, null], {"x": {}, "y": []}, []],
+            "deps": {"tuikit": {"optional": false}},
+            "12": "- dash"
+        }"#;
+        let root = JSON::from_text(text, &arena).unwrap();
+        let expected = r#"name: sapling
+tags:
+  - editor
+  - "true"
+  - ""
+  - "a: b"
+  - "line\nbreak"
+nested:
+  - - true
+    - null
+  - x: {}
+    "y": []
+  - []
+deps:
+  tuikit:
+    optional: false
+"12": "- dash""#;
+        // `y` means 'yes' in YAML 1.1, so it has to be quoted
+        assert_eq!(root.to_yaml(), expected);
+        // Leaves and empty containers are written on their own
+        for (text, expected_yaml) in &[
+            ("null", "null"),
+            (r#""plain text""#, "plain text"),
+            ("[]", "[]"),
+            ("{}", "{}"),
+        ] {
+            let root = JSON::from_text(text, &arena).unwrap();
+            assert_eq!(root.to_yaml(), *expected_yaml);
+        }
+    }
+
+    #[test]
+    fn formatters() {
+        let arena = Arena::new();
+        let root = JSON::from_text(r#"{"a": [true, null], "b": {}}"#, &arena).unwrap();
+        // Format styles write the same text through either interface
+        for format in &[
+            JSONFormat::COMPACT,
+            JSONFormat::MINIFIED,
+            JSONFormat::PRETTY,
+        ] {
+            assert_eq!(root.to_text(format), format.format(root));
+            let mut written = String::new();
+            root.write_text(&mut written, format).unwrap();
+            assert_eq!(root.to_text(format), written);
+        }
+        // Each JSON formatter writes the same text on its own as it does in a `JSONFormat`
+        assert_eq!(
+            root.to_text(&CompactFormat::DEFAULT),
+            root.to_text(&JSONFormat::COMPACT)
+        );
+        assert_eq!(
+            root.to_text(&CompactFormat::MINIFIED),
+            root.to_text(&JSONFormat::MINIFIED)
+        );
+        assert_eq!(
+            root.to_text(&PrettyFormat::DEFAULT),
+            root.to_text(&JSONFormat::PRETTY)
+        );
+        assert_eq!(
+            root.to_text(&JSONFormat::MINIFIED),
+            r#"{"a":[true,null],"b":{}}"#
+        );
+        // Other formatters can be passed to `to_text` without changing `JSONFormat`
+        assert_eq!(root.to_text(&YAMLFormat), "a:\n  - true\n  - null\nb: {}");
+        assert_eq!(root.to_text(&YAMLFormat), root.to_yaml());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_round_trip() {
+        use super::ConversionError;
+        use std::convert::TryFrom;
+
+        let arena = Arena::new();
+        let text = r#"{"z": [true, false, null], "a": {"nested": ["string", []]}, "m": {}}"#;
+        let root = JSON::from_text(text, &arena).unwrap();
+        // Trees and values convert both ways, keeping the order of the fields
+        let value = serde_json::Value::try_from(root).unwrap();
+        assert_eq!(
+            value,
+            serde_json::from_str::<serde_json::Value>(text).unwrap()
+        );
+        assert_eq!(
+            value.to_string(),
+            text.replace(", ", ",").replace(": ", ":")
+        );
+        assert_eq!(JSON::from_serde(&value, &arena).unwrap(), root);
+        // Numbers aren't supported
+        let number: serde_json::Value = serde_json::from_str("[true, 1.5]").unwrap();
+        assert!(matches!(
+            JSON::from_serde(&number, &arena),
+            Err(ConversionError::UnsupportedNumber(_))
+        ));
+        // Invalid trees (like objects with duplicate keys) have no equivalent value
+        let duplicated = JSON::from_text(r#"{"a": true, "a": false}"#, &arena).unwrap();
+        let error = serde_json::Value::try_from(duplicated).unwrap_err();
+        assert_eq!(error.to_string(), r#"Duplicate key "a" at [1]."#);
+    }
+
+    #[test]
+    fn tree_view_folded() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::Object(vec![
+                ("foo".to_string(), TestJSON::Null),
+                (
+                    "bar".to_string(),
+                    TestJSON::Array(vec![TestJSON::False, TestJSON::True]),
+                ),
+            ]),
+            TestJSON::Array(vec![TestJSON::Null]),
+        ])
+        .add_to_arena(&arena);
+        let object = root.children()[1];
+        let inner_array = root.children()[2];
+        let fold = |nodes: &[&JSON]| {
+            let collapsed: HashSet<*const JSON> = nodes.iter().map(|n| *n as *const JSON).collect();
+            root.tree_view_folded(&collapsed)
+        };
+        // Folding nothing is the same as the normal tree view
+        assert_eq!(fold(&[]), root.tree_view());
+        // Collapsing the object hides its fields, but its sibling is still indented correctly
+        assert_eq!(
+            fold(&[object]),
+            "array
+  true
+  object (+2)
+  array
+    null"
+        );
+        // Collapsing a node inside a collapsed node has no effect
+        assert_eq!(fold(&[object, object.children()[0]]), fold(&[object]));
+        // Collapsing the last child
+        assert_eq!(
+            fold(&[object.children()[1], inner_array]),
+            r#"array
+  true
+  object
+    field
+      "foo"
+      null
+    field (+2)
+  array (+1)"#
+        );
+        // Collapsing the root hides everything
+        assert_eq!(fold(&[root]), "array (+3)");
+    }
+
+    #[test]
+    fn tree_view_to_depth() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::Object(vec![("foo".to_string(), TestJSON::Null)]),
+            TestJSON::Array(vec![]),
+        ])
+        .add_to_arena(&arena);
+        for (max_depth, expected_tree_view) in &[
+            (0, "array (…)"),
+            // Empty containers have nothing to hide, so they don't get an ellipsis
+            (1, "array\n  true\n  object (…)\n  array"),
+            (2, "array\n  true\n  object\n    field (…)\n  array"),
+        ] {
+            assert_eq!(root.tree_view_to_depth(*max_depth), *expected_tree_view);
+        }
+        // A large enough depth shows the whole tree
+        assert_eq!(root.tree_view_to_depth(3), root.tree_view());
+    }
+
+    #[test]
+    fn visit() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::Object(vec![(
+                "foo".to_string(),
+                TestJSON::Array(vec![TestJSON::False]),
+            )]),
+            TestJSON::Null,
+        ])
+        .add_to_arena(&arena);
+        let mut visited = Vec::new();
+        root.visit(|node, depth| visited.push((node.display_name(), depth)));
+        let expected: Vec<(String, usize)> = [
+            ("array", 0),
+            ("true", 1),
+            ("object", 1),
+            ("field", 2),
+            (r#""foo""#, 3),
+            ("array", 3),
+            ("false", 4),
+            ("null", 1),
+        ]
+        .iter()
+        .map(|(name, depth)| (name.to_string(), *depth))
+        .collect();
+        assert_eq!(visited, expected);
+        // Visiting a subtree starts again from a depth of 0
+        let mut depths = Vec::new();
+        root.children()[1].visit(|_, depth| depths.push(depth));
+        assert_eq!(depths, vec![0, 1, 2, 2, 3]);
+    }
+
+    #[test]
+    fn node_count_and_depth() {
+        for (tree, expected_node_count, expected_depth) in &[
+            (TestJSON::True, 1, 1),
+            (TestJSON::Array(vec![]), 1, 1),
+            (TestJSON::Object(vec![]), 1, 1),
+            (TestJSON::Array(vec![TestJSON::Null, TestJSON::False]), 3, 2),
+            // Each field adds two levels: the field itself, and its key or value
+            (
+                TestJSON::Array(vec![
+                    TestJSON::True,
+                    TestJSON::Object(vec![
+                        ("foo".to_string(), TestJSON::Null),
+                        (
+                            "bar".to_string(),
+                            TestJSON::Array(vec![TestJSON::False, TestJSON::True]),
+                        ),
+                    ]),
+                    TestJSON::Array(vec![TestJSON::Null]),
+                ]),
+                13,
+                5,
+            ),
+        ] {
+            let arena = Arena::new();
+            let root = tree.add_to_arena(&arena);
+            assert_eq!(root.node_count(), *expected_node_count);
+            assert_eq!(root.depth(), *expected_depth);
+        }
+    }
+
+    #[test]
+    fn to_dot() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::Object(vec![("foo".to_string(), TestJSON::Null)]),
+        ])
+        .add_to_arena(&arena);
+        assert_eq!(
+            root.to_dot(),
+            r#"digraph {
+    n0 [label="array"];
+    n1 [label="true"];
+    n0 -> n1;
+    n2 [label="object"];
+    n3 [label="field"];
+    n4 [label="\"foo\""];
+    n3 -> n4;
+    n5 [label="null"];
+    n3 -> n5;
+    n2 -> n3 [label="foo"];
+    n0 -> n2;
+}"#
+        );
+    }
+
+    #[test]
+    fn indent() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::Object(vec![
+                (
+                    "foos".to_string(),
+                    TestJSON::Array(vec![TestJSON::False, TestJSON::True, TestJSON::False]),
+                ),
+                ("bar".to_string(), TestJSON::False),
+            ]),
+            TestJSON::True,
+        ])
+        .add_to_arena(&arena);
+        let two_spaces = PrettyFormat {
+            indent: Indent::Spaces(2),
+            ..PrettyFormat::DEFAULT
+        };
+        assert_eq!(
+            root.to_text(&two_spaces),
+            r#"[
+  {
+    "foos": [
+      false,
+      true,
+      false
+    ],
+    "bar": false
+  },
+  true
+]"#
+        );
+        let tabs = PrettyFormat {
+            indent: Indent::Tab,
+            ..PrettyFormat::DEFAULT
+        };
+        assert_eq!(
+            root.to_text(&tabs),
+            "[\n\t{\n\t\t\"foos\": [\n\t\t\tfalse,\n\t\t\ttrue,\n\t\t\tfalse\n\t\t],\n\t\t\"bar\": false\n\t},\n\ttrue\n]"
+        );
+        // The indentation only applies to the pretty formatter
+        let mut compact = JSONFormat::COMPACT;
+        assert_eq!(
+            JSON::set_format_option(&mut compact, "indent", "tab"),
+            Err("'indent' only applies to the pretty format.".to_string())
+        );
+        assert_eq!(
+            root.to_text(&CompactFormat::DEFAULT),
+            r#"[{"foos": [false, true, false], "bar": false}, true]"#
+        );
+    }
+
+    /// Generate a pseudo-random [`TestJSON`] tree from a seed, using a simple linear congruential
+    /// generator so that the tests are deterministic
+    fn random_tree(seed: &mut u64, depth: usize) -> TestJSON {
+        *seed = seed
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        let r = (*seed >> 33) as usize;
+        // Only generate leaves once we get deep enough
+        let num_variants = if depth == 0 { 4 } else { 6 };
+        match r % num_variants {
+            0 => TestJSON::True,
+            1 => TestJSON::False,
+            2 => TestJSON::Null,
+            3 => TestJSON::Str(format!("s{}", r % 100)),
+            4 => TestJSON::Array(
+                (0..r / 7 % 4)
+                    .map(|_| random_tree(seed, depth - 1))
+                    .collect(),
+            ),
+            _ => TestJSON::Object(
+                (0..r / 7 % 4)
+                    .map(|i| (format!("k{}", i), random_tree(seed, depth - 1)))
+                    .collect(),
+            ),
+        }
+    }
+
+    #[test]
+    fn from_text_round_trip() {
+        let arena = Arena::new();
+        // Build some trees with tricky edge cases, followed by lots of random trees
+        let mut trees = vec![
+            TestJSON::Array(vec![]),
+            TestJSON::Object(vec![]),
+            TestJSON::Array(vec![TestJSON::Array(vec![]), TestJSON::Object(vec![])]),
+            TestJSON::Str("".to_string()),
+        ];
+        // Deeply nested arrays and objects
+        let mut deep_tree = TestJSON::True;
+        for i in 0..200 {
+            deep_tree = if i % 2 == 0 {
+                TestJSON::Array(vec![deep_tree])
+            } else {
+                TestJSON::Object(vec![("k".to_string(), deep_tree)])
+            };
+        }
+        trees.push(deep_tree);
+        let mut seed = 0;
+        for _ in 0..200 {
+            trees.push(random_tree(&mut seed, 5));
+        }
+
+        for tree in &trees {
+            let root = tree.add_to_arena(&arena);
+            let trailing_commas = JSONFormat::Pretty(PrettyFormat {
+                trailing_commas: true,
+                ..PrettyFormat::DEFAULT
+            });
+            for format in &[JSONFormat::COMPACT, JSONFormat::PRETTY, trailing_commas] {
+                let text = root.to_text(format);
+                let parsed = JSON::from_text(&text, &arena).unwrap();
+                assert_eq!(parsed, root, "Round trip failed for {}", text);
+            }
+        }
+    }
+
+    #[test]
+    fn minified() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::Object(vec![
+                ("foo".to_string(), TestJSON::True),
+                (
+                    "bar".to_string(),
+                    TestJSON::Array(vec![TestJSON::False, TestJSON::Null]),
+                ),
+            ]),
+            TestJSON::Str("a, b: c".to_string()),
+        ])
+        .add_to_arena(&arena);
+        for (format, expected_text) in &[
+            (
+                JSONFormat::COMPACT,
+                r#"[{"foo": true, "bar": [false, null]}, "a, b: c"]"#,
+            ),
+            (
+                JSONFormat::MINIFIED,
+                r#"[{"foo":true,"bar":[false,null]},"a, b: c"]"#,
+            ),
+        ] {
+            let text = root.to_text(format);
+            assert_eq!(text.as_bytes(), expected_text.as_bytes());
+            assert_eq!(root.size(format), Size::from(*expected_text));
+        }
+        // The minified layout can be chosen with `:set`, and switches to pretty with `F`
+        let mut format = JSONFormat::PRETTY;
+        JSON::set_format_option(&mut format, "format", "minified").unwrap();
+        assert_eq!(format, JSONFormat::MINIFIED);
+        assert_eq!(JSON::format_style_name(&format), "minified");
+        JSON::cycle_format_style(&mut format);
+        assert_eq!(format, JSONFormat::PRETTY);
+    }
+
+    #[test]
+    fn write_text_to_custom_sink() {
+        /// A `fmt::Write` sink which records every chunk of text it is given
+        struct ChunkRecorder(Vec<String>);
+
+        impl std::fmt::Write for ChunkRecorder {
+            fn write_str(&mut self, s: &str) -> std::fmt::Result {
+                self.0.push(s.to_string());
+                Ok(())
+            }
+        }
+
+        let arena = Arena::new();
+        let root =
+            TestJSON::Array(vec![TestJSON::True, TestJSON::Array(vec![])]).add_to_arena(&arena);
+        let mut recorder = ChunkRecorder(Vec::new());
+        root.write_text(&mut recorder, &JSONFormat::COMPACT)
+            .unwrap();
+        // The text should be written in pieces, rather than all at once
+        assert_eq!(recorder.0, vec!["[", "true", ",", " ", "[]", "]"]);
+        assert_eq!(recorder.0.concat(), root.to_text(&JSONFormat::COMPACT));
+    }
+
+    #[test]
+    fn write_io() {
+        /// An `io::Write` sink which fails once it has been given more than `limit` bytes
+        struct LimitedSink {
+            bytes: Vec<u8>,
+            limit: usize,
+        }
+
+        impl std::io::Write for LimitedSink {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                if self.bytes.len() + buf.len() > self.limit {
+                    return Err(std::io::Error::other("sink is full"));
+                }
+                self.bytes.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        // Build a deeply nested tree, which is where building the whole string would be expensive
+        let mut tree = TestJSON::Str("leaf".to_string());
+        for i in 0..50 {
+            tree = if i % 2 == 0 {
+                TestJSON::Array(vec![tree, TestJSON::True])
+            } else {
+                TestJSON::Object(vec![("key".to_string(), tree)])
+            };
+        }
+        let arena = Arena::new();
+        let root = tree.add_to_arena(&arena);
+        for format in &[JSONFormat::COMPACT, JSONFormat::PRETTY] {
+            let expected_text = root.to_text(format);
+            // Writing to a big enough sink should give the same text as `to_text`
+            let mut sink = LimitedSink {
+                bytes: Vec::new(),
+                limit: usize::MAX,
+            };
+            root.write_io(&mut sink, format).unwrap();
+            assert_eq!(String::from_utf8(sink.bytes).unwrap(), expected_text);
+            // IO errors should be passed back to the caller
+            let mut sink = LimitedSink {
+                bytes: Vec::new(),
+                limit: expected_text.len() / 2,
+            };
+            let error = root.write_io(&mut sink, format).unwrap_err();
+            assert_eq!(error.to_string(), "sink is full");
+        }
+    }
+
+    #[test]
+    fn max_width() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::Array(vec![TestJSON::True, TestJSON::False, TestJSON::Null]),
+            TestJSON::Object(vec![
+                ("key".to_string(), TestJSON::Str("value".to_string())),
+                (
+                    "tags".to_string(),
+                    TestJSON::Array(vec![
+                        TestJSON::Str("a".to_string()),
+                        TestJSON::Str("b".to_string()),
+                    ]),
+                ),
+            ]),
+        ])
+        .add_to_arena(&arena);
+        let mut format = JSONFormat::PRETTY;
+        JSON::set_format_option(&mut format, "max_width", "30").unwrap();
+        assert_eq!(format, JSONFormat::pretty_fill(30));
+        // The short array stays on one line, but the object is too wide so it is exploded (even
+        // though the array inside it isn't)
+        let text = root.to_text(&format);
+        assert_eq!(
+            text,
+            r#"[
+    [true, false, null],
+    {
+        "key": "value",
+        "tags": ["a", "b"]
+    }
+]"#
+        );
+        assert_eq!(root.size(&format), Size::from(text.as_str()));
+        assert_eq!(JSON::from_text(&text, &arena).unwrap(), root);
+        // Containers written on one line don't get trailing commas
+        JSON::set_format_option(&mut format, "trailing_commas", "true").unwrap();
+        let text = root.to_text(&format);
+        assert_eq!(
+            text,
+            r#"[
+    [true, false, null],
+    {
+        "key": "value",
+        "tags": ["a", "b"],
+    },
+]"#
+        );
+        assert_eq!(root.size(&format), Size::from(text.as_str()));
+        // If everything fits, the whole tree is written like the compact layout
+        JSON::set_format_option(&mut format, "max_width", "80").unwrap();
+        assert_eq!(root.to_text(&format), root.to_text(&JSONFormat::COMPACT));
+        // Without a maximum width, every container is exploded
+        JSON::set_format_option(&mut format, "max_width", "none").unwrap();
+        assert_eq!(format.pretty().unwrap().max_width, None);
+        assert_eq!(root.size(&format).lines(), 13);
+        assert_eq!(
+            JSON::set_format_option(&mut format, "max_width", "wide"),
+            Err("Expected a number of columns or 'none', found 'wide'.".to_string())
+        );
+    }
+
+    #[test]
+    fn single_line_width() {
+        let arena = Arena::new();
+        let root = JSON::from_text(r#"[{"key": "日本"}, [], true, null]"#, &arena).unwrap();
+        // 31 characters, but both of the Japanese characters are two columns wide
+        assert_eq!(root.to_text(&JSONFormat::COMPACT).chars().count(), 31);
+        let width = 33;
+        assert_eq!(root.single_line_width(100), Some(width));
+        assert_eq!(root.single_line_width(width), Some(width));
+        assert_eq!(root.single_line_width(width - 1), None);
+        // Measuring gives up as soon as the limit is passed, without reaching the end of a long
+        // array
+        let long = arena.alloc(JSON::Array(vec![root; 10_000]));
+        assert_eq!(long.single_line_width(80), None);
+        assert_eq!(long.size(&JSONFormat::pretty_fill(80)).lines(), 10_001);
+    }
+
+    #[test]
+    fn pretty_fill() {
+        let arena = Arena::new();
+        let format = JSONFormat::pretty_fill(40);
+        assert_eq!(format, JSONFormat::Pretty(PrettyFormat::fill(40)));
+        // A short array of scalars fits, so it is written inline
+        let short = JSON::from_text(r#"{"flags": [true, false, null]}"#, &arena).unwrap();
+        assert_eq!(short.to_text(&format), r#"{"flags": [true, false, null]}"#);
+        // A long one is 51 columns wide, so it is broken across lines as usual
+        let long = JSON::from_text(
+            r#"{"flags": [true, false, null, true, false, null, true, false]}"#,
+            &arena,
+        )
+        .unwrap();
+        let text = long.to_text(&format);
+        assert_eq!(
+            text,
+            r#"{
+    "flags": [
+        true,
+        false,
+        null,
+        true,
+        false,
+        null,
+        true,
+        false
+    ]
+}"#
+        );
+        assert_eq!(long.size(&format), Size::from(text.as_str()));
+        // ...but fits in a wider view
+        assert_eq!(
+            long.to_text(&JSONFormat::pretty_fill(80)),
+            long.to_text(&JSONFormat::COMPACT)
+        );
+        // The indentation and key in front of a container count towards its width.  This array is
+        // only 32 columns wide, but it would end in column 45.
+        let indented =
+            JSON::from_text(r#"{"flags": [true, false, null, true, false]}"#, &arena).unwrap();
+        let text = indented.to_text(&format);
+        assert_eq!(
+            text,
+            r#"{
+    "flags": [
+        true,
+        false,
+        null,
+        true,
+        false
+    ]
+}"#
+        );
+        assert_eq!(indented.size(&format), Size::from(text.as_str()));
+    }
+
+    #[test]
+    fn align_values() {
+        let arena = Arena::new();
+        let root = JSON::from_text(
+            r#"{"foo": true, "longer": false, "nested": {"a": null, "bcd": [true]}, "x": {}}"#,
+            &arena,
+        )
+        .unwrap();
+        let mut format = JSONFormat::PRETTY;
+        JSON::set_format_option(&mut format, "align_values", "true").unwrap();
+        // Each object is aligned separately
+        let text = root.to_text(&format);
+        assert_eq!(
+            text,
+            r#"{
+    "foo":    true,
+    "longer": false,
+    "nested": {
+        "a":   null,
+        "bcd": [
+            true
+        ]
+    },
+    "x":      {}
+}"#
+        );
+        assert_eq!(root.size(&format), Size::from(text.as_str()));
+        assert_eq!(JSON::from_text(&text, &arena).unwrap(), root);
+        // Objects written on one line aren't aligned
+        JSON::set_format_option(&mut format, "max_width", "40").unwrap();
+        assert_eq!(
+            root.to_text(&format),
+            r#"{
+    "foo":    true,
+    "longer": false,
+    "nested": {"a": null, "bcd": [true]},
+    "x":      {}
+}"#
+        );
+        JSON::set_format_option(&mut format, "format", "compact").unwrap();
+        assert_eq!(root.to_text(&format), root.to_text(&JSONFormat::COMPACT));
+    }
+
+    #[test]
+    fn single_child_containers() {
+        let arena = Arena::new();
+        let root = JSON::from_text(
+            r#"{"a": [true], "b": [{"c": null}], "d": [null, false], "e": {"f": [], "g": {}}}"#,
+            &arena,
+        )
+        .unwrap();
+        let mut format = JSONFormat::PRETTY;
+        JSON::set_format_option(&mut format, "trailing_commas", "true").unwrap();
+        // By default, only empty containers are written on one line
+        assert_eq!(format.pretty().unwrap().options, PrettyOptions::default());
+        assert_eq!(root.size(&format).lines(), 17);
+        // Single-element arrays hug their element, even if it is spread over several lines
+        JSON::set_format_option(&mut format, "collapse_single_element_arrays", "true").unwrap();
+        let text = root.to_text(&format);
+        assert_eq!(
+            text,
+            r#"{
+    "a": [true],
+    "b": [{
+        "c": null,
+    }],
+    "d": [
+        null,
+        false,
+    ],
+    "e": {
+        "f": [],
+        "g": {},
+    },
+}"#
+        );
+        assert_eq!(root.size(&format), Size::from(text.as_str()));
+        assert_eq!(JSON::from_text(&text, &arena).unwrap(), root);
+        // Single-field objects are written on one line, but the root has more than one field
+        JSON::set_format_option(&mut format, "collapse_single_element_arrays", "false").unwrap();
+        JSON::set_format_option(&mut format, "inline_single_field_objects", "true").unwrap();
+        let text = root.to_text(&format);
+        assert_eq!(
+            text,
+            r#"{
+    "a": [
+        true,
+    ],
+    "b": [
+        {"c": null},
+    ],
+    "d": [
+        null,
+        false,
+    ],
+    "e": {
+        "f": [],
+        "g": {},
+    },
+}"#
+        );
+        assert_eq!(root.size(&format), Size::from(text.as_str()));
+        // Both options together
+        JSON::set_format_option(&mut format, "collapse_single_element_arrays", "true").unwrap();
+        assert_eq!(
+            root.to_text(&format),
+            r#"{
+    "a": [true],
+    "b": [{"c": null}],
+    "d": [
+        null,
+        false,
+    ],
+    "e": {
+        "f": [],
+        "g": {},
+    },
+}"#
+        );
+        assert!(
+            JSON::set_format_option(&mut format, "inline_single_field_objects", "maybe").is_err()
+        );
+    }
+
+    #[test]
+    fn trailing_commas() {
+        let arena = Arena::new();
+        // [{"foo": [true], "bar": {}}, []]
+        let root = TestJSON::Array(vec![
+            TestJSON::Object(vec![
+                ("foo".to_string(), TestJSON::Array(vec![TestJSON::True])),
+                ("bar".to_string(), TestJSON::Object(vec![])),
+            ]),
+            TestJSON::Array(vec![]),
+        ])
+        .add_to_arena(&arena);
+        let mut format = JSONFormat::PRETTY;
+        JSON::set_format_option(&mut format, "trailing_commas", "true").unwrap();
+        // Only non-empty containers get a trailing comma
+        let text = root.to_text(&format);
+        assert_eq!(
+            text,
+            r#"[
+    {
+        "foo": [
+            true,
+        ],
+        "bar": {},
+    },
+    [],
+]"#
+        );
+        assert_eq!(root.size(&format), Size::from(text.as_str()));
+        // Trailing commas can be parsed, but only directly before the closing bracket
+        assert_eq!(JSON::from_text(&text, &arena).unwrap(), root);
+        for text in &["[,]", "[true,,]", r#"{"a": true,,}"#] {
+            assert!(JSON::from_text(text, &arena).is_err(), "Parsing {:?}", text);
+        }
+        assert_eq!(
+            JSON::set_format_option(&mut format, "trailing_commas", "yes"),
+            Err("Expected 'true' or 'false', found 'yes'.".to_string())
+        );
+        // The compact formatter never writes trailing commas
+        JSON::set_format_option(&mut format, "format", "compact").unwrap();
+        assert_eq!(root.to_text(&format), r#"[{"foo": [true], "bar": {}}, []]"#);
+        assert_eq!(
+            JSON::set_format_option(&mut format, "trailing_commas", "true"),
+            Err("'trailing_commas' only applies to the pretty format.".to_string())
+        );
+    }
+
+    #[test]
+    fn sort_keys() {
+        let arena = Arena::new();
+        let root = TestJSON::Object(vec![
+            ("foo".to_string(), TestJSON::True),
+            (
+                "bar".to_string(),
+                TestJSON::Object(vec![
+                    ("z".to_string(), TestJSON::Null),
+                    ("y".to_string(), TestJSON::True),
+                    ("z".to_string(), TestJSON::False),
+                ]),
+            ),
+            ("baz".to_string(), TestJSON::Array(vec![])),
+        ])
+        .add_to_arena(&arena);
+        let compact = CompactFormat {
+            sort_keys: true,
+            ..CompactFormat::DEFAULT
+        };
+        let pretty = PrettyFormat {
+            sort_keys: true,
+            ..PrettyFormat::DEFAULT
+        };
+        // Keys should be sorted at every level, and duplicate keys should stay in order
+        assert_eq!(
+            root.to_text(&compact),
+            r#"{"bar": {"y": true, "z": null, "z": false}, "baz": [], "foo": true}"#
+        );
+        assert_eq!(
+            root.to_text(&pretty),
+            r#"{
+    "bar": {
+        "y": true,
+        "z": null,
+        "z": false
+    },
+    "baz": [],
+    "foo": true
+}"#
+        );
+        // Sorting is only done when writing, so the tree itself should be unchanged
+        assert_eq!(
+            root.to_text(&JSONFormat::COMPACT),
+            r#"{"foo": true, "bar": {"z": null, "y": true, "z": false}, "baz": []}"#
+        );
+    }
+
+    #[test]
+    fn explode_and_merge() {
+        let arena = Arena::new();
+        let root = TestJSON::Object(vec![
+            ("a".to_string(), TestJSON::True),
+            ("b".to_string(), TestJSON::Array(vec![TestJSON::Null])),
+        ])
+        .add_to_arena(&arena);
+        // Explode the object into an array of single-field objects
+        let exploded = root.explode(&arena).unwrap();
+        assert_eq!(
+            exploded.to_text(&JSONFormat::COMPACT),
+            r#"[{"a": true}, {"b": [null]}]"#
+        );
+        assert_eq!(exploded.explode(&arena), None);
+        // Merging should get us back to where we started
+        let merged = exploded.merge(&arena).unwrap();
+        assert_eq!(merged, *root);
+        assert_eq!(merged.merge(&arena), None);
+        // Arrays containing anything other than objects can't be merged
+        let mixed =
+            TestJSON::Array(vec![TestJSON::Object(vec![]), TestJSON::True]).add_to_arena(&arena);
+        assert_eq!(mixed.merge(&arena), None);
+    }
+
+    #[test]
+    fn display() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::Object(vec![
+                ("foo".to_string(), TestJSON::Array(vec![TestJSON::False])),
+                ("bar".to_string(), TestJSON::Null),
+            ]),
+            TestJSON::True,
+        ])
+        .add_to_arena(&arena);
+        assert_eq!(root.to_string(), root.to_text(&JSONFormat::COMPACT));
+        assert_eq!(
+            format!("{}", root),
+            r#"[{"foo": [false], "bar": null}, true]"#
+        );
+    }
+
+    #[test]
+    fn from_text_errors() {
+        for (text, offset, kind) in &[
+            ("", 0, ParseErrorKind::UnexpectedEnd),
+            ("  tru", 5, ParseErrorKind::UnexpectedEnd),
+            ("trux", 3, ParseErrorKind::UnexpectedChar('x')),
+            ("[true, 0]", 7, ParseErrorKind::UnexpectedChar('0')),
+            ("[true false]", 6, ParseErrorKind::UnexpectedChar('f')),
+            ("[true, [false]", 0, ParseErrorKind::UnterminatedArray),
+            (r#"{"a": true"#, 0, ParseErrorKind::UnterminatedObject),
+            (r#"{true: false}"#, 1, ParseErrorKind::UnexpectedChar('t')),
+            (r#"{"a" true}"#, 5, ParseErrorKind::UnexpectedChar('t')),
+            (r#"["abc]"#, 1, ParseErrorKind::UnterminatedString),
+            (r#""\q""#, 3, ParseErrorKind::InvalidEscape),
+            (r#""\u+123""#, 3, ParseErrorKind::InvalidEscape),
+            (r#""\u12""#, 3, ParseErrorKind::InvalidEscape),
+            (r#""\ud83d""#, 3, ParseErrorKind::InvalidEscape),
+            (r#""\ud83d\u0041""#, 3, ParseErrorKind::InvalidEscape),
+            (r#""\ude00""#, 3, ParseErrorKind::InvalidEscape),
+            ("[] []", 3, ParseErrorKind::TrailingChars),
+            ("[\u{a0}]", 1, ParseErrorKind::UnexpectedChar('\u{a0}')),
+            (
+                "\u{2028}true",
+                0,
+                ParseErrorKind::UnexpectedChar('\u{2028}'),
+            ),
+        ] {
+            let arena = Arena::new();
+            // All of these are on one line, so the column is one more than the offset
+            assert_eq!(
+                JSON::from_text(text, &arena),
+                Err(ParseError {
+                    offset: *offset,
+                    line: 1,
+                    column: *offset + 1,
+                    kind: kind.clone()
+                }),
+                "Parsing {:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn unicode_escapes() {
+        let arena = Arena::new();
+        for (text, expected) in &[
+            (r#""\u0041\u00e9""#, "Aé"),
+            (r#""\u65E5\u672c""#, "日本"),
+            // Surrogate pairs are decoded into a single char
+            (r#""\ud83d\ude00""#, "😀"),
+            (r#""\uD83D\uDE00!""#, "😀!"),
+        ] {
+            assert_eq!(
+                JSON::from_text(text, &arena).unwrap(),
+                &JSON::Str(expected.to_string()),
+                "Parsing {:?}",
+                text
+            );
+        }
+        // Only spaces, tabs, newlines and carriage returns count as whitespace
+        assert_eq!(
+            JSON::from_text(" \t\r\n[ true\r\n]\n", &arena).unwrap(),
+            &JSON::Array(vec![&JSON::True])
+        );
+    }
+
+    #[test]
+    fn parse_error_position() {
+        let arena = Arena::new();
+        let text = "[\n    \"héllo\",\n    \"wörld\" true\n]";
+        let error = JSON::from_text(text, &arena).unwrap_err();
+        // Columns are counted in chars, not bytes
+        assert_eq!(
+            (error.offset, error.line, error.column),
+            (text.find("true").unwrap(), 3, 13)
+        );
+        assert_eq!(
+            error.to_string(),
+            "Unexpected char 't' at line 3, column 13."
+        );
+        // Unterminated containers are reported where they start
+        let error = JSON::from_text("[\n  [true,\n  false", &arena).unwrap_err();
+        assert_eq!(
+            (error.kind, error.line, error.column),
+            (ParseErrorKind::UnterminatedArray, 2, 3)
+        );
+        let error = JSON::from_text("true\n\n[\n", &arena).unwrap_err();
+        assert_eq!(
+            (error.kind, error.line, error.column),
+            (ParseErrorKind::TrailingChars, 3, 1)
+        );
+        let error = JSON::from_text(r#"{"a": [{}, {"b": null"#, &arena).unwrap_err();
+        assert_eq!(
+            (error.kind, error.column),
+            (ParseErrorKind::UnterminatedObject, 12)
+        );
+    }
+
+    #[test]
+    fn parse_many_containers() {
+        // Positions are only worked out for errors which are returned, so parsing a document with
+        // lots of containers doesn't scan back through the text for every one of them
+        let arena = Arena::new();
+        let text = format!("[{}{{}}]", "[], {}, ".repeat(100_000));
+        let root = JSON::from_text(&text, &arena).unwrap();
+        assert_eq!(root.children().len(), 200_001);
+    }
 }
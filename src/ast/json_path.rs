@@ -0,0 +1,283 @@
+//! Addressing the nodes of JSON trees with [JSONPath](https://goessner.net/articles/JsonPath/)
+//! expressions, like `$.foo[2]`.  Only the simplest JSONPath expressions are supported, which
+//! are a `$` (for the root) followed by steps which each move to one child.
+
+use super::cursor_path::CursorPath;
+use super::json::JSON;
+use super::Ast;
+
+/// Returns the JSONPath expression for the node at `path` under `root`, like `$.foo[2]`.  Fields
+/// are accessed by their key, and other children are accessed by their index.  The key and value
+/// of a field don't add anything to the path, so the path of a field is the same as the path of
+/// its value.
+pub fn format<'arena>(root: &'arena JSON<'arena>, path: &CursorPath) -> String {
+    let mut json_path = "$".to_string();
+    let nodes: Vec<&'arena JSON<'arena>> = path.node_iter(root).collect();
+    for (parent_and_child, index) in nodes.windows(2).zip(path.iter()) {
+        let (parent, child) = (parent_and_child[0], parent_and_child[1]);
+        if let Some(key) = child.key() {
+            json_path.push_str(&json_path_key(key));
+        } else if parent.key().is_none() {
+            json_path.push_str(&format!("[{}]", index));
+        }
+    }
+    json_path
+}
+
+/// One step of a JSONPath expression (see [`parse`])
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum JSONPathStep {
+    /// `.key` or `["key"]`, which moves to the child with a given key
+    Key(String),
+    /// `[index]`, which moves to the child with a given index
+    Index(usize),
+}
+
+/// Parses the simple JSONPath expressions generated by [`format`]: a `$` followed by any
+/// number of `.key`, `["key"]` and `[index]` steps.  Returns an error message if `text` isn't
+/// such an expression.
+fn parse(text: &str) -> Result<Vec<JSONPathStep>, String> {
+    let mut rest = text
+        .trim()
+        .strip_prefix('$')
+        .ok_or_else(|| "JSONPaths must start with '$'.".to_string())?;
+    let mut steps = Vec::new();
+    while !rest.is_empty() {
+        if let Some(after_dot) = rest.strip_prefix('.') {
+            // A bare key continues until the start of the next step
+            let end = after_dot.find(&['.', '['][..]).unwrap_or(after_dot.len());
+            if end == 0 {
+                return Err("Expected a key after '.'.".to_string());
+            }
+            steps.push(JSONPathStep::Key(after_dot[..end].to_string()));
+            rest = &after_dot[end..];
+        } else if let Some(after_bracket) = rest.strip_prefix("[\"") {
+            // Read a quoted key, unescaping any quotes and backslashes
+            let mut key = String::new();
+            let mut chars = after_bracket.char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((i, '"')) => break i,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, c)) => key.push(c),
+                        None => return Err("Unterminated key in '[\"'.".to_string()),
+                    },
+                    Some((_, c)) => key.push(c),
+                    None => return Err("Unterminated key in '[\"'.".to_string()),
+                }
+            };
+            rest = after_bracket[end + 1..]
+                .strip_prefix(']')
+                .ok_or_else(|| "Expected ']' after a quoted key.".to_string())?;
+            steps.push(JSONPathStep::Key(key));
+        } else if let Some(after_bracket) = rest.strip_prefix('[') {
+            let end = after_bracket
+                .find(']')
+                .ok_or_else(|| "Expected ']' after an index.".to_string())?;
+            let index = after_bracket[..end]
+                .parse()
+                .map_err(|_| format!("Expected an index, found '{}'.", &after_bracket[..end]))?;
+            steps.push(JSONPathStep::Index(index));
+            rest = &after_bracket[end + 1..];
+        } else {
+            return Err(format!("Expected '.' or '[', found '{}'.", rest));
+        }
+    }
+    Ok(steps)
+}
+
+/// Returns the JSONPath accessor for a given key: `.key` if the key is a valid identifier, or
+/// `["key"]` (with quotes and backslashes escaped) if it isn't.
+fn json_path_key(key: &str) -> String {
+    let mut chars = key.chars();
+    let is_identifier = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if is_identifier {
+        format!(".{}", key)
+    } else {
+        format!("[\"{}\"]", key.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+/// Returns the path to the node under `root` addressed by a JSONPath expression like
+/// `$.foo[2].bar` (the same kind of expression as [`format`] generates).  Returns an error
+/// message if the expression can't be parsed, or if one of its steps doesn't exist.
+pub fn resolve<'arena>(root: &'arena JSON<'arena>, text: &str) -> Result<CursorPath, String> {
+    let mut path = CursorPath::root();
+    let mut node = root;
+    for step in parse(text)? {
+        let children = node.children();
+        let location = format(root, &path);
+        match step {
+            JSONPathStep::Key(key) => {
+                if !matches!(node, JSON::Object(_)) {
+                    return Err(format!(
+                        "Cannot look up key {:?} in {} at {}.",
+                        key,
+                        node.display_name(),
+                        location
+                    ));
+                }
+                let index = children
+                    .iter()
+                    .position(|c| c.key() == Some(&key))
+                    .ok_or_else(|| format!("There is no key {:?} in {}.", key, location))?;
+                // Keys lead to the value of their field, which is the field's second child
+                match children[index] {
+                    JSON::Field([_, value]) => {
+                        path.push(index);
+                        path.push(1);
+                        node = value;
+                    }
+                    field => {
+                        return Err(format!(
+                            "Expected a field in {}, found {}.",
+                            location,
+                            field.display_name()
+                        ))
+                    }
+                }
+            }
+            JSONPathStep::Index(index) => {
+                if !matches!(node, JSON::Array(_)) {
+                    return Err(format!(
+                        "Cannot index into {} at {}.",
+                        node.display_name(),
+                        location
+                    ));
+                }
+                node = children.get(index).ok_or_else(|| {
+                    format!(
+                        "Index {} is out of range, because {} only has {} children.",
+                        index,
+                        location,
+                        children.len()
+                    )
+                })?;
+                path.push(index);
+            }
+        }
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format, resolve};
+    use crate::arena::Arena;
+    use crate::ast::cursor_path::CursorPath;
+    use crate::ast::test_json::TestJSON;
+
+    #[test]
+    fn format_paths() {
+        // [true, null, {"value": [false], "a.b": {"": true, "say \"hi\"": null}}]
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::Null,
+            TestJSON::Object(vec![
+                ("value".to_string(), TestJSON::Array(vec![TestJSON::False])),
+                (
+                    "a.b".to_string(),
+                    TestJSON::Object(vec![
+                        ("".to_string(), TestJSON::True),
+                        ("say \"hi\"".to_string(), TestJSON::Null),
+                    ]),
+                ),
+            ]),
+        ])
+        .add_to_arena(&arena);
+        for (path, expected_json_path) in &[
+            (vec![], "$"),
+            (vec![1], "$[1]"),
+            (vec![2], "$[2]"),
+            (vec![2, 0], "$[2].value"),
+            // The key and value of a field have the same path as the field
+            (vec![2, 0, 0], "$[2].value"),
+            (vec![2, 0, 1], "$[2].value"),
+            (vec![2, 0, 1, 0], "$[2].value[0]"),
+            // Keys which aren't identifiers are quoted
+            (vec![2, 1], r#"$[2]["a.b"]"#),
+            (vec![2, 1, 1, 0], r#"$[2]["a.b"][""]"#),
+            (vec![2, 1, 1, 1], r#"$[2]["a.b"]["say \"hi\""]"#),
+        ] {
+            let path = CursorPath::from_vec(path.clone());
+            assert_eq!(format(root, &path), *expected_json_path);
+        }
+    }
+
+    #[test]
+    fn resolve_paths() {
+        // [true, {"value": [false, []], "a.b": {"say \"hi\"": null}, "empty": {}}]
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::Object(vec![
+                (
+                    "value".to_string(),
+                    TestJSON::Array(vec![TestJSON::False, TestJSON::Array(vec![])]),
+                ),
+                (
+                    "a.b".to_string(),
+                    TestJSON::Object(vec![("say \"hi\"".to_string(), TestJSON::Null)]),
+                ),
+                ("empty".to_string(), TestJSON::Object(vec![])),
+            ]),
+        ])
+        .add_to_arena(&arena);
+        let resolve =
+            |text: &str| resolve(root, text).map(|path| path.iter().copied().collect::<Vec<_>>());
+        for (json_path, expected_path) in &[
+            ("$", vec![]),
+            ("$[1]", vec![1]),
+            // Keys lead to the value of their field
+            ("$[1].value", vec![1, 0, 1]),
+            ("$[1].value[1]", vec![1, 0, 1, 1]),
+            (r#"$[1]["a.b"]["say \"hi\""]"#, vec![1, 1, 1, 0, 1]),
+            (r#" $[1]["value"][0] "#, vec![1, 0, 1, 0]),
+        ] {
+            assert_eq!(
+                resolve(json_path),
+                Ok(expected_path.clone()),
+                "{}",
+                json_path
+            );
+        }
+        for (json_path, expected_error) in &[
+            ("[1]", "JSONPaths must start with '$'."),
+            ("$.", "Expected a key after '.'."),
+            ("$[1", "Expected ']' after an index."),
+            ("$[x]", "Expected an index, found 'x'."),
+            (r#"$["a"#, "Unterminated key in '[\"'."),
+            ("$ [1]", "Expected '.' or '[', found ' [1]'."),
+            ("$[1].values", r#"There is no key "values" in $[1]."#),
+            (
+                "$[2]",
+                "Index 2 is out of range, because $ only has 2 children.",
+            ),
+            (
+                "$[1].value[1][0]",
+                "Index 0 is out of range, because $[1].value[1] only has 0 children.",
+            ),
+            ("$[0].foo", r#"Cannot look up key "foo" in true at $[0]."#),
+            ("$[0][0]", "Cannot index into true at $[0]."),
+            ("$.foo", r#"Cannot look up key "foo" in array at $."#),
+            ("$[1][0]", "Cannot index into object at $[1]."),
+            // Empty containers still only allow the right kind of step
+            (
+                "$[1].value[1].foo",
+                r#"Cannot look up key "foo" in array at $[1].value[1]."#,
+            ),
+            ("$[1].empty[0]", "Cannot index into object at $[1].empty."),
+        ] {
+            assert_eq!(
+                resolve(json_path),
+                Err(expected_error.to_string()),
+                "{}",
+                json_path
+            );
+        }
+    }
+}
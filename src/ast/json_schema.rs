@@ -0,0 +1,333 @@
+//! Checking JSON trees against a [JSON Schema](https://json-schema.org/).
+//!
+//! Only the subset of JSON Schema which makes sense for Sapling's JSON trees is supported:
+//! - `type`, which is either a type name or an array of type names.  Sapling's JSON has no
+//!   numbers, so `number` and `integer` never match.
+//! - `properties`, `required` and `additionalProperties`, for objects
+//! - `items`, `minItems` and `maxItems`, for arrays
+//! - the schemas `true` and `false`, which match every value and no values
+//!
+//! Any other keywords are ignored.  When Sapling is built with the `serde` feature, schema files
+//! are read with `serde_json`, so they can contain numbers (like `"minItems": 1`, or the values of
+//! keywords that aren't supported, like `"minimum": 0.5`).  Otherwise they are read with Sapling's
+//! own JSON parser, which can't read numbers, so the values of `minItems` and `maxItems` must be
+//! written as strings (e.g. `"minItems": "1"`).  Counts written as strings are accepted either
+//! way.
+
+use super::cursor_path::CursorPath;
+use super::json::JSON;
+use super::Ast;
+use crate::arena::Arena;
+
+/// The type names that can be used in a schema's `type` keyword
+const TYPE_NAMES: [&str; 7] = [
+    "null", "boolean", "object", "array", "string", "number", "integer",
+];
+
+/// Checks `root` against the schema in `schema_text`, returning the path to and a description of
+/// every part of the tree which doesn't match the schema, in document order.  Returns an error
+/// message if the schema can't be parsed or uses keywords incorrectly.
+pub fn validate<'arena>(
+    root: &'arena JSON<'arena>,
+    schema_text: &str,
+) -> Result<Vec<(CursorPath, String)>, String> {
+    let arena = Arena::new();
+    let schema =
+        parse_schema(schema_text, &arena).map_err(|e| format!("Couldn't parse schema: {}", e))?;
+    let mut violations = Vec::new();
+    check(root, schema, &mut CursorPath::root(), &mut violations)?;
+    // Violations are found keyword by keyword, so sort them into document order.  Comparing
+    // paths index by index puts parents before their children, and this sort is stable so the
+    // violations of each node stay in the order they were found.
+    violations.sort_by(|(a, _), (b, _)| a.iter().cmp(b.iter()));
+    Ok(violations)
+}
+
+/// Parses the text of a schema with Sapling's own JSON parser
+#[cfg(not(feature = "serde"))]
+fn parse_schema<'s>(text: &str, arena: &'s Arena<JSON<'s>>) -> Result<&'s JSON<'s>, String> {
+    JSON::from_text(text, arena).map_err(|e| e.to_string())
+}
+
+/// Parses the text of a schema with `serde_json`.  Sapling's JSON can't contain numbers, so every
+/// number is replaced by the string of its digits (which is how counts are written without the
+/// `serde` feature).
+#[cfg(feature = "serde")]
+fn parse_schema<'s>(text: &str, arena: &'s Arena<JSON<'s>>) -> Result<&'s JSON<'s>, String> {
+    fn numbers_to_strings(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Number(n) => *value = serde_json::Value::String(n.to_string()),
+            serde_json::Value::Array(values) => values.iter_mut().for_each(numbers_to_strings),
+            serde_json::Value::Object(map) => map.values_mut().for_each(numbers_to_strings),
+            _ => {}
+        }
+    }
+    let mut value: serde_json::Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    numbers_to_strings(&mut value);
+    JSON::from_serde(&value, arena).map_err(|e| e.to_string())
+}
+
+/// Checks `node` (which is at `path`) against `schema`, adding the path and description of every
+/// violation to `violations`
+fn check<'arena, 's>(
+    node: &'arena JSON<'arena>,
+    schema: &'s JSON<'s>,
+    path: &mut CursorPath,
+    violations: &mut Vec<(CursorPath, String)>,
+) -> Result<(), String> {
+    let keywords = match schema {
+        JSON::True => return Ok(()),
+        JSON::False => {
+            violations.push((path.clone(), "No value is allowed here.".to_string()));
+            return Ok(());
+        }
+        JSON::Object(keywords) => keywords,
+        _ => return Err(format!("Expected a schema, found {}.", type_name(schema))),
+    };
+    let keyword = |name: &str| -> Option<&'s JSON<'s>> {
+        keywords
+            .iter()
+            .find(|f| f.key() == Some(name))
+            .map(|f| f.children()[1])
+    };
+
+    if let Some(types) = keyword("type") {
+        let names = string_list(types, "type")?;
+        if let Some(name) = names.iter().find(|n| !TYPE_NAMES.contains(n)) {
+            return Err(format!("Unknown type '{}' in schema.", name));
+        }
+        if !names.contains(&type_name(node)) {
+            violations.push((
+                path.clone(),
+                format!(
+                    "Expected {}, found {}.",
+                    names.join(" or "),
+                    type_name(node)
+                ),
+            ));
+        }
+    }
+
+    match node {
+        JSON::Object(fields) => {
+            let properties: &[&JSON] = match keyword("properties") {
+                Some(JSON::Object(properties)) => properties,
+                Some(_) => return Err("'properties' must be an object.".to_string()),
+                None => &[],
+            };
+            let additional = keyword("additionalProperties");
+            for (i, field) in fields.iter().enumerate() {
+                let property = properties.iter().find(|p| p.key() == field.key());
+                let field_schema = match (property, additional) {
+                    (Some(property), _) => property.children()[1],
+                    (None, Some(JSON::False)) => {
+                        path.push(i);
+                        violations.push((
+                            path.clone(),
+                            format!("Unexpected key {:?}.", field.key().unwrap_or_default()),
+                        ));
+                        path.pop();
+                        continue;
+                    }
+                    (None, Some(additional)) => additional,
+                    (None, None) => continue,
+                };
+                // The value of a field is its second child
+                path.push(i);
+                path.push(1);
+                check(field.children()[1], field_schema, path, violations)?;
+                path.pop();
+                path.pop();
+            }
+            if let Some(required) = keyword("required") {
+                for key in string_list(required, "required")? {
+                    if !fields.iter().any(|f| f.key() == Some(key)) {
+                        violations.push((path.clone(), format!("Missing required key {:?}.", key)));
+                    }
+                }
+            }
+        }
+        JSON::Array(items) => {
+            if let Some(item_schema) = keyword("items") {
+                for (i, item) in items.iter().enumerate() {
+                    path.push(i);
+                    check(item, item_schema, path, violations)?;
+                    path.pop();
+                }
+            }
+            if let Some(min_items) = keyword("minItems") {
+                let min_items = count(min_items, "minItems")?;
+                if items.len() < min_items {
+                    violations.push((
+                        path.clone(),
+                        format!(
+                            "Expected at least {} items, found {}.",
+                            min_items,
+                            items.len()
+                        ),
+                    ));
+                }
+            }
+            if let Some(max_items) = keyword("maxItems") {
+                let max_items = count(max_items, "maxItems")?;
+                if items.len() > max_items {
+                    violations.push((
+                        path.clone(),
+                        format!(
+                            "Expected at most {} items, found {}.",
+                            max_items,
+                            items.len()
+                        ),
+                    ));
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Returns the JSON Schema name of the type of a node
+fn type_name(node: &JSON) -> &'static str {
+    match node {
+        JSON::True | JSON::False => "boolean",
+        JSON::Null => "null",
+        JSON::Array(_) => "array",
+        JSON::Object(_) => "object",
+        JSON::Field(_) => "field",
+        JSON::Str(_) => "string",
+    }
+}
+
+/// Reads the value of a keyword which is either a string or an array of strings (like `type`)
+fn string_list<'s>(value: &'s JSON<'s>, keyword: &str) -> Result<Vec<&'s str>, String> {
+    let error = || format!("'{}' must be a string or an array of strings.", keyword);
+    match value {
+        JSON::Str(s) => Ok(vec![s.as_str()]),
+        JSON::Array(values) => values
+            .iter()
+            .map(|v| match v {
+                JSON::Str(s) => Ok(s.as_str()),
+                _ => Err(error()),
+            })
+            .collect(),
+        _ => Err(error()),
+    }
+}
+
+/// Reads the value of a keyword which is a count (like `minItems`).  Sapling's JSON can't contain
+/// numbers, so counts are written as strings (see [`parse_schema`]).
+fn count(value: &JSON, keyword: &str) -> Result<usize, String> {
+    match value {
+        JSON::Str(s) if s.bytes().all(|b| b.is_ascii_digit()) => s.parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| format!("'{}' must be a count, like 1 or \"1\".", keyword))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate;
+    use crate::arena::Arena;
+    use crate::ast::cursor_path::CursorPath;
+    use crate::ast::{json::JSON, Ast};
+
+    const SCHEMA: &str = r#"{
+        "type": "object",
+        "required": ["name", "tags"],
+        "properties": {
+            "name": {"type": "string"},
+            "tags": {"type": "array", "items": {"type": "string"}, "maxItems": "2"},
+            "extra": {"type": ["null", "boolean"]}
+        },
+        "additionalProperties": false
+    }"#;
+
+    #[test]
+    fn violations() {
+        let arena = Arena::new();
+        let violations = |text: &str| {
+            let root = JSON::from_text(text, &arena).unwrap();
+            validate(root, SCHEMA)
+                .unwrap()
+                .into_iter()
+                .map(|(path, message)| (path.iter().copied().collect(), message))
+                .collect::<Vec<(Vec<usize>, String)>>()
+        };
+        assert!(violations(r#"{"name": "a", "tags": ["b"], "extra": null}"#).is_empty());
+        assert_eq!(
+            violations(r#"{"tags": ["a", true, "c"], "extra": "yes", "other": true}"#),
+            vec![
+                (vec![], r#"Missing required key "name"."#.to_string()),
+                (vec![0, 1], "Expected at most 2 items, found 3.".to_string()),
+                (vec![0, 1, 1], "Expected string, found boolean.".to_string()),
+                (
+                    vec![1, 1],
+                    "Expected null or boolean, found string.".to_string()
+                ),
+                (vec![2], r#"Unexpected key "other"."#.to_string()),
+            ]
+        );
+        assert_eq!(
+            violations(r#"[{"extra": "yes"}]"#),
+            vec![(vec![], "Expected object, found array.".to_string())]
+        );
+    }
+
+    #[test]
+    fn invalid_schemas() {
+        let arena = Arena::new();
+        let root = JSON::from_text(r#"{"a": [true]}"#, &arena).unwrap();
+        for (schema, expected_error) in &[
+            (r#"{"type": "list"}"#, "Unknown type 'list' in schema."),
+            (
+                r#"{"type": [true]}"#,
+                "'type' must be a string or an array of strings.",
+            ),
+            (r#"{"properties": []}"#, "'properties' must be an object."),
+            (
+                r#"{"properties": {"a": {"minItems": "-1"}}}"#,
+                "'minItems' must be a count, like 1 or \"1\".",
+            ),
+            (
+                r#"{"additionalProperties": null}"#,
+                "Expected a schema, found null.",
+            ),
+        ] {
+            assert_eq!(validate(root, schema), Err(expected_error.to_string()));
+        }
+        // Without the `serde` feature, schemas can't contain numbers
+        if cfg!(feature = "serde") {
+            assert_eq!(
+                validate(
+                    root,
+                    r#"{"properties": {"a": {"minItems": 2, "minimum": 0.5}}}"#
+                ),
+                Ok(vec![(
+                    CursorPath::from_vec(vec![0, 1]),
+                    "Expected at least 2 items, found 1.".to_string()
+                )])
+            );
+            assert_eq!(
+                validate(root, r#"{"properties": {"a": {"minItems": 1.5}}}"#),
+                Err("'minItems' must be a count, like 1 or \"1\".".to_string())
+            );
+        } else {
+            assert!(validate(root, r#"{"minItems": 1}"#)
+                .unwrap_err()
+                .starts_with("Couldn't parse schema: "));
+        }
+        assert!(validate(root, "{")
+            .unwrap_err()
+            .starts_with("Couldn't parse schema: "));
+        // Boolean schemas match everything or nothing
+        assert_eq!(validate(root, "true"), Ok(vec![]));
+        assert_eq!(
+            validate(root, "false"),
+            Ok(vec![(
+                CursorPath::root(),
+                "No value is allowed here.".to_string()
+            )])
+        );
+    }
+}
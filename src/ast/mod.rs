@@ -1,62 +1,193 @@
 //! A module to contain Rust representations of ASTs in a format that sapling can work with.
 
+pub mod cursor_path;
 pub mod display_token;
+pub mod extension;
+pub mod formatter;
 pub mod json;
+pub mod json_path;
+pub mod json_schema;
+pub mod sexpr;
 pub mod size;
 pub mod test_json;
+pub mod toml;
 
-use display_token::{write_tokens, DisplayToken, RecTok};
+use crate::arena::Arena;
+use display_token::{
+    write_tokens, write_tokens_io, DisplayToken, DisplayTokenIter, Indent, RecTok,
+};
+use extension::LanguageExtension;
+use formatter::Formatter;
 use size::Size;
+use std::collections::HashSet;
+
+/// The syntactic category of a node, which decides the colour that the node is drawn with in the
+/// tree view (see [`Ast::syntax_class`])
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum SyntaxClass {
+    /// A node which contains other nodes, like an array or object
+    Container,
+    /// The key of a field or pair
+    Key,
+    /// `true` or `false`
+    Boolean,
+    /// `null`
+    Null,
+    /// A number
+    Number,
+    /// A string
+    String,
+    /// A node whose only text is punctuation between its children, like `: ` in a JSON field
+    Punctuation,
+    /// Any other node
+    Other,
+}
+
+impl SyntaxClass {
+    /// Returns the lower-case name of this class, which is also the name of its colour in a theme
+    /// file
+    pub fn name(&self) -> &'static str {
+        match self {
+            SyntaxClass::Container => "container",
+            SyntaxClass::Key => "key",
+            SyntaxClass::Boolean => "boolean",
+            SyntaxClass::Null => "null",
+            SyntaxClass::Number => "number",
+            SyntaxClass::String => "string",
+            SyntaxClass::Punctuation => "punctuation",
+            SyntaxClass::Other => "other",
+        }
+    }
+}
 
 /// The specification of an AST that sapling can edit
 pub trait Ast<'arena>: std::fmt::Debug + Clone + Eq + Default + std::hash::Hash {
-    /// A type parameter that will represent the different ways this AST can be rendered
-    type FormatStyle;
+    /// A type parameter that will represent the different ways this AST can be rendered.  Every
+    /// format style is also a [`Formatter`], which writes the same text as
+    /// [`write_text`](Ast::write_text).
+    type FormatStyle: Formatter<'arena, Self>;
     type InsertError: std::error::Error;
+    type ParseError: std::error::Error;
 
     /* FORMATTING FUNCTIONS */
 
     /// Returns an iterator of all the items that need to be rendered to the screen to make up this
     /// node, along with their on-screen locations.
-    fn display_tokens_rec(
-        &'arena self,
+    fn display_tokens_rec(&self, format_style: &Self::FormatStyle) -> Vec<RecTok<'arena, Self>>;
+
+    /// Returns the same tokens as [`display_tokens_rec`](Ast::display_tokens_rec), for a node
+    /// whose first token is drawn `column` columns into its line.  This is what the tokens of a
+    /// tree are generated with, so ASTs whose layout depends on how much of the line is left can
+    /// override it.
+    fn display_tokens_at(
+        &self,
         format_style: &Self::FormatStyle,
-    ) -> Vec<RecTok<'arena, Self>>;
+        _column: usize,
+    ) -> Vec<RecTok<'arena, Self>> {
+        self.display_tokens_rec(format_style)
+    }
 
     fn display_tokens(
         &'arena self,
         format_style: &Self::FormatStyle,
     ) -> Vec<(&'arena Self, DisplayToken)> {
-        let mut tok_pairs: Vec<(&'arena Self, DisplayToken)> = Vec::new();
-        for i in self.display_tokens_rec(format_style) {
-            match i {
-                RecTok::Tok(t) => {
-                    tok_pairs.push((self, t));
-                }
-                RecTok::Child(c) => {
-                    tok_pairs.extend(c.display_tokens(format_style));
-                }
-            }
-        }
-        tok_pairs
+        self.display_token_iter(format_style).collect()
+    }
+
+    /// Returns a lazy iterator over the same tokens as [`display_tokens`](Ast::display_tokens),
+    /// which only generates the tokens of each node once they are reached
+    fn display_token_iter<'f>(
+        &'arena self,
+        format_style: &'f Self::FormatStyle,
+    ) -> DisplayTokenIter<'arena, 'f, Self> {
+        DisplayTokenIter::new(self, format_style)
+    }
+
+    /// Returns the whitespace that should be added for each [`Indent`](DisplayToken::Indent)
+    /// token when this AST is rendered in a given format
+    fn indent(_format_style: &Self::FormatStyle) -> Indent {
+        Indent::DEFAULT
+    }
+
+    /// Switches a format style to the next of this AST's main layouts (e.g. from compact to
+    /// pretty), wrapping around after the last one.  ASTs with only one layout leave the format
+    /// style unchanged.
+    fn cycle_format_style(_format_style: &mut Self::FormatStyle) {}
+
+    /// Returns a short name for a format style (e.g. `"pretty"`), to show in the status line
+    fn format_style_name(_format_style: &Self::FormatStyle) -> String {
+        "default".to_string()
+    }
+
+    /// Changes one option of a format style, as typed by the user with a command like `:set
+    /// indent 2`.  Returns an error message if the option doesn't exist for this AST, or if the
+    /// value isn't valid for that option.
+    fn set_format_option(
+        _format_style: &mut Self::FormatStyle,
+        option: &str,
+        _value: &str,
+    ) -> Result<(), String> {
+        Err(format!("Unknown option '{}'.", option))
     }
 
     /// Determine the space on the screen occupied by this node in an AST
     fn size(&self, format_style: &Self::FormatStyle) -> Size;
 
-    /// Write the textual representation of this AST to a string
-    fn write_text(&'arena self, string: &mut String, format_style: &Self::FormatStyle) {
-        write_tokens(self, string, format_style);
+    /// Returns the [`Size`] of this node when its first token is drawn `column` columns into a
+    /// line whose indentation is `indentation` columns wide, but only if that can be calculated
+    /// without generating the node's text.  This lets the parts of a tree before the top of the
+    /// screen be skipped over without being laid out, so ASTs whose [`size`](Ast::size) measures
+    /// their text should return [`None`].
+    fn cheap_size_at(
+        &self,
+        _format_style: &Self::FormatStyle,
+        _indentation: usize,
+        _column: usize,
+    ) -> Option<Size> {
+        None
     }
 
-    /// Make a [`String`] representing this AST.
-    /// Same as [`write_text`](ASTSpec::write_text) but creates a new [`String`].
-    fn to_text(&'arena self, format_style: &Self::FormatStyle) -> String {
-        let mut s = String::new();
-        self.write_text(&mut s, format_style);
-        s
+    /// Write the textual representation of this AST to any [`std::fmt::Write`] sink.  This writes
+    /// the text incrementally, so the whole text never has to be stored in memory at once.
+    fn write_text(
+        &self,
+        out: &mut dyn std::fmt::Write,
+        format_style: &Self::FormatStyle,
+    ) -> std::fmt::Result
+    where
+        Self: 'arena,
+    {
+        write_tokens(self, out, format_style)
+    }
+
+    /// Write the textual representation of this AST to any [`std::io::Write`] sink (like a
+    /// [`BufWriter`](std::io::BufWriter) around a file).  Like [`write_text`](Ast::write_text),
+    /// this streams the text rather than building it all in memory first.
+    fn write_io(
+        &self,
+        out: &mut dyn std::io::Write,
+        format_style: &Self::FormatStyle,
+    ) -> std::io::Result<()>
+    where
+        Self: 'arena,
+    {
+        write_tokens_io(self, out, format_style)
+    }
+
+    /// Make a [`String`] representing this AST, written by any [`Formatter`].  Passing this
+    /// AST's [`FormatStyle`](Ast::FormatStyle) gives the same text as
+    /// [`write_text`](Ast::write_text), but in a new [`String`].
+    fn to_text(&self, formatter: &dyn Formatter<'arena, Self>) -> String {
+        formatter.format(self)
     }
 
+    /* PARSING FUNCTIONS */
+
+    /// Parse a string into a tree of nodes, all of which are added to a given [`Arena`], and return
+    /// a reference to the root.  This is the inverse of [`to_text`](Ast::to_text), and any text
+    /// generated by [`to_text`](Ast::to_text) must be parsed back into an identical tree.
+    fn from_text(text: &str, arena: &'arena Arena<Self>) -> Result<&'arena Self, Self::ParseError>;
+
     /* DEBUG VIEW FUNCTIONS */
 
     /// Get a slice over the direct children of this node.  This operation is expected to be
@@ -75,23 +206,72 @@ pub trait Ast<'arena>: std::fmt::Debug + Clone + Eq + Default + std::hash::Hash
         index: usize,
     ) -> Result<(), Self::InsertError>;
 
+    /// Remove the child of this node at a given index.
+    fn remove_child(&mut self, index: usize) -> Result<(), Self::InsertError>;
+
+    /// Calls `f` on every node in the subtree rooted at this node (including this node) in
+    /// pre-order, i.e. every node is visited before its children.  `f` is also given the depth of
+    /// each node below this one, so this node has a depth of 0 and its children have a depth of 1.
+    fn visit<F: FnMut(&'arena Self, usize)>(&'arena self, mut f: F) {
+        let mut stack = vec![(self, 0)];
+        while let Some((node, depth)) = stack.pop() {
+            f(node, depth);
+            // Push the children in reverse, so that the first child is visited first
+            stack.extend(node.children().iter().rev().map(|c| (*c, depth + 1)));
+        }
+    }
+
+    /// Returns the total number of nodes in the subtree rooted at this node (including this node).
+    fn node_count(&'arena self) -> usize {
+        let mut count = 0;
+        self.visit(|_, _| count += 1);
+        count
+    }
+
+    /// Returns the number of nodes on the longest path from this node down to a leaf, so a node
+    /// with no children has a depth of 1.
+    fn depth(&'arena self) -> usize {
+        let mut max_depth = 0;
+        self.visit(|_, depth| max_depth = max_depth.max(depth));
+        max_depth + 1
+    }
+
     /// Get the display name of this node
     fn display_name(&self) -> String;
 
+    /// Returns the key that identifies this node within its parent, if this kind of node has one.
+    /// For example, the fields of a JSON object are identified by their keys.
+    fn key(&self) -> Option<&str> {
+        None
+    }
+
     fn write_tree_view_recursive(
         &'arena self,
         string: &mut String,
         indentation_string: &mut String,
+        collapsed: &HashSet<*const Self>,
+        max_depth: usize,
     ) {
-        // Push the node's display name with indentation and a newline
+        // Push the node's display name with indentation
         string.push_str(indentation_string);
         string.push_str(&self.display_name());
+        // If this node is collapsed, then mark how many children are hidden instead of writing
+        // them
+        if collapsed.contains(&(self as *const Self)) {
+            string.push_str(&format!(" (+{})\n", self.children().len()));
+            return;
+        }
+        // If we've reached the maximum depth, then mark that the children have been left out
+        if max_depth == 0 && !self.children().is_empty() {
+            string.push_str(" (…)\n");
+            return;
+        }
         string.push('\n');
         // Indent by two spaces
         indentation_string.push_str("  ");
         // Write all the children
         for child in self.children().iter() {
-            child.write_tree_view_recursive(string, indentation_string);
+            child.write_tree_view_recursive(string, indentation_string, collapsed, max_depth - 1);
         }
         // Reset indentation
         for _ in 0..2 {
@@ -101,8 +281,16 @@ pub trait Ast<'arena>: std::fmt::Debug + Clone + Eq + Default + std::hash::Hash
 
     /// Render a tree view of this node, similar to the output of the Unix command 'tree'
     fn write_tree_view(&'arena self, string: &mut String) {
+        self.write_tree_view_folded(string, &HashSet::new());
+    }
+
+    /// Render a tree view of this node, where the children of any node in `collapsed` are
+    /// replaced by a marker like ` (+3)` saying how many children have been hidden.  Nodes are
+    /// identified by their address in the arena, so only the exact nodes in `collapsed` are
+    /// folded (not other nodes which happen to be equal to them).
+    fn write_tree_view_folded(&'arena self, string: &mut String, collapsed: &HashSet<*const Self>) {
         let mut indentation_string = String::new();
-        self.write_tree_view_recursive(string, &mut indentation_string);
+        self.write_tree_view_recursive(string, &mut indentation_string, collapsed, usize::MAX);
         // Pop the unnecessary newline at the end
         let popped_char = string.pop();
         debug_assert_eq!(Some('\n'), popped_char);
@@ -117,21 +305,88 @@ pub trait Ast<'arena>: std::fmt::Debug + Clone + Eq + Default + std::hash::Hash
         s
     }
 
+    /// Build a string of a tree view of this node, where only nodes which are at most `max_depth`
+    /// levels below this one are shown.  Nodes at the maximum depth which have children are
+    /// marked with ` (…)`, so a depth of 0 renders just this node.
+    fn tree_view_to_depth(&'arena self, max_depth: usize) -> String {
+        let mut s = String::new();
+        let mut indentation_string = String::new();
+        self.write_tree_view_recursive(&mut s, &mut indentation_string, &HashSet::new(), max_depth);
+        // Pop the unnecessary newline at the end
+        let popped_char = s.pop();
+        debug_assert_eq!(Some('\n'), popped_char);
+        s
+    }
+
+    /// Render this node as a Graphviz `digraph`, where every node is labelled with its
+    /// [`display_name`](Ast::display_name).  Edges to children which have a [`key`](Ast::key) are
+    /// labelled with that key.
+    fn write_dot(&'arena self, string: &mut String) {
+        string.push_str("digraph {\n");
+        write_dot_node(self, string, &mut 0);
+        string.push('}');
+    }
+
+    /// Build a string of the Graphviz representation of this node.  This is the same as
+    /// [`write_dot`](Ast::write_dot), except that it returns a [`String`] rather than appending to
+    /// an existing [`String`].
+    fn to_dot(&'arena self) -> String {
+        let mut s = String::new();
+        self.write_dot(&mut s);
+        s
+    }
+
+    /// Build a string of a folded tree view of this node.  This is the same as
+    /// [`write_tree_view_folded`](Ast::write_tree_view_folded), except that it returns a
+    /// [`String`] rather than appending to an existing [`String`].
+    fn tree_view_folded(&'arena self, collapsed: &HashSet<*const Self>) -> String {
+        let mut s = String::new();
+        self.write_tree_view_folded(&mut s, collapsed);
+        s
+    }
+
     /* AST EDITING FUNCTIONS */
 
-    /// Generate an iterator over the possible shorthand [`char`]s that a user could type to replace
-    /// this node with something else.
-    fn replace_chars(&self) -> Box<dyn Iterator<Item = char>>;
+    /// Generate an iterator over the possible shorthand sequences of [`char`]s that a user could
+    /// type to replace this node with something else.  Sequences can be longer than one [`char`]
+    /// (e.g. `tr` and `ts`), and the editor waits until the typed [`char`]s match exactly one
+    /// sequence.  If one sequence is the start of another, the editor also waits for the
+    /// [sequence timeout](crate::editor::config::EditorConfig::sequence_timeout) before using the
+    /// shorter one, so this is best avoided.
+    fn replace_seqs(&self) -> Box<dyn Iterator<Item = String>>;
 
-    /// Returns whether or not a given [`char`] is in [`Self::replace_chars`]
-    fn is_replace_char(&self, c: char) -> bool {
-        self.replace_chars().any(|x| x == c)
+    /// Returns whether or not a given sequence is in [`Self::replace_seqs`]
+    fn is_replace_seq(&self, seq: &str) -> bool {
+        self.replace_seqs().any(|x| x == seq)
     }
 
-    /// Generate a new node from a [`char`] that a user typed as part of the `r` command.  If `c` is
-    /// an element of [`get_replace_chars`](ASTSpec::replace_chars), this must return [`Some`] node,
-    /// if it isn't, then this should return [`None`].
-    fn from_char(&self, c: char) -> Option<Self>;
+    /// Generate a new node from a [`char`] that a user typed as part of an insert command (like
+    /// `o`).  If `c` is an element of [`insert_chars`](Ast::insert_chars), this must return
+    /// [`Some`] node, if it isn't, then this should return [`None`].
+    fn node_for_char(&self, c: char) -> Option<Self>;
+
+    /// Generate a new node from a sequence that a user typed as part of the `r` command.  If `seq`
+    /// is an element of [`replace_seqs`](Ast::replace_seqs), this must return [`Some`] node, if it
+    /// isn't, then this should return [`None`].  By default, sequences of a single [`char`] are
+    /// passed to [`node_for_char`](Ast::node_for_char), which is enough for ASTs whose replace
+    /// sequences are all one [`char`] long.
+    fn node_for_replace_seq(&self, seq: &str) -> Option<Self> {
+        let mut chars = seq.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => self.node_for_char(c),
+            _ => None,
+        }
+    }
+
+    /// Generate a node of the type that a user could get by typing `seq` as part of the `r`
+    /// command, but which keeps as many of this node's children as possible (for example,
+    /// converting a JSON array into an object keeps the array's elements as the values of the new
+    /// fields).  Any new nodes are added to the `arena`.  Like
+    /// [`node_for_replace_seq`](Ast::node_for_replace_seq), this must return [`Some`] node exactly
+    /// when `seq` is one of the [`replace_seqs`](Ast::replace_seqs).
+    fn convert(&self, seq: &str, _arena: &'arena Arena<Self>) -> Option<Self> {
+        self.node_for_replace_seq(seq)
+    }
 
     /// Generate an iterator over the possible shorthand [`char`]s that a user could type to insert
     /// other nodes into this one
@@ -141,4 +396,93 @@ pub trait Ast<'arena>: std::fmt::Debug + Clone + Eq + Default + std::hash::Hash
     fn is_insert_char(&self, c: char) -> bool {
         self.insert_chars().any(|x| x == c)
     }
+
+    /// Generate a copy of this node where every child has been split into its own container.  For
+    /// example, a JSON object is exploded into an array of single-field objects.  Any new nodes
+    /// are added to the `arena`.  This returns [`None`] if this node can't be exploded.
+    fn explode(&self, _arena: &'arena Arena<Self>) -> Option<Self> {
+        None
+    }
+
+    /// The inverse of [`explode`](Ast::explode): generate a copy of this node where the children
+    /// of all of its children have been merged into one container.  For example, an array of JSON
+    /// objects is merged into one object.  This returns [`None`] if this node can't be merged.
+    fn merge(&self, _arena: &'arena Arena<Self>) -> Option<Self> {
+        None
+    }
+
+    /// Returns one key per child of this node, such that sorting the children by their keys puts
+    /// them into a sensible order (e.g. the fields of a JSON object are sorted by their keys).
+    /// This returns an error message if the children of this node can't be sorted.
+    fn child_sort_keys(&self) -> Result<Vec<String>, String> {
+        Err(format!(
+            "Cannot sort the children of {}.",
+            self.display_name()
+        ))
+    }
+
+    /// Generate a new node that could be inserted as a child of this node, and which is
+    /// identified by a given `key` (see [`key`](Ast::key)).  Any other nodes required to build the
+    /// new node are added to the `arena`.  This returns [`None`] if the children of this node
+    /// aren't identified by keys.
+    fn new_keyed_child(&self, _key: String, _arena: &'arena Arena<Self>) -> Option<Self> {
+        None
+    }
+
+    /// Generate a copy of this node which is identified by a different `key` (see
+    /// [`key`](Ast::key)).  Any other nodes required to build the new node are added to the
+    /// `arena`.  This returns [`None`] if this node isn't identified by a key.
+    fn with_key(&self, _key: String, _arena: &'arena Arena<Self>) -> Option<Self> {
+        None
+    }
+
+    /// Returns `true` if this node is a container with no contents, which
+    /// [`DAG::prune_empty`](crate::editable_tree::DAG::prune_empty) can remove from its parent
+    /// (e.g. an empty JSON array).
+    fn is_empty_container(&self) -> bool {
+        false
+    }
+
+    /// Returns the syntactic category of this node, given its `parent` (or [`None`] if it's the
+    /// root).  The parent is needed because the same node can mean different things in different
+    /// places, e.g. a JSON string could be a key or a value.
+    fn syntax_class(&self, _parent: Option<&Self>) -> SyntaxClass {
+        SyntaxClass::Other
+    }
+
+    /// Returns this node as a [`LanguageExtension`], if this AST's language has features that
+    /// other languages don't (like schemas).  By default, ASTs have no extension.
+    fn extension(&'arena self) -> Option<&'arena dyn LanguageExtension<'arena>> {
+        None
+    }
+}
+
+/// Writes the Graphviz lines for a node and its descendants, giving each node a unique ID of the
+/// form `n<number>` (where `next_id` is the next unused number).  Returns the ID of `node`.
+fn write_dot_node<'arena, Node: Ast<'arena>>(
+    node: &'arena Node,
+    string: &mut String,
+    next_id: &mut usize,
+) -> usize {
+    let id = *next_id;
+    *next_id += 1;
+    string.push_str(&format!(
+        "    n{} [label=\"{}\"];\n",
+        id,
+        escape_dot(&node.display_name())
+    ));
+    for child in node.children() {
+        let child_id = write_dot_node(*child, string, next_id);
+        string.push_str(&format!("    n{} -> n{}", id, child_id));
+        if let Some(key) = child.key() {
+            string.push_str(&format!(" [label=\"{}\"]", escape_dot(key)));
+        }
+        string.push_str(";\n");
+    }
+    id
+}
+
+/// Escapes a string so that it can be put inside a double-quoted Graphviz string
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
 }
@@ -0,0 +1,502 @@
+//! An [`Ast`] implementation for Lisp-style S-expressions, made up of atoms (symbols and
+//! numbers) and lists.
+
+use super::display_token::{write_tokens, DisplayToken, RecTok};
+use super::size::Size;
+use super::{Ast, SyntaxClass};
+use crate::arena::Arena;
+
+/// The different ways that an S-expression can be formatted
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum SExprFormat {
+    /// Every expression is on one line.  E.g. `(define (square x) (* x x))`
+    Compact,
+    /// Lists which contain other lists are split over multiple lines, with every element after
+    /// the first on its own indented line.  Lists which only contain atoms stay on one line.  E.g.
+    /// ```text
+    /// (define
+    ///     (square x)
+    ///     (* x x))
+    /// ```
+    Pretty,
+}
+
+const CHAR_ATOM: char = 'a';
+const CHAR_NUMBER: char = 'n';
+const CHAR_LIST: char = 'l';
+
+/// Error produced when inserting a child into an S-expression fails
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub enum InsertError {
+    /// A child was attempted to be inserted into an atom, which cannot have any children.
+    NoPossibleChildren(String),
+}
+
+impl std::fmt::Display for InsertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InsertError::NoPossibleChildren(node) => {
+                write!(f, "Node {} cannot contain other nodes.", node)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InsertError {}
+
+/// The different ways that parsing an S-expression can fail
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub enum ParseErrorKind {
+    /// A `)` was found which doesn't close any list
+    UnexpectedCloseParen,
+    /// The text ended whilst the parser was still expecting an expression
+    UnexpectedEnd,
+    /// A list was opened with `(` but never closed
+    UnterminatedList,
+    /// A complete expression was parsed, but it was followed by more non-whitespace text
+    TrailingChars,
+}
+
+/// Error produced when parsing an S-expression fails
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct ParseError {
+    /// The byte offset into the source text where the error was found
+    pub offset: usize,
+    /// What went wrong
+    pub kind: ParseErrorKind,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ParseErrorKind::UnexpectedCloseParen => write!(f, "Unexpected ')'"),
+            ParseErrorKind::UnexpectedEnd => write!(f, "Unexpected end of text"),
+            ParseErrorKind::UnterminatedList => write!(f, "Unterminated list"),
+            ParseErrorKind::TrailingChars => {
+                write!(f, "Unexpected text after the end of the expression")
+            }
+        }?;
+        write!(f, " at byte {}.", self.offset)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// The sapling representation of an S-expression
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
+pub enum SExpr<'arena> {
+    /// A symbol atom, such as `define` or `+`.  This can contain any chars except whitespace,
+    /// parentheses and `;`, and can't be a valid number.
+    Symbol(String),
+    /// An integer atom, such as `42` or `-7`
+    Number(i64),
+    /// A list of expressions, written as `(<e1> <e2> ...)`
+    List(Vec<&'arena SExpr<'arena>>),
+}
+
+impl SExpr<'_> {
+    /// Return an iterator over all the possible chars that could represent S-expressions
+    fn all_expr_chars() -> Box<dyn Iterator<Item = char>> {
+        Box::new([CHAR_ATOM, CHAR_NUMBER, CHAR_LIST].iter().copied())
+    }
+}
+
+/// Returns `true` if `c` can't be part of a symbol
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || c == '(' || c == ')' || c == ';'
+}
+
+impl std::fmt::Display for SExpr<'_> {
+    /// Writes the [`Compact`](SExprFormat::Compact) representation of this S-expression
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_tokens(self, f, &SExprFormat::Compact)
+    }
+}
+
+impl Default for SExpr<'_> {
+    fn default() -> SExpr<'static> {
+        SExpr::List(vec![])
+    }
+}
+
+impl<'arena> Ast<'arena> for SExpr<'arena> {
+    type FormatStyle = SExprFormat;
+    type InsertError = InsertError;
+    type ParseError = ParseError;
+
+    /* FORMATTING FUNCTIONS */
+
+    fn display_tokens_rec(&self, format_style: &Self::FormatStyle) -> Vec<RecTok<'arena, Self>> {
+        match self {
+            SExpr::Symbol(_) | SExpr::Number(_) => {
+                vec![RecTok::Tok(DisplayToken::Text(self.display_name()))]
+            }
+            SExpr::List(children) => {
+                // Only split lists over multiple lines if they contain other lists, otherwise the
+                // pretty format would put every atom on its own line
+                let is_multiline = *format_style == SExprFormat::Pretty
+                    && children.iter().any(|c| matches!(c, SExpr::List(_)));
+
+                let mut tokens: Vec<RecTok<'_, Self>> = Vec::with_capacity(4 + 2 * children.len());
+                tokens.push(RecTok::Tok(DisplayToken::Text("(".to_string())));
+                if is_multiline {
+                    tokens.push(RecTok::Tok(DisplayToken::Indent));
+                }
+                for (i, c) in children.iter().enumerate() {
+                    // The first element stays on the same line as the `(`
+                    if i > 0 {
+                        if is_multiline {
+                            tokens.push(RecTok::Tok(DisplayToken::Newline));
+                        } else {
+                            tokens.push(RecTok::Tok(DisplayToken::Whitespace(1)));
+                        }
+                    }
+                    tokens.push(RecTok::Child(*c));
+                }
+                if is_multiline {
+                    tokens.push(RecTok::Tok(DisplayToken::Dedent));
+                }
+                tokens.push(RecTok::Tok(DisplayToken::Text(")".to_string())));
+                tokens
+            }
+        }
+    }
+
+    fn format_style_name(format_style: &Self::FormatStyle) -> String {
+        match format_style {
+            SExprFormat::Compact => "compact".to_string(),
+            SExprFormat::Pretty => "pretty".to_string(),
+        }
+    }
+
+    fn cycle_format_style(format_style: &mut Self::FormatStyle) {
+        *format_style = match format_style {
+            SExprFormat::Compact => SExprFormat::Pretty,
+            SExprFormat::Pretty => SExprFormat::Compact,
+        };
+    }
+
+    fn set_format_option(
+        format_style: &mut Self::FormatStyle,
+        option: &str,
+        value: &str,
+    ) -> Result<(), String> {
+        match (option, value) {
+            ("format", "compact") => *format_style = SExprFormat::Compact,
+            ("format", "pretty") => *format_style = SExprFormat::Pretty,
+            ("format", _) => {
+                return Err(format!(
+                    "Expected 'compact' or 'pretty', found '{}'.",
+                    value
+                ))
+            }
+            _ => return Err(format!("Unknown option '{}'.", option)),
+        }
+        Ok(())
+    }
+
+    fn size(&self, format_style: &Self::FormatStyle) -> Size {
+        // Measure the text rather than calculating the size directly like the JSON
+        // implementation does
+        Size::from(self.to_text(format_style).as_str())
+    }
+
+    /* PARSING FUNCTIONS */
+
+    fn from_text(
+        text: &str,
+        arena: &'arena Arena<SExpr<'arena>>,
+    ) -> Result<&'arena SExpr<'arena>, ParseError> {
+        let mut parser = Parser {
+            text,
+            offset: 0,
+            arena,
+        };
+        let root = parser.parse_expr()?;
+        // Make sure that the expression we parsed used up the whole string
+        parser.skip_whitespace_and_comments();
+        if parser.offset < text.len() {
+            return Err(parser.error(ParseErrorKind::TrailingChars));
+        }
+        Ok(root)
+    }
+
+    /* DEBUG VIEW FUNCTIONS */
+
+    fn children<'s>(&'s self) -> &'s [&'arena SExpr<'arena>] {
+        match self {
+            SExpr::Symbol(_) | SExpr::Number(_) => &[],
+            SExpr::List(children) => children,
+        }
+    }
+
+    fn children_mut<'s>(&'s mut self) -> &'s mut [&'arena SExpr<'arena>] {
+        match self {
+            SExpr::Symbol(_) | SExpr::Number(_) => &mut [],
+            SExpr::List(children) => children,
+        }
+    }
+
+    fn insert_child(&mut self, new_node: &'arena Self, index: usize) -> Result<(), InsertError> {
+        match self {
+            SExpr::Symbol(_) | SExpr::Number(_) => {
+                Err(InsertError::NoPossibleChildren(self.display_name()))
+            }
+            SExpr::List(children) => {
+                children.insert(index, new_node);
+                Ok(())
+            }
+        }
+    }
+
+    fn remove_child(&mut self, index: usize) -> Result<(), InsertError> {
+        match self {
+            SExpr::Symbol(_) | SExpr::Number(_) => {
+                Err(InsertError::NoPossibleChildren(self.display_name()))
+            }
+            SExpr::List(children) => {
+                children.remove(index);
+                Ok(())
+            }
+        }
+    }
+
+    fn display_name(&self) -> String {
+        match self {
+            SExpr::Symbol(name) => name.clone(),
+            SExpr::Number(n) => n.to_string(),
+            SExpr::List(_) => "list".to_string(),
+        }
+    }
+
+    fn syntax_class(&self, _parent: Option<&Self>) -> SyntaxClass {
+        match self {
+            SExpr::Symbol(_) => SyntaxClass::Other,
+            SExpr::Number(_) => SyntaxClass::Number,
+            SExpr::List(_) => SyntaxClass::Container,
+        }
+    }
+
+    /* AST EDITING FUNCTIONS */
+
+    fn replace_seqs(&self) -> Box<dyn Iterator<Item = String>> {
+        Box::new(Self::all_expr_chars().map(String::from))
+    }
+
+    fn node_for_char(&self, c: char) -> Option<Self> {
+        match c {
+            CHAR_ATOM => Some(SExpr::Symbol("nil".to_string())),
+            CHAR_NUMBER => Some(SExpr::Number(0)),
+            CHAR_LIST => Some(SExpr::List(vec![])),
+            _ => None,
+        }
+    }
+
+    fn insert_chars(&self) -> Box<dyn Iterator<Item = char>> {
+        match self {
+            SExpr::Symbol(_) | SExpr::Number(_) => Box::new(std::iter::empty()),
+            SExpr::List(_) => Self::all_expr_chars(),
+        }
+    }
+}
+
+/// A simple recursive-descent parser which reads S-expressions into nodes in an [`Arena`]
+struct Parser<'t, 'arena> {
+    /// The full text being parsed
+    text: &'t str,
+    /// The byte offset of the next unread char in `text`
+    offset: usize,
+    /// The arena that new nodes are added to
+    arena: &'arena Arena<SExpr<'arena>>,
+}
+
+impl<'t, 'arena> Parser<'t, 'arena> {
+    /// Build a [`ParseError`] of a given kind at the current location
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            offset: self.offset,
+            kind,
+        }
+    }
+
+    /// Returns the next unread char, without consuming it
+    fn peek(&self) -> Option<char> {
+        self.text[self.offset..].chars().next()
+    }
+
+    /// Consume whitespace, and comments which start with `;` and run to the end of the line
+    fn skip_whitespace_and_comments(&mut self) {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => self.offset += c.len_utf8(),
+                Some(';') => {
+                    let rest = &self.text[self.offset..];
+                    self.offset += rest.find('\n').unwrap_or(rest.len());
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// Parse any S-expression, skipping any whitespace before it
+    fn parse_expr(&mut self) -> Result<&'arena SExpr<'arena>, ParseError> {
+        self.skip_whitespace_and_comments();
+        match self.peek() {
+            Some('(') => self.parse_list(),
+            Some(')') => Err(self.error(ParseErrorKind::UnexpectedCloseParen)),
+            Some(_) => Ok(self.parse_atom()),
+            None => Err(self.error(ParseErrorKind::UnexpectedEnd)),
+        }
+    }
+
+    /// Parse a list, assuming that the next char is `(`
+    fn parse_list(&mut self) -> Result<&'arena SExpr<'arena>, ParseError> {
+        let start_offset = self.offset;
+        // Consume the opening '('
+        self.offset += 1;
+        let mut children = Vec::new();
+        loop {
+            self.skip_whitespace_and_comments();
+            match self.peek() {
+                Some(')') => {
+                    self.offset += 1;
+                    return Ok(self.arena.alloc(SExpr::List(children)));
+                }
+                Some(_) => children.push(self.parse_expr()?),
+                None => {
+                    return Err(ParseError {
+                        offset: start_offset,
+                        kind: ParseErrorKind::UnterminatedList,
+                    })
+                }
+            }
+        }
+    }
+
+    /// Parse an atom, which runs until the next delimiter.  The atom is a number if it can be
+    /// parsed as one, and a symbol otherwise.
+    fn parse_atom(&mut self) -> &'arena SExpr<'arena> {
+        let rest = &self.text[self.offset..];
+        let len = rest.find(is_delimiter).unwrap_or(rest.len());
+        self.offset += len;
+        let atom = &rest[..len];
+        self.arena.alloc(match atom.parse() {
+            Ok(n) => SExpr::Number(n),
+            Err(_) => SExpr::Symbol(atom.to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ParseError, ParseErrorKind, SExpr, SExprFormat};
+    use crate::arena::Arena;
+    use crate::ast::{cursor_path::CursorPath, Ast};
+    use crate::editable_tree::{Direction, DAG};
+
+    #[test]
+    fn to_text() {
+        let arena = Arena::new();
+        for (text, expected_pretty) in &[
+            ("()", "()"),
+            ("foo", "foo"),
+            ("(+ 1 -2)", "(+ 1 -2)"),
+            (
+                "(define (square x) (* x x))",
+                "(define\n    (square x)\n    (* x x))",
+            ),
+            (
+                "(a (b (c d) e) f)",
+                "(a\n    (b\n        (c d)\n        e)\n    f)",
+            ),
+            ("((()))", "((()))"),
+        ] {
+            let root = SExpr::from_text(text, &arena).unwrap();
+            assert_eq!(root.to_text(&SExprFormat::Compact), *text);
+            assert_eq!(root.to_text(&SExprFormat::Pretty), *expected_pretty);
+            // The pretty text should parse back to the same tree
+            assert_eq!(SExpr::from_text(expected_pretty, &arena), Ok(root));
+        }
+    }
+
+    #[test]
+    fn from_text() {
+        let arena = Arena::new();
+        let root =
+            SExpr::from_text("  ; A comment\n(foo 12 -3 4x) ; Another comment\n", &arena).unwrap();
+        let children: Vec<&SExpr> = root.children().to_vec();
+        assert_eq!(
+            children,
+            vec![
+                &SExpr::Symbol("foo".to_string()),
+                &SExpr::Number(12),
+                &SExpr::Number(-3),
+                &SExpr::Symbol("4x".to_string())
+            ]
+        );
+        // S-expressions don't have schemas or path expressions
+        assert!(root.extension().is_none());
+    }
+
+    #[test]
+    fn from_text_errors() {
+        let arena = Arena::new();
+        for (text, offset, kind) in &[
+            ("", 0, ParseErrorKind::UnexpectedEnd),
+            ("  ; only a comment", 18, ParseErrorKind::UnexpectedEnd),
+            (")", 0, ParseErrorKind::UnexpectedCloseParen),
+            ("(a (b c)", 0, ParseErrorKind::UnterminatedList),
+            ("(a b) c", 6, ParseErrorKind::TrailingChars),
+            ("(a b))", 5, ParseErrorKind::TrailingChars),
+        ] {
+            assert_eq!(
+                SExpr::from_text(text, &arena),
+                Err(ParseError {
+                    offset: *offset,
+                    kind: kind.clone()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn edit() {
+        let arena = Arena::new();
+        let root = SExpr::from_text("(f x)", &arena).unwrap();
+        let mut tree = DAG::new(&arena, root);
+        tree.insert_child(SExpr::List(vec![])).unwrap();
+        tree.insert_child(SExpr::Number(0)).unwrap();
+        assert_eq!(tree.to_text(&SExprFormat::Compact), "(f x (0))");
+        assert_eq!(tree.to_text(&SExprFormat::Pretty), "(f\n    x\n    (0))");
+        // Atoms can't contain other expressions
+        assert!(tree.insert_child(SExpr::Number(1)).is_err());
+        tree.move_cursor(Direction::Up);
+        tree.move_cursor(Direction::Prev);
+        tree.replace_cursor(SExpr::Symbol("y".to_string()));
+        assert_eq!(tree.to_text(&SExprFormat::Compact), "(f y (0))");
+    }
+
+    #[test]
+    fn reflow() {
+        let arena = Arena::new();
+        let root = SExpr::from_text("(f (g x) y)", &arena).unwrap();
+        let mut tree = DAG::new(&arena, root);
+        // Symbols which look like numbers or contain parentheses are read back as different nodes
+        tree.move_cursor(Direction::Down);
+        tree.replace_cursor(SExpr::Symbol("12".to_string()));
+        tree.move_cursor(Direction::Next);
+        tree.move_cursor(Direction::Down);
+        tree.replace_cursor(SExpr::Symbol(") (".to_string()));
+        tree.move_cursor(Direction::Next);
+        assert_eq!(tree.to_text(&SExprFormat::Compact), "(12 () ( x) y)");
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![1, 1]));
+        assert_eq!(tree.reflow(&SExprFormat::Compact), Ok(true));
+        assert_eq!(tree.to_text(&SExprFormat::Compact), "(12 () (x) y)");
+        assert_eq!(tree.root().children()[0], &SExpr::Number(12));
+        // `()` is now empty, so the cursor moves up out of where `x` used to be
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![1]));
+        // Reflowing is one edit, so can be undone
+        assert!(tree.undo());
+        assert_eq!(tree.to_text(&SExprFormat::Compact), "(12 () ( x) y)");
+    }
+}
@@ -1,9 +1,13 @@
+use unicode_width::UnicodeWidthStr;
+
 pub const ZERO: Size = Size::new(0, 0);
 
 /// A struct used to represent the screen space occupied by a single node of an AST.  This can be
 /// thought of as the size of the bounding box of that node.  The important thing about this is
 /// that it is independent of the text indentation, meaning that if a node gets reused multiple
-/// times in a tree, then it's `Size` will always be the same.
+/// times in a tree, then it's `Size` will always be the same.  The exception is an AST whose
+/// layout depends on how much of the line is left, like JSON with a
+/// [`max_width`](super::json::PrettyFormat::max_width).
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct Size {
     lines: usize,
@@ -32,15 +36,17 @@ impl Size {
         self.lines
     }
 
-    /// Returns how many characters long the last line of this `Size` occupies.  For example, the
-    /// last (and only) line of `true` occupies `4` [`char`]s, whereas the last line of
+    /// Returns how many columns the last line of this `Size` occupies on the screen.  For example,
+    /// the last (and only) line of `true` occupies `4` columns, whereas the last line of
     /// ```text
     /// {
     ///     "foo": true,
     ///     "bar": false
     /// }
     /// ```
-    /// occupies `1` [`char`].
+    /// occupies `1` column.  This is the display width of the text rather than the number of
+    /// [`char`]s in it, so wide characters (like `日`) count as two columns and combining
+    /// characters count as none.
     pub fn last_line_length(&self) -> usize {
         self.last_line_length
     }
@@ -49,8 +55,8 @@ impl Size {
 impl From<&str> for Size {
     fn from(string: &str) -> Size {
         let lines = string.chars().filter(|x| *x == '\n').count();
-        let last_line_length = string.chars().rev().take_while(|x| *x != '\n').count();
-        Size::new(lines, last_line_length)
+        let last_line = string.rsplit('\n').next().unwrap_or_default();
+        Size::new(lines, last_line.width())
     }
 }
 
@@ -107,12 +113,18 @@ mod tests {
             ("\n,", 1, 1),
             ("Some text\n", 1, 0),
             ("{\n   true\n},", 2, 2),
+            // Wide characters take up two columns, and combining characters take up none
+            ("日本語", 0, 6),
+            ("{\n\"日本\"", 1, 6),
+            ("e\u{301}", 0, 1),
         ] {
             assert_eq!(Size::from(*string), Size::new(*lines, *last_line_length));
         }
     }
 
     #[test]
+    // `+` is used on purpose, to check that it agrees with `+=`
+    #[allow(clippy::assign_op_pattern)]
     fn add() {
         let tests: &[&[&str]] = &[
             &["[", "]"],
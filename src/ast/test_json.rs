@@ -6,6 +6,7 @@ pub enum TestJSON {
     True,
     False,
     Null,
+    Str(String),
     Array(Vec<TestJSON>),
     Object(Vec<(String, TestJSON)>),
 }
@@ -18,6 +19,7 @@ impl TestJSON {
             TestJSON::True => arena.alloc(JSON::True),
             TestJSON::False => arena.alloc(JSON::False),
             TestJSON::Null => arena.alloc(JSON::Null),
+            TestJSON::Str(string) => arena.alloc(JSON::Str(string.clone())),
             TestJSON::Array(children) => {
                 let mut child_vec: Vec<&'arena JSON<'arena>> = Vec::with_capacity(children.len());
                 for c in children {
@@ -0,0 +1,884 @@
+//! An [`Ast`] implementation for a subset of [TOML](https://toml.io), containing tables, arrays,
+//! booleans and strings.
+
+use super::display_token::{write_tokens, DisplayToken, RecTok};
+use super::size::Size;
+use super::{Ast, SyntaxClass};
+use crate::arena::Arena;
+
+/// The ways that a TOML AST can be formatted.  Currently there is only one layout: the root table
+/// has one `key = value` pair per line, and all other tables are written as inline tables.
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Default)]
+pub struct TOMLFormat;
+
+const CHAR_TRUE: char = 't';
+const CHAR_FALSE: char = 'f';
+const CHAR_STRING: char = 's';
+const CHAR_ARRAY: char = 'a';
+const CHAR_TABLE: char = 'o';
+const CHAR_PAIR: char = 'i';
+
+/// Error produced when inserting a child into a TOML node fails
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub enum InsertError {
+    /// A child was attempted to be inserted into a node such as `true` or a key, which cannot
+    /// have any children.
+    NoPossibleChildren(String),
+    /// A child was attempted to be inserted into a node that can only have a fixed number of
+    /// children.  The second argument is the number of children that this node has to have.  This
+    /// is used by pairs, which are required to have 2 children.
+    FixedChildCount(String, usize),
+    /// A child was attempted to be inserted into a node which can't contain children of that
+    /// type.  The first argument is the parent node, and the second is the child.  This is used
+    /// because tables can only contain pairs, and pairs can only be contained in tables.
+    InvalidChild(String, String),
+}
+
+impl std::fmt::Display for InsertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InsertError::NoPossibleChildren(node) => {
+                write!(f, "Node {} cannot contain other nodes.", node)
+            }
+            InsertError::FixedChildCount(node, num_children) => {
+                write!(f, "Node {} can only have {} children.", node, num_children)
+            }
+            InsertError::InvalidChild(node, child) => {
+                write!(f, "Node {} cannot contain node {}.", node, child)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InsertError {}
+
+/// The different ways that parsing a TOML string can fail
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub enum ParseErrorKind {
+    /// The parser found a char that can't start or continue any valid TOML value
+    UnexpectedChar(char),
+    /// The text ended whilst the parser was still expecting more
+    UnexpectedEnd,
+    /// An array was opened with `[` but never closed
+    UnterminatedArray,
+    /// An inline table was opened with `{` but not closed on the same line
+    UnterminatedInlineTable,
+    /// A string was opened with `"` but not closed on the same line
+    UnterminatedString,
+    /// A string contained a `\` escape sequence that isn't valid TOML
+    InvalidEscape,
+    /// A dotted key (like `a.b = true`) was used.  These aren't supported yet.
+    DottedKey,
+    /// The same key was defined twice in the same table
+    DuplicateKey(String),
+}
+
+/// Error produced when parsing a TOML string fails
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct ParseError {
+    /// The byte offset into the source text where the error was found
+    pub offset: usize,
+    /// What went wrong
+    pub kind: ParseErrorKind,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ParseErrorKind::UnexpectedChar(c) => write!(f, "Unexpected char {:?}", c),
+            ParseErrorKind::UnexpectedEnd => write!(f, "Unexpected end of text"),
+            ParseErrorKind::UnterminatedArray => write!(f, "Unterminated array"),
+            ParseErrorKind::UnterminatedInlineTable => write!(f, "Unterminated inline table"),
+            ParseErrorKind::UnterminatedString => write!(f, "Unterminated string"),
+            ParseErrorKind::InvalidEscape => write!(f, "Invalid escape sequence"),
+            ParseErrorKind::DottedKey => write!(f, "Dotted keys are not supported"),
+            ParseErrorKind::DuplicateKey(key) => write!(f, "Duplicate key {:?}", key),
+        }?;
+        write!(f, " at byte {}.", self.offset)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// The sapling representation of the AST for a subset of TOML
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
+pub enum TOML<'arena> {
+    /// The TOML value `true`
+    True,
+    /// The TOML value `false`
+    False,
+    /// A TOML string, which is always written as a basic (double-quoted) string
+    Str(String),
+    /// A TOML array of multiple values.  Corresponds to a string `[<v1>, <v2>, ...]`.
+    Array(Vec<&'arena TOML<'arena>>),
+    /// The root table of a TOML document, written as one `key = value` pair per line.  The `Ref`s
+    /// contained inside this must be [`Pair`](TOML::Pair)s.  This can only be the root of a tree.
+    Table(Vec<&'arena TOML<'arena>>),
+    /// A table nested inside another value, written as `{ <key1> = <v1>, <key2> = <v2>, ... }`.
+    /// The `Ref`s contained inside this must be [`Pair`](TOML::Pair)s.
+    InlineTable(Vec<&'arena TOML<'arena>>),
+    /// A `key = value` pair in a table.  The first `Ref` must be a [`Key`](TOML::Key), and the
+    /// second is any TOML value.
+    Pair([&'arena TOML<'arena>; 2]),
+    /// The key of a pair.  This is written without quotes if it's a valid bare key.
+    Key(String),
+}
+
+impl TOML<'_> {
+    /// Return an iterator over all the possible chars that could represent TOML values
+    fn all_value_chars() -> Box<dyn Iterator<Item = char>> {
+        Box::new(
+            [CHAR_TRUE, CHAR_FALSE, CHAR_STRING, CHAR_ARRAY, CHAR_TABLE]
+                .iter()
+                .copied(),
+        )
+    }
+
+    /// Returns `true` if this node can be the value of a pair or an element of an array
+    fn is_value(&self) -> bool {
+        !matches!(self, TOML::Table(_) | TOML::Pair(_) | TOML::Key(_))
+    }
+}
+
+/// Returns `true` if `key` can be written without quotes
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Write a string as a TOML basic string, including the quotes and any escape sequences
+fn quote_string(string: &str) -> String {
+    let mut quoted = String::with_capacity(string.len() + 2);
+    quoted.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04X}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl std::fmt::Display for TOML<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_tokens(self, f, &TOMLFormat)
+    }
+}
+
+impl Default for TOML<'_> {
+    fn default() -> TOML<'static> {
+        TOML::Table(vec![])
+    }
+}
+
+impl<'arena> Ast<'arena> for TOML<'arena> {
+    type FormatStyle = TOMLFormat;
+    type InsertError = InsertError;
+    type ParseError = ParseError;
+
+    /* FORMATTING FUNCTIONS */
+
+    fn display_tokens_rec(&self, _format_style: &Self::FormatStyle) -> Vec<RecTok<'arena, Self>> {
+        match self {
+            TOML::True => vec![RecTok::Tok(DisplayToken::Text("true".to_string()))],
+            TOML::False => vec![RecTok::Tok(DisplayToken::Text("false".to_string()))],
+            TOML::Str(string) => vec![RecTok::Tok(DisplayToken::Text(quote_string(string)))],
+            TOML::Key(_) => vec![RecTok::Tok(DisplayToken::Text(self.display_name()))],
+            TOML::Pair([key, value]) => vec![
+                RecTok::Child(*key),
+                RecTok::Tok(DisplayToken::Text(" = ".to_string())),
+                RecTok::Child(*value),
+            ],
+            TOML::Table(pairs) => {
+                let mut tokens: Vec<RecTok<'_, Self>> = Vec::with_capacity(2 * pairs.len());
+                // Put every pair on its own line
+                for (i, p) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        tokens.push(RecTok::Tok(DisplayToken::Newline));
+                    }
+                    tokens.push(RecTok::Child(*p));
+                }
+                tokens
+            }
+            TOML::Array(children) | TOML::InlineTable(children) => {
+                let (open, close) = match self {
+                    TOML::Array(_) => ("[", "]"),
+                    _ => ("{", "}"),
+                };
+                // Special case: if this container is empty, render it as '[]' or '{}'
+                if children.is_empty() {
+                    return vec![RecTok::Tok(DisplayToken::Text(format!(
+                        "{}{}",
+                        open, close
+                    )))];
+                }
+
+                let mut tokens: Vec<RecTok<'_, Self>> = Vec::with_capacity(4 + 3 * children.len());
+                tokens.push(RecTok::Tok(DisplayToken::Text(open.to_string())));
+                // Inline tables have spaces inside the braces, like `{ a = true }`
+                if let TOML::InlineTable(_) = self {
+                    tokens.push(RecTok::Tok(DisplayToken::Whitespace(1)));
+                }
+                // Push the children, delimited by commas
+                for (i, c) in children.iter().enumerate() {
+                    if i > 0 {
+                        tokens.push(RecTok::Tok(DisplayToken::Text(",".to_string())));
+                        tokens.push(RecTok::Tok(DisplayToken::Whitespace(1)));
+                    }
+                    tokens.push(RecTok::Child(*c));
+                }
+                if let TOML::InlineTable(_) = self {
+                    tokens.push(RecTok::Tok(DisplayToken::Whitespace(1)));
+                }
+                tokens.push(RecTok::Tok(DisplayToken::Text(close.to_string())));
+                tokens
+            }
+        }
+    }
+
+    fn size(&self, format_style: &Self::FormatStyle) -> Size {
+        // TOML trees are usually small, so we just measure the text rather than calculating the
+        // size directly like the JSON implementation does
+        Size::from(self.to_text(format_style).as_str())
+    }
+
+    /* PARSING FUNCTIONS */
+
+    fn from_text(
+        text: &str,
+        arena: &'arena Arena<TOML<'arena>>,
+    ) -> Result<&'arena TOML<'arena>, ParseError> {
+        let mut parser = Parser {
+            text,
+            offset: 0,
+            arena,
+        };
+        parser.parse_document()
+    }
+
+    /* DEBUG VIEW FUNCTIONS */
+
+    fn children<'s>(&'s self) -> &'s [&'arena TOML<'arena>] {
+        match self {
+            TOML::True | TOML::False | TOML::Str(_) | TOML::Key(_) => &[],
+            TOML::Array(children) | TOML::Table(children) | TOML::InlineTable(children) => children,
+            TOML::Pair(key_value) => &key_value[..],
+        }
+    }
+
+    fn children_mut<'s>(&'s mut self) -> &'s mut [&'arena TOML<'arena>] {
+        match self {
+            TOML::True | TOML::False | TOML::Str(_) | TOML::Key(_) => &mut [],
+            TOML::Array(children) | TOML::Table(children) | TOML::InlineTable(children) => children,
+            TOML::Pair(key_value) => &mut key_value[..],
+        }
+    }
+
+    fn insert_child(&mut self, new_node: &'arena Self, index: usize) -> Result<(), InsertError> {
+        let is_valid_child = match self {
+            TOML::True | TOML::False | TOML::Str(_) | TOML::Key(_) => {
+                return Err(InsertError::NoPossibleChildren(self.display_name()));
+            }
+            TOML::Pair(_) => return Err(InsertError::FixedChildCount(self.display_name(), 2)),
+            TOML::Table(_) | TOML::InlineTable(_) => matches!(new_node, TOML::Pair(_)),
+            TOML::Array(_) => new_node.is_value(),
+        };
+        if !is_valid_child {
+            return Err(InsertError::InvalidChild(
+                self.display_name(),
+                new_node.display_name(),
+            ));
+        }
+        match self {
+            TOML::Array(children) | TOML::Table(children) | TOML::InlineTable(children) => {
+                children.insert(index, new_node);
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    fn remove_child(&mut self, index: usize) -> Result<(), InsertError> {
+        match self {
+            TOML::True | TOML::False | TOML::Str(_) | TOML::Key(_) => {
+                Err(InsertError::NoPossibleChildren(self.display_name()))
+            }
+            TOML::Pair(_) => Err(InsertError::FixedChildCount(self.display_name(), 2)),
+            TOML::Array(children) | TOML::Table(children) | TOML::InlineTable(children) => {
+                children.remove(index);
+                Ok(())
+            }
+        }
+    }
+
+    fn display_name(&self) -> String {
+        match self {
+            TOML::True => "true".to_string(),
+            TOML::False => "false".to_string(),
+            TOML::Str(content) => quote_string(content),
+            TOML::Array(_) => "array".to_string(),
+            TOML::Table(_) => "table".to_string(),
+            TOML::InlineTable(_) => "inline table".to_string(),
+            TOML::Pair(_) => "pair".to_string(),
+            TOML::Key(key) if is_bare_key(key) => key.clone(),
+            TOML::Key(key) => quote_string(key),
+        }
+    }
+
+    fn key(&self) -> Option<&str> {
+        match self {
+            TOML::Pair([TOML::Key(key), _]) => Some(key),
+            _ => None,
+        }
+    }
+
+    fn syntax_class(&self, _parent: Option<&Self>) -> SyntaxClass {
+        match self {
+            TOML::True | TOML::False => SyntaxClass::Boolean,
+            TOML::Str(_) => SyntaxClass::String,
+            TOML::Array(_) | TOML::Table(_) | TOML::InlineTable(_) => SyntaxClass::Container,
+            TOML::Pair(_) => SyntaxClass::Punctuation,
+            TOML::Key(_) => SyntaxClass::Key,
+        }
+    }
+
+    /* AST EDITING FUNCTIONS */
+
+    fn replace_seqs(&self) -> Box<dyn Iterator<Item = String>> {
+        if self.is_value() {
+            Box::new(Self::all_value_chars().map(String::from))
+        } else {
+            // Keys, pairs and the root table can only be replaced with nodes of the same type
+            Box::new(std::iter::empty())
+        }
+    }
+
+    fn node_for_char(&self, c: char) -> Option<Self> {
+        match c {
+            CHAR_TRUE => Some(TOML::True),
+            CHAR_FALSE => Some(TOML::False),
+            CHAR_STRING => Some(TOML::Str("".to_string())),
+            CHAR_ARRAY => Some(TOML::Array(vec![])),
+            CHAR_TABLE => Some(TOML::InlineTable(vec![])),
+            _ => None,
+        }
+    }
+
+    fn insert_chars(&self) -> Box<dyn Iterator<Item = char>> {
+        match self {
+            TOML::True | TOML::False | TOML::Str(_) | TOML::Key(_) | TOML::Pair(_) => {
+                Box::new(std::iter::empty())
+            }
+            TOML::Table(_) | TOML::InlineTable(_) => Box::new(std::iter::once(CHAR_PAIR)),
+            TOML::Array(_) => Self::all_value_chars(),
+        }
+    }
+
+    fn new_keyed_child(&self, key: String, arena: &'arena Arena<Self>) -> Option<Self> {
+        match self {
+            TOML::Table(_) | TOML::InlineTable(_) => Some(TOML::Pair([
+                arena.alloc(TOML::Key(key)),
+                arena.alloc(TOML::InlineTable(vec![])),
+            ])),
+            _ => None,
+        }
+    }
+
+    fn with_key(&self, key: String, arena: &'arena Arena<Self>) -> Option<Self> {
+        match self {
+            TOML::Pair([_, value]) => Some(TOML::Pair([arena.alloc(TOML::Key(key)), value])),
+            _ => None,
+        }
+    }
+}
+
+/// A simple recursive-descent parser which reads TOML text into nodes in an [`Arena`].  Tables
+/// declared with `[header]`s are read into inline tables in the root table.
+struct Parser<'t, 'arena> {
+    /// The full text being parsed
+    text: &'t str,
+    /// The byte offset of the next unread char in `text`
+    offset: usize,
+    /// The arena that new nodes are added to
+    arena: &'arena Arena<TOML<'arena>>,
+}
+
+impl<'t, 'arena> Parser<'t, 'arena> {
+    /// Build a [`ParseError`] of a given kind at the current location
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            offset: self.offset,
+            kind,
+        }
+    }
+
+    /// Build a [`ParseError`] for whatever char is next (or the end of the text)
+    fn unexpected(&self) -> ParseError {
+        match self.peek() {
+            Some(c) => self.error(ParseErrorKind::UnexpectedChar(c)),
+            None => self.error(ParseErrorKind::UnexpectedEnd),
+        }
+    }
+
+    /// Returns the next unread char, without consuming it
+    fn peek(&self) -> Option<char> {
+        self.text[self.offset..].chars().next()
+    }
+
+    /// Consumes and returns the next unread char
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.offset += c.len_utf8();
+        Some(c)
+    }
+
+    /// Consume a given char, returning an error if the next char is something else
+    fn expect_char(&mut self, expected: char) -> Result<(), ParseError> {
+        if self.peek() == Some(expected) {
+            self.offset += expected.len_utf8();
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    /// Consume spaces and tabs, but not newlines
+    fn skip_inline_whitespace(&mut self) {
+        while let Some(' ') | Some('\t') = self.peek() {
+            self.offset += 1;
+        }
+    }
+
+    /// Consume whitespace, newlines and comments
+    fn skip_whitespace_and_comments(&mut self) {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => self.offset += c.len_utf8(),
+                Some('#') => self.skip_comment(),
+                _ => break,
+            }
+        }
+    }
+
+    /// If the next char starts a comment, consume it up to (but not including) the newline
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            let rest = &self.text[self.offset..];
+            self.offset += rest.find('\n').unwrap_or(rest.len());
+        }
+    }
+
+    /// Consume the end of a line, including any trailing whitespace or comment
+    fn expect_line_end(&mut self) -> Result<(), ParseError> {
+        self.skip_inline_whitespace();
+        self.skip_comment();
+        match self.peek() {
+            None => Ok(()),
+            Some('\n') => {
+                self.offset += 1;
+                Ok(())
+            }
+            Some('\r') if self.text[self.offset..].starts_with("\r\n") => {
+                self.offset += 2;
+                Ok(())
+            }
+            Some(c) => Err(self.error(ParseErrorKind::UnexpectedChar(c))),
+        }
+    }
+
+    /// Add a pair to a list of pairs, returning an error if the pair's key has already been used
+    fn push_pair(
+        &self,
+        pairs: &mut Vec<&'arena TOML<'arena>>,
+        pair: &'arena TOML<'arena>,
+        offset: usize,
+    ) -> Result<(), ParseError> {
+        if pairs.iter().any(|p| p.key() == pair.key()) {
+            // We can unwrap, because the parser only creates pairs with keys
+            let key = pair.key().unwrap().to_string();
+            return Err(ParseError {
+                offset,
+                kind: ParseErrorKind::DuplicateKey(key),
+            });
+        }
+        pairs.push(pair);
+        Ok(())
+    }
+
+    /// Parse a whole TOML document into a [`Table`](TOML::Table)
+    fn parse_document(&mut self) -> Result<&'arena TOML<'arena>, ParseError> {
+        let mut root_pairs: Vec<&'arena TOML<'arena>> = Vec::new();
+        // The header of the table currently being read (if we're not in the root table), along
+        // with the byte offset of the header and the pairs read into that table so far
+        let mut section: Option<(String, usize, Vec<&'arena TOML<'arena>>)> = None;
+
+        loop {
+            self.skip_whitespace_and_comments();
+            let line_offset = self.offset;
+            match self.peek() {
+                None => break,
+                Some('[') => {
+                    // Finish the previous table, then start a new one
+                    if let Some((key, offset, pairs)) = section.take() {
+                        let pair = self.section_pair(key, pairs);
+                        self.push_pair(&mut root_pairs, pair, offset)?;
+                    }
+                    self.offset += 1;
+                    self.skip_inline_whitespace();
+                    let key = self.parse_key()?;
+                    self.expect_char(']')?;
+                    self.expect_line_end()?;
+                    section = Some((key, line_offset, Vec::new()));
+                }
+                Some(_) => {
+                    let pair = self.parse_pair()?;
+                    self.expect_line_end()?;
+                    let pairs = match &mut section {
+                        Some((_, _, pairs)) => pairs,
+                        None => &mut root_pairs,
+                    };
+                    self.push_pair(pairs, pair, line_offset)?;
+                }
+            }
+        }
+        // Finish the last table
+        if let Some((key, offset, pairs)) = section.take() {
+            let pair = self.section_pair(key, pairs);
+            self.push_pair(&mut root_pairs, pair, offset)?;
+        }
+        Ok(self.arena.alloc(TOML::Table(root_pairs)))
+    }
+
+    /// Create the pair that a table declared with a `[header]` is stored as
+    fn section_pair(&self, key: String, pairs: Vec<&'arena TOML<'arena>>) -> &'arena TOML<'arena> {
+        self.arena.alloc(TOML::Pair([
+            self.arena.alloc(TOML::Key(key)),
+            self.arena.alloc(TOML::InlineTable(pairs)),
+        ]))
+    }
+
+    /// Parse a key (either bare or quoted), and any whitespace after it
+    fn parse_key(&mut self) -> Result<String, ParseError> {
+        let key = if self.peek() == Some('"') {
+            self.parse_string()?
+        } else {
+            let rest = &self.text[self.offset..];
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+                .unwrap_or(rest.len());
+            if len == 0 {
+                return Err(self.unexpected());
+            }
+            self.offset += len;
+            rest[..len].to_string()
+        };
+        self.skip_inline_whitespace();
+        if self.peek() == Some('.') {
+            return Err(self.error(ParseErrorKind::DottedKey));
+        }
+        Ok(key)
+    }
+
+    /// Parse a `key = value` pair
+    fn parse_pair(&mut self) -> Result<&'arena TOML<'arena>, ParseError> {
+        let key = self.parse_key()?;
+        self.expect_char('=')?;
+        self.skip_inline_whitespace();
+        let value = self.parse_value()?;
+        Ok(self
+            .arena
+            .alloc(TOML::Pair([self.arena.alloc(TOML::Key(key)), value])))
+    }
+
+    /// Consume a keyword (such as `true`), assuming that the first char has already been checked
+    fn parse_keyword(
+        &mut self,
+        keyword: &str,
+        node: TOML<'arena>,
+    ) -> Result<&'arena TOML<'arena>, ParseError> {
+        for expected_char in keyword.chars() {
+            self.expect_char(expected_char)?;
+        }
+        Ok(self.arena.alloc(node))
+    }
+
+    /// Parse any TOML value
+    fn parse_value(&mut self) -> Result<&'arena TOML<'arena>, ParseError> {
+        match self.peek() {
+            Some('t') => self.parse_keyword("true", TOML::True),
+            Some('f') => self.parse_keyword("false", TOML::False),
+            Some('"') => {
+                let string = self.parse_string()?;
+                Ok(self.arena.alloc(TOML::Str(string)))
+            }
+            Some('[') => self.parse_array(),
+            Some('{') => self.parse_inline_table(),
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// Parse a basic string (including the quotes), returning the unescaped contents
+    fn parse_string(&mut self) -> Result<String, ParseError> {
+        let start_offset = self.offset;
+        // Consume the opening '"'
+        debug_assert_eq!(self.peek(), Some('"'));
+        self.offset += 1;
+
+        let mut string = String::new();
+        loop {
+            match self.next_char() {
+                Some('"') => return Ok(string),
+                Some('\\') => {
+                    let escaped_char = match self.next_char() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.parse_unicode_escape(4)?,
+                        Some('U') => self.parse_unicode_escape(8)?,
+                        Some(_) => return Err(self.error(ParseErrorKind::InvalidEscape)),
+                        None => break,
+                    };
+                    string.push(escaped_char);
+                }
+                // Basic strings can't span multiple lines
+                Some('\n') | None => break,
+                Some(c) => string.push(c),
+            }
+        }
+        Err(ParseError {
+            offset: start_offset,
+            kind: ParseErrorKind::UnterminatedString,
+        })
+    }
+
+    /// Parse the `num_digits` hex digits after a `\u` or `\U` escape
+    fn parse_unicode_escape(&mut self, num_digits: usize) -> Result<char, ParseError> {
+        let hex_digits = self
+            .text
+            .get(self.offset..self.offset + num_digits)
+            .ok_or_else(|| self.error(ParseErrorKind::InvalidEscape))?;
+        let c = u32::from_str_radix(hex_digits, 16)
+            .ok()
+            .and_then(std::char::from_u32)
+            .ok_or_else(|| self.error(ParseErrorKind::InvalidEscape))?;
+        self.offset += num_digits;
+        Ok(c)
+    }
+
+    /// Parse an array, which can span multiple lines and have a trailing comma
+    fn parse_array(&mut self) -> Result<&'arena TOML<'arena>, ParseError> {
+        let start_offset = self.offset;
+        // Consume the opening '['
+        self.offset += 1;
+        let mut children = Vec::new();
+        loop {
+            self.skip_whitespace_and_comments();
+            if self.peek() == Some(']') {
+                self.offset += 1;
+                break;
+            }
+            children.push(self.parse_value()?);
+            self.skip_whitespace_and_comments();
+            match self.next_char() {
+                Some(',') => {}
+                Some(']') => break,
+                Some(c) => {
+                    self.offset -= c.len_utf8();
+                    return Err(self.error(ParseErrorKind::UnexpectedChar(c)));
+                }
+                None => {
+                    return Err(ParseError {
+                        offset: start_offset,
+                        kind: ParseErrorKind::UnterminatedArray,
+                    })
+                }
+            }
+        }
+        Ok(self.arena.alloc(TOML::Array(children)))
+    }
+
+    /// Parse an inline table, which must be on one line
+    fn parse_inline_table(&mut self) -> Result<&'arena TOML<'arena>, ParseError> {
+        let start_offset = self.offset;
+        // Consume the opening '{'
+        self.offset += 1;
+        self.skip_inline_whitespace();
+        let mut pairs = Vec::new();
+        if self.peek() == Some('}') {
+            self.offset += 1;
+            return Ok(self.arena.alloc(TOML::InlineTable(pairs)));
+        }
+        loop {
+            self.skip_inline_whitespace();
+            let pair_offset = self.offset;
+            let pair = self.parse_pair()?;
+            self.push_pair(&mut pairs, pair, pair_offset)?;
+            self.skip_inline_whitespace();
+            match self.peek() {
+                Some(',') => self.offset += 1,
+                Some('}') => {
+                    self.offset += 1;
+                    break;
+                }
+                Some('\n') | None => {
+                    return Err(ParseError {
+                        offset: start_offset,
+                        kind: ParseErrorKind::UnterminatedInlineTable,
+                    })
+                }
+                Some(c) => return Err(self.error(ParseErrorKind::UnexpectedChar(c))),
+            }
+        }
+        Ok(self.arena.alloc(TOML::InlineTable(pairs)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ParseError, ParseErrorKind, TOMLFormat, TOML};
+    use crate::arena::Arena;
+    use crate::ast::Ast;
+    use crate::editable_tree::{Direction, DAG};
+
+    #[test]
+    fn to_text() {
+        let arena = Arena::new();
+        let key = |k: &str| arena.alloc(TOML::Key(k.to_string())) as &TOML;
+        let pair = |k: &str, v: TOML<'static>| arena.alloc(TOML::Pair([key(k), arena.alloc(v)]));
+        let tab = arena.alloc(TOML::Str("a\t\"b\"".to_string()));
+        let inner = arena.alloc(TOML::InlineTable(vec![
+            pair("x", TOML::True),
+            pair("y", TOML::Array(vec![])),
+        ]));
+        let root = TOML::Table(vec![
+            pair("enabled", TOML::False),
+            arena.alloc(TOML::Pair([key("needs quotes"), tab])),
+            arena.alloc(TOML::Pair([
+                key("list"),
+                arena.alloc(TOML::Array(vec![
+                    inner,
+                    arena.alloc(TOML::InlineTable(vec![])),
+                ])),
+            ])),
+        ]);
+        assert_eq!(
+            root.to_text(&TOMLFormat),
+            r#"enabled = false
+"needs quotes" = "a\t\"b\""
+list = [{ x = true, y = [] }, {}]"#
+        );
+        assert_eq!(TOML::default().to_text(&TOMLFormat), "");
+    }
+
+    #[test]
+    fn from_text() {
+        let arena = Arena::new();
+        let root = TOML::from_text(
+            r#"
+# A comment
+title = "Sapling"  # A trailing comment
+features = [
+    "editing",
+    "undo", # Comments in arrays
+]
+
+[owner]
+name = "kneasle"
+"active?" = true
+point = { x = false, "y" = [true] }
+"#,
+            &arena,
+        )
+        .unwrap();
+        assert_eq!(
+            root.to_text(&TOMLFormat),
+            r#"title = "Sapling"
+features = ["editing", "undo"]
+owner = { name = "kneasle", "active?" = true, point = { x = false, y = [true] } }"#
+        );
+        // Writing the tree and reading it back should give an identical tree
+        let text = root.to_text(&TOMLFormat);
+        assert_eq!(TOML::from_text(&text, &arena).unwrap(), root);
+    }
+
+    #[test]
+    fn from_text_errors() {
+        let arena = Arena::new();
+        for (text, offset, kind) in &[
+            ("a = ", 4, ParseErrorKind::UnexpectedEnd),
+            ("a = 3", 4, ParseErrorKind::UnexpectedChar('3')),
+            ("a = true b = false", 9, ParseErrorKind::UnexpectedChar('b')),
+            ("a = [true", 4, ParseErrorKind::UnterminatedArray),
+            (
+                "a = { b = true\n}",
+                4,
+                ParseErrorKind::UnterminatedInlineTable,
+            ),
+            ("a = \"foo\nbar\"", 4, ParseErrorKind::UnterminatedString),
+            ("a = \"\\q\"", 7, ParseErrorKind::InvalidEscape),
+            ("a.b = true", 1, ParseErrorKind::DottedKey),
+            (
+                "a = true\na = false",
+                9,
+                ParseErrorKind::DuplicateKey("a".to_string()),
+            ),
+            ("[a]\n[a]", 4, ParseErrorKind::DuplicateKey("a".to_string())),
+        ] {
+            assert_eq!(
+                TOML::from_text(text, &arena),
+                Err(ParseError {
+                    offset: *offset,
+                    kind: kind.clone()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn edit() {
+        let arena = Arena::new();
+        let root = TOML::from_text("a = true\nb = []", &arena).unwrap();
+        let mut tree = DAG::new(&arena, root);
+        // The root table can't be replaced, and can only contain pairs
+        assert!(!tree.cursor().is_replace_seq("t"));
+        assert!(tree.insert_child(TOML::True).is_err());
+        tree.insert_keyed_child("c".to_string()).unwrap();
+        assert_eq!(tree.to_text(&TOMLFormat), "a = true\nb = []\nc = {}");
+        // Values can be replaced, and added to arrays
+        tree.move_cursor(Direction::Down);
+        tree.move_cursor(Direction::Next);
+        tree.replace_cursor(TOML::Str("new".to_string()));
+        tree.move_cursor(Direction::Up);
+        tree.move_cursor(Direction::Prev);
+        tree.move_cursor(Direction::Down);
+        tree.move_cursor(Direction::Next);
+        tree.insert_child(TOML::False).unwrap();
+        assert_eq!(
+            tree.to_text(&TOMLFormat),
+            "a = true\nb = [false]\nc = \"new\""
+        );
+        // Tables and pairs can't be put into arrays
+        tree.move_cursor(Direction::Up);
+        assert!(tree.insert_child(TOML::Table(vec![])).is_err());
+        assert!(tree.insert_keyed_child("d".to_string()).is_err());
+        // Keys can be renamed
+        tree.move_cursor(Direction::Up);
+        tree.rename_cursor("renamed key".to_string()).unwrap();
+        assert_eq!(
+            tree.to_text(&TOMLFormat),
+            "a = true\n\"renamed key\" = [false]\nc = \"new\""
+        );
+    }
+}
@@ -0,0 +1,285 @@
+//! A tree-shaped undo history, where undoing some changes and then making a new change creates a
+//! new branch instead of throwing away the changes that were undone.
+
+/// One state in an [`UndoTree`]
+#[derive(Debug, Clone)]
+struct State<T> {
+    /// The value stored in this state
+    value: T,
+    /// The index of the state that this state was created from, or [`None`] for the oldest state
+    parent: Option<usize>,
+    /// The indices of the states that were created from this state, oldest first
+    children: Vec<usize>,
+    /// The index into `children` of the branch that [`redo`](UndoTree::redo) moves to.  This is
+    /// the branch that was most recently created or visited.
+    redo_branch: usize,
+}
+
+/// A tree of every state that some value has been in, like Vim's undo tree.  Every state is
+/// created from the state which was current at the time, so undoing and then making a new change
+/// starts a new branch.  States are numbered in the order they were created, so the history can
+/// also be walked in time order with [`earlier`](UndoTree::earlier) and
+/// [`later`](UndoTree::later), which can move between branches.
+#[derive(Debug, Clone)]
+pub struct UndoTree<T> {
+    /// Every state, in the order they were created.  This always contains at least one state.
+    states: Vec<State<T>>,
+    /// The index of the current state
+    current: usize,
+}
+
+impl<T> UndoTree<T> {
+    /// Creates a history which contains only one state
+    pub fn new(value: T) -> Self {
+        UndoTree {
+            states: vec![State {
+                value,
+                parent: None,
+                children: Vec::new(),
+                redo_branch: 0,
+            }],
+            current: 0,
+        }
+    }
+
+    /// Returns the value of the current state
+    pub fn current(&self) -> &T {
+        &self.states[self.current].value
+    }
+
+    /// Returns the number of the current state.  States are numbered from 0 in the order they were
+    /// created.
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+
+    /// Returns the number of states in the history
+    pub fn num_states(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Returns an iterator over the values of every state, in the order they were created
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.states.iter().map(|s| &s.value)
+    }
+
+    /// Adds a new state as a child of the current state, and makes it the current state
+    pub fn push(&mut self, value: T) {
+        let index = self.states.len();
+        let parent = &mut self.states[self.current];
+        parent.redo_branch = parent.children.len();
+        parent.children.push(index);
+        self.states.push(State {
+            value,
+            parent: Some(self.current),
+            children: Vec::new(),
+            redo_branch: 0,
+        });
+        self.current = index;
+    }
+
+    /// Replaces the value of the current state, without adding a new state
+    pub fn set_current(&mut self, value: T) {
+        self.states[self.current].value = value;
+    }
+
+    /// Returns the value of the state that the current state was created from, or [`None`] if the
+    /// current state is the oldest
+    pub fn parent(&self) -> Option<&T> {
+        let parent = self.states[self.current].parent?;
+        Some(&self.states[parent].value)
+    }
+
+    /// Removes the current state and moves to its parent, as if it had never been
+    /// [`push`](UndoTree::push)ed.  This returns [`None`] (and does nothing) unless the current
+    /// state is the newest state and has a parent.
+    pub fn pop(&mut self) -> Option<T> {
+        let parent = self.states[self.current].parent?;
+        if self.current + 1 != self.states.len() {
+            return None;
+        }
+        // The newest state can't have any children, and is the newest child of its parent
+        let parent_state = &mut self.states[parent];
+        parent_state.children.pop();
+        parent_state.redo_branch = parent_state.children.len().saturating_sub(1);
+        self.current = parent;
+        self.states.pop().map(|s| s.value)
+    }
+
+    /// Moves to the parent of the current state, returning `false` if there is no parent
+    pub fn undo(&mut self) -> bool {
+        match self.states[self.current].parent {
+            Some(parent) => self.move_to(parent),
+            None => false,
+        }
+    }
+
+    /// Moves to the child of the current state on the most recently used branch, returning `false`
+    /// if the current state has no children
+    pub fn redo(&mut self) -> bool {
+        let state = &self.states[self.current];
+        match state.children.get(state.redo_branch) {
+            Some(&child) => self.move_to(child),
+            None => false,
+        }
+    }
+
+    /// Moves to the state that was created just before the current one (like Vim's `g-`),
+    /// returning `false` if the current state is the oldest
+    pub fn earlier(&mut self) -> bool {
+        match self.current.checked_sub(1) {
+            Some(index) => self.move_to(index),
+            None => false,
+        }
+    }
+
+    /// Moves to the state that was created just after the current one (like Vim's `g+`),
+    /// returning `false` if the current state is the newest
+    pub fn later(&mut self) -> bool {
+        if self.current + 1 < self.states.len() {
+            self.move_to(self.current + 1)
+        } else {
+            false
+        }
+    }
+
+    /// Returns the numbers of the states that could be redone from the current state (one per
+    /// branch), oldest first
+    pub fn branches(&self) -> &[usize] {
+        &self.states[self.current].children
+    }
+
+    /// Returns the index into [`branches`](UndoTree::branches) of the branch that
+    /// [`redo`](UndoTree::redo) will follow, or [`None`] if there is nothing to redo
+    pub fn redo_branch(&self) -> Option<usize> {
+        let state = &self.states[self.current];
+        if state.children.is_empty() {
+            None
+        } else {
+            Some(state.redo_branch)
+        }
+    }
+
+    /// Chooses which of the [`branches`](UndoTree::branches) [`redo`](UndoTree::redo) should
+    /// follow, returning `false` if there is no such branch
+    pub fn select_branch(&mut self, branch: usize) -> bool {
+        let state = &mut self.states[self.current];
+        if branch < state.children.len() {
+            state.redo_branch = branch;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Removes every state except the current one, returning the number of states removed
+    pub fn clear(&mut self) -> usize {
+        let num_removed = self.states.len() - 1;
+        let current = self.states.swap_remove(self.current);
+        *self = UndoTree::new(current.value);
+        num_removed
+    }
+
+    /// Makes the state with a given index current.  Every state between it and the root is
+    /// updated so that redoing from the root would lead back to this state.  Always returns `true`.
+    fn move_to(&mut self, index: usize) -> bool {
+        let mut child = index;
+        while let Some(parent) = self.states[child].parent {
+            let parent_state = &mut self.states[parent];
+            // Every child is in its parent's list of children, so we can unwrap
+            parent_state.redo_branch = parent_state
+                .children
+                .iter()
+                .position(|&c| c == child)
+                .unwrap();
+            child = parent;
+        }
+        self.current = index;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UndoTree;
+
+    #[test]
+    fn branches() {
+        // Build the history:
+        // 0 - 1 - 2
+        //      \
+        //       3 - 4
+        let mut history = UndoTree::new("a");
+        history.push("ab");
+        history.push("abc");
+        assert!(history.undo());
+        history.push("abd");
+        history.push("abde");
+        assert_eq!(history.num_states(), 5);
+        // Undoing follows the current branch back to the root
+        assert!(history.undo());
+        assert!(history.undo());
+        assert_eq!((history.current_index(), *history.current()), (1, "ab"));
+        assert_eq!(history.branches(), &[2, 3]);
+        assert_eq!(history.redo_branch(), Some(1));
+        // Redoing follows the most recently used branch
+        assert!(history.redo());
+        assert_eq!(*history.current(), "abd");
+        assert!(history.undo());
+        assert!(history.select_branch(0));
+        assert!(!history.select_branch(2));
+        assert!(history.redo());
+        assert_eq!(*history.current(), "abc");
+        assert!(!history.redo());
+        assert!(history.undo() && history.undo());
+        assert!(!history.undo());
+        assert_eq!(history.branches(), &[1]);
+    }
+
+    #[test]
+    fn time_order() {
+        // 0 - 1
+        //  \
+        //   2 - 3
+        let mut history = UndoTree::new(0);
+        history.push(1);
+        history.undo();
+        history.push(2);
+        history.push(3);
+        // Moving through time jumps between branches
+        let mut visited = vec![*history.current()];
+        while history.earlier() {
+            visited.push(*history.current());
+        }
+        assert_eq!(visited, vec![3, 2, 1, 0]);
+        assert!(history.later() && history.later());
+        assert_eq!(*history.current(), 2);
+        // Jumping to a state makes redo lead back to it
+        assert!(history.undo());
+        assert!(history.redo());
+        assert_eq!(*history.current(), 2);
+        assert!(history.later());
+        assert!(!history.later());
+        // The newest state can be removed, but no other state can
+        assert_eq!(history.parent(), Some(&2));
+        assert_eq!(history.pop(), Some(3));
+        assert_eq!((history.current_index(), history.branches()), (2, &[][..]));
+        history.set_current(4);
+        assert!(history.undo());
+        assert_eq!(history.pop(), None);
+        assert_eq!(history.branches(), &[1, 2]);
+        assert!(history.redo());
+        assert_eq!(*history.current(), 4);
+        history.push(3);
+        // Clearing keeps only the current state
+        assert_eq!(history.clear(), 3);
+        assert_eq!(
+            (
+                history.num_states(),
+                history.current_index(),
+                *history.current()
+            ),
+            (1, 0, 3)
+        );
+    }
+}
@@ -1,10 +1,10 @@
 //! Specification of an editable, undoable buffer of trees and some implementations thereof.
 
-pub mod cursor_path;
+pub mod history;
 
 use crate::arena::Arena;
-use crate::ast::Ast;
-use cursor_path::CursorPath;
+use crate::ast::{cursor_path::CursorPath, Ast};
+use history::UndoTree;
 
 /// The possible ways you can move the cursor
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
@@ -22,6 +22,136 @@ pub enum Side {
     Next,
 }
 
+/// The ways that a tree can fail to survive being converted to text and back
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RoundTripError<E> {
+    /// The text generated from the tree couldn't be parsed
+    Parse(E),
+    /// The text was parsed, but into a different tree.  The [`CursorPath`] points to the first node
+    /// (in both trees) that differs.
+    Mismatch(CursorPath),
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for RoundTripError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RoundTripError::Parse(e) => write!(f, "Failed to re-parse the tree: {}", e),
+            RoundTripError::Mismatch(path) => write!(
+                f,
+                "Re-parsed tree differs at path {:?}",
+                path.iter().collect::<Vec<_>>()
+            ),
+        }
+    }
+}
+
+/// Check that a tree can be re-parsed from the `text` generated from it.  This returns an error if
+/// the text either can't be parsed or is parsed into a different tree.  Any nodes created by
+/// parsing `text` are added to `arena`.
+pub fn check_round_trip<'arena, Node: Ast<'arena>>(
+    root: &'arena Node,
+    text: &str,
+    arena: &'arena Arena<Node>,
+) -> Result<(), RoundTripError<Node::ParseError>> {
+    let parsed_root = Node::from_text(text, arena).map_err(RoundTripError::Parse)?;
+    match first_difference(root, parsed_root) {
+        Some(path) => Err(RoundTripError::Mismatch(path)),
+        None => Ok(()),
+    }
+}
+
+/// Returns the [`CursorPath`] of the first node (in pre-order) that differs between two trees, or
+/// [`None`] if the trees are identical.  Nodes are compared by their display names and how many
+/// children they have.
+pub fn first_difference<'arena, Node: Ast<'arena>>(
+    a: &'arena Node,
+    b: &'arena Node,
+) -> Option<CursorPath> {
+    if a.display_name() != b.display_name() || a.children().len() != b.children().len() {
+        return Some(CursorPath::root());
+    }
+    for (i, (child_a, child_b)) in a.children().iter().zip(b.children()).enumerate() {
+        if let Some(path) = first_difference(*child_a, *child_b) {
+            let mut indices = vec![i];
+            indices.extend(path.iter());
+            return Some(CursorPath::from_vec(indices));
+        }
+    }
+    None
+}
+
+/// Finds the node in the tree under `new_root` which corresponds to the node at `path` in the tree
+/// under `old_root` (usually an earlier version of the same tree), returning its path or [`None`]
+/// if it no longer exists.  Each node along `path` is found amongst its new siblings:
+/// - by address, since edits only copy the nodes which they change, so a node which hasn't been
+///   edited is still there even if its siblings have been inserted or removed;
+/// - otherwise by [key](Ast::key), if it has one;
+/// - otherwise by position: if the closest siblings on either side of it which can be found in
+///   those ways have exactly one node between them, then that node is probably an edited copy.
+///
+/// This works best when there are few edits between the two trees.
+pub fn relocate_path<'arena, Node: Ast<'arena>>(
+    old_root: &'arena Node,
+    new_root: &'arena Node,
+    path: &CursorPath,
+) -> Option<CursorPath> {
+    let mut old_node = old_root;
+    let mut new_node = new_root;
+    let mut new_indices = Vec::new();
+    for &index in path.iter() {
+        let old_siblings = old_node.children();
+        let new_siblings = new_node.children();
+        let old_child = *old_siblings.get(index)?;
+        // Finds the new index of the old sibling at `old_index` by its address or key
+        let find = |old_index: usize| {
+            let old_sibling = old_siblings[old_index];
+            // No node appears twice in a tree, so its address picks out exactly one sibling
+            new_siblings
+                .iter()
+                .position(|c| std::ptr::eq(*c, old_sibling))
+                .or_else(|| {
+                    let key = old_sibling.key()?;
+                    new_siblings.iter().position(|c| c.key() == Some(key))
+                })
+        };
+        let new_index = find(index).or_else(|| {
+            let start = match (0..index).rev().find_map(find) {
+                Some(prev_index) => prev_index + 1,
+                None => 0,
+            };
+            let end = (index + 1..old_siblings.len())
+                .find_map(find)
+                .unwrap_or(new_siblings.len());
+            (end == start + 1).then_some(start)
+        })?;
+        new_indices.push(new_index);
+        old_node = old_child;
+        new_node = new_siblings[new_index];
+    }
+    Some(CursorPath::from_vec(new_indices))
+}
+
+/// Returns a human-readable description of the location of the node at `path` under `root`, like
+/// `root > [2] > "value"`.  Children with a [`key`](Ast::key) are labelled with that key, the
+/// contents of keyed nodes (e.g. the key and value of a JSON field) are labelled with their
+/// [`display_name`](Ast::display_name), and all other children are labelled with their index.
+pub fn breadcrumb<'arena, Node: Ast<'arena>>(root: &'arena Node, path: &CursorPath) -> String {
+    let mut breadcrumb = "root".to_string();
+    let nodes: Vec<&'arena Node> = path.node_iter(root).collect();
+    for (parent_and_child, index) in nodes.windows(2).zip(path.iter()) {
+        let (parent, child) = (parent_and_child[0], parent_and_child[1]);
+        breadcrumb.push_str(" > ");
+        if let Some(key) = child.key() {
+            breadcrumb.push_str(&format!("{:?}", key));
+        } else if parent.key().is_some() {
+            breadcrumb.push_str(&child.display_name());
+        } else {
+            breadcrumb.push_str(&format!("[{}]", index));
+        }
+    }
+    breadcrumb
+}
+
 /// An [`EditableTree`] that stores the history as a DAG (Directed Acyclic Graph) of **immutable**
 /// nodes.
 ///
@@ -31,17 +161,21 @@ pub enum Side {
 /// and every edit is analogous to a Git rebase.
 ///
 /// Therefore, moving back through the history is as simple as reading a different root node from
-/// the `roots` vector, and following its descendants through the DAG of nodes.
+/// the history, and following its descendants through the DAG of nodes.  The history is a tree
+/// (see [`UndoTree`]), so making an edit after undoing doesn't lose the changes that were undone.
 pub struct DAG<'arena, Node: Ast<'arena>> {
     /// The arena in which all the [`Node`]s will be stored
     arena: &'arena Arena<Node>,
-    /// A [`Vec`] containing a reference to the root node at every edit in the undo history.  This
-    /// is required to always have length at least one.
-    root_history: Vec<(&'arena Node, CursorPath)>,
-    /// An index into [`root_history`](DAG::root_history) of the current edit.  This is required to
-    /// be in `0..root_history.len()`.
-    history_index: usize,
+    /// The root node at every edit in the undo history, along with the cursor path at the time of
+    /// that edit
+    root_history: UndoTree<(&'arena Node, CursorPath)>,
     current_cursor_path: CursorPath,
+    /// The number of transactions which have been started but not yet committed (see
+    /// [`DAG::begin`])
+    transaction_depth: usize,
+    /// `true` if an edit has been made during the current transaction, so that the current
+    /// snapshot belongs to the transaction and later edits replace it instead of adding new ones
+    transaction_edited: bool,
 }
 
 impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
@@ -49,9 +183,10 @@ impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
     pub fn new(arena: &'arena Arena<Node>, root: &'arena Node) -> Self {
         DAG {
             arena,
-            root_history: vec![(root, CursorPath::root())],
-            history_index: 0,
+            root_history: UndoTree::new((root, CursorPath::root())),
             current_cursor_path: CursorPath::root(),
+            transaction_depth: 0,
+            transaction_edited: false,
         }
     }
 
@@ -59,40 +194,177 @@ impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
 
     /// Move one step back in the tree history, returning `false` if there are no more changes
     pub fn undo(&mut self) -> bool {
-        if self.history_index > 0 {
-            self.history_index -= 1;
-            // Follow the behaviour of other text editors and update the location of the cursor
-            // with its location in the snapshot we are going back to
-            self.current_cursor_path
-                .clone_from(&self.root_history[self.history_index].1);
-            true
+        let moved = self.root_history.undo();
+        self.restore_cursor_path(moved)
+    }
+
+    /// Move one step forward in the tree history (following the most recently used branch),
+    /// return `false` if there was no change to be redone
+    pub fn redo(&mut self) -> bool {
+        let moved = self.root_history.redo();
+        self.restore_cursor_path(moved)
+    }
+
+    /// Move to the snapshot that was created just before the current one, even if it is on a
+    /// different branch of the history (like Vim's `g-`).  Returns `false` if the current snapshot
+    /// is the oldest.
+    pub fn earlier(&mut self) -> bool {
+        let moved = self.root_history.earlier();
+        self.restore_cursor_path(moved)
+    }
+
+    /// Move to the snapshot that was created just after the current one, even if it is on a
+    /// different branch of the history (like Vim's `g+`).  Returns `false` if the current snapshot
+    /// is the newest.
+    pub fn later(&mut self) -> bool {
+        let moved = self.root_history.later();
+        self.restore_cursor_path(moved)
+    }
+
+    /// Returns the numbers of the snapshots that can be redone from the current snapshot (one per
+    /// branch of the history), oldest first.  Snapshots are numbered in the order they were
+    /// created, starting from 0.
+    pub fn redo_branches(&self) -> &[usize] {
+        self.root_history.branches()
+    }
+
+    /// Chooses which of the [`redo_branches`](DAG::redo_branches) [`redo`](DAG::redo) follows,
+    /// returning `false` if there is no such branch
+    pub fn select_redo_branch(&mut self, branch: usize) -> bool {
+        self.root_history.select_branch(branch)
+    }
+
+    /// Returns the number of the current snapshot in the history
+    pub fn history_index(&self) -> usize {
+        self.root_history.current_index()
+    }
+
+    /// Starts a transaction: every edit until the matching [`commit`](DAG::commit) is grouped into
+    /// a single snapshot in the undo history, so that commands which make several edits can be
+    /// undone in one step.  Transactions can be nested, in which case the edits are grouped until
+    /// the outermost transaction is committed.
+    pub fn begin(&mut self) {
+        self.transaction_depth += 1;
+    }
+
+    /// Finishes the innermost transaction started by [`begin`](DAG::begin).  If this finishes
+    /// the outermost transaction and the tree is the same as it was before the transaction (e.g.
+    /// because no edits were made), no snapshot is added to the history.
+    ///
+    /// # Panics
+    /// Panics if there is no transaction to commit.
+    pub fn commit(&mut self) {
+        assert!(self.transaction_depth > 0, "No transaction to commit");
+        self.transaction_depth -= 1;
+        if self.transaction_depth > 0 {
+            return;
+        }
+        let root = self.root();
+        let is_unchanged = self
+            .root_history
+            .parent()
+            .is_some_and(|(parent_root, _)| std::ptr::eq(*parent_root, root));
+        if self.transaction_edited && is_unchanged {
+            self.root_history.pop();
+        }
+        self.transaction_edited = false;
+    }
+
+    /// Runs `edit` inside a transaction (see [`begin`](DAG::begin)), so that all of its edits can
+    /// be undone in one step.  Returns whatever `edit` returns.
+    pub fn with_transaction<T>(&mut self, edit: impl FnOnce(&mut Self) -> T) -> T {
+        self.begin();
+        let result = edit(self);
+        self.commit();
+        result
+    }
+
+    /// Adds a snapshot to the undo history, or replaces the current snapshot if it was added by
+    /// an earlier edit in the same transaction
+    fn push_snapshot(&mut self, root: &'arena Node, cursor_path: CursorPath) {
+        if self.transaction_edited {
+            self.root_history.set_current((root, cursor_path));
         } else {
-            false
+            self.root_history.push((root, cursor_path));
+            self.transaction_edited = self.transaction_depth > 0;
         }
     }
 
-    /// Move one step forward in the tree history, return `false` if there was no change to be
-    /// redone
-    pub fn redo(&mut self) -> bool {
-        if self.history_index < self.root_history.len() - 1 {
-            self.history_index += 1;
-            // Follow the behaviour of other text editors and update the location of the cursor
-            // with its location in the snapshot we are going back to
+    /// If `moved` is `true`, follows the behaviour of other text editors and moves the cursor to
+    /// its location in the snapshot we just moved to.  Returns `moved`.
+    fn restore_cursor_path(&mut self, moved: bool) -> bool {
+        if moved {
+            // The transaction's snapshot is no longer current, so any more edits need a new one
+            self.transaction_edited = false;
             self.current_cursor_path
-                .clone_from(&self.root_history[self.history_index].1);
-            true
-        } else {
-            false
+                .clone_from(&self.root_history.current().1);
+        }
+        moved
+    }
+
+    /// Forget every snapshot in the undo and redo history except the current one, returning the
+    /// number of snapshots that were removed.  The nodes which were only used by those snapshots
+    /// become unreachable, and can then be freed by [`gc`](Self::gc).
+    pub fn clear_history(&mut self) -> usize {
+        self.transaction_edited = false;
+        self.root_history.clear()
+    }
+
+    /// Replaces the whole tree with a new document (e.g. one loaded from a file).  This moves the
+    /// cursor to the new root and clears the undo history, since undoing back into a different
+    /// document wouldn't make sense.
+    pub fn load(&mut self, root: &'arena Node) {
+        self.root_history = UndoTree::new((root, CursorPath::root()));
+        self.current_cursor_path = CursorPath::root();
+        self.transaction_edited = false;
+    }
+
+    /// Returns the arena in which all the nodes of this tree are stored
+    pub fn arena(&self) -> &'arena Arena<Node> {
+        self.arena
+    }
+
+    /// Returns the number of distinct nodes which are reachable from any root in the undo history.
+    /// Any other nodes in the [`Arena`] (e.g. the trees built by
+    /// [`check_round_trip`](Self::check_round_trip)) are no longer used by this tree, and are
+    /// freed by [`gc`](Self::gc).
+    pub fn reachable_node_count(&self) -> usize {
+        let mut seen: std::collections::HashSet<*const Node> = std::collections::HashSet::new();
+        let mut stack: Vec<&'arena Node> = self.root_history.iter().map(|(r, _)| *r).collect();
+        while let Some(node) = stack.pop() {
+            // Nodes are shared between snapshots, so only visit each node once
+            if seen.insert(node as *const Node) {
+                stack.extend(node.children().iter().copied());
+            }
         }
+        seen.len()
+    }
+
+    /// Returns the number of nodes in the [`Arena`] which can't be reached from any root in the
+    /// undo history, which is how many nodes [`gc`](Self::gc) would free.  Note that they might
+    /// still be in use by something other than this tree (e.g. the editor's yank register).
+    pub fn unreachable_node_count(&self) -> usize {
+        self.arena.len() - self.reachable_node_count()
+    }
+
+    /// Frees every node in the [`Arena`] which can't be reached from any root in the undo history
+    /// (see [`Arena::gc`]), returning how many nodes were freed.  Calling
+    /// [`clear_history`](Self::clear_history) first also frees the nodes of old tree states.
+    ///
+    /// # Safety
+    ///
+    /// Any other references into the arena (e.g. nodes in the editor's yank register, or other
+    /// trees in the same arena) are left dangling, so mustn't be used after this call.
+    pub unsafe fn gc(&mut self) -> usize {
+        let roots: Vec<&'arena Node> = self.root_history.iter().map(|(root, _)| *root).collect();
+        self.arena.gc(&roots)
     }
 
     /* NAVIGATION METHODS */
 
     /// Returns a reference to the node that is currently the root of the AST.
     pub fn root(&self) -> &'arena Node {
-        // This indexing shouldn't panic because we require that `self.history_index` is a valid index
-        // into `self.root_history`, and `self.root_history` has at least one element
-        self.root_history[self.history_index].0
+        self.root_history.current().0
     }
 
     /// Returns the cursor node and its direct parent (if such a parent exists)
@@ -100,6 +372,45 @@ impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
         self.current_cursor_path.cursor_and_parent(self.root())
     }
 
+    /// Returns the path from the root to the node that is currently under the cursor.
+    pub fn cursor_path(&self) -> &CursorPath {
+        &self.current_cursor_path
+    }
+
+    /// Moves the cursor to a given path, failing if that path doesn't lead to a node in the
+    /// current tree.
+    pub fn set_cursor_path(&mut self, path: CursorPath) -> Result<(), String> {
+        if path.node_iter(self.root()).count() != path.iter().count() + 1 {
+            return Err("That node doesn't exist.".to_string());
+        }
+        self.current_cursor_path = path;
+        Ok(())
+    }
+
+    /// Returns the paths to every node in the current tree in document order, i.e. every node
+    /// comes before its descendants, and siblings are in the same order as in their parent.
+    pub fn paths_in_order(&self) -> Vec<CursorPath> {
+        let mut paths = Vec::new();
+        // A stack of the nodes still to be visited, with the next node to visit on top
+        let mut stack = vec![(self.root(), CursorPath::root())];
+        while let Some((node, path)) = stack.pop() {
+            // Push the children in reverse order, so that the first child gets popped first
+            for (index, child) in node.children().iter().enumerate().rev() {
+                let mut child_path = path.clone();
+                child_path.push(index);
+                stack.push((child, child_path));
+            }
+            paths.push(path);
+        }
+        paths
+    }
+
+    /// Returns a human-readable description of where the cursor is, like `root > [2] > "value"`
+    /// (see [`breadcrumb`])
+    pub fn breadcrumb(&self) -> String {
+        breadcrumb(self.root(), &self.current_cursor_path)
+    }
+
     /// Returns a reference to the node that is currently under the cursor.
     pub fn cursor(&self) -> &'arena Node {
         self.current_cursor_path.cursor(self.root())
@@ -108,86 +419,129 @@ impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
     /// Move the cursor in a given direction across the tree.  Returns [`Some`] error string if an
     /// error is found, or [`None`] if the movement was possible.
     pub fn move_cursor(&mut self, direction: Direction) -> Option<String> {
+        match self.moved_cursor_path(direction) {
+            Ok(path) => {
+                self.current_cursor_path = path;
+                None
+            }
+            Err(message) => Some(message),
+        }
+    }
+
+    /// Moves the cursor one step around the cycle `container -> first child -> last child ->
+    /// container`, where `container` is the path to either the cursor or the cursor's parent.  If
+    /// the cursor is on some other child of `container`, it moves to the first child.  Returns
+    /// [`Some`] error string if `container` has no children, or [`None`] if the cursor moved.
+    pub fn jump_to_boundary(&mut self, container: &CursorPath) -> Option<String> {
+        let num_children = container.cursor(self.root()).children().len();
+        if num_children == 0 {
+            return Some("Cannot jump inside a node with no children.".to_string());
+        }
+        let mut path = container.clone();
+        if self.current_cursor_path != *container {
+            debug_assert_eq!(
+                self.current_cursor_path.iter().count(),
+                path.iter().count() + 1
+            );
+            match self.current_cursor_path.iter().last() {
+                // The last child jumps back to the container (this includes only children)
+                Some(&index) if index + 1 == num_children => {}
+                Some(0) => path.push(num_children - 1),
+                _ => path.push(0),
+            }
+        } else {
+            path.push(0);
+        }
+        self.current_cursor_path = path;
+        None
+    }
+
+    /// Moves the cursor like Vim's `%`: from a node with children to its last descendant (found
+    /// by repeatedly moving to the last child), and from a node without children to its parent.
+    /// Does nothing if the cursor is a root with no children.
+    pub fn jump_to_match(&mut self) {
+        let mut node = self.cursor();
+        if node.children().is_empty() {
+            self.current_cursor_path.pop();
+            return;
+        }
+        while let Some(last_child) = node.children().last() {
+            self.current_cursor_path.push(node.children().len() - 1);
+            node = last_child;
+        }
+    }
+
+    /// Returns the node that the cursor would be on if it were moved in a given direction, or
+    /// [`None`] if the cursor can't move that way (e.g. moving down from a node with no
+    /// children).  This doesn't move the cursor.
+    pub fn cursor_neighbour(&self, direction: Direction) -> Option<&'arena Node> {
+        let path = self.moved_cursor_path(direction).ok()?;
+        path.node_iter(self.root()).last()
+    }
+
+    /// Returns the path that the cursor would have if it were moved in a given direction, or an
+    /// error message explaining why that movement isn't possible.
+    fn moved_cursor_path(&self, direction: Direction) -> Result<CursorPath, String> {
         let (current_cursor, cursor_parent) = self.cursor_and_parent();
+        let mut path = self.current_cursor_path.clone();
         match direction {
             Direction::Down => {
                 if current_cursor.children().is_empty() {
-                    Some("Cannot move down the tree if the cursor has no children.".to_string())
-                } else {
-                    self.current_cursor_path.push(0);
-                    None
+                    return Err(
+                        "Cannot move down the tree if the cursor has no children.".to_string()
+                    );
                 }
+                path.push(0);
             }
             Direction::Up => {
-                if self.current_cursor_path.is_root() {
-                    return Some("Cannot move to the parent of the root.".to_string());
-                }
-                self.current_cursor_path.pop();
-                None
-            }
-            Direction::Prev => {
-                if let Some(index) = self.current_cursor_path.last_mut() {
-                    if *index == 0 {
-                        Some("Cannot move before the first child of a node.".to_string())
-                    } else {
-                        *index -= 1;
-                        None
-                    }
-                } else {
-                    Some("Cannot move to a sibling of the root.".to_string())
-                }
-            }
-            Direction::Next => {
-                if let Some(last_index) = self.current_cursor_path.last_mut() {
-                    // We can unwrap here, because the only way for a node to not have a parent is
-                    // if it's the root.  And if the cursor is at the root, then the `if let` would
-                    // have failed and this code would not be run.
-                    if *last_index + 1 < cursor_parent.unwrap().children().len() {
-                        *last_index += 1;
-                        None
-                    } else {
-                        Some("Cannot move past the last sibling of a node.".to_string())
-                    }
-                } else {
-                    Some("Cannot move to a sibling of the root.".to_string())
+                if path.pop().is_none() {
+                    return Err("Cannot move to the parent of the root.".to_string());
                 }
             }
+            Direction::Prev => match path.last_mut() {
+                Some(0) => return Err("Cannot move before the first child of a node.".to_string()),
+                Some(index) => *index -= 1,
+                None => return Err("Cannot move to a sibling of the root.".to_string()),
+            },
+            Direction::Next => match path.last_mut() {
+                // We can unwrap here, because the only way for a node to not have a parent is if
+                // it's the root, in which case the path would be empty.
+                Some(index) if *index + 1 < cursor_parent.unwrap().children().len() => *index += 1,
+                Some(_) => return Err("Cannot move past the last sibling of a node.".to_string()),
+                None => return Err("Cannot move to a sibling of the root.".to_string()),
+            },
         }
+        Ok(path)
     }
 
     /* EDITING FUNCTIONS */
 
-    /// Utility function to finish an edit.  This handles removing any redo history, and cloning
-    /// the nodes that are parents of the node that changed.
+    /// Utility function to finish an edit.  This handles cloning the nodes that are parents of the
+    /// node that changed, and adding the new tree to the history.
     pub fn finish_edit(&mut self, nodes_to_clone: &[&'arena Node], new_node: Node) {
-        // Remove future trees from the history vector so that the currently 'checked-out' tree is
-        // the most recent tree in the history.
-        while self.history_index < self.root_history.len() - 1 {
-            // TODO: Deallocate the tree so that we don't get a 'memory leak'
-            self.root_history.pop();
-        }
         // Because AST nodes are immutable, we make changes to nodes by entirely cloning the path
         // down to the node under the cursor.  We do this starting at the node under the cursor and
         // work our way up parent by parent until we reach the root of the tree.  At that point,
         // this node becomes the root of the new tree.
         let mut node = self.arena.alloc(new_node);
         // Iterate backwards over the child indices and the nodes, whilst cloning the tree and
-        // replacing the correct child reference to point to the newly created node.
-        for (n, child_index) in nodes_to_clone
-            .iter()
-            .rev()
-            .zip(self.current_cursor_path.iter().rev())
-        {
+        // replacing the correct child reference to point to the newly created node.  The nth node
+        // in `nodes_to_clone` is the nth node along the cursor path, so its child that needs
+        // replacing is given by the nth child index of the cursor path.
+        for (n, child_index) in nodes_to_clone.iter().rev().zip(
+            self.current_cursor_path
+                .iter()
+                .take(nodes_to_clone.len())
+                .rev(),
+        ) {
             let mut cloned_node = (*n).clone();
             cloned_node.children_mut()[*child_index] = node;
             node = self.arena.alloc(cloned_node);
         }
         // At this point, `node` contains a reference to the root of the new tree, so we just add
-        // this to the history, along with the cursor path.
-        self.root_history
-            .push((node, self.current_cursor_path.clone()));
-        // Move the history index on by one so that we are pointing at the latest change
-        self.history_index = self.root_history.len() - 1;
+        // this to the history, along with the cursor path.  If some changes have been undone, this
+        // starts a new branch of the history rather than discarding them.
+        self.push_snapshot(node, self.current_cursor_path.clone());
     }
 
     /// Updates the internal state so that the tree now contains `new_node` in the position of the
@@ -203,26 +557,236 @@ impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
         self.finish_edit(&nodes_to_clone, new_node);
     }
 
-    /// Updates the internal state so that the tree now contains `new_node` inserted as the first
+    /// Regenerates the tree by rendering it as text in a given format style and parsing that text
+    /// again (like a 'format document' command), so that the tree is exactly what would be read
+    /// back from a file written in that style.  If the parsed tree is different, it replaces the
+    /// current tree as a new edit, and the cursor is moved to the deepest node along its old path
+    /// which still exists.  If the text can't be parsed, the tree is left untouched.  Returns
+    /// `true` if the tree was replaced.
+    pub fn reflow(&mut self, format_style: &Node::FormatStyle) -> Result<bool, Node::ParseError> {
+        let text = self.root().to_text(format_style);
+        let new_root = Node::from_text(&text, self.arena)?;
+        if first_difference(self.root(), new_root).is_none() {
+            return Ok(false);
+        }
+        self.replace_root(new_root);
+        Ok(true)
+    }
+
+    /// Removes every empty container (see [`Ast::is_empty_container`]) from the tree as one edit,
+    /// returning how many nodes were removed from their parents.  Removing a container can leave
+    /// its parent empty, in which case the parent is removed too.  The root is never removed, even
+    /// if it is (or becomes) empty.  If nothing is removed, the tree is left untouched.
+    pub fn prune_empty(&mut self) -> usize {
+        let (new_root, num_removed) = self.prune_rec(self.root());
+        if num_removed > 0 {
+            self.replace_root(new_root);
+        }
+        num_removed
+    }
+
+    /// Returns a copy of `node` with every empty container removed from its descendants, along
+    /// with the number of containers removed.  Nodes which don't change are reused.
+    fn prune_rec(&self, node: &'arena Node) -> (&'arena Node, usize) {
+        let mut new_node = node.clone();
+        let mut num_removed = 0;
+        let mut changed = false;
+        // Go backwards, so that removing a child doesn't change the indices of the ones we haven't
+        // reached yet
+        for (i, &child) in node.children().iter().enumerate().rev() {
+            let (new_child, num_removed_from_child) = self.prune_rec(child);
+            num_removed += num_removed_from_child;
+            // Some children can't be removed (like the value of a JSON field), in which case the
+            // parent decides whether it is empty itself
+            if new_child.is_empty_container() && new_node.remove_child(i).is_ok() {
+                num_removed += 1;
+                changed = true;
+            } else if !std::ptr::eq(new_child, child) {
+                new_node.children_mut()[i] = new_child;
+                changed = true;
+            }
+        }
+        if changed {
+            (self.arena.alloc(new_node), num_removed)
+        } else {
+            (node, num_removed)
+        }
+    }
+
+    /// Replaces every node which is equal to `from` with `to` as one edit, returning how many
+    /// nodes were replaced.  A node is never replaced if that would change the key of its parent
+    /// (see [`Ast::key`]), so only values are affected.  Every match is replaced by its own deep
+    /// clone of `to`, so no node appears twice in the tree.  The replacements aren't searched for
+    /// more matches.  If nothing is replaced, the tree is left untouched.
+    pub fn replace_all(&mut self, from: &Node, to: &'arena Node) -> usize {
+        let (new_root, num_replaced) = self.replace_all_rec(self.root(), from, to);
+        if num_replaced > 0 {
+            self.replace_root(new_root);
+        }
+        num_replaced
+    }
+
+    /// Returns a copy of `node` with every node equal to `from` replaced by `to`, along with the
+    /// number of nodes replaced.  Nodes which don't change are reused.
+    fn replace_all_rec(
+        &self,
+        node: &'arena Node,
+        from: &Node,
+        to: &'arena Node,
+    ) -> (&'arena Node, usize) {
+        if node == from {
+            return (self.deep_clone(to), 1);
+        }
+        let mut new_node = node.clone();
+        let mut num_replaced = 0;
+        for (i, &child) in node.children().iter().enumerate() {
+            let (new_child, num_replaced_in_child) = self.replace_all_rec(child, from, to);
+            if num_replaced_in_child == 0 {
+                continue;
+            }
+            let old_child = std::mem::replace(&mut new_node.children_mut()[i], new_child);
+            if new_node.key() == node.key() {
+                num_replaced += num_replaced_in_child;
+            } else {
+                // The child was the parent's key, which shouldn't be changed
+                new_node.children_mut()[i] = old_child;
+            }
+        }
+        if num_replaced > 0 {
+            (self.arena.alloc(new_node), num_replaced)
+        } else {
+            (node, 0)
+        }
+    }
+
+    /// Replaces the whole tree with `new_root` as a new edit, moving the cursor to the deepest
+    /// node along its old path which still exists
+    fn replace_root(&mut self, new_root: &'arena Node) {
+        let depth = self.current_cursor_path.node_iter(new_root).count() - 1;
+        let new_path = CursorPath::from_vec(
+            self.current_cursor_path
+                .iter()
+                .take(depth)
+                .copied()
+                .collect(),
+        );
+        self.current_cursor_path = new_path.clone();
+        self.push_snapshot(new_root, new_path);
+    }
+
+    /// Converts the node under the cursor into the type of node represented by the replace
+    /// sequence `seq` (see [`Ast::convert`]), keeping as many of its children as possible.
+    pub fn convert_cursor(&mut self, seq: &str) -> Result<(), String> {
+        let cursor = self.cursor();
+        let new_node = cursor
+            .convert(seq, self.arena)
+            .ok_or_else(|| format!("Cannot convert {} into '{}'.", cursor.display_name(), seq))?;
+        self.replace_cursor(new_node);
+        Ok(())
+    }
+
+    /// Updates the internal state so that the tree now contains `new_node` inserted as the last
     /// child of the selected node.  Also moves the cursor so that the new node is selected.
     pub fn insert_child(&mut self, new_node: Node) -> Result<(), Node::InsertError> {
+        let new_child_index = self.cursor().children().len();
+        self.insert_child_at_index(new_node, new_child_index)
+    }
+
+    /// Updates the internal state so that the tree now contains `new_node` inserted as a child of
+    /// the selected node, such that it has a given `index` among the cursor's children.  Also
+    /// moves the cursor so that the new node is selected.
+    pub fn insert_child_at(&mut self, new_node: Node, index: usize) -> Result<(), String> {
+        let cursor = self.cursor();
+        let num_children = cursor.children().len();
+        if index > num_children {
+            return Err(format!(
+                "Cannot insert at index {}, because {} only has {} children.",
+                index,
+                cursor.display_name(),
+                num_children
+            ));
+        }
+        self.insert_child_at_index(new_node, index)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Shared code of [`insert_child`](DAG::insert_child) and
+    /// [`insert_child_at`](DAG::insert_child_at).  `index` must be at most the number of children
+    /// of the cursor.
+    fn insert_child_at_index(
+        &mut self,
+        new_node: Node,
+        new_child_index: usize,
+    ) -> Result<(), Node::InsertError> {
         // Generate a vec of pointers to the nodes that we will have to clone.  We have to store
         // this as a vec because the iterator that produces them (cursor_path::NodeIter) can only
         // yield values from the root downwards, whereas we need the nodes in the opposite order.
         let mut nodes_to_clone: Vec<_> = self.current_cursor_path.node_iter(self.root()).collect();
         let new_child_node = self.arena.alloc(new_node);
-        // Clone the node that currently is the cursor, and add the new child to the end of its
+        // Clone the node that currently is the cursor, so that we can add the new child to its
         // children.  Unwrapping here is fine, because `cursor_path::NodeIter` will always return
         // one value.
         let mut cloned_cursor = nodes_to_clone.pop().unwrap().clone();
         // Add the new child to the children of the cloned cursor
-        cloned_cursor.insert_child(new_child_node, cloned_cursor.children().len())?;
+        cloned_cursor.insert_child(new_child_node, new_child_index)?;
         self.finish_edit(&nodes_to_clone, cloned_cursor);
+        // Move the cursor onto the new child
+        self.current_cursor_path.push(new_child_index);
         Ok(())
     }
 
-    /// Updates the internal state so that the tree now contains `new_node` inserted as the first
-    /// child of the selected node.  Also moves the cursor so that the new node is selected.
+    /// Inserts a new child, identified by a given `key`, as the last child of the selected node
+    /// (see [`Ast::new_keyed_child`]).  This fails if the selected node already has a child with
+    /// that key, or if its children can't be identified by keys.  Also moves the cursor so that
+    /// the new node is selected.
+    pub fn insert_keyed_child(&mut self, key: String) -> Result<(), String> {
+        let cursor = self.cursor();
+        if cursor.children().iter().any(|c| c.key() == Some(&key)) {
+            return Err(format!(
+                "Cannot insert key {:?}, because {} already contains it.",
+                key,
+                cursor.display_name()
+            ));
+        }
+        let new_node = cursor.new_keyed_child(key, self.arena).ok_or_else(|| {
+            format!(
+                "Node {} cannot contain children with keys.",
+                cursor.display_name()
+            )
+        })?;
+        self.insert_child(new_node).map_err(|e| e.to_string())
+    }
+
+    /// Changes the key of the node under the cursor (see [`Ast::key`]).  This fails if the cursor
+    /// isn't identified by a key, or if one of its siblings already has the new key.
+    pub fn rename_cursor(&mut self, key: String) -> Result<(), String> {
+        let (cursor, parent) = self.cursor_and_parent();
+        let new_node = cursor
+            .with_key(key.clone(), self.arena)
+            .ok_or_else(|| format!("Node {} doesn't have a key.", cursor.display_name()))?;
+        if let Some(p) = parent {
+            // We can unwrap, because the cursor has a parent and therefore isn't the root
+            let cursor_index = *self.current_cursor_path.last_mut().unwrap();
+            let is_duplicate = p
+                .children()
+                .iter()
+                .enumerate()
+                .any(|(i, c)| i != cursor_index && c.key() == Some(&key));
+            if is_duplicate {
+                return Err(format!(
+                    "Cannot rename to {:?}, because {} already contains it.",
+                    key,
+                    p.display_name()
+                ));
+            }
+        }
+        self.replace_cursor(new_node);
+        Ok(())
+    }
+
+    /// Updates the internal state so that the tree now contains `new_node` inserted as a sibling
+    /// of the selected node, on the given `side` of it.  Also moves the cursor so that the new
+    /// node is selected.
     pub fn insert_next_to_cursor(
         &mut self,
         new_node: Node,
@@ -256,20 +820,1360 @@ impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
         // Add the new child to the children of the cloned cursor
         cloned_parent.insert_child(new_child_node, insert_index)?;
         self.finish_edit(&nodes_to_clone, cloned_parent);
+        // Move the cursor onto the new node.  If the new node was inserted before the cursor,
+        // then it now has the cursor's old index so the cursor path doesn't need to change.
+        *self.current_cursor_path.last_mut().unwrap() = insert_index;
         Ok(())
     }
 
+    /// Inserts a new sibling of the cursor, identified by a given `key`, on the given `side` of
+    /// the cursor (see [`Ast::new_keyed_child`]).  This fails if the cursor is the root, if its
+    /// parent already has a child with that key, or if the parent's children can't be identified
+    /// by keys.  Also moves the cursor so that the new node is selected.
+    pub fn insert_keyed_next_to_cursor(&mut self, key: String, side: Side) -> Result<(), String> {
+        let parent = self
+            .cursor_and_parent()
+            .1
+            .ok_or_else(|| "Cannot add siblings of the root.".to_string())?;
+        if parent.children().iter().any(|c| c.key() == Some(&key)) {
+            return Err(format!(
+                "Cannot insert key {:?}, because {} already contains it.",
+                key,
+                parent.display_name()
+            ));
+        }
+        let new_node = parent.new_keyed_child(key, self.arena).ok_or_else(|| {
+            format!(
+                "Node {} cannot contain children with keys.",
+                parent.display_name()
+            )
+        })?;
+        self.insert_next_to_cursor(new_node, side)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Removes the node under the cursor and up to `count - 1` of its next siblings from their
+    /// parent, and moves the cursor to the previous sibling of the deleted nodes (or their parent
+    /// if there is no previous sibling).  The root can't be removed, so deleting the root instead
+    /// replaces it with the default node.
+    pub fn delete_cursor(&mut self, count: usize) -> Result<(), String> {
+        match self.current_cursor_path.last_mut() {
+            Some(index) => {
+                let index = *index;
+                self.delete_siblings(index..index.saturating_add(count))
+            }
+            None => {
+                // The cursor is at the root
+                self.replace_cursor(Node::default());
+                Ok(())
+            }
+        }
+    }
+
+    /// Removes the children of the cursor's parent whose indices are in `range` (which doesn't
+    /// have to contain the cursor), and moves the cursor to the previous sibling of the deleted
+    /// nodes (or their parent if there is no previous sibling).  Any part of `range` past the last
+    /// child is ignored.
+    pub fn delete_siblings(&mut self, range: std::ops::Range<usize>) -> Result<(), String> {
+        let mut nodes_to_clone: Vec<_> = self.current_cursor_path.node_iter(self.root()).collect();
+        // Pop the cursor, because it won't be part of the new tree.
+        assert!(nodes_to_clone.pop().is_some());
+        let parent = nodes_to_clone
+            .pop()
+            .ok_or_else(|| "The root has no siblings to delete.".to_string())?;
+        // Don't try to delete more siblings than exist
+        let end = range.end.min(parent.children().len());
+        if range.start >= end {
+            return Err("There are no nodes to delete.".to_string());
+        }
+        let mut cloned_parent = parent.clone();
+        for _ in range.start..end {
+            cloned_parent
+                .remove_child(range.start)
+                .map_err(|e| e.to_string())?;
+        }
+        // Move the cursor before finishing the edit.  This doesn't affect `finish_edit`, because
+        // it only reads the part of the path leading to the parent.
+        if range.start > 0 {
+            *self.current_cursor_path.last_mut().unwrap() = range.start - 1;
+        } else {
+            self.current_cursor_path.pop();
+        }
+        self.finish_edit(&nodes_to_clone, cloned_parent);
+        Ok(())
+    }
+
+    /// Swaps the node under the cursor with its previous or next sibling, keeping the cursor on the
+    /// moved node.  This fails if there is no sibling in that direction, or if the parent's
+    /// children can't be reordered.
+    pub fn move_cursor_node(&mut self, side: Side) -> Result<(), String> {
+        let mut nodes_to_clone: Vec<_> = self.current_cursor_path.node_iter(self.root()).collect();
+        // Unwrapping here is fine, because `cursor_path::NodeIter` will always return one value.
+        let cursor = nodes_to_clone.pop().unwrap();
+        let parent = nodes_to_clone
+            .pop()
+            .ok_or_else(|| "Cannot move the root.".to_string())?;
+        // We can unwrap, because if we were at the root then we'd have returned already
+        let cursor_index = *self.current_cursor_path.last_mut().unwrap();
+        let new_index = match side {
+            Side::Prev if cursor_index > 0 => cursor_index - 1,
+            Side::Next if cursor_index + 1 < parent.children().len() => cursor_index + 1,
+            Side::Prev => return Err("Cannot move the first child any earlier.".to_string()),
+            Side::Next => return Err("Cannot move the last child any later.".to_string()),
+        };
+        // Move the cursor by removing it and reinserting it in its new position.  This way, nodes
+        // which can't have their children reordered (like JSON fields) will return an error.
+        let mut cloned_parent = parent.clone();
+        cloned_parent
+            .remove_child(cursor_index)
+            .map_err(|e| e.to_string())?;
+        cloned_parent
+            .insert_child(cursor, new_index)
+            .map_err(|e| e.to_string())?;
+        *self.current_cursor_path.last_mut().unwrap() = new_index;
+        self.finish_edit(&nodes_to_clone, cloned_parent);
+        Ok(())
+    }
+
+    /// Stably sorts the children of the node under the cursor by their
+    /// [sort keys](Ast::child_sort_keys), in ascending or descending order.  The cursor stays on
+    /// the sorted node.  This fails if the cursor's children can't be sorted.
+    pub fn sort_cursor(&mut self, descending: bool) -> Result<(), String> {
+        let cursor = self.cursor();
+        let keys = cursor.child_sort_keys()?;
+        let mut order: Vec<usize> = (0..keys.len()).collect();
+        if descending {
+            order.sort_by(|&a, &b| keys[b].cmp(&keys[a]));
+        } else {
+            order.sort_by(|&a, &b| keys[a].cmp(&keys[b]));
+        }
+        // Don't add a snapshot to the history if sorting wouldn't change anything
+        if order.iter().enumerate().all(|(i, &j)| i == j) {
+            return Ok(());
+        }
+        let mut sorted = cursor.clone();
+        for (child, &index) in sorted.children_mut().iter_mut().zip(order.iter()) {
+            *child = cursor.children()[index];
+        }
+        self.replace_cursor(sorted);
+        Ok(())
+    }
+
+    /// Inserts a deep clone of `node` next to the cursor, and moves the cursor onto the new node.
+    /// The clone shares no nodes with `node`, so that editing one copy can't affect the other.
+    pub fn paste_next_to_cursor(
+        &mut self,
+        node: &'arena Node,
+        side: Side,
+    ) -> Result<(), Node::InsertError> {
+        let mut copy = node.clone();
+        for child in copy.children_mut() {
+            *child = self.deep_clone(child);
+        }
+        self.insert_next_to_cursor(copy, side)
+    }
+
+    /// Inserts a deep clone of the node under the cursor as its next sibling, and moves the cursor
+    /// onto the copy.  If the cursor is identified by a [key](Ast::key), the copy's key gets a
+    /// `_copy` suffix (followed by a number if that key is also taken) so that the parent doesn't
+    /// end up with a duplicate key.
+    pub fn duplicate_cursor(&mut self) -> Result<(), String> {
+        let (cursor, parent) = self.cursor_and_parent();
+        let parent = parent.ok_or_else(|| "Cannot duplicate the root.".to_string())?;
+        let node = match cursor.key() {
+            Some(key) => {
+                let is_taken = |k: &str| parent.children().iter().any(|c| c.key() == Some(k));
+                let mut new_key = format!("{}_copy", key);
+                let mut n = 2;
+                while is_taken(&new_key) {
+                    new_key = format!("{}_copy{}", key, n);
+                    n += 1;
+                }
+                // We can unwrap, because nodes with keys can always have their key changed
+                self.arena
+                    .alloc(cursor.with_key(new_key, self.arena).unwrap())
+            }
+            None => cursor,
+        };
+        self.paste_next_to_cursor(node, Side::Next)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Appends `count` copies of the first child of the cursor to the end of the cursor's
+    /// children, as a single edit.  This is useful for quickly building up sample data in an
+    /// array from a single example element.  Every copy is deep-cloned, so that the copies don't
+    /// share any nodes with each other or with the original.
+    pub fn generate_samples(&mut self, count: usize) -> Result<(), String> {
+        let mut nodes_to_clone: Vec<_> = self.current_cursor_path.node_iter(self.root()).collect();
+        // Unwrapping here is fine, because `cursor_path::NodeIter` will always return one value.
+        let cursor = nodes_to_clone.pop().unwrap();
+        let template = *cursor
+            .children()
+            .first()
+            .ok_or_else(|| "Cannot generate samples from a node with no children.".to_string())?;
+        // Clone the cursor and add all the copies to the end of its children
+        let mut cloned_cursor = cursor.clone();
+        for _ in 0..count {
+            let copy = self.deep_clone(template);
+            cloned_cursor
+                .insert_child(copy, cloned_cursor.children().len())
+                .map_err(|e| e.to_string())?;
+        }
+        self.finish_edit(&nodes_to_clone, cloned_cursor);
+        Ok(())
+    }
+
+    /// Allocates a copy of the subtree rooted at `node`, where every node in the copy is freshly
+    /// allocated in the arena.  This is needed because every node in a tree has to have a unique
+    /// reference, so subtrees can't simply be reused in two places of the same tree.
+    pub fn deep_clone(&self, node: &'arena Node) -> &'arena Node {
+        let mut cloned_node = node.clone();
+        for child in cloned_node.children_mut() {
+            *child = self.deep_clone(child);
+        }
+        self.arena.alloc(cloned_node)
+    }
+
     /* DISPLAY METHODS */
 
-    /// Build the text representation of the current tree into the given [`String`]
-    pub fn write_text(&self, string: &mut String, format: &Node::FormatStyle) {
-        self.root().write_text(string, format);
+    /// Convert the current tree to text in a given format, parse it back and check that the
+    /// resulting tree is identical to the current tree.  This is a self-check for bugs in the
+    /// formatting and parsing code.
+    pub fn check_round_trip(
+        &self,
+        format: &Node::FormatStyle,
+    ) -> Result<(), RoundTripError<Node::ParseError>> {
+        check_round_trip(self.root(), &self.to_text(format), self.arena)
+    }
+
+    /// Write the text representation of the current tree to any [`std::fmt::Write`] sink
+    pub fn write_text(
+        &self,
+        out: &mut dyn std::fmt::Write,
+        format: &Node::FormatStyle,
+    ) -> std::fmt::Result {
+        self.root().write_text(out, format)
+    }
+
+    /// Write the text representation of the current tree to any [`std::io::Write`] sink, without
+    /// building the whole text in memory first
+    pub fn write_io(
+        &self,
+        out: &mut dyn std::io::Write,
+        format: &Node::FormatStyle,
+    ) -> std::io::Result<()> {
+        self.root().write_io(out, format)
     }
 
     /// Build and return a [`String`] of the current tree
     pub fn to_text(&self, format: &Node::FormatStyle) -> String {
-        let mut s = String::new();
-        self.write_text(&mut s, format);
-        s
+        self.root().to_text(format)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_round_trip, relocate_path, Direction, RoundTripError, Side, DAG};
+    use crate::arena::Arena;
+    use crate::ast::{
+        cursor_path::CursorPath,
+        json::{JSONFormat, JSON},
+        test_json::TestJSON,
+        Ast,
+    };
+
+    #[test]
+    fn jump_to_boundary() {
+        // [true, [false, null, false], [null], []]
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::Array(vec![TestJSON::False, TestJSON::Null, TestJSON::False]),
+            TestJSON::Array(vec![TestJSON::Null]),
+            TestJSON::Array(vec![]),
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        let path = |indices: &[usize]| CursorPath::from_vec(indices.to_vec());
+        // Cycle around the second child: first child, last child, container and back again
+        tree.set_cursor_path(path(&[1])).unwrap();
+        for expected in &[vec![1, 0], vec![1, 2], vec![1], vec![1, 0]] {
+            tree.jump_to_boundary(&path(&[1]));
+            assert_eq!(tree.cursor_path(), &path(expected));
+        }
+        // From a middle child, go to the first child
+        tree.set_cursor_path(path(&[1, 1])).unwrap();
+        tree.jump_to_boundary(&path(&[1]));
+        assert_eq!(tree.cursor_path(), &path(&[1, 0]));
+        // An only child is both the first and last child, so jumps straight back out
+        tree.set_cursor_path(path(&[2])).unwrap();
+        for expected in &[vec![2, 0], vec![2]] {
+            tree.jump_to_boundary(&path(&[2]));
+            assert_eq!(tree.cursor_path(), &path(expected));
+        }
+        // Empty containers can't be jumped into
+        tree.set_cursor_path(path(&[3])).unwrap();
+        assert_eq!(
+            tree.jump_to_boundary(&path(&[3])),
+            Some("Cannot jump inside a node with no children.".to_string())
+        );
+        assert_eq!(tree.cursor_path(), &path(&[3]));
+    }
+
+    #[test]
+    fn jump_to_match() {
+        // [true, {"a": [false, null]}, []]
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::Object(vec![(
+                "a".to_string(),
+                TestJSON::Array(vec![TestJSON::False, TestJSON::Null]),
+            )]),
+            TestJSON::Array(vec![]),
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        let path = |indices: &[usize]| CursorPath::from_vec(indices.to_vec());
+        for (start, expected) in &[
+            // Containers jump to their last descendant...
+            (vec![1], vec![1, 0, 1, 1]),
+            (vec![1, 0, 1], vec![1, 0, 1, 1]),
+            (vec![], vec![2]),
+            // ...and other nodes jump to their parent container
+            (vec![1, 0, 1, 0], vec![1, 0, 1]),
+            (vec![1, 0, 0], vec![1, 0]),
+            (vec![0], vec![]),
+            (vec![2], vec![]),
+        ] {
+            tree.set_cursor_path(path(start)).unwrap();
+            tree.jump_to_match();
+            assert_eq!(
+                tree.cursor_path(),
+                &path(expected),
+                "starting from {:?}",
+                start
+            );
+        }
+        // A root with no children has nowhere to go
+        let root = TestJSON::Null.add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        tree.jump_to_match();
+        assert_eq!(tree.cursor_path(), &CursorPath::root());
+    }
+
+    #[test]
+    fn move_cursor() {
+        // [true, {"foo": [false]}, null]
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::Object(vec![(
+                "foo".to_string(),
+                TestJSON::Array(vec![TestJSON::False]),
+            )]),
+            TestJSON::Null,
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        for (direction, should_move, expected_path, expected_name) in &[
+            // The root has no parent or siblings
+            (Direction::Up, false, vec![], "array"),
+            (Direction::Next, false, vec![], "array"),
+            (Direction::Prev, false, vec![], "array"),
+            (Direction::Down, true, vec![0], "true"),
+            // The first child has no previous sibling, and `true` has no children
+            (Direction::Prev, false, vec![0], "true"),
+            (Direction::Down, false, vec![0], "true"),
+            (Direction::Next, true, vec![1], "object"),
+            (Direction::Down, true, vec![1, 0], "field"),
+            (Direction::Down, true, vec![1, 0, 0], r#""foo""#),
+            (Direction::Next, true, vec![1, 0, 1], "array"),
+            (Direction::Down, true, vec![1, 0, 1, 0], "false"),
+            // The only child has no next sibling
+            (Direction::Next, false, vec![1, 0, 1, 0], "false"),
+            (Direction::Up, true, vec![1, 0, 1], "array"),
+            (Direction::Up, true, vec![1, 0], "field"),
+            (Direction::Up, true, vec![1], "object"),
+            (Direction::Next, true, vec![2], "null"),
+            // The last child has no next sibling
+            (Direction::Next, false, vec![2], "null"),
+            (Direction::Up, true, vec![], "array"),
+        ] {
+            // Peeking at the neighbour should agree with actually moving
+            let neighbour = tree.cursor_neighbour(*direction);
+            assert_eq!(neighbour.is_some(), *should_move);
+            assert_eq!(tree.move_cursor(*direction).is_none(), *should_move);
+            if let Some(neighbour) = neighbour {
+                assert!(std::ptr::eq(neighbour, tree.cursor()));
+            }
+            assert_eq!(
+                tree.cursor_path(),
+                &CursorPath::from_vec(expected_path.clone())
+            );
+            assert_eq!(tree.cursor().display_name(), *expected_name);
+        }
+    }
+
+    #[test]
+    fn cursor_survives_edits() {
+        // [true, [null, false]]
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::Array(vec![TestJSON::Null, TestJSON::False]),
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        tree.move_cursor(Direction::Down);
+        tree.move_cursor(Direction::Next);
+        tree.move_cursor(Direction::Down);
+        tree.move_cursor(Direction::Next);
+        // Replacing the cursor clones all of its ancestors, but the cursor stays in the same place
+        tree.replace_cursor(JSON::True);
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "[true, [null, true]]");
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![1, 1]));
+        assert_eq!(tree.cursor().display_name(), "true");
+        // Navigation still works in the new tree
+        tree.move_cursor(Direction::Up);
+        assert_eq!(tree.move_cursor(Direction::Prev), None);
+        assert_eq!(tree.cursor().display_name(), "true");
+        assert!(std::ptr::eq(tree.cursor(), tree.root().children()[0]));
+    }
+
+    #[test]
+    fn relocate() {
+        let arena = Arena::new();
+        let root = JSON::from_text(
+            r#"[true, [null, false], {"a": true, "b": [false]}]"#,
+            &arena,
+        )
+        .unwrap();
+        let mut tree = DAG::new(&arena, root);
+        fn relocate<'arena>(
+            old_root: &'arena JSON<'arena>,
+            tree: &DAG<'arena, JSON<'arena>>,
+            path: Vec<usize>,
+        ) -> Option<Vec<usize>> {
+            relocate_path(old_root, tree.root(), &CursorPath::from_vec(path))
+                .map(|p| p.iter().copied().collect())
+        }
+        // Deleting a node moves the nodes after it, and the deleted node is gone
+        tree.set_cursor_path(CursorPath::from_vec(vec![0])).unwrap();
+        tree.delete_cursor(1).unwrap();
+        assert_eq!(relocate(root, &tree, vec![1, 1]), Some(vec![0, 1]));
+        assert_eq!(
+            relocate(root, &tree, vec![2, 1, 1, 0]),
+            Some(vec![1, 1, 1, 0])
+        );
+        assert_eq!(relocate(root, &tree, vec![0]), None);
+        // A node which is edited in place is found by its position between its unedited siblings
+        let old_root = tree.root();
+        tree.set_cursor_path(CursorPath::from_vec(vec![0, 1]))
+            .unwrap();
+        tree.replace_cursor(JSON::Null);
+        assert_eq!(relocate(old_root, &tree, vec![0, 1]), Some(vec![0, 1]));
+        assert_eq!(
+            relocate(old_root, &tree, vec![1, 1, 1]),
+            Some(vec![1, 1, 1])
+        );
+        // Fields are found by their keys, even if their siblings and values change
+        let old_root = tree.root();
+        tree.set_cursor_path(CursorPath::from_vec(vec![1, 0]))
+            .unwrap();
+        tree.delete_cursor(1).unwrap();
+        tree.set_cursor_path(CursorPath::from_vec(vec![1, 0, 1, 0]))
+            .unwrap();
+        tree.replace_cursor(JSON::Null);
+        assert_eq!(
+            tree.to_text(&JSONFormat::COMPACT),
+            r#"[[null, null], {"b": [null]}]"#
+        );
+        assert_eq!(relocate(old_root, &tree, vec![1, 1]), Some(vec![1, 0]));
+        assert_eq!(
+            relocate(old_root, &tree, vec![1, 1, 1, 0]),
+            Some(vec![1, 0, 1, 0])
+        );
+        assert_eq!(relocate(old_root, &tree, vec![1, 0]), None);
+        // Paths which didn't exist in the old tree can't be relocated
+        assert_eq!(relocate(old_root, &tree, vec![5]), None);
+    }
+
+    #[test]
+    fn paths_in_order() {
+        // [true, {"foo": null}]
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::Object(vec![("foo".to_string(), TestJSON::Null)]),
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        let expected_paths: Vec<CursorPath> = vec![
+            vec![],
+            vec![0],
+            vec![1],
+            vec![1, 0],
+            vec![1, 0, 0],
+            vec![1, 0, 1],
+        ]
+        .into_iter()
+        .map(CursorPath::from_vec)
+        .collect();
+        assert_eq!(tree.paths_in_order(), expected_paths);
+        // Every path can be jumped to
+        for path in expected_paths {
+            tree.set_cursor_path(path.clone()).unwrap();
+            assert_eq!(tree.cursor_path(), &path);
+        }
+        // Paths that don't exist can't be jumped to, and leave the cursor where it was
+        assert!(tree.set_cursor_path(CursorPath::from_vec(vec![2])).is_err());
+        assert!(tree
+            .set_cursor_path(CursorPath::from_vec(vec![0, 0]))
+            .is_err());
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![1, 0, 1]));
+    }
+
+    #[test]
+    fn breadcrumb() {
+        // [true, null, {"value": [false], "foo": true}]
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::Null,
+            TestJSON::Object(vec![
+                ("value".to_string(), TestJSON::Array(vec![TestJSON::False])),
+                ("foo".to_string(), TestJSON::True),
+            ]),
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        for (path, expected_breadcrumb) in &[
+            (vec![], "root"),
+            (vec![1], "root > [1]"),
+            (vec![2], "root > [2]"),
+            (vec![2, 0], r#"root > [2] > "value""#),
+            (vec![2, 1], r#"root > [2] > "foo""#),
+            (vec![2, 1, 0], r#"root > [2] > "foo" > "foo""#),
+            (vec![2, 1, 1], r#"root > [2] > "foo" > true"#),
+            (vec![2, 0, 1, 0], r#"root > [2] > "value" > array > [0]"#),
+        ] {
+            tree.current_cursor_path = CursorPath::from_vec(path.clone());
+            assert_eq!(tree.breadcrumb(), *expected_breadcrumb);
+        }
+    }
+
+    #[test]
+    fn reachable_node_count() {
+        // [true, null]
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![TestJSON::True, TestJSON::Null]).add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        assert_eq!((arena.len(), tree.reachable_node_count()), (3, 3));
+        // Replacing `true` allocates a new root and `false`, but the old nodes are still
+        // reachable through the undo history
+        tree.move_cursor(Direction::Down);
+        tree.replace_cursor(JSON::False);
+        assert_eq!((arena.len(), tree.reachable_node_count()), (5, 5));
+        // Round-trip checks parse a new tree into the arena, which is unreachable afterwards
+        tree.check_round_trip(&JSONFormat::COMPACT).unwrap();
+        assert_eq!((arena.len(), tree.reachable_node_count()), (8, 5));
+    }
+
+    #[test]
+    fn clear_history() {
+        // [true, null]
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![TestJSON::True, TestJSON::Null]).add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        // Make three edits, each of which clones the root and adds one new node
+        tree.move_cursor(Direction::Down);
+        tree.replace_cursor(JSON::False);
+        tree.move_cursor(Direction::Next);
+        tree.replace_cursor(JSON::True);
+        tree.replace_cursor(JSON::Null);
+        assert!(tree.undo());
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "[false, true]");
+        // Everything is reachable through the history
+        assert_eq!((arena.len(), tree.unreachable_node_count()), (9, 0));
+        // Clearing the history keeps the current tree (including the cursor), but nothing else
+        assert_eq!(tree.clear_history(), 3);
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "[false, true]");
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![1]));
+        assert!(!tree.undo());
+        assert!(!tree.redo());
+        // Only the current root, `false` and `true` are still reachable, but the other nodes stay
+        // in the arena until they are collected
+        assert_eq!(tree.reachable_node_count(), 3);
+        assert_eq!(tree.unreachable_node_count(), 6);
+        assert_eq!(arena.len(), 9);
+    }
+
+    #[test]
+    fn gc() {
+        // [true, null]
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![TestJSON::True, TestJSON::Null]).add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        // Build several tree states, each of which clones the root and adds one new node
+        tree.move_cursor(Direction::Down);
+        tree.replace_cursor(JSON::False);
+        tree.move_cursor(Direction::Next);
+        tree.replace_cursor(JSON::Str("x".to_string()));
+        tree.replace_cursor(JSON::True);
+        // A round-trip check parses a whole new tree, which nothing refers to afterwards
+        tree.check_round_trip(&JSONFormat::COMPACT).unwrap();
+        assert_eq!((arena.len(), tree.unreachable_node_count()), (12, 3));
+        // Only the round-trip tree is freed, because every other node is in the undo history
+        assert_eq!(unsafe { tree.gc() }, 3);
+        assert_eq!(arena.len(), 9);
+        // Dropping the history makes the old states unreachable too
+        tree.clear_history();
+        assert_eq!(tree.unreachable_node_count(), 6);
+        assert_eq!(unsafe { tree.gc() }, 6);
+        assert_eq!((arena.len(), tree.unreachable_node_count()), (3, 0));
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "[false, true]");
+        // The tree can still be edited, reusing the freed slots
+        let capacity = arena.capacity();
+        tree.replace_cursor(JSON::Null);
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "[false, null]");
+        assert_eq!(arena.capacity(), capacity);
+        assert!(tree.undo());
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "[false, true]");
+    }
+
+    #[test]
+    fn generate_samples() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![TestJSON::Object(vec![
+            ("foo".to_string(), TestJSON::True),
+            ("bar".to_string(), TestJSON::Array(vec![TestJSON::Null])),
+        ])])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        tree.generate_samples(3).unwrap();
+        // There should now be 4 structurally identical children ...
+        let children = tree.root().children();
+        assert_eq!(children.len(), 4);
+        for c in children {
+            assert_eq!(*c, root.children()[0]);
+        }
+        // ... which don't share any nodes
+        for (i, a) in children.iter().enumerate() {
+            for b in &children[i + 1..] {
+                assert!(!std::ptr::eq(*a, *b));
+                assert!(!std::ptr::eq(a.children()[0], b.children()[0]));
+            }
+        }
+        // Generating samples should be a single edit
+        assert!(tree.undo());
+        assert_eq!(
+            tree.to_text(&JSONFormat::COMPACT),
+            r#"[{"foo": true, "bar": [null]}]"#
+        );
+        // Leaves have no child to copy
+        for _ in 0..3 {
+            tree.move_cursor(super::Direction::Down);
+        }
+        assert_eq!(tree.cursor().display_name(), r#""foo""#);
+        assert!(tree.generate_samples(3).is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::Object(vec![
+                (
+                    "foos".to_string(),
+                    TestJSON::Array(vec![TestJSON::False, TestJSON::True, TestJSON::False]),
+                ),
+                ("bar".to_string(), TestJSON::False),
+            ]),
+            TestJSON::True,
+        ])
+        .add_to_arena(&arena);
+        let tree = DAG::new(&arena, root);
+        assert_eq!(tree.check_round_trip(&JSONFormat::COMPACT), Ok(()));
+        assert_eq!(tree.check_round_trip(&JSONFormat::PRETTY), Ok(()));
+
+        // A 'formatter' which forgets to write the last element of every array
+        let broken_format = |text: String| text.replace(", false]", "]");
+        let broken_text = broken_format(tree.to_text(&JSONFormat::COMPACT));
+        assert_eq!(
+            check_round_trip(root, &broken_text, &arena),
+            Err(RoundTripError::Mismatch(CursorPath::from_vec(vec![
+                0, 0, 1
+            ])))
+        );
+        // A 'formatter' which generates invalid JSON
+        assert!(matches!(
+            check_round_trip(root, "[true", &arena),
+            Err(RoundTripError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn delete_cursor() {
+        let arena = Arena::new();
+        let root = TestJSON::Object(vec![(
+            "foo".to_string(),
+            TestJSON::Array(vec![TestJSON::True, TestJSON::Null, TestJSON::False]),
+        )])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        // Move to the `null` in the array and delete it
+        tree.move_cursor(super::Direction::Down);
+        tree.move_cursor(super::Direction::Down);
+        tree.move_cursor(super::Direction::Next);
+        tree.move_cursor(super::Direction::Down);
+        tree.move_cursor(super::Direction::Next);
+        assert_eq!(tree.cursor().display_name(), "null");
+        tree.delete_cursor(1).unwrap();
+        assert_eq!(
+            tree.to_text(&JSONFormat::COMPACT),
+            r#"{"foo": [true, false]}"#
+        );
+        // The cursor should have moved to the previous sibling
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![0, 1, 0]));
+        // Deleting the first child moves the cursor to the parent
+        tree.delete_cursor(1).unwrap();
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), r#"{"foo": [false]}"#);
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![0, 1]));
+        // The key of a field can't be deleted
+        tree.move_cursor(super::Direction::Prev);
+        assert!(tree.delete_cursor(1).is_err());
+        // Deleting a field removes both the key and the value
+        tree.move_cursor(super::Direction::Up);
+        tree.delete_cursor(1).unwrap();
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "{}");
+        // Deleting the root replaces it with the default node
+        tree.move_cursor(super::Direction::Down);
+        tree.replace_cursor(JSON::True);
+        tree.delete_cursor(1).unwrap();
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "{}");
+        // Every deletion can be undone
+        assert!(tree.undo());
+        assert!(tree.undo());
+        assert!(tree.undo());
+        assert!(tree.undo());
+        assert_eq!(
+            tree.to_text(&JSONFormat::COMPACT),
+            r#"{"foo": [true, false]}"#
+        );
+    }
+
+    #[test]
+    fn undo_redo() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![TestJSON::True, TestJSON::False]).add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        // There's nothing to undo or redo yet
+        assert!(!tree.undo());
+        assert!(!tree.redo());
+        // Replace the `false` with `null`
+        tree.move_cursor(super::Direction::Down);
+        tree.move_cursor(super::Direction::Next);
+        tree.replace_cursor(JSON::Null);
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "[true, null]");
+        // Undoing should return to the original tree ...
+        assert!(tree.undo());
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "[true, false]");
+        assert!(!tree.undo());
+        // ... and redoing should bring the edit back
+        assert!(tree.redo());
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "[true, null]");
+        assert!(!tree.redo());
+        // Undoing also restores the cursor to where it was in that snapshot
+        assert!(tree.undo());
+        assert_eq!(tree.cursor_path(), &CursorPath::root());
+        // Making a new edit after an undo starts a new branch of the history, so there's nothing
+        // to redo from the new edit ...
+        tree.move_cursor(super::Direction::Down);
+        tree.move_cursor(super::Direction::Next);
+        tree.replace_cursor(JSON::True);
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "[true, true]");
+        assert!(!tree.redo());
+        // ... but the undone edit is still on the other branch
+        assert!(tree.undo());
+        assert_eq!(tree.redo_branches(), &[1, 2]);
+        assert!(tree.select_redo_branch(0));
+        assert!(!tree.select_redo_branch(2));
+        assert!(tree.redo());
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "[true, null]");
+        assert_eq!(tree.history_index(), 1);
+    }
+
+    #[test]
+    fn transactions() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![TestJSON::True, TestJSON::False]).add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        tree.move_cursor(super::Direction::Down);
+        // Every edit in a transaction is undone in one step, including nested transactions
+        tree.with_transaction(|tree| {
+            tree.replace_cursor(JSON::Null);
+            tree.begin();
+            tree.move_cursor(super::Direction::Next);
+            tree.replace_cursor(JSON::Null);
+            tree.commit();
+            tree.duplicate_cursor().unwrap();
+        });
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "[null, null, null]");
+        assert_eq!(tree.history_index(), 1);
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![2]));
+        assert!(tree.undo());
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "[true, false]");
+        assert!(!tree.undo());
+        assert!(tree.redo());
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "[null, null, null]");
+        // Transactions which don't change the tree don't add to the history
+        tree.with_transaction(|_| {});
+        tree.move_cursor(super::Direction::Up);
+        assert!(tree
+            .with_transaction(|tree| tree.duplicate_cursor())
+            .is_err());
+        assert_eq!(tree.history_index(), 1);
+        // Edits after a transaction are undone separately
+        tree.move_cursor(super::Direction::Down);
+        tree.replace_cursor(JSON::True);
+        assert!(tree.undo());
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "[null, null, null]");
+    }
+
+    #[test]
+    fn earlier_later() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![TestJSON::True]).add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        // Make the history:
+        // [true] --+-- [false]
+        //          |
+        //          +-- [null] --- [[]]
+        tree.move_cursor(super::Direction::Down);
+        tree.replace_cursor(JSON::False);
+        tree.undo();
+        tree.move_cursor(super::Direction::Down);
+        tree.replace_cursor(JSON::Null);
+        tree.replace_cursor(JSON::Array(vec![]));
+        // Moving through time visits every snapshot, jumping between branches
+        let mut texts = vec![tree.to_text(&JSONFormat::COMPACT)];
+        while tree.earlier() {
+            texts.push(tree.to_text(&JSONFormat::COMPACT));
+        }
+        assert_eq!(texts, vec!["[[]]", "[null]", "[false]", "[true]"]);
+        assert!(tree.later() && tree.later());
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "[null]");
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![0]));
+        assert!(tree.later());
+        assert!(!tree.later());
+        // Every snapshot keeps its nodes reachable
+        assert_eq!(tree.unreachable_node_count(), 0);
+    }
+
+    #[test]
+    fn rename_cursor() {
+        let arena = Arena::new();
+        let root = TestJSON::Object(vec![
+            ("foo".to_string(), TestJSON::True),
+            ("bar".to_string(), TestJSON::Array(vec![TestJSON::Null])),
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        // Objects don't have keys, so can't be renamed
+        assert!(tree.rename_cursor("baz".to_string()).is_err());
+        // Rename the "bar" field
+        tree.move_cursor(super::Direction::Down);
+        tree.move_cursor(super::Direction::Next);
+        tree.rename_cursor("baz".to_string()).unwrap();
+        assert_eq!(
+            tree.to_text(&JSONFormat::COMPACT),
+            r#"{"foo": true, "baz": [null]}"#
+        );
+        // Renaming a field to its own key is fine, but duplicating a sibling's key isn't
+        tree.rename_cursor("baz".to_string()).unwrap();
+        assert!(tree.rename_cursor("foo".to_string()).is_err());
+        assert_eq!(
+            tree.to_text(&JSONFormat::COMPACT),
+            r#"{"foo": true, "baz": [null]}"#
+        );
+    }
+
+    /// Returns every node in the tree rooted at `node`, including `node` itself
+    fn all_nodes<'arena>(node: &'arena JSON<'arena>) -> Vec<&'arena JSON<'arena>> {
+        let mut nodes = vec![node];
+        for child in node.children() {
+            nodes.extend(all_nodes(child));
+        }
+        nodes
+    }
+
+    #[test]
+    fn delete_siblings() {
+        // [true, false, null, true]
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::False,
+            TestJSON::Null,
+            TestJSON::True,
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        // The root has no siblings
+        assert!(tree.delete_siblings(0..1).is_err());
+        // The range doesn't have to include the cursor
+        tree.move_cursor(Direction::Down);
+        tree.delete_siblings(1..3).unwrap();
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "[true, true]");
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![0]));
+        // Ranges are clamped to the existing children, but can't be empty
+        assert!(tree.delete_siblings(2..5).is_err());
+        assert!(tree.delete_siblings(1..1).is_err());
+        tree.delete_siblings(0..5).unwrap();
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "[]");
+        assert_eq!(tree.cursor_path(), &CursorPath::root());
+    }
+
+    #[test]
+    fn yank_and_paste() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::Array(vec![TestJSON::True, TestJSON::False]),
+            TestJSON::Null,
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        // Yank the inner array, then paste it after the `null`
+        tree.move_cursor(super::Direction::Down);
+        let yanked = tree.cursor();
+        tree.move_cursor(super::Direction::Next);
+        tree.paste_next_to_cursor(yanked, Side::Next).unwrap();
+        assert_eq!(
+            tree.to_text(&JSONFormat::COMPACT),
+            "[[true, false], null, [true, false]]"
+        );
+        // The cursor should be on the pasted copy, which shouldn't share any nodes with the
+        // original
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![2]));
+        let pasted = tree.cursor();
+        for original_node in all_nodes(yanked) {
+            assert!(all_nodes(pasted)
+                .iter()
+                .all(|n| !std::ptr::eq(*n, original_node)));
+        }
+        // Editing the pasted copy shouldn't change the original
+        tree.move_cursor(super::Direction::Down);
+        tree.replace_cursor(JSON::Null);
+        assert_eq!(
+            tree.to_text(&JSONFormat::COMPACT),
+            "[[true, false], null, [null, false]]"
+        );
+        assert_eq!(yanked.to_text(&JSONFormat::COMPACT), "[true, false]");
+    }
+
+    #[test]
+    fn convert_cursor() {
+        let arena = Arena::new();
+        let root = JSON::from_text(r#"[[true, {"x": null}, []], "foo"]"#, &arena).unwrap();
+        let mut tree = DAG::new(&arena, root);
+        tree.move_cursor(Direction::Down);
+        let array = tree.cursor();
+        // Converting an array to an object keys the elements by their index...
+        tree.convert_cursor("o").unwrap();
+        assert_eq!(
+            tree.to_text(&JSONFormat::COMPACT),
+            r#"[{"0": true, "1": {"x": null}, "2": []}, "foo"]"#
+        );
+        // ...and converting back drops the keys, leaving the original elements
+        tree.convert_cursor("a").unwrap();
+        assert_eq!(tree.cursor(), array);
+        for (original, converted) in array.children().iter().zip(tree.cursor().children()) {
+            assert!(std::ptr::eq(*original, *converted));
+        }
+        // Leaves become empty containers, and containers can become leaves
+        tree.move_cursor(Direction::Next);
+        tree.convert_cursor("o").unwrap();
+        tree.move_cursor(Direction::Prev);
+        tree.convert_cursor("t").unwrap();
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "[true, {}]");
+        assert_eq!(
+            tree.convert_cursor("x"),
+            Err("Cannot convert true into 'x'.".to_string())
+        );
+    }
+
+    #[test]
+    fn reflow() {
+        let arena = Arena::new();
+        let root = JSON::from_text(r#"[true, {"a": [null]}]"#, &arena).unwrap();
+        let mut tree = DAG::new(&arena, root);
+        tree.set_cursor_path(CursorPath::from_vec(vec![1, 0, 1]))
+            .unwrap();
+        // A tree which reads back as itself is left alone, so no edit is added to the history
+        assert_eq!(tree.reflow(&JSONFormat::PRETTY), Ok(false));
+        assert_eq!(tree.root(), root);
+        assert!(!tree.undo());
+        // A tree which can't be written as valid JSON gives the parse error, and isn't changed
+        tree.move_cursor(Direction::Up);
+        tree.move_cursor(Direction::Up);
+        tree.replace_cursor(JSON::Object(vec![arena.alloc(JSON::True)]));
+        let invalid_root = tree.root();
+        assert_eq!(
+            tree.reflow(&JSONFormat::COMPACT).unwrap_err().to_string(),
+            "Unexpected char 't' at line 1, column 9."
+        );
+        assert_eq!(tree.root(), invalid_root);
+    }
+
+    #[test]
+    fn prune_empty() {
+        let arena = Arena::new();
+        let text = r#"[true, {"a": [], "b": {"c": [{}]}}, [[]], {}, [null, []]]"#;
+        let root = JSON::from_text(text, &arena).unwrap();
+        let mut tree = DAG::new(&arena, root);
+        tree.set_cursor_path(CursorPath::from_vec(vec![1, 1, 1, 0, 1]))
+            .unwrap();
+        // Emptying `[{}]` empties the object containing it, which empties the object containing
+        // that, and so on
+        assert_eq!(tree.prune_empty(), 9);
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "[true, [null]]");
+        // The cursor's old path goes through a removed node, so it is cut short
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![1]));
+        // Nodes which haven't changed are shared with the old tree
+        assert!(std::ptr::eq(tree.root().children()[0], root.children()[0]));
+        // Pruning is a single edit
+        assert!(tree.undo());
+        assert_eq!(tree.root(), root);
+        assert!(!tree.undo());
+
+        // The root is never removed, even if it becomes empty
+        for (text, num_removed, expected_text) in &[("{}", 0, "{}"), ("[[], {}]", 2, "[]")] {
+            let root = JSON::from_text(text, &arena).unwrap();
+            let mut tree = DAG::new(&arena, root);
+            assert_eq!(tree.prune_empty(), *num_removed);
+            assert_eq!(tree.to_text(&JSONFormat::COMPACT), *expected_text);
+        }
+        // If nothing is removed, no edit is made
+        let mut tree = DAG::new(&arena, root);
+        tree.prune_empty();
+        assert_eq!(tree.prune_empty(), 0);
+        assert!(tree.undo());
+        assert!(!tree.undo());
+    }
+
+    #[test]
+    fn replace_all() {
+        let arena = Arena::new();
+        let root = JSON::from_text(
+            r#"[true, {"true": true, "a": [true, false]}, "true", {"a": "a"}]"#,
+            &arena,
+        )
+        .unwrap();
+        let mut tree = DAG::new(&arena, root);
+        tree.move_cursor(super::Direction::Down);
+        tree.move_cursor(super::Direction::Next);
+        tree.move_cursor(super::Direction::Down);
+        // Every `true` value is replaced in one edit, but the cursor stays where it was
+        assert_eq!(tree.replace_all(&JSON::True, arena.alloc(JSON::False)), 3);
+        assert_eq!(
+            tree.to_text(&JSONFormat::COMPACT),
+            r#"[false, {"true": false, "a": [false, false]}, "true", {"a": "a"}]"#
+        );
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![1, 0]));
+        // Each replacement is a separate node
+        let replaced: Vec<&JSON> = [vec![0], vec![1, 0, 1], vec![1, 1, 1, 0]]
+            .iter()
+            .map(|p| CursorPath::from_vec(p.clone()).cursor(tree.root()))
+            .collect();
+        assert!(replaced.iter().all(|n| **n == JSON::False));
+        assert!(!std::ptr::eq(replaced[0], replaced[1]));
+        assert!(!std::ptr::eq(replaced[0], replaced[2]));
+        assert!(!std::ptr::eq(replaced[1], replaced[2]));
+        // Strings are matched exactly, and keys are never replaced
+        let a = JSON::Str("a".to_string());
+        assert_eq!(tree.replace_all(&a, arena.alloc(JSON::Null)), 1);
+        assert_eq!(
+            tree.to_text(&JSONFormat::COMPACT),
+            r#"[false, {"true": false, "a": [false, false]}, "true", {"a": null}]"#
+        );
+        // Replacing nothing doesn't make an edit
+        assert_eq!(tree.replace_all(&a, arena.alloc(JSON::Null)), 0);
+        assert!(tree.undo());
+        assert!(tree.undo());
+        assert!(std::ptr::eq(tree.root(), root));
+    }
+
+    #[test]
+    fn sort_cursor() {
+        let arena = Arena::new();
+        let text = r#"[
+            {"b": true, "c": [], "a": null},
+            [true, false, true],
+            ["b", "a", "c"],
+            [true, null],
+            {"a": true, "a": false}
+        ]"#;
+        let root = JSON::from_text(text, &arena).unwrap();
+        let mut tree = DAG::new(&arena, root);
+        let expected = [
+            // Objects are sorted by their keys
+            (vec![0], false, Ok(r#"{"a": null, "b": true, "c": []}"#)),
+            (vec![0], true, Ok(r#"{"c": [], "b": true, "a": null}"#)),
+            // Arrays of leaves are sorted by value
+            (vec![1], false, Ok("[false, true, true]")),
+            (vec![1], true, Ok("[true, true, false]")),
+            (vec![2], true, Ok(r#"["c", "b", "a"]"#)),
+            // Things which can't be compared aren't sorted
+            (vec![3], false, Err("Cannot sort an array of mixed types.")),
+            (
+                vec![4],
+                false,
+                Err(r#"Cannot sort an object with duplicate key "a"."#),
+            ),
+            (
+                vec![],
+                false,
+                Err("Cannot sort an array containing object."),
+            ),
+            (vec![1, 0], false, Err("Cannot sort the children of true.")),
+        ];
+        for (path, descending, expected_text) in expected.iter() {
+            tree.set_cursor_path(CursorPath::from_vec(path.clone()))
+                .unwrap();
+            let result = tree.sort_cursor(*descending);
+            assert_eq!(result, expected_text.map(|_| ()).map_err(str::to_string));
+            // The cursor stays on the sorted node
+            assert_eq!(tree.cursor_path(), &CursorPath::from_vec(path.clone()));
+            if let Ok(text) = expected_text {
+                assert_eq!(tree.cursor().to_text(&JSONFormat::COMPACT), *text);
+            }
+        }
+        // Sorting is stable, and sorting something that's already sorted isn't an edit
+        let root = TestJSON::Object(vec![
+            (
+                "a".to_string(),
+                TestJSON::Array(vec![TestJSON::Null, TestJSON::Null]),
+            ),
+            ("b".to_string(), TestJSON::True),
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        tree.sort_cursor(false).unwrap();
+        assert!(!tree.undo());
+        tree.move_cursor(Direction::Down);
+        tree.move_cursor(Direction::Down);
+        tree.move_cursor(Direction::Next);
+        tree.sort_cursor(true).unwrap();
+        assert!(std::ptr::eq(tree.root(), root));
+    }
+
+    #[test]
+    fn duplicate_cursor() {
+        let arena = Arena::new();
+        let root = JSON::from_text(r#"[{"a": [true]}, null]"#, &arena).unwrap();
+        let mut tree = DAG::new(&arena, root);
+        assert_eq!(
+            tree.duplicate_cursor(),
+            Err("Cannot duplicate the root.".to_string())
+        );
+        // Duplicating an array element makes an equal copy, which shares no nodes with the
+        // original
+        tree.move_cursor(Direction::Down);
+        tree.duplicate_cursor().unwrap();
+        assert_eq!(
+            tree.to_text(&JSONFormat::COMPACT),
+            r#"[{"a": [true]}, {"a": [true]}, null]"#
+        );
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![1]));
+        let original = tree.root().children()[0];
+        let copy = tree.cursor();
+        assert_eq!(original, copy);
+        fn descendants<'arena>(node: &'arena JSON<'arena>) -> Vec<*const JSON<'arena>> {
+            let mut nodes = vec![node as *const _];
+            for child in node.children() {
+                nodes.extend(descendants(child));
+            }
+            nodes
+        }
+        let copy_nodes = descendants(copy);
+        assert!(descendants(original)
+            .iter()
+            .all(|n| !copy_nodes.contains(n)));
+        // Duplicating a field renames the copy's key
+        tree.move_cursor(Direction::Down);
+        tree.duplicate_cursor().unwrap();
+        tree.move_cursor(Direction::Prev);
+        tree.duplicate_cursor().unwrap();
+        assert_eq!(
+            tree.to_text(&JSONFormat::COMPACT),
+            r#"[{"a": [true]}, {"a": [true], "a_copy2": [true], "a_copy": [true]}, null]"#
+        );
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![1, 1]));
+        assert_eq!(tree.root().validate(), Ok(()));
+    }
+
+    #[test]
+    fn move_cursor_node() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::Null,
+            TestJSON::Object(vec![
+                ("foo".to_string(), TestJSON::True),
+                ("bar".to_string(), TestJSON::False),
+            ]),
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        // The root has no siblings to swap with
+        assert!(tree.move_cursor_node(Side::Next).is_err());
+        // Move the `null` up and down, making sure the cursor follows it
+        tree.move_cursor(super::Direction::Down);
+        tree.move_cursor(super::Direction::Next);
+        tree.move_cursor_node(Side::Prev).unwrap();
+        assert_eq!(
+            tree.to_text(&JSONFormat::COMPACT),
+            r#"[null, true, {"foo": true, "bar": false}]"#
+        );
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![0]));
+        assert!(tree.move_cursor_node(Side::Prev).is_err());
+        tree.move_cursor_node(Side::Next).unwrap();
+        tree.move_cursor_node(Side::Next).unwrap();
+        assert_eq!(
+            tree.to_text(&JSONFormat::COMPACT),
+            r#"[true, {"foo": true, "bar": false}, null]"#
+        );
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![2]));
+        assert!(tree.move_cursor_node(Side::Next).is_err());
+        // Fields can be reordered within objects ...
+        tree.move_cursor(super::Direction::Prev);
+        tree.move_cursor(super::Direction::Down);
+        tree.move_cursor_node(Side::Next).unwrap();
+        assert_eq!(
+            tree.to_text(&JSONFormat::COMPACT),
+            r#"[true, {"bar": false, "foo": true}, null]"#
+        );
+        // ... but keys and values can't be swapped
+        tree.move_cursor(super::Direction::Down);
+        assert!(tree.move_cursor_node(Side::Next).is_err());
+    }
+
+    #[test]
+    fn insert_child() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::Array(vec![]),
+            TestJSON::Object(vec![("foo".to_string(), TestJSON::True)]),
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        // Insert into the empty array, and check that the cursor moved onto the new node
+        tree.move_cursor(super::Direction::Down);
+        tree.insert_child(JSON::True).unwrap();
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![0, 0]));
+        assert_eq!(tree.cursor().display_name(), "true");
+        // Inserting a key into an array isn't possible
+        tree.move_cursor(super::Direction::Up);
+        assert!(tree.insert_keyed_child("bar".to_string()).is_err());
+        // Insert a new key into the object
+        tree.move_cursor(super::Direction::Next);
+        tree.insert_keyed_child("bar".to_string()).unwrap();
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![1, 1]));
+        assert_eq!(tree.cursor().key(), Some("bar"));
+        assert_eq!(
+            tree.to_text(&JSONFormat::COMPACT),
+            r#"[[true], {"foo": true, "bar": {}}]"#
+        );
+        // Inserting a duplicate key is an error, and doesn't change the tree
+        tree.move_cursor(super::Direction::Up);
+        assert!(tree.insert_keyed_child("foo".to_string()).is_err());
+        assert_eq!(
+            tree.to_text(&JSONFormat::COMPACT),
+            r#"[[true], {"foo": true, "bar": {}}]"#
+        );
+    }
+
+    #[test]
+    fn insert_next_to_cursor() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::Array(vec![TestJSON::True, TestJSON::False]),
+            TestJSON::Object(vec![("foo".to_string(), TestJSON::True)]),
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        // Insert before the first element of the inner array
+        tree.move_cursor(super::Direction::Down);
+        tree.move_cursor(super::Direction::Down);
+        tree.insert_next_to_cursor(JSON::Null, Side::Prev).unwrap();
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![0, 0]));
+        assert_eq!(tree.cursor().display_name(), "null");
+        // Insert after the last element
+        tree.move_cursor(super::Direction::Next);
+        tree.move_cursor(super::Direction::Next);
+        tree.insert_next_to_cursor(JSON::default(), Side::Next)
+            .unwrap();
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![0, 3]));
+        assert_eq!(
+            tree.to_text(&JSONFormat::COMPACT),
+            r#"[[null, true, false, {}], {"foo": true}]"#
+        );
+        // Fields of an object need a key, which can't already be taken
+        tree.move_cursor(super::Direction::Up);
+        assert!(tree
+            .insert_keyed_next_to_cursor("bar".to_string(), Side::Next)
+            .is_err());
+        tree.move_cursor(super::Direction::Next);
+        tree.move_cursor(super::Direction::Down);
+        assert!(tree
+            .insert_keyed_next_to_cursor("foo".to_string(), Side::Prev)
+            .is_err());
+        tree.insert_keyed_next_to_cursor("bar".to_string(), Side::Prev)
+            .unwrap();
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![1, 0]));
+        assert_eq!(tree.cursor().key(), Some("bar"));
+        assert_eq!(
+            tree.to_text(&JSONFormat::COMPACT),
+            r#"[[null, true, false, {}], {"bar": {}, "foo": true}]"#
+        );
+        // The root has no siblings
+        tree.move_cursor(super::Direction::Up);
+        tree.move_cursor(super::Direction::Up);
+        assert!(tree
+            .insert_keyed_next_to_cursor("baz".to_string(), Side::Next)
+            .is_err());
+    }
+
+    #[test]
+    fn insert_child_at() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::Array(vec![]),
+            TestJSON::Array(vec![TestJSON::True, TestJSON::False]),
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        // Insert the default node into the empty array
+        tree.move_cursor(super::Direction::Down);
+        assert!(tree.insert_child_at(JSON::default(), 1).is_err());
+        tree.insert_child_at(JSON::default(), 0).unwrap();
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![0, 0]));
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "[[{}], [true, false]]");
+        // Insert into the middle of the populated array
+        tree.move_cursor(super::Direction::Up);
+        tree.move_cursor(super::Direction::Next);
+        tree.insert_child_at(JSON::Null, 1).unwrap();
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![1, 1]));
+        assert_eq!(
+            tree.to_text(&JSONFormat::COMPACT),
+            "[[{}], [true, null, false]]"
+        );
+        // Nodes without children can't have children inserted into them
+        assert!(tree.insert_child_at(JSON::Null, 0).is_err());
+        // Both insertions can be undone
+        assert!(tree.undo());
+        assert!(tree.undo());
+        assert_eq!(tree.to_text(&JSONFormat::COMPACT), "[[], [true, false]]");
     }
 }
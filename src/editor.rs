@@ -1,12 +1,56 @@
 //! The top-level functionality of Sapling
 
 use crate::ast::display_token::DisplayToken;
-use crate::ast::{size, Ast};
-use crate::editable_tree::{Direction, Side, DAG};
-use std::collections::hash_map::DefaultHasher;
-use std::hash::Hasher;
+use crate::ast::{cursor_path::CursorPath, size, Ast, SyntaxClass};
+use crate::editable_tree::{breadcrumb, relocate_path, Direction, Side, DAG};
 use tuikit::prelude::*;
 
+pub mod clipboard;
+mod command_line;
+pub mod config;
+mod help;
+mod lint;
+mod search;
+mod stats;
+mod status_line;
+pub mod theme;
+mod viewport;
+
+/// A line of the tree's text, stored as a list of spans along with the column that each span starts
+/// at, the attributes it should be drawn with (before the cursor and selection are highlighted)
+/// and the node that it belongs to (both as a pointer and as a path from the root, since nodes can
+/// be shared)
+type NodeLine<Node> = Vec<(usize, String, Attr, *const Node, CursorPath)>;
+
+/// The layout of the tree when it was last drawn.  Moving the cursor only changes which spans are
+/// highlighted, so this is reused until the tree changes (see [`Editor::needs_full_render`]) or
+/// the view scrolls.
+struct TreeLayout<Node> {
+    /// The root of the tree that was laid out
+    root: *const Node,
+    /// The number of lines that were laid out, the index of the first visible line and the
+    /// indices of the pinned lines, which together decide which lines were laid out
+    window: (usize, usize, Vec<usize>),
+    /// The unhighlighted lines of the tree
+    lines: Vec<NodeLine<Node>>,
+}
+
+impl<Node> TreeLayout<Node> {
+    /// Returns the path to the node whose text is drawn at a given line and column (e.g. so that
+    /// clicking on a node moves the cursor to it).  A column between two pieces of text picks the
+    /// text to its left, and a column before the first piece of text on the line (e.g. in the
+    /// indentation) picks that first piece.  This returns [`None`] if the line is past the end of
+    /// the tree or wasn't laid out.
+    fn path_at(&self, line: usize, col: usize) -> Option<&CursorPath> {
+        let spans = self.lines.get(line)?;
+        let span = (spans.iter())
+            .rev()
+            .find(|(span_col, ..)| *span_col <= col)
+            .or_else(|| spans.first())?;
+        Some(&span.4)
+    }
+}
+
 mod command_log {
     //! A utility datastructure to store and render a log of commands.  This is mostly used to give
     //! the viewers of my streams feedback for what I'm typing.
@@ -118,7 +162,10 @@ mod command_log {
                     log::error!("Empty command executed!");
                     ("<empty command>".to_string(), Color::LIGHT_RED)
                 } else {
-                    if let Some(action) = super::parse_command(&keymap, &command) {
+                    // The command has already run, so its replace sequence (if any) is complete
+                    if let Some((_count, action)) =
+                        super::parse_command(keymap, &[], &command, false)
+                    {
                         action.description_and_color()
                     } else {
                         log::error!("Incomplete command executed!");
@@ -159,9 +206,138 @@ pub enum Command {
     Undo,
     /// Redo a change
     Redo,
+    /// Append copies of the cursor's first child, expects a digit argument
+    GenerateSamples,
+    /// Check that the tree survives being converted to text and parsed back
+    CheckRoundTrip,
+    /// Copy the node under the cursor into the register
+    Yank,
+    /// Paste a copy of the register's contents after the cursor
+    Paste,
+    /// Insert a copy of the node under the cursor after it
+    Duplicate,
+    /// Split every child of the cursor into its own container
+    Explode,
+    /// Merge the children of the cursor's children into one container
+    Merge,
+    /// Delete the node under the cursor
+    Delete,
+    /// Change the key of the node under the cursor
+    Rename,
+    /// Swap the node under the cursor with one of its siblings
+    MoveNode(Side),
+    /// Log how much memory the arena is using
+    ArenaStats,
+    /// Hide or show the children of nodes in the tree view, expects an argument saying which
+    /// nodes: `a` toggles the cursor, `M` folds every node at the depth given by the count and `R`
+    /// unfolds everything
+    Fold,
+    /// Start recording a macro, expects a register argument.  Pressing this key again stops the
+    /// recording.
+    RecordMacro,
+    /// Replay a recorded macro, expects a register argument (or `@` for the last replayed macro)
+    ReplayMacro,
+    /// Start or stop selecting a range of siblings
+    Visual,
+    /// Prompt for a query, and move the cursor to the next node which matches it
+    Search,
+    /// Move the cursor to the next or previous match of the last search
+    SearchAgain(Side),
+    /// Open the command line, where longer commands (like `:set indent 2`) can be typed
+    CommandLine,
+    /// Switch to the next format style (e.g. from compact to pretty)
+    CycleFormat,
+    /// Jump between a container and its first and last children, going round the cycle `container
+    /// -> first child -> last child -> container`.  Unlike [`JumpToMatch`](Command::JumpToMatch),
+    /// this never goes deeper than the container's children.
+    JumpToBoundary,
+    /// Sort the children of the cursor, expects `a` (ascending) or `d` (descending) as an
+    /// argument
+    Sort,
+    /// Convert the cursor to a different type of node whilst keeping its children, expects a
+    /// node char as an argument
+    Convert,
+    /// Move through the history in the order the changes were made, across branches, expects `-`
+    /// (earlier) or `+` (later) as an argument
+    TimeTravel,
+    /// Move the cursor to the root of the tree
+    MoveToRoot,
+    /// Move the cursor from a container to its last descendant, or from any other node to its
+    /// parent (like Vim's `%`).  Unlike [`JumpToBoundary`](Command::JumpToBoundary), this goes all
+    /// the way down to a leaf and never visits the first child.
+    JumpToMatch,
+    /// Show every key binding, until the next key is pressed
+    Help,
+    /// Remember the cursor's location, expects a mark argument
+    SetMark,
+    /// Move the cursor to a location remembered by [`SetMark`](Command::SetMark), expects a mark
+    /// argument
+    JumpToMark,
+    /// Move the cursor to the next or previous node which doesn't match the schema loaded by
+    /// `:validate`, or which has a duplicated key
+    JumpToViolation(Side),
+    /// Write the tree to the file it was opened from (like `:w`)
+    Write,
 }
 
 impl Command {
+    /// Every possible [`Command`]
+    const ALL: [Command; 44] = [
+        Command::Quit,
+        Command::Replace,
+        Command::InsertChild,
+        Command::InsertBefore,
+        Command::InsertAfter,
+        Command::MoveCursor(Direction::Down),
+        Command::MoveCursor(Direction::Up),
+        Command::MoveCursor(Direction::Prev),
+        Command::MoveCursor(Direction::Next),
+        Command::Undo,
+        Command::Redo,
+        Command::GenerateSamples,
+        Command::CheckRoundTrip,
+        Command::Yank,
+        Command::Paste,
+        Command::Duplicate,
+        Command::Explode,
+        Command::Merge,
+        Command::Delete,
+        Command::Rename,
+        Command::MoveNode(Side::Prev),
+        Command::MoveNode(Side::Next),
+        Command::ArenaStats,
+        Command::Fold,
+        Command::RecordMacro,
+        Command::ReplayMacro,
+        Command::Visual,
+        Command::Search,
+        Command::SearchAgain(Side::Next),
+        Command::SearchAgain(Side::Prev),
+        Command::CommandLine,
+        Command::CycleFormat,
+        Command::JumpToBoundary,
+        Command::Sort,
+        Command::Convert,
+        Command::TimeTravel,
+        Command::MoveToRoot,
+        Command::JumpToMatch,
+        Command::Help,
+        Command::SetMark,
+        Command::JumpToMark,
+        Command::JumpToViolation(Side::Next),
+        Command::JumpToViolation(Side::Prev),
+        Command::Write,
+    ];
+
+    /// Returns the [`Command`] with a given [summary string](Command::summary_string), or
+    /// [`None`] if no such command exists
+    pub fn from_summary_string(name: &str) -> Option<Command> {
+        Command::ALL
+            .iter()
+            .find(|c| c.summary_string() == name)
+            .cloned()
+    }
+
     /// Returns a lower-case summary string of the given command
     pub fn summary_string(&self) -> &'static str {
         match self {
@@ -176,27 +352,173 @@ impl Command {
             Command::MoveCursor(Direction::Next) => "move to next sibling",
             Command::Undo => "undo",
             Command::Redo => "redo",
+            Command::GenerateSamples => "generate samples",
+            Command::CheckRoundTrip => "check round trip",
+            Command::Yank => "yank",
+            Command::Paste => "paste",
+            Command::Duplicate => "duplicate",
+            Command::Explode => "explode",
+            Command::Merge => "merge",
+            Command::Delete => "delete",
+            Command::Rename => "rename",
+            Command::MoveNode(Side::Prev) => "move node up",
+            Command::MoveNode(Side::Next) => "move node down",
+            Command::ArenaStats => "arena stats",
+            Command::Fold => "fold",
+            Command::RecordMacro => "record macro",
+            Command::ReplayMacro => "replay macro",
+            Command::Visual => "visual mode",
+            Command::Search => "search",
+            Command::SearchAgain(Side::Next) => "next match",
+            Command::SearchAgain(Side::Prev) => "previous match",
+            Command::CommandLine => "command line",
+            Command::CycleFormat => "cycle format",
+            Command::JumpToBoundary => "jump between first/last child",
+            Command::Sort => "sort",
+            Command::Convert => "convert",
+            Command::TimeTravel => "time travel",
+            Command::MoveToRoot => "move to root",
+            Command::JumpToMatch => "jump to last descendant/parent",
+            Command::Help => "help",
+            Command::SetMark => "set mark",
+            Command::JumpToMark => "jump to mark",
+            Command::JumpToViolation(Side::Next) => "next violation",
+            Command::JumpToViolation(Side::Prev) => "previous violation",
+            Command::Write => "write",
         }
     }
 }
 
-/// Mapping of keys to commands.
-/// Shortcut definition, also allows us to change the type if needed.
-pub type KeyMap = std::collections::HashMap<char, Command>;
+/// Mapping of keys to commands.  As well as single keys, commands can be bound to sequences of
+/// keys (like `gg`).  A sequence takes priority over the single key that it starts with, so
+/// typing that key waits for the rest of the sequence until the editor's sequence timeout runs
+/// out.  Sequences which start with the leader key (see [`config::DEFAULT_LEADER`]) group
+/// commands without using up any single keys, since the leader isn't bound on its own.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct KeyMap {
+    bindings: std::collections::HashMap<char, Command>,
+    /// The commands bound to sequences of more than one key
+    sequences: std::collections::HashMap<String, Command>,
+}
+
+impl KeyMap {
+    /// Returns the [`Command`] bound to a given key, if any
+    pub fn get(&self, key: char) -> Option<&Command> {
+        self.bindings.get(&key)
+    }
+
+    /// Returns the [`Command`] bound to a given sequence of more than one key, if any
+    pub fn get_sequence(&self, keys: &str) -> Option<&Command> {
+        self.sequences.get(keys)
+    }
+
+    /// Returns `true` if `keys` is the start of (but not all of) a sequence of keys that is bound
+    /// to a command
+    pub fn is_sequence_prefix(&self, keys: &str) -> bool {
+        !keys.is_empty()
+            && self
+                .sequences
+                .keys()
+                .any(|s| s.len() > keys.len() && s.starts_with(keys))
+    }
+
+    /// Binds a key, or a sequence of keys, to a command
+    pub fn bind(&mut self, keys: &str, command: Command) {
+        let mut chars = keys.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => {
+                self.bindings.insert(c, command);
+            }
+            _ => {
+                self.sequences.insert(keys.to_string(), command);
+            }
+        }
+    }
+
+    /// Reads a [`KeyMap`] from a keybinding file (see the [`config`] module for the format).  Any
+    /// keys not bound by the file keep their bindings from [`default_keymap`].  Bindings to
+    /// commands that don't exist are skipped, and returned as warnings alongside the keymap.
+    pub fn from_config(
+        path: &std::path::Path,
+    ) -> std::result::Result<(KeyMap, Vec<config::ConfigError>), config::ConfigError> {
+        KeyMap::from_config_str(&std::fs::read_to_string(path)?)
+    }
+
+    /// Same as [`from_config`](KeyMap::from_config), but reads the config from a string
+    pub fn from_config_str(
+        text: &str,
+    ) -> std::result::Result<(KeyMap, Vec<config::ConfigError>), config::ConfigError> {
+        let (bindings, leader, warnings) = config::parse_bindings(text)?;
+        // The default leader bindings move to the configured leader, and `<leader>` is only
+        // expanded once the whole file has been read, so the leader can be set anywhere in it
+        let leader = leader.unwrap_or(config::DEFAULT_LEADER);
+        let mut keymap = keymap_with_leader(leader);
+        for (keys, command) in bindings {
+            keymap.bind(&config::expand_key_names(&keys, leader), command);
+        }
+        Ok((keymap, warnings))
+    }
+}
 
 pub fn default_keymap() -> KeyMap {
-    hmap::hmap! {
-        'q' => Command::Quit,
+    keymap_with_leader(config::DEFAULT_LEADER)
+}
+
+/// The default keymap, with its leader sequences starting with `leader`
+fn keymap_with_leader(leader: char) -> KeyMap {
+    let bindings = hmap::hmap! {
+        'Q' => Command::Quit,
         'i' => Command::InsertBefore,
         'a' => Command::InsertAfter,
         'o' => Command::InsertChild,
         'r' => Command::Replace,
         'c' => Command::MoveCursor(Direction::Down),
         'p' => Command::MoveCursor(Direction::Up),
+        'l' => Command::MoveCursor(Direction::Down),
+        'h' => Command::MoveCursor(Direction::Up),
         'k' => Command::MoveCursor(Direction::Prev),
         'j' => Command::MoveCursor(Direction::Next),
         'u' => Command::Undo,
-        'R' => Command::Redo
+        'R' => Command::Redo,
+        'S' => Command::GenerateSamples,
+        'C' => Command::CheckRoundTrip,
+        'y' => Command::Yank,
+        'P' => Command::Paste,
+        'D' => Command::Duplicate,
+        'E' => Command::Explode,
+        'M' => Command::Merge,
+        'd' => Command::Delete,
+        'e' => Command::Rename,
+        'A' => Command::ArenaStats,
+        'z' => Command::Fold,
+        'q' => Command::RecordMacro,
+        '@' => Command::ReplayMacro,
+        'v' => Command::Visual,
+        '/' => Command::Search,
+        'n' => Command::SearchAgain(Side::Next),
+        'N' => Command::SearchAgain(Side::Prev),
+        ':' => Command::CommandLine,
+        'F' => Command::CycleFormat,
+        '%' => Command::JumpToBoundary,
+        'B' => Command::JumpToMatch,
+        's' => Command::Sort,
+        'T' => Command::Convert,
+        'g' => Command::TimeTravel,
+        'K' => Command::MoveNode(Side::Prev),
+        'J' => Command::MoveNode(Side::Next),
+        '?' => Command::Help,
+        'm' => Command::SetMark,
+        '\'' => Command::JumpToMark
+    };
+    let sequences = hmap::hmap! {
+        "gg".to_string() => Command::MoveToRoot,
+        "]v".to_string() => Command::JumpToViolation(Side::Next),
+        "[v".to_string() => Command::JumpToViolation(Side::Prev),
+        format!("{}w", leader) => Command::Write
+    };
+    KeyMap {
+        bindings,
+        sequences,
     }
 }
 
@@ -207,8 +529,9 @@ enum Action {
     Undefined,
     /// Quit Sapling
     Quit,
-    /// Replace the selected node with a node represented by some [`char`]
-    Replace(char),
+    /// Replace the selected node with a node represented by some replace sequence (see
+    /// [`Ast::replace_seqs`])
+    Replace(String),
     /// Insert a new node (given by some [`char`]) as the first child of the selected node
     InsertChild(char),
     /// Insert a new node (given by some [`char`]) as the first child of the selected node
@@ -221,9 +544,103 @@ enum Action {
     Undo,
     /// Redo a change
     Redo,
+    /// Append copies of the cursor's first child, where the number of copies is given by some
+    /// [`char`]
+    GenerateSamples(char),
+    /// Check that the tree survives being converted to text and parsed back
+    CheckRoundTrip,
+    /// Copy the node under the cursor into the register
+    Yank,
+    /// Paste a copy of the register's contents after the cursor
+    Paste,
+    /// Insert a copy of the node under the cursor after it
+    Duplicate,
+    /// Split every child of the cursor into its own container
+    Explode,
+    /// Merge the children of the cursor's children into one container
+    Merge,
+    /// Delete the node under the cursor
+    Delete,
+    /// Change the key of the node under the cursor
+    Rename,
+    /// Swap the node under the cursor with one of its siblings
+    MoveNode(Side),
+    /// Log how much memory the arena is using
+    ArenaStats,
+    /// Hide or show the children of the cursor in the tree view
+    ToggleFold,
+    /// Hide the children of every node at the depth given by the count
+    FoldAtDepth,
+    /// Show the children of every node
+    UnfoldAll,
+    /// Start recording a macro into the register given by some [`char`]
+    RecordMacro(char),
+    /// Replay the macro in the register given by some [`char`]
+    ReplayMacro(char),
+    /// Remember the cursor's location under the mark given by some [`char`]
+    SetMark(char),
+    /// Move the cursor to the location remembered under the mark given by some [`char`]
+    JumpToMark(char),
+    /// Move the cursor to the next or previous node which doesn't match the schema, or which has
+    /// a duplicated key
+    JumpToViolation(Side),
+    /// Start or stop selecting a range of siblings
+    Visual,
+    /// Prompt for a query, and move the cursor to the next node which matches it
+    Search,
+    /// Move the cursor to the next or previous match of the last search
+    SearchAgain(Side),
+    /// Open the command line
+    CommandLine,
+    /// Switch to the next format style
+    CycleFormat,
+    /// Jump between a container and its first and last children
+    JumpToBoundary,
+    /// Sort the children of the cursor, in descending order if the argument is `true`
+    Sort(bool),
+    /// Convert the cursor to the type of node represented by a replace sequence, keeping its
+    /// children
+    Convert(String),
+    /// Move to the previous or next change in the order the changes were made
+    TimeTravel(Side),
+    /// Move the cursor to the root of the tree
+    MoveToRoot,
+    /// Move the cursor from a container to its last descendant, or from any other node to its
+    /// parent
+    JumpToMatch,
+    /// Show the help screen
+    Help,
+    /// Write the tree to the file it was opened from
+    Write,
 }
 
 impl Action {
+    /// Returns `true` if this action can only move the cursor (or change nothing at all), so
+    /// doesn't change how the tree is laid out (see [`Editor::needs_full_render`])
+    pub fn only_moves_cursor(&self) -> bool {
+        matches!(
+            self,
+            Action::Undefined
+                | Action::MoveCursor(_)
+                | Action::CheckRoundTrip
+                | Action::Yank
+                | Action::ArenaStats
+                | Action::RecordMacro(_)
+                | Action::SetMark(_)
+                | Action::JumpToMark(_)
+                | Action::JumpToViolation(_)
+                | Action::Visual
+                | Action::Search
+                | Action::SearchAgain(_)
+                | Action::CommandLine
+                | Action::JumpToBoundary
+                | Action::MoveToRoot
+                | Action::JumpToMatch
+                | Action::Help
+                | Action::Write
+        )
+    }
+
     /// Returns a lower-case summary of the given command, along with the color with which it
     /// should be displayed in the log.
     pub fn description_and_color(&self) -> (String, Color) {
@@ -246,42 +663,281 @@ impl Action {
             Action::MoveCursor(Direction::Next) => ("move to next sibling".to_string(), COL_MOVE),
             Action::Undo => ("undo a change".to_string(), COL_HISTORY),
             Action::Redo => ("redo a change".to_string(), COL_HISTORY),
+            Action::GenerateSamples(c) => (format!("generate '{}' samples", c), COL_INSERT),
+            Action::CheckRoundTrip => ("check round trip".to_string(), Color::LIGHT_MAGENTA),
+            Action::Yank => ("yank cursor".to_string(), Color::LIGHT_CYAN),
+            Action::Paste => ("paste after cursor".to_string(), COL_INSERT),
+            Action::Duplicate => ("duplicate cursor".to_string(), COL_INSERT),
+            Action::Explode => ("explode cursor".to_string(), Color::CYAN),
+            Action::Merge => ("merge cursor".to_string(), Color::CYAN),
+            Action::Delete => ("delete cursor".to_string(), Color::LIGHT_RED),
+            Action::Rename => ("rename cursor".to_string(), Color::CYAN),
+            Action::MoveNode(Side::Prev) => ("move cursor up".to_string(), Color::CYAN),
+            Action::MoveNode(Side::Next) => ("move cursor down".to_string(), Color::CYAN),
+            Action::ArenaStats => ("show arena stats".to_string(), Color::LIGHT_MAGENTA),
+            Action::ToggleFold => ("toggle fold".to_string(), COL_MOVE),
+            Action::FoldAtDepth => ("fold at depth".to_string(), COL_MOVE),
+            Action::UnfoldAll => ("unfold all".to_string(), COL_MOVE),
+            Action::RecordMacro(c) => (format!("record macro '{}'", c), COL_HISTORY),
+            Action::ReplayMacro(c) => (format!("replay macro '{}'", c), COL_HISTORY),
+            Action::SetMark(c) => (format!("set mark '{}'", c), COL_MOVE),
+            Action::JumpToMark(c) => (format!("jump to mark '{}'", c), COL_MOVE),
+            Action::JumpToViolation(Side::Next) => ("move to next violation".to_string(), COL_MOVE),
+            Action::JumpToViolation(Side::Prev) => {
+                ("move to previous violation".to_string(), COL_MOVE)
+            }
+            Action::Visual => ("toggle visual mode".to_string(), COL_MOVE),
+            Action::Search => ("search".to_string(), COL_MOVE),
+            Action::SearchAgain(Side::Next) => ("move to next match".to_string(), COL_MOVE),
+            Action::SearchAgain(Side::Prev) => ("move to previous match".to_string(), COL_MOVE),
+            Action::CommandLine => ("open command line".to_string(), Color::LIGHT_MAGENTA),
+            Action::CycleFormat => ("cycle format".to_string(), Color::LIGHT_MAGENTA),
+            Action::JumpToBoundary => ("jump between first/last child".to_string(), COL_MOVE),
+            Action::Sort(false) => ("sort ascending".to_string(), Color::CYAN),
+            Action::Sort(true) => ("sort descending".to_string(), Color::CYAN),
+            Action::Convert(c) => (format!("convert cursor to '{}'", c), Color::CYAN),
+            Action::TimeTravel(Side::Prev) => ("go to earlier change".to_string(), COL_HISTORY),
+            Action::TimeTravel(Side::Next) => ("go to later change".to_string(), COL_HISTORY),
+            Action::MoveToRoot => ("move to root".to_string(), COL_MOVE),
+            Action::JumpToMatch => ("jump to last descendant/parent".to_string(), COL_MOVE),
+            Action::Help => ("show help".to_string(), Color::LIGHT_MAGENTA),
+            Action::Write => ("write file".to_string(), Color::LIGHT_MAGENTA),
         }
     }
 }
 
+/// Returns the location of a problem at `path` under `root`, as a [path
+/// expression](crate::ast::extension::LanguageExtension::path_expr) if the AST has them and a
+/// [`breadcrumb`] if it doesn't.  If the path goes through a node whose key is shared with one of
+/// its siblings, the location doesn't say which of them is meant, so the index of the deepest such
+/// node is added (like `$.a (field 2)`).
+fn problem_location<'arena, Node: Ast<'arena>>(root: &'arena Node, path: &CursorPath) -> String {
+    let nodes: Vec<&'arena Node> = path.node_iter(root).collect();
+    let ambiguous_index = nodes
+        .windows(2)
+        .zip(path.iter())
+        .rev()
+        .find(|(parent_and_child, _)| {
+            let (parent, child) = (parent_and_child[0], parent_and_child[1]);
+            child.key().is_some_and(|key| {
+                let siblings = parent.children().iter();
+                siblings.filter(|s| s.key() == Some(key)).count() > 1
+            })
+        })
+        .map(|(_, index)| *index);
+    let location = match root.extension() {
+        Some(extension) => extension.path_expr(path),
+        None => breadcrumb(root, path),
+    };
+    match ambiguous_index {
+        Some(index) => format!("{} (field {})", location, index),
+        None => location,
+    }
+}
+
 /// Attempt to convert a command as a `&`[`str`] into an [`Action`].
 /// This parses the string from the start, and returns when it finds a valid command.
 ///
-/// Therefore, `"q489flshb"` will be treated like `"q"`, and will return `Some(Action::Quit)` even
-/// though `"q489flshb"` is not technically valid.
+/// Therefore, `"Q489flshb"` will be treated like `"Q"`, and will return `Some(Action::Quit)` even
+/// though `"Q489flshb"` is not technically valid.
 /// This function is run every time the user types a command character, and so the user would not
 /// be able to input `"q489flshb"` to this function because doing so would require them to first
-/// input every possible prefix of `"q489flshb"`, including `"q"`.
+/// input every possible prefix of `"Q489flshb"`, including `"Q"`.
+///
+/// A command can be prefixed by a decimal count (like `"3d"`), which tells the command how many
+/// times it should be repeated.  A missing count or a count of 0 is treated as 1.
+///
+/// If `wait_for_sequences` is `true`, a command which is the start of a multi-key sequence (see
+/// [`KeyMap`]) is treated as incomplete, even if it would be a complete command on its own.
+///
+/// `replace_seqs` are the sequences that the cursor can be replaced with (see
+/// [`Ast::replace_seqs`]), which are read after commands like `r` (see [`parse_replace_seq`]).
 ///
 /// This returns:
 /// - [`None`] if the command is incomplete.
 /// - [`Action::Undefined`] if the command is not defined (like the command "X").
-/// - The corresponding [`Action`], otherwise.
-fn parse_command(keymap: &KeyMap, command: &str) -> Option<Action> {
-    let mut command_char_iter = command.chars();
+/// - The count and the corresponding [`Action`], otherwise.
+fn parse_command(
+    keymap: &KeyMap,
+    replace_seqs: &[String],
+    command: &str,
+    wait_for_sequences: bool,
+) -> Option<(usize, Action)> {
+    let (count, command) = split_count(command);
+    // Wait in case the user is part way through typing a sequence
+    if wait_for_sequences && keymap.is_sequence_prefix(command) {
+        return None;
+    }
 
-    // Consume the first char of the command
-    return if let Some(command) = command_char_iter.next().and_then(|c| keymap.get(&c)) {
-        match command {
-            // "q" quits Sapling
-            Command::Quit => Some(Action::Quit),
-            Command::InsertChild => command_char_iter.next().map(Action::InsertChild),
-            Command::InsertBefore => command_char_iter.next().map(Action::InsertBefore),
-            Command::InsertAfter => command_char_iter.next().map(Action::InsertAfter),
-            Command::Replace => command_char_iter.next().map(Action::Replace),
-            Command::MoveCursor(direction) => Some(Action::MoveCursor(*direction)),
-            Command::Undo => Some(Action::Undo),
-            Command::Redo => Some(Action::Redo),
+    // Find the command, and the chars after it (which are the command's arguments).  A sequence
+    // takes priority over the single key that it starts with, so look for the longest sequence
+    // at the start of the command first.
+    let sequence = command
+        .char_indices()
+        .skip(1)
+        .map(|(i, c)| i + c.len_utf8())
+        .filter_map(|end| keymap.get_sequence(&command[..end]).map(|cmd| (cmd, end)))
+        .last();
+    let (command, args) = match sequence {
+        Some((command_for_sequence, end)) => (command_for_sequence, &command[end..]),
+        None => {
+            let mut chars = command.chars();
+            // Return early if the command is incomplete
+            match chars.next() {
+                Some(c) => match keymap.get(c) {
+                    Some(command_for_key) => (command_for_key, chars.as_str()),
+                    None => return Some((count, Action::Undefined)),
+                },
+                None => return None,
+            }
         }
-    } else {
-        None
     };
+    let mut command_char_iter = args.chars();
+    let action = match command {
+        // "Q" quits Sapling
+        Command::Quit => Some(Action::Quit),
+        Command::InsertChild => command_char_iter.next().map(Action::InsertChild),
+        Command::InsertBefore => command_char_iter.next().map(Action::InsertBefore),
+        Command::InsertAfter => command_char_iter.next().map(Action::InsertAfter),
+        Command::Replace => {
+            parse_replace_seq(args, replace_seqs, wait_for_sequences).map(Action::Replace)
+        }
+        Command::MoveCursor(direction) => Some(Action::MoveCursor(*direction)),
+        Command::Undo => Some(Action::Undo),
+        Command::Redo => Some(Action::Redo),
+        Command::GenerateSamples => command_char_iter.next().map(Action::GenerateSamples),
+        Command::CheckRoundTrip => Some(Action::CheckRoundTrip),
+        Command::Yank => Some(Action::Yank),
+        Command::Paste => Some(Action::Paste),
+        Command::Duplicate => Some(Action::Duplicate),
+        Command::Explode => Some(Action::Explode),
+        Command::Merge => Some(Action::Merge),
+        Command::Delete => Some(Action::Delete),
+        Command::Rename => Some(Action::Rename),
+        Command::MoveNode(side) => Some(Action::MoveNode(*side)),
+        Command::ArenaStats => Some(Action::ArenaStats),
+        Command::Fold => command_char_iter.next().map(|c| match c {
+            'a' => Action::ToggleFold,
+            'M' => Action::FoldAtDepth,
+            'R' => Action::UnfoldAll,
+            _ => Action::Undefined,
+        }),
+        Command::RecordMacro => command_char_iter.next().map(Action::RecordMacro),
+        Command::ReplayMacro => command_char_iter.next().map(Action::ReplayMacro),
+        Command::SetMark => command_char_iter.next().map(Action::SetMark),
+        Command::JumpToMark => command_char_iter.next().map(Action::JumpToMark),
+        Command::JumpToViolation(side) => Some(Action::JumpToViolation(*side)),
+        Command::Visual => Some(Action::Visual),
+        Command::Search => Some(Action::Search),
+        Command::SearchAgain(side) => Some(Action::SearchAgain(*side)),
+        Command::CommandLine => Some(Action::CommandLine),
+        Command::CycleFormat => Some(Action::CycleFormat),
+        Command::JumpToBoundary => Some(Action::JumpToBoundary),
+        Command::Sort => command_char_iter.next().map(|c| match c {
+            'a' => Action::Sort(false),
+            'd' => Action::Sort(true),
+            _ => Action::Undefined,
+        }),
+        Command::Convert => {
+            parse_replace_seq(args, replace_seqs, wait_for_sequences).map(Action::Convert)
+        }
+        Command::MoveToRoot => Some(Action::MoveToRoot),
+        Command::JumpToMatch => Some(Action::JumpToMatch),
+        Command::Help => Some(Action::Help),
+        Command::Write => Some(Action::Write),
+        Command::TimeTravel => command_char_iter.next().map(|c| match c {
+            '-' => Action::TimeTravel(Side::Prev),
+            '+' => Action::TimeTravel(Side::Next),
+            _ => Action::Undefined,
+        }),
+    };
+    action.map(|a| (count, a))
+}
+
+/// Reads the replace sequence typed after a command like `r`, given every sequence in
+/// `replace_seqs`.  Like a multi-key sequence of commands, the typed chars are incomplete until
+/// they either match exactly one sequence, or aren't the start of any sequence (in which case
+/// they are returned anyway, so that the command can fail with a helpful message).  If they are
+/// both a sequence and the start of a longer one, they are only complete if `wait_for_sequences`
+/// is `false`.
+fn parse_replace_seq(
+    typed: &str,
+    replace_seqs: &[String],
+    wait_for_sequences: bool,
+) -> Option<String> {
+    let is_prefix = replace_seqs
+        .iter()
+        .any(|seq| seq.len() > typed.len() && seq.starts_with(typed));
+    let is_match = replace_seqs.iter().any(|seq| seq == typed);
+    if typed.is_empty() || (is_prefix && (wait_for_sequences || !is_match)) {
+        None
+    } else {
+        Some(typed.to_string())
+    }
+}
+
+/// Splits the decimal count off the front of a command, returning the count and the rest of the
+/// command.  A missing count or a count of 0 is treated as 1.
+fn split_count(command: &str) -> (usize, &str) {
+    let count_len = command
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(command.len());
+    let (count_str, command) = command.split_at(count_len);
+    // Saturate rather than overflowing if the user types a ridiculously large count
+    let count = count_str
+        .bytes()
+        .fold(0usize, |n, d| {
+            n.saturating_mul(10).saturating_add((d - b'0') as usize)
+        })
+        .max(1);
+    (count, command)
+}
+
+/// The reasons that the user could be asked to type a line of text.  Whilst a prompt is open,
+/// keypresses are added to the prompt's text instead of being treated as commands.
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Prompt {
+    /// The typed text is the key of a new child that will be inserted under the cursor
+    InsertKeyedChild,
+    /// The typed text is the key of a new sibling that will be inserted on the given side of the
+    /// cursor
+    InsertKeyedSibling(Side),
+    /// The typed text is the new key of the node under the cursor
+    RenameKey,
+    /// The typed text is a query to search for
+    Search,
+    /// The typed text is a command (see the [`command_line`] module)
+    CommandLine,
+    /// The user is asked whether to replace the cursor with the node represented by the given
+    /// replace sequence, even though that would discard the given number of children.  Unlike
+    /// the other prompts, this is answered by a single key: `y` replaces the node, and any other
+    /// key cancels.
+    ConfirmReplace(String, usize),
+}
+
+impl Prompt {
+    /// Returns the message that is shown to the user whilst they are typing into this prompt
+    fn message(&self) -> String {
+        match self {
+            Prompt::InsertKeyedChild | Prompt::InsertKeyedSibling(_) => "New key: ".to_string(),
+            Prompt::RenameKey => "Rename key: ".to_string(),
+            Prompt::Search => "/".to_string(),
+            Prompt::CommandLine => ":".to_string(),
+            Prompt::ConfirmReplace(_, 1) => "Replace and discard 1 child? (y/n) ".to_string(),
+            Prompt::ConfirmReplace(_, num_children) => {
+                format!("Replace and discard {} children? (y/n) ", num_children)
+            }
+        }
+    }
+}
+
+/// Logs a message at a given level (e.g. `warn`) and marks the command that is being executed as
+/// failed, so that replaying a macro stops at the first command which goes wrong.
+macro_rules! fail {
+    ($editor: expr, $level: ident, $($arg: tt)*) => {{
+        log::$level!($($arg)*);
+        $editor.command_failed = true;
+    }};
 }
 
 /// A struct to hold the top-level components of the editor.
@@ -290,355 +946,1926 @@ pub struct Editor<'arena, Node: Ast<'arena>> {
     tree: &'arena mut DAG<'arena, Node>,
     /// The style that the tree is being printed to the screen
     format_style: Node::FormatStyle,
-    /// The `tuikit` terminal that the `Editor` is rendering to
-    term: Term,
     /// The current contents of the command buffer
     command: String,
     /// The configured key map
     keymap: KeyMap,
     /// A list of the commands that have been executed, along with a summary of what they mean
     command_log: command_log::CommandLog,
+    /// The nodes that were most recently yanked (empty if nothing has been yanked).  Because
+    /// nodes are immutable, this can safely hold references into the arena instead of copies.
+    register: Vec<&'arena Node>,
+    /// The clipboard which yanked nodes are also copied to as text
+    clipboard: clipboard::Clipboard,
+    /// In visual mode, the index of the sibling where the selection started.  The selection is
+    /// every sibling between this and the cursor (see [`Editor::selection`]).
+    selection_anchor: Option<usize>,
+    /// The query that was most recently searched for, if any
+    search_query: Option<search::Query>,
+    /// The text of the schema that was most recently loaded by `:validate`, if any.  The tree is
+    /// checked again whenever the cursor jumps between violations, so that they stay up to date
+    /// with any edits.
+    schema: Option<String>,
+    /// The problems found by `:validate` or `:lint`, which are listed instead of the tree until
+    /// the next key is pressed
+    violation_list: Option<Vec<String>>,
+    /// The nodes in the tree whose keys are duplicated, which are highlighted in the tree view
+    duplicate_keys: lint::DuplicateKeys<Node>,
+    /// The container that repeated presses of `%` are cycling around (see
+    /// [`DAG::jump_to_boundary`])
+    boundary_container: Option<CursorPath>,
+    /// The file that the tree was most recently written to, if any
+    path: Option<std::path::PathBuf>,
+    /// If `true`, a newline is added to the end of every file that is written
+    trailing_newline: bool,
+    /// How long to wait for the rest of a multi-key sequence before running the keys typed so far
+    sequence_timeout: std::time::Duration,
+    /// The colours that the tree is drawn with
+    theme: theme::Theme,
+    /// If `true`, the terminal reports mouse events (see [`config::EditorConfig::mouse`])
+    mouse: bool,
+    /// A message to show in the status bar (e.g. the error from a command typed into the command
+    /// line), which disappears when the next key is pressed
+    status: Option<String>,
+    /// The cached text of the status line
+    status_line: status_line::StatusLine<Node>,
+    /// The index of the first line of the tree that is visible on the screen
+    scroll: usize,
+    /// The prompt that the user is currently typing into (if any), along with the text that has
+    /// been typed so far
+    prompt: Option<(Prompt, String)>,
+    /// The node which will replace the cursor if the user answers a [`Prompt::ConfirmReplace`]
+    /// with `y`, so that it doesn't have to be converted again
+    pending_replacement: Option<Node>,
+    /// The nodes whose children are hidden in the tree view.  Nodes are identified by their
+    /// address in the arena, so editing a folded node (or any of its descendants) unfolds it, as
+    /// does moving the cursor inside it.
+    folded: std::collections::HashSet<*const Node>,
+    /// The keys recorded into each macro register
+    macros: std::collections::HashMap<char, Vec<Key>>,
+    /// The register and keys of the macro that is currently being recorded, if any
+    recording: Option<(char, Vec<Key>)>,
+    /// The register of the macro that was most recently replayed, which is replayed by `@@`
+    last_macro: Option<char>,
+    /// The registers of the macros that are currently being replayed (innermost last), so that a
+    /// macro which replays itself stops instead of looping forever
+    replaying: Vec<char>,
+    /// The locations remembered by each mark.  These are paths in the tree under `marks_root`,
+    /// and are moved to the current tree whenever it changes (see [`Editor::update_marks`]).
+    marks: std::collections::HashMap<char, CursorPath>,
+    /// The root of the tree that the paths in `marks` refer to
+    marks_root: &'arena Node,
+    /// Set whenever a command fails, so that replaying a macro can stop at the first failure
+    command_failed: bool,
+    /// If `true`, the help screen is drawn instead of the tree until the next key is pressed
+    show_help: bool,
+    /// The root of the tree when it was last written or loaded.  The tree has unsaved changes
+    /// whenever its root is a different node (see [`Editor::is_modified`]).
+    saved_root: *const Node,
+    /// The layout of the tree when it was last drawn, if it has been drawn
+    tree_layout: std::cell::RefCell<Option<TreeLayout<Node>>>,
+    /// Set by every command which might change how the tree is drawn (rather than just moving the
+    /// cursor), and cleared when the tree is laid out again
+    render_dirty: std::cell::Cell<bool>,
 }
 
 impl<'arena, Node: Ast<'arena> + 'arena> Editor<'arena, Node> {
-    /// Create a new [`Editor`] with a given tree
+    /// Create a new [`Editor`] with a given tree, format style and keymap.  This is a shorthand for
+    /// [`Editor::with_config`] which leaves every other option at its default, apart from using an
+    /// [in-memory clipboard](clipboard::ClipboardKind::InMemory) rather than the system one.
     pub fn new(
         tree: &'arena mut DAG<'arena, Node>,
         format_style: Node::FormatStyle,
         keymap: KeyMap,
     ) -> Editor<'arena, Node> {
-        let term = Term::new().unwrap();
+        Self::with_config(
+            tree,
+            config::EditorConfig {
+                format: format_style,
+                keymap,
+                path: None,
+                trailing_newline: false,
+                sequence_timeout: config::DEFAULT_SEQUENCE_TIMEOUT,
+                theme: theme::Theme::default(),
+                mouse: true,
+                clipboard: clipboard::ClipboardKind::InMemory,
+            },
+        )
+    }
+
+    /// Create a new [`Editor`] with a given tree, using the options in `config`
+    pub fn with_config(
+        tree: &'arena mut DAG<'arena, Node>,
+        config: config::EditorConfig<Node::FormatStyle>,
+    ) -> Editor<'arena, Node> {
+        let saved_root = tree.root();
+        let marks_root = tree.root();
         Editor {
             tree,
-            term,
-            format_style,
+            format_style: config.format,
             command: String::new(),
-            keymap,
+            keymap: config.keymap,
             command_log: command_log::CommandLog::new(10),
+            register: Vec::new(),
+            clipboard: clipboard::Clipboard::new(config.clipboard),
+            selection_anchor: None,
+            search_query: None,
+            schema: None,
+            violation_list: None,
+            duplicate_keys: lint::DuplicateKeys::new(),
+            boundary_container: None,
+            path: config.path,
+            trailing_newline: config.trailing_newline,
+            sequence_timeout: config.sequence_timeout,
+            theme: config.theme,
+            mouse: config.mouse,
+            status: None,
+            status_line: status_line::StatusLine::new(),
+            scroll: 0,
+            prompt: None,
+            pending_replacement: None,
+            folded: std::collections::HashSet::new(),
+            macros: std::collections::HashMap::new(),
+            recording: None,
+            last_macro: None,
+            replaying: Vec::new(),
+            marks: std::collections::HashMap::new(),
+            marks_root,
+            command_failed: false,
+            show_help: false,
+            saved_root,
+            tree_layout: std::cell::RefCell::new(None),
+            render_dirty: std::cell::Cell::new(false),
         }
     }
 
     /* ===== COMMAND FUNCTIONS ===== */
 
-    /// Replace the node under the cursor with the node represented by a given [`char`]
-    fn replace_cursor(&mut self, c: char) {
-        if self.tree.cursor().is_replace_char(c) {
-            // We know that `c` corresponds to a valid node, so we can unwrap
-            let new_node = self.tree.cursor().from_char(c).unwrap();
-            log::debug!("Replacing with '{}'/{:?}", c, new_node);
-            self.tree.replace_cursor(new_node);
-        } else {
-            log::warn!("Cannot replace node with '{}'", c);
+    /// Replace the node under the cursor with the node represented by a given replace sequence
+    /// (see [`Ast::replace_seqs`]), keeping as many of its children as possible (see
+    /// [`Ast::convert`]).  If that would discard any children, the user is asked to confirm the
+    /// replacement first.
+    fn replace_cursor(&mut self, seq: String) {
+        let cursor = self.tree.cursor();
+        if !cursor.is_replace_seq(&seq) {
+            fail!(self, warn, "Cannot replace node with '{}'", seq);
+            return;
+        }
+        // We know that `seq` corresponds to a valid node, so we can unwrap
+        let new_node = cursor.convert(&seq, self.tree.arena()).unwrap();
+        let num_discarded = cursor
+            .children()
+            .len()
+            .saturating_sub(new_node.children().len());
+        if num_discarded > 0 {
+            log::debug!("Asking before replacing with '{}'", seq);
+            self.prompt = Some((Prompt::ConfirmReplace(seq, num_discarded), String::new()));
+            self.pending_replacement = Some(new_node);
+            return;
+        }
+        log::debug!("Replacing with '{}'/{:?}", seq, new_node);
+        self.tree.replace_cursor(new_node);
+    }
+
+    /// Answers a [`Prompt::ConfirmReplace`], replacing the cursor only if `key` is `y`
+    fn confirm_replace(&mut self, seq: &str, key: Key) {
+        // The node was converted when the prompt was opened, so we can unwrap
+        let new_node = self.pending_replacement.take().unwrap();
+        if key != Key::Char('y') {
+            self.status = Some("Replace cancelled".to_string());
+            return;
         }
+        log::debug!("Replacing with '{}'/{:?}", seq, new_node);
+        self.tree.replace_cursor(new_node);
     }
 
     /// Move the cursor
-    fn move_cursor(&mut self, direction: Direction) {
-        if let Some(error_message) = self.tree.move_cursor(direction) {
-            log::warn!("{}", error_message);
+    fn move_cursor(&mut self, direction: Direction, count: usize) {
+        if self.selection_anchor.is_some()
+            && (direction == Direction::Up || direction == Direction::Down)
+        {
+            fail!(
+                self,
+                warn,
+                "Selections can't contain nodes with different parents."
+            );
+            return;
+        }
+        for _ in 0..count {
+            // Folded nodes hide their children, so the cursor can't move into them
+            if direction == Direction::Down
+                && self.folded.contains(&(self.tree.cursor() as *const Node))
+            {
+                fail!(self, warn, "Cannot move into a folded node.");
+                break;
+            }
+            if let Some(error_message) = self.tree.move_cursor(direction) {
+                fail!(self, warn, "{}", error_message);
+                break;
+            }
+        }
+    }
+
+    /// Move the cursor to the root of the tree
+    fn move_to_root(&mut self) {
+        if self.selection_anchor.is_some() {
+            fail!(
+                self,
+                warn,
+                "Selections can't contain nodes with different parents."
+            );
+            return;
+        }
+        // The root always exists, so we can unwrap
+        self.tree.set_cursor_path(CursorPath::root()).unwrap();
+    }
+
+    /// Jump between a container and its first and last children.  If the cursor is still inside the
+    /// container of the previous jump then that cycle continues, otherwise a new cycle starts
+    /// around the cursor (or around the cursor's parent if the cursor has no children).
+    fn jump_to_boundary(&mut self, count: usize) {
+        for _ in 0..count {
+            let cursor_path = self.tree.cursor_path().clone();
+            let mut parent_path = cursor_path.clone();
+            let has_parent = parent_path.pop().is_some();
+            let container = match self.boundary_container.take() {
+                Some(c) if c == cursor_path || (has_parent && c == parent_path) => c,
+                _ if !self.tree.cursor().children().is_empty() || !has_parent => cursor_path,
+                _ => parent_path,
+            };
+            if let Some(error_message) = self.tree.jump_to_boundary(&container) {
+                fail!(self, warn, "{}", error_message);
+                break;
+            }
+            self.boundary_container = Some(container);
         }
     }
 
-    /// Insert new child as the first child of the selected node
+    /// Insert new child as the last child of the selected node.  If `c` is a valid insert char
+    /// that doesn't correspond to a node on its own, then the new child needs a key so the user is
+    /// prompted to type one.
     fn insert_child(&mut self, c: char) {
         let cursor = self.tree.cursor();
         if cursor.is_insert_char(c) {
-            if let Some(node) = cursor.from_char(c) {
+            if let Some(node) = cursor.node_for_char(c) {
                 if let Err(e) = self.tree.insert_child(node) {
-                    log::error!("{}", e);
+                    fail!(self, error, "{}", e);
                 } else {
                     log::debug!("Inserting with '{}'", c);
                 }
             } else {
-                log::warn!("Char '{}' does not correspond to a valid node", c);
+                log::debug!("Prompting for a key to insert");
+                self.prompt = Some((Prompt::InsertKeyedChild, String::new()));
             }
         } else {
-            log::warn!("Cannot insert node with '{}'", c);
+            fail!(self, warn, "Cannot insert node with '{}'", c);
         }
     }
 
-    /// Insert new child as the first child of the selected node
+    /// Insert a new sibling of the selected node on the given `side` of it.  As with
+    /// [`insert_child`](Editor::insert_child), the user is prompted for a key if the new node
+    /// needs one.
     fn insert_next_to_cursor(&mut self, c: char, side: Side) {
         let (_cursor, parent) = self.tree.cursor_and_parent();
         if let Some(p) = parent {
             if p.is_insert_char(c) {
-                if let Some(node) = p.from_char(c) {
+                if let Some(node) = p.node_for_char(c) {
                     if let Err(e) = self.tree.insert_next_to_cursor(node, side) {
-                        log::error!("{}", e);
+                        fail!(self, error, "{}", e);
                     } else {
                         log::debug!("Inserting with '{}'", c);
                     }
                 } else {
-                    log::warn!("Char '{}' does not correspond to a valid node", c);
+                    log::debug!("Prompting for a key to insert");
+                    self.prompt = Some((Prompt::InsertKeyedSibling(side), String::new()));
                 }
             } else {
-                log::warn!("Cannot insert node with '{}'", c);
+                fail!(self, warn, "Cannot insert node with '{}'", c);
             }
         } else {
-            log::warn!("Cannot add siblings of the root.");
+            fail!(self, warn, "Cannot add siblings of the root.");
         }
     }
 
-    /// Undo the latest change
-    fn undo(&mut self) {
-        if self.tree.undo() {
-            log::debug!("Undo successful");
+    /// Insert a new child with a given key as the last child of the selected node
+    fn insert_keyed_child(&mut self, key: String) {
+        if let Err(e) = self.tree.insert_keyed_child(key) {
+            fail!(self, warn, "{}", e);
         } else {
-            log::warn!("No changes to undo");
+            log::debug!("Inserted keyed child");
         }
     }
 
-    /// Move one change forward in the history
-    fn redo(&mut self) {
-        if self.tree.redo() {
-            log::debug!("Redo successful");
+    /// Insert a new sibling with a given key on the given `side` of the selected node
+    fn insert_keyed_sibling(&mut self, key: String, side: Side) {
+        if let Err(e) = self.tree.insert_keyed_next_to_cursor(key, side) {
+            fail!(self, warn, "{}", e);
         } else {
-            log::warn!("No changes to redo");
+            log::debug!("Inserted keyed sibling");
         }
     }
 
-    /// Render the tree to the screen
-    fn render_tree(&self, row: usize, col: usize) {
-        // Mutable variables to track where the terminal cursor should go
-        let mut row = row;
-        let mut col = col;
-        let mut indentation_amount = 0;
+    /// Prompt the user for a new key for the node under the cursor.  The prompt starts with the
+    /// cursor's existing key, so that it can be edited.
+    fn start_rename(&mut self) {
+        let cursor = self.tree.cursor();
+        match cursor.key() {
+            Some(key) => {
+                log::debug!("Prompting for a new key");
+                self.prompt = Some((Prompt::RenameKey, key.to_string()));
+            }
+            None => fail!(
+                self,
+                warn,
+                "Node {} doesn't have a key.",
+                cursor.display_name()
+            ),
+        }
+    }
 
-        let cols = [
-            Color::MAGENTA,
-            Color::RED,
-            Color::YELLOW,
-            Color::GREEN,
-            Color::CYAN,
-            Color::BLUE,
-            Color::WHITE,
-            Color::LIGHT_RED,
-            Color::LIGHT_BLUE,
-            Color::LIGHT_CYAN,
-            Color::LIGHT_GREEN,
-            Color::LIGHT_YELLOW,
-            Color::LIGHT_MAGENTA,
-            Color::LIGHT_WHITE,
-        ];
+    /// Change the key of the node under the cursor.  If that fails (e.g. because a sibling already
+    /// has that key), the error is shown and the prompt is reopened with the typed key so that it
+    /// can be corrected.
+    fn rename_cursor(&mut self, key: String) {
+        if let Err(e) = self.tree.rename_cursor(key.clone()) {
+            self.report_error(e);
+            self.prompt = Some((Prompt::RenameKey, key));
+        } else {
+            log::debug!("Renamed cursor");
+        }
+    }
 
-        /// A cheeky macro to print a string to the terminal
-        macro_rules! term_print {
-            ($string: expr) => {{
-                let string = $string;
-                // Print the string
-                self.term.print(row, col, string).unwrap();
-                // Move the cursor to the end of the string
-                let size = size::Size::from(string);
-                if size.lines() == 0 {
-                    col += size.last_line_length();
-                } else {
-                    row += size.lines();
-                    col = size.last_line_length();
-                }
-            }};
-            ($string: expr, $attr: expr) => {{
-                let string = $string;
-                // Print the string
-                self.term.print_with_attr(row, col, string, $attr).unwrap();
-                // Move the cursor to the end of the string
-                let size = size::Size::from(string);
-                if size.lines() == 0 {
-                    col += size.last_line_length();
-                } else {
-                    row += size.lines();
-                    col = size.last_line_length();
+    /// Undo the latest change
+    fn undo(&mut self, count: usize) {
+        for _ in 0..count {
+            if self.tree.undo() {
+                log::debug!("Undo successful");
+                let branches = self.tree.redo_branches();
+                if branches.len() > 1 {
+                    log::info!("Changes {:?} can be redone from here", branches);
                 }
-            }};
-        };
+            } else {
+                fail!(self, warn, "No changes to undo");
+                break;
+            }
+        }
+    }
 
-        for (node, tok) in self.tree.root().display_tokens(&self.format_style) {
-            match tok {
-                DisplayToken::Text(s) => {
-                    // Hash the ref to decide on the colour
-                    let col = {
-                        let mut hasher = DefaultHasher::new();
-                        node.hash(&mut hasher);
-                        let hash = hasher.finish();
-                        cols[hash as usize % cols.len()]
-                    };
-                    // Generate the display attributes depending on if the node is selected
-                    let attr = if std::ptr::eq(node, self.tree.cursor()) {
-                        Attr::default().fg(Color::BLACK).bg(col)
-                    } else {
-                        Attr::default().fg(col)
-                    };
-                    // Print the token
-                    term_print!(s.as_str(), attr);
-                }
-                DisplayToken::Whitespace(n) => {
-                    col += n;
-                }
-                DisplayToken::Newline => {
-                    row += 1;
-                    col = indentation_amount;
-                }
-                DisplayToken::Indent => {
-                    indentation_amount += 4;
-                }
-                DisplayToken::Dedent => {
-                    indentation_amount -= 4;
-                }
+    /// Move one change forward in the history
+    fn redo(&mut self, count: usize) {
+        for _ in 0..count {
+            if self.tree.redo() {
+                log::debug!("Redo successful");
+            } else {
+                fail!(self, warn, "No changes to redo");
+                break;
             }
         }
     }
 
-    /* ===== MAIN FUNCTIONS ===== */
+    /// Move through the history in the order the changes were made (like Vim's `g-` and `g+`)
+    fn time_travel(&mut self, side: Side, count: usize) {
+        for _ in 0..count {
+            let moved = match side {
+                Side::Prev => self.tree.earlier(),
+                Side::Next => self.tree.later(),
+            };
+            if !moved {
+                fail!(self, warn, "No more changes");
+                break;
+            }
+        }
+        log::debug!("Moved to change {}", self.tree.history_index());
+    }
 
-    /// Update the terminal UI display
-    fn update_display(&self) {
-        // Put the terminal size into some convenient variables
-        let (width, height) = self.term.term_size().unwrap();
-        // Clear the terminal
-        self.term.clear().unwrap();
+    /// Append copies of the cursor's first child, where the number of copies is given by a digit
+    fn generate_samples(&mut self, c: char) {
+        if let Some(count) = c.to_digit(10) {
+            if let Err(e) = self.tree.generate_samples(count as usize) {
+                fail!(self, warn, "{}", e);
+            } else {
+                log::debug!("Generated {} samples", count);
+            }
+        } else {
+            fail!(self, warn, "Expected a number of samples, found '{}'", c);
+        }
+    }
 
-        /* RENDER MAIN TEXT VIEW */
+    /// Check that the tree is unchanged by converting it to text and parsing it back
+    fn check_round_trip(&mut self) {
+        match self.tree.check_round_trip(&self.format_style) {
+            Ok(()) => log::info!("Round trip check passed"),
+            Err(e) => fail!(self, error, "{}", e),
+        }
+    }
 
-        self.render_tree(0, 0);
+    /// Log how much memory the arena is using, and how many of its nodes are still reachable
+    fn log_arena_stats(&self) {
+        log::info!(
+            "Arena: {} ({} reachable)",
+            self.tree.arena().stats(),
+            self.tree.reachable_node_count()
+        );
+    }
 
-        /* RENDER LOG SECTION */
+    /// Fold the cursor if it isn't folded, otherwise unfold it
+    fn toggle_fold(&mut self) {
+        let cursor = self.tree.cursor() as *const Node;
+        if !self.folded.remove(&cursor) {
+            self.folded.insert(cursor);
+        }
+        log::debug!("Tree view:\n{}", self.tree_view());
+    }
 
-        self.command_log.render(&self.term, 0, width / 2);
+    /// Fold every node which is `depth` levels below the root and has children, so that the tree
+    /// view only shows the nodes down to that depth
+    fn fold_at_depth(&mut self, depth: usize) {
+        let mut stack = vec![(self.tree.root(), 0)];
+        while let Some((node, node_depth)) = stack.pop() {
+            if node_depth == depth {
+                if !node.children().is_empty() {
+                    self.folded.insert(node as *const Node);
+                }
+            } else {
+                stack.extend(node.children().iter().map(|c| (*c, node_depth + 1)));
+            }
+        }
+        log::debug!("Tree view:\n{}", self.tree_view());
+    }
 
-        /* RENDER BOTTOM BAR */
+    /// Unfolds every folded node that contains the cursor, so that the cursor is never hidden (e.g.
+    /// after searching for a node or jumping to a mark).  The cursor itself can stay folded, since
+    /// it is still drawn as a placeholder.
+    fn unfold_cursor_ancestors(&mut self) {
+        if self.folded.is_empty() {
+            return;
+        }
+        let mut ancestors: Vec<&Node> = self
+            .tree
+            .cursor_path()
+            .node_iter(self.tree.root())
+            .collect();
+        ancestors.pop();
+        let mut unfolded = false;
+        for node in ancestors {
+            unfolded |= self.folded.remove(&(node as *const Node));
+        }
+        if unfolded {
+            log::debug!("Unfolded the ancestors of the cursor");
+            self.render_dirty.set(true);
+        }
+    }
 
-        // Add the `Press 'q' to exit.` message
-        self.term
-            .print(height - 1, 0, "Press 'q' to exit.")
-            .unwrap();
-        // Draw the current command buffer
-        self.term
-            .print(
-                height - 1,
-                width - 5 - self.command.chars().count(),
-                &self.command,
-            )
-            .unwrap();
+    /// Returns a tree view of the current tree, where the folded nodes are collapsed
+    pub fn tree_view(&self) -> String {
+        self.tree.root().tree_view_folded(&self.folded)
+    }
 
-        /* UPDATE THE TERMINAL SCREEN */
+    /// Moves every mark to the current tree (see [`relocate_path`]), if the tree has changed since
+    /// the marks were last updated.  Marks on nodes which no longer exist are removed.
+    fn update_marks(&mut self) {
+        let new_root = self.tree.root();
+        if std::ptr::eq(new_root, self.marks_root) {
+            return;
+        }
+        let old_root = self.marks_root;
+        self.marks
+            .retain(|mark, path| match relocate_path(old_root, new_root, path) {
+                Some(new_path) => {
+                    *path = new_path;
+                    true
+                }
+                None => {
+                    log::debug!("Removed mark '{}', because its node was removed", mark);
+                    false
+                }
+            });
+        self.marks_root = new_root;
+    }
 
-        self.term.present().unwrap();
+    /// Remember the cursor's location under `mark`, replacing any location already there
+    fn set_mark(&mut self, mark: char) {
+        // The mark's path is in the current tree, so the other marks have to be moved there first
+        self.update_marks();
+        self.marks.insert(mark, self.tree.cursor_path().clone());
+        log::debug!("Set mark '{}'", mark);
     }
 
-    /// Consumes a [`char`] and adds it to the command buffer.  If the command buffer contains a
-    /// valid command, then execute that command.  This returns `true` if the command 'Quit' was
-    /// executed, otherwise `false` is returned.
-    fn consume_command_char(&mut self, c: char) -> bool {
+    /// Move the cursor to the location remembered under `mark`
+    fn jump_to_mark(&mut self, mark: char) {
+        self.update_marks();
+        match self.marks.get(&mark) {
+            // The marks have just been moved to the current tree, so the path must exist
+            Some(path) => self.tree.set_cursor_path(path.clone()).unwrap(),
+            None => self.report_error(format!(
+                "Mark '{}' isn't set, or its node has been removed.",
+                mark
+            )),
+        }
+    }
+
+    /// Start recording keys into the macro register `register`
+    fn start_recording(&mut self, register: char) {
+        if register == '@' {
+            fail!(self, warn, "'@' can't be used as a macro register.");
+            return;
+        }
+        log::info!("Recording macro '{}'", register);
+        self.recording = Some((register, Vec::new()));
+    }
+
+    /// Stop recording the current macro, and store its keys in its register
+    fn stop_recording(&mut self) {
+        if let Some((register, keys)) = self.recording.take() {
+            log::info!("Recorded {} keys into macro '{}'", keys.len(), register);
+            self.macros.insert(register, keys);
+        }
+    }
+
+    /// Replay the keys of the macro in `register` `count` times, stopping as soon as any command
+    /// fails.  `@` replays the macro that was replayed most recently.  This returns `true` if the
+    /// macro quit the editor.
+    fn replay_macro(&mut self, register: char, count: usize) -> bool {
+        let register = match (register, self.last_macro) {
+            ('@', Some(last_register)) => last_register,
+            ('@', None) => {
+                fail!(self, warn, "No macro has been replayed yet.");
+                return false;
+            }
+            _ => register,
+        };
+        let keys = match self.macros.get(&register) {
+            Some(keys) => keys.clone(),
+            None => {
+                fail!(self, warn, "Macro '{}' is empty.", register);
+                return false;
+            }
+        };
+        if self.replaying.contains(&register) {
+            fail!(self, warn, "Macro '{}' can't replay itself.", register);
+            return false;
+        }
+        self.last_macro = Some(register);
+        self.replaying.push(register);
         let mut should_quit = false;
-        // Add the new keypress to the command
-        self.command.push(c);
-        // Attempt to parse the command, and take action if the command is
-        // complete
-        if let Some(action) = parse_command(&self.keymap, &self.command) {
-            // Respond to the action
-            match action {
-                Action::Undefined => {
-                    log::warn!("'{}' is not a command.", self.command);
-                }
-                Action::Quit => {
-                    // Break the mainloop to quit
-                    log::trace!("Recieved command 'Quit', so exiting mainloop");
+        'replay: for _ in 0..count {
+            for key in &keys {
+                self.command_failed = false;
+                if self.consume_key(*key) {
                     should_quit = true;
+                    break 'replay;
                 }
-                Action::MoveCursor(direction) => {
-                    self.move_cursor(direction);
-                }
-                Action::Replace(c) => {
-                    self.replace_cursor(c);
-                }
-                Action::InsertChild(c) => {
-                    self.insert_child(c);
-                }
-                Action::InsertBefore(c) => {
-                    self.insert_next_to_cursor(c, Side::Prev);
-                }
-                Action::InsertAfter(c) => {
-                    self.insert_next_to_cursor(c, Side::Next);
-                }
-                Action::Undo => {
-                    self.undo();
-                }
-                Action::Redo => {
-                    self.redo();
+                if self.command_failed {
+                    self.report_error(format!(
+                        "Stopped replaying macro '{}' because a command failed.",
+                        register
+                    ));
+                    break 'replay;
                 }
             }
-            // Add the command to the command log
-            self.command_log.push(self.command.clone(), &self.keymap);
-            // Clear the command box
-            self.command.clear();
         }
+        self.replaying.pop();
         should_quit
     }
 
-    fn mainloop(&mut self) {
-        log::trace!("Starting mainloop");
-        // Sit in the infinte mainloop
-        while let Ok(event) = self.term.poll_event() {
-            /* RESPOND TO THE USER'S INPUT */
-            if let Event::Key(key) = event {
-                match key {
-                    Key::Char(c) => {
-                        // `self.add_char_to_command` returns `true` if the editor should quit
-                        if self.consume_command_char(c) {
-                            break;
-                        }
-                    }
-                    Key::ESC => {
-                        self.command.clear();
+    /// Runs a command typed into the command line.  This returns `true` if the editor should quit.
+    fn run_command_line(&mut self, text: String) -> bool {
+        let command = match command_line::parse(&text) {
+            Ok(command) => command,
+            Err(e) => {
+                self.report_error(e);
+                return false;
+            }
+        };
+        match command {
+            command_line::CommandLine::Write(path) => {
+                self.write(path);
+                false
+            }
+            command_line::CommandLine::Quit => self.try_quit(),
+            command_line::CommandLine::ForceQuit => true,
+            // Only quit if the tree was actually written
+            command_line::CommandLine::WriteQuit(path) => self.write(path),
+            command_line::CommandLine::Edit(path) => {
+                self.edit(path, false);
+                false
+            }
+            command_line::CommandLine::ForceEdit(path) => {
+                self.edit(path, true);
+                false
+            }
+            command_line::CommandLine::Reflow => {
+                self.reflow();
+                false
+            }
+            command_line::CommandLine::Prune => {
+                self.prune_empty();
+                false
+            }
+            command_line::CommandLine::ExportDot(path) => {
+                self.export_dot(path);
+                false
+            }
+            // The theme is an option of the editor rather than the format style
+            command_line::CommandLine::Set { option, value } if option == "theme" => {
+                match theme::Theme::builtin(&value) {
+                    Some(theme) => self.theme = theme,
+                    None => self.report_error(format!(
+                        "Expected 'dark', 'light' or 'monochrome', found '{}'.",
+                        value
+                    )),
+                }
+                false
+            }
+            command_line::CommandLine::Set { option, value } if option == "mouse" => {
+                match value.as_str() {
+                    "true" => self.mouse = true,
+                    "false" => self.mouse = false,
+                    _ => {
+                        self.report_error(format!("Expected 'true' or 'false', found '{}'.", value))
                     }
-                    _ => {}
                 }
+                false
+            }
+            command_line::CommandLine::Set { option, value } => {
+                if let Err(e) = Node::set_format_option(&mut self.format_style, &option, &value) {
+                    self.report_error(e);
+                }
+                false
+            }
+            command_line::CommandLine::Substitute { from, to } => {
+                self.substitute(&from, &to);
+                false
+            }
+            command_line::CommandLine::Validate(path) => {
+                self.validate(path);
+                false
+            }
+            command_line::CommandLine::Lint => {
+                self.lint();
+                false
+            }
+            command_line::CommandLine::Stats => {
+                self.status = Some(stats::Stats::new(self.tree.root()).to_string());
+                false
+            }
+            command_line::CommandLine::Goto(json_path) => {
+                let resolved = match self.tree.root().extension() {
+                    Some(extension) => extension.resolve_path_expr(&json_path),
+                    None => Err("Only JSON trees can be addressed by a path.".to_string()),
+                };
+                match resolved {
+                    // The path came from the tree, so we can unwrap
+                    Ok(path) => self.tree.set_cursor_path(path).unwrap(),
+                    Err(e) => self.report_error(e),
+                }
+                false
             }
-
-            // Make sure that the logger isn't taller than the screen
-            self.command_log
-                .set_max_entries(self.term.term_size().unwrap().1.min(10));
-
-            // Update the screen after every input (if this becomes a bottleneck then we can
-            // optimise the number of calls to `update_display` but for now it's not worth the
-            // added complexity)
-            self.update_display();
         }
     }
 
-    /// Start the editor and enter the mainloop
-    pub fn run(mut self) {
-        // Start the mainloop
-        self.mainloop();
-        log::trace!("Making the cursor reappear.");
-        // Show the cursor before closing so that the cursor isn't permanently disabled
-        // (see issue https://github.com/lotabout/tuikit/issues/28)
-        self.term.show_cursor(true).unwrap();
-        self.term.present().unwrap();
+    /// Writes the tree to `path`, or the file that was last written if `path` is [`None`].  This
+    /// returns `true` if the tree was written successfully.
+    fn write(&mut self, path: Option<std::path::PathBuf>) -> bool {
+        let path = match path.or_else(|| self.path.clone()) {
+            Some(path) => path,
+            None => {
+                self.report_error("No file name given.".to_string());
+                return false;
+            }
+        };
+        let result = std::fs::File::create(&path).and_then(|file| {
+            let mut writer = std::io::BufWriter::new(file);
+            self.tree.write_io(&mut writer, &self.format_style)?;
+            if self.trailing_newline {
+                std::io::Write::write_all(&mut writer, b"\n")?;
+            }
+            std::io::Write::flush(&mut writer)
+        });
+        match result {
+            Ok(()) => {
+                log::info!("Wrote {:?}", path);
+                self.status = Some(format!("Wrote {}", path.display()));
+                self.path = Some(path);
+                self.saved_root = self.tree.root();
+                true
+            }
+            Err(e) => {
+                self.report_error(format!("Couldn't write {}: {}", path.display(), e));
+                false
+            }
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::{parse_command, Action};
-    use crate::editable_tree::Direction;
+    /// Replaces the tree with the contents of the file at `path`.  If the file can't be read or
+    /// parsed, the error is shown and the current tree is left untouched.  Unless `force` is
+    /// `true`, the tree is also left untouched if it has changes which haven't been written.
+    fn edit(&mut self, path: std::path::PathBuf, force: bool) {
+        if !force && self.is_modified() {
+            self.report_error("No write since last change (add ! to override).".to_string());
+            return;
+        }
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) => {
+                self.report_error(format!("Couldn't read {}: {}", path.display(), e));
+                return;
+            }
+        };
+        let root = match Node::from_text(&text, self.tree.arena()) {
+            Ok(root) => root,
+            Err(e) => {
+                self.report_error(format!("Couldn't parse {}: {}", path.display(), e));
+                return;
+            }
+        };
+        self.tree.load(root);
+        self.saved_root = self.tree.root();
+        // Any state that refers to nodes or paths in the old tree is now meaningless
+        self.folded.clear();
+        self.marks.clear();
+        self.marks_root = self.tree.root();
+        self.selection_anchor = None;
+        self.boundary_container = None;
+        self.scroll = 0;
+        log::info!("Opened {:?}", path);
+        self.status = Some(format!("Opened {}", path.display()));
+        self.path = Some(path);
+    }
+
+    /// Returns `true` if the tree has changed since it was last written or loaded.  Undoing back
+    /// to the saved state makes the tree unmodified again.
+    fn is_modified(&self) -> bool {
+        !std::ptr::eq(self.tree.root(), self.saved_root)
+    }
+
+    /// Returns `true` if the editor should quit, or shows an error if the tree has unsaved changes
+    fn try_quit(&mut self) -> bool {
+        if self.is_modified() {
+            self.report_error(
+                "There are unsaved changes (use :q! to quit anyway, or :wq to save and quit)."
+                    .to_string(),
+            );
+            false
+        } else {
+            true
+        }
+    }
+
+    /// Writes a Graphviz graph of the tree (see [`Ast::to_dot`]) to `path`
+    fn export_dot(&mut self, path: std::path::PathBuf) {
+        match std::fs::write(&path, self.tree.root().to_dot()) {
+            Ok(()) => {
+                log::info!("Exported {:?}", path);
+                self.status = Some(format!("Exported {}", path.display()));
+            }
+            Err(e) => self.report_error(format!("Couldn't write {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Regenerates the tree from its text in the current format (see [`DAG::reflow`]), showing the
+    /// parse error if the tree can't be written as valid text
+    fn reflow(&mut self) {
+        match self.tree.reflow(&self.format_style) {
+            Ok(replaced) => {
+                if replaced {
+                    // The old paths might not exist in the new tree
+                    self.selection_anchor = None;
+                    self.boundary_container = None;
+                }
+                self.status = Some(format!(
+                    "Reflowed as {}",
+                    Node::format_style_name(&self.format_style)
+                ));
+            }
+            Err(e) => self.report_error(format!("Couldn't reflow: {}", e)),
+        }
+    }
+
+    /// Removes every empty container from the tree (see [`DAG::prune_empty`]), and shows how many
+    /// nodes were removed
+    fn prune_empty(&mut self) {
+        match self.tree.prune_empty() {
+            0 => self.report_error("There are no empty containers to remove.".to_string()),
+            num_removed => {
+                // The old paths might not exist in the new tree
+                self.selection_anchor = None;
+                self.boundary_container = None;
+                self.status = Some(format!("Removed {} empty nodes", num_removed));
+            }
+        }
+    }
+
+    /// Replaces every node whose text is `from` with the node whose text is `to`, as one edit
+    fn substitute(&mut self, from: &str, to: &str) {
+        let arena = self.tree.arena();
+        let (from_node, to_node) = match (Node::from_text(from, arena), Node::from_text(to, arena))
+        {
+            (Ok(from_node), Ok(to_node)) => (from_node, to_node),
+            (Err(e), _) | (_, Err(e)) => {
+                self.report_error(format!("Couldn't parse substitution: {}", e));
+                return;
+            }
+        };
+        match self.tree.replace_all(from_node, to_node) {
+            0 => self.report_error(format!("There are no nodes matching '{}'.", from)),
+            num_replaced => {
+                // The old paths might not exist in the new tree
+                self.selection_anchor = None;
+                self.boundary_container = None;
+                self.status = Some(format!("Replaced {} nodes", num_replaced));
+            }
+        }
+    }
+
+    /// Shows an error message in the status bar (as well as logging it)
+    fn report_error(&mut self, message: String) {
+        fail!(self, warn, "{}", message);
+        self.status = Some(message);
+    }
+
+    /// Search for `text`, and move the cursor to the next node that matches it
+    fn search(&mut self, text: String) {
+        if text.is_empty() {
+            fail!(self, warn, "Cannot search for an empty query.");
+            return;
+        }
+        self.search_query = Some(search::Query::new(&text));
+        self.search_again(Side::Next, 1);
+    }
+
+    /// Move the cursor to the `count`th next or previous node that matches the last search
+    fn search_again(&mut self, side: Side, count: usize) {
+        let query = match &self.search_query {
+            Some(query) => query.clone(),
+            None => {
+                fail!(self, warn, "Nothing has been searched for yet.");
+                return;
+            }
+        };
+        for _ in 0..count {
+            match search::find_match(self.tree, &query, side) {
+                // We can unwrap because the path came from the tree
+                Some(path) => self.tree.set_cursor_path(path).unwrap(),
+                None => {
+                    fail!(self, warn, "No nodes match '{}'.", query.text());
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Checks the tree against a schema with the AST's
+    /// [`LanguageExtension`](crate::ast::extension::LanguageExtension), returning an error message
+    /// if the AST doesn't have schemas
+    fn schema_violations(
+        &self,
+        schema: &str,
+    ) -> std::result::Result<Vec<(CursorPath, String)>, String> {
+        match self.tree.root().extension() {
+            Some(extension) => extension.validate(schema),
+            None => Err("Only JSON can be validated against a schema.".to_string()),
+        }
+    }
+
+    /// Checks the tree against the schema in the file at `path`, and lists any violations
+    fn validate(&mut self, path: std::path::PathBuf) {
+        let schema = match std::fs::read_to_string(&path) {
+            Ok(schema) => schema,
+            Err(e) => {
+                self.report_error(format!("Couldn't read {}: {}", path.display(), e));
+                return;
+            }
+        };
+        let violations = match self.schema_violations(&schema) {
+            Ok(violations) => violations,
+            Err(e) => {
+                self.report_error(e);
+                return;
+            }
+        };
+        self.schema = Some(schema);
+        self.status = Some(match violations.len() {
+            0 => "The tree matches the schema.".to_string(),
+            1 => "Found 1 violation of the schema.".to_string(),
+            n => format!("Found {} violations of the schema.", n),
+        });
+        self.list_problems(&violations);
+    }
+
+    /// Lists every likely mistake in the tree (see the [`lint`] module)
+    fn lint(&mut self) {
+        let problems = self.duplicate_key_problems();
+        self.status = Some(match problems.len() {
+            0 => "No problems found.".to_string(),
+            1 => "Found 1 problem.".to_string(),
+            n => format!("Found {} problems.", n),
+        });
+        self.list_problems(&problems);
+    }
+
+    /// Returns the path to and description of every duplicated key in the tree, in document order
+    fn duplicate_key_problems(&self) -> Vec<(CursorPath, String)> {
+        let root = self.tree.root();
+        self.duplicate_keys
+            .find(root)
+            .iter()
+            .map(|path| {
+                // Only nodes with keys can be duplicated, so we can unwrap
+                let key = path.cursor(root).key().unwrap();
+                (path.clone(), format!("Duplicate key {:?}.", key))
+            })
+            .collect()
+    }
+
+    /// Lists some problems with the tree instead of the tree, until the next key is pressed.  If
+    /// there are no problems, nothing is listed.
+    fn list_problems(&mut self, problems: &[(CursorPath, String)]) {
+        if problems.is_empty() {
+            return;
+        }
+        let root = self.tree.root();
+        self.violation_list = Some(
+            problems
+                .iter()
+                .map(|(path, message)| format!("{}: {}", problem_location(root, path), message))
+                .collect(),
+        );
+    }
+
+    /// Move the cursor to the `count`th next or previous node which doesn't match the schema
+    /// loaded by `:validate` or has a duplicated key, and show what's wrong with it
+    fn jump_to_violation(&mut self, side: Side, count: usize) {
+        let mut violations = self.duplicate_key_problems();
+        match &self.schema {
+            Some(schema) => match self.schema_violations(schema) {
+                Ok(schema_violations) => violations.extend(schema_violations),
+                Err(e) => {
+                    self.report_error(e);
+                    return;
+                }
+            },
+            None if violations.is_empty() => {
+                self.report_error(
+                    "No schema has been loaded (use ':validate <path>').".to_string(),
+                );
+                return;
+            }
+            None => {}
+        }
+        violations.sort_by(|(a, _), (b, _)| a.iter().cmp(b.iter()));
+        // A node can break more than one rule, but the cursor should only stop there once.  The
+        // violations are in document order, so duplicate paths are next to each other.
+        let mut paths: Vec<&CursorPath> = violations.iter().map(|(path, _)| path).collect();
+        paths.dedup();
+        for _ in 0..count {
+            let cursor = self.tree.cursor_path().clone();
+            // Comparing paths index by index gives document order.  If there are no violations
+            // past the cursor, wrap around to the other end of the document.
+            let path = match side {
+                Side::Next => paths
+                    .iter()
+                    .find(|p| p.iter().gt(cursor.iter()))
+                    .or_else(|| paths.first()),
+                Side::Prev => paths
+                    .iter()
+                    .rev()
+                    .find(|p| p.iter().lt(cursor.iter()))
+                    .or_else(|| paths.last()),
+            };
+            match path {
+                // We can unwrap because the path came from the tree
+                Some(path) => self.tree.set_cursor_path((*path).clone()).unwrap(),
+                None => {
+                    self.report_error("The tree matches the schema.".to_string());
+                    return;
+                }
+            }
+        }
+        let cursor = self.tree.cursor_path();
+        // The cursor is on a violation, so we can unwrap
+        let index = paths.iter().position(|p| *p == cursor).unwrap();
+        let messages: Vec<&str> = violations
+            .iter()
+            .filter(|(path, _)| path == cursor)
+            .map(|(_, message)| message.as_str())
+            .collect();
+        self.status = Some(format!(
+            "Violation {} of {}: {}",
+            index + 1,
+            paths.len(),
+            messages.join(" ")
+        ));
+    }
+
+    /// Start selecting siblings of the cursor, or stop if a selection has already been started
+    fn toggle_visual(&mut self) {
+        if self.selection_anchor.take().is_some() {
+            return;
+        }
+        match self.tree.cursor_path().iter().last() {
+            Some(index) => self.selection_anchor = Some(*index),
+            None => fail!(self, warn, "Cannot select the root."),
+        }
+    }
+
+    /// Returns the indices of the selected siblings of the cursor, or [`None`] if the editor
+    /// isn't in visual mode
+    pub fn selection(&self) -> Option<std::ops::Range<usize>> {
+        let anchor = self.selection_anchor?;
+        let cursor_index = *self.tree.cursor_path().iter().last()?;
+        Some(anchor.min(cursor_index)..anchor.max(cursor_index) + 1)
+    }
+
+    /// Returns the selected nodes, or just the cursor if the editor isn't in visual mode
+    fn selected_nodes(&self) -> Vec<&'arena Node> {
+        match (self.selection(), self.tree.cursor_and_parent().1) {
+            (Some(range), Some(parent)) => parent.children()[range].to_vec(),
+            _ => vec![self.tree.cursor()],
+        }
+    }
+
+    /// Copy the selected nodes into the register
+    fn yank(&mut self) {
+        self.register = self.selected_nodes();
+        log::debug!("Yanking {:?}", self.register);
+        // Also copy the nodes as text, so that they can be pasted into other programs
+        let text = self
+            .register
+            .iter()
+            .map(|node| node.to_text(&self.format_style))
+            .collect::<Vec<_>>()
+            .join("\n");
+        if let Err(e) = self.clipboard.set_text(text) {
+            log::debug!("Not copying to the clipboard: {}", e);
+        }
+    }
+
+    /// Paste a copy of the register's contents after the cursor.  If another program has put text
+    /// on the clipboard since the last yank, that text is parsed and pasted instead.
+    fn paste(&mut self) {
+        if self.tree.cursor_and_parent().1.is_none() {
+            fail!(self, warn, "Cannot paste next to the root.");
+            return;
+        }
+        let nodes = match self.clipboard.external_text() {
+            Some(text) => match Node::from_text(&text, self.tree.arena()) {
+                Ok(node) => vec![node],
+                Err(e) => {
+                    log::warn!(
+                        "Couldn't parse the clipboard, so pasting the register: {}",
+                        e
+                    );
+                    self.register.clone()
+                }
+            },
+            None => self.register.clone(),
+        };
+        if nodes.is_empty() {
+            fail!(self, warn, "Nothing to paste; the register is empty.");
+            return;
+        }
+        // Pasting a node moves the cursor onto it, so pasting the nodes one by one keeps them in
+        // the same order.  They are pasted in one transaction, so that they are undone together.
+        self.tree.begin();
+        for node in nodes {
+            if let Err(e) = self.tree.paste_next_to_cursor(node, Side::Next) {
+                fail!(self, error, "{}", e);
+                break;
+            }
+            log::debug!("Pasted {:?}", node);
+        }
+        self.tree.commit();
+    }
+
+    /// Insert `count` copies of the node under the cursor after it, moving the cursor onto the
+    /// last copy
+    fn duplicate_cursor(&mut self, count: usize) {
+        for _ in 0..count {
+            if let Err(e) = self.tree.duplicate_cursor() {
+                fail!(self, warn, "{}", e);
+                break;
+            }
+            log::debug!("Duplicated cursor");
+        }
+    }
+
+    /// Delete the selected nodes, or the node under the cursor along with `count - 1` of its next
+    /// siblings if nothing is selected
+    fn delete_cursor(&mut self, count: usize) {
+        let result = match self.selection() {
+            Some(range) => self.tree.delete_siblings(range),
+            None => self.tree.delete_cursor(count),
+        };
+        if let Err(e) = result {
+            fail!(self, warn, "{}", e);
+        } else {
+            log::debug!("Deleted cursor");
+        }
+    }
+
+    /// Swap the node under the cursor with one of its siblings
+    fn move_node(&mut self, side: Side, count: usize) {
+        for _ in 0..count {
+            if let Err(e) = self.tree.move_cursor_node(side) {
+                fail!(self, warn, "{}", e);
+                break;
+            } else {
+                log::debug!("Moved cursor node");
+            }
+        }
+    }
+
+    /// Split every child of the cursor into its own container
+    fn explode(&mut self) {
+        let cursor = self.tree.cursor();
+        if let Some(new_node) = cursor.explode(self.tree.arena()) {
+            self.tree.replace_cursor(new_node);
+        } else {
+            fail!(self, warn, "Cannot explode {}", cursor.display_name());
+        }
+    }
+
+    /// Merge the children of the cursor's children into one container
+    fn merge(&mut self) {
+        let cursor = self.tree.cursor();
+        if let Some(new_node) = cursor.merge(self.tree.arena()) {
+            self.tree.replace_cursor(new_node);
+        } else {
+            fail!(self, warn, "Cannot merge {}", cursor.display_name());
+        }
+    }
+
+    /// Returns the nodes which should be highlighted as selected: the selected nodes in visual
+    /// mode, and none otherwise
+    fn highlighted_selection(&self) -> Vec<*const Node> {
+        match self.selection() {
+            Some(_) => self.selected_nodes().into_iter().map(|n| n as _).collect(),
+            None => vec![],
+        }
+    }
+
+    /// Highlights a span of text (given its attributes and the node it belongs to) if it is part
+    /// of the cursor or one of the `selected_nodes`
+    fn highlight(&self, attr: Attr, node: *const Node, selected_nodes: &[*const Node]) -> Attr {
+        self.theme.highlight(
+            attr,
+            std::ptr::eq(node, self.tree.cursor()),
+            selected_nodes.contains(&node),
+        )
+    }
+
+    /// Lay out the text of the tree into lines (see [`NodeLine`]).  Only the first `num_lines`
+    /// lines are laid out, and only the lines from `scroll` onwards and the `pinned` lines are
+    /// filled in (the others are left empty), so that the parts of a large tree which are off the
+    /// screen cost as little as possible.  Nodes which end before the first of those lines are
+    /// skipped over without being laid out at all.  Folded nodes are drawn as a placeholder
+    /// instead of their text.  The cursor and selection aren't highlighted, so that the lines can
+    /// be cached while the cursor moves around.
+    fn layout_unhighlighted(
+        &self,
+        num_lines: usize,
+        scroll: usize,
+        pinned: &[usize],
+    ) -> Vec<NodeLine<Node>> {
+        let is_visible = |line: usize| line >= scroll || pinned.contains(&line);
+        let first_line = pinned.iter().copied().fold(scroll, usize::min);
+        // Mutable variables to track where the next span should go
+        let mut lines: Vec<NodeLine<Node>> = vec![vec![]];
+        let mut col = 0;
+        let mut indentation_amount = 0;
+        let indent_width = Node::indent(&self.format_style).width();
+
+        let duplicate_keys = self.duplicate_keys.node_set(self.tree.root());
+        let mut tokens = (self.tree.root())
+            .display_token_iter(&self.format_style)
+            .with_folded(&self.folded)
+            .skip_to_line(first_line);
+        while let Some((node, tok)) = tokens.next() {
+            match tok {
+                // Text on hidden lines is skipped, since the column is reset by the next newline
+                DisplayToken::Text(_) if !is_visible(lines.len() - 1) => {}
+                DisplayToken::Text(s) => {
+                    // Colour the text by the kind of node it belongs to
+                    let class = node.syntax_class(tokens.parent());
+                    let mut attr = self.theme.attr(class, false, false);
+                    // Highlight duplicated keys (and anything that a keyed node draws itself, like
+                    // the `:` of a JSON field)
+                    let is_duplicate = |n: &Node| duplicate_keys.contains(&(n as *const Node));
+                    if is_duplicate(node)
+                        || (class == SyntaxClass::Key && tokens.parent().is_some_and(is_duplicate))
+                    {
+                        attr = self.theme.error_attr(attr);
+                    }
+                    // Add the span to the current line, and move the column to the end of it
+                    let size = size::Size::from(s.as_str());
+                    // We can unwrap here because `lines` always has at least one line
+                    let path = tokens.path();
+                    lines.last_mut().unwrap().push((col, s, attr, node, path));
+                    col += size.last_line_length();
+                }
+                DisplayToken::Whitespace(n) => {
+                    col += n;
+                }
+                DisplayToken::Newline => {
+                    if lines.len() == num_lines {
+                        break;
+                    }
+                    lines.push(vec![]);
+                    col = indentation_amount;
+                }
+                DisplayToken::Indent => {
+                    indentation_amount += indent_width;
+                }
+                DisplayToken::Dedent => {
+                    indentation_amount -= indent_width;
+                }
+            }
+        }
+        lines
+    }
+
+    /// Make sure that the line containing the cursor is on the screen (and not hidden behind the
+    /// sticky header), given that the tree view is `view_height` rows tall.
+    fn scroll_to_cursor(&mut self, view_height: usize) {
+        let mut start_lines = viewport::node_start_lines(
+            self.tree.root(),
+            self.tree.cursor_path(),
+            &self.format_style,
+            &self.folded,
+        );
+        // We can unwrap here because the path always contains at least the root
+        let cursor_line = start_lines.pop().unwrap();
+        self.scroll = viewport::scroll_to_cursor(
+            self.scroll,
+            &start_lines,
+            cursor_line,
+            view_height,
+            view_height / 2,
+        );
+    }
+
+    /// Render the tree to the screen, with the top-left corner at a given location.  Only the
+    /// lines that fit in the `view_height` rows will be drawn.  The lines of any ancestors of the
+    /// cursor that have been scrolled off the top of the screen are pinned to the top of the view.
+    fn render_tree(&self, term: &Term, row: usize, col: usize, view_height: usize) {
+        let pinned = self.pinned_lines(view_height);
+        // Only lay out the lines that will be drawn, and only if they aren't already laid out
+        self.update_layout((self.scroll + view_height, self.scroll, pinned.clone()));
+        let layout = self.tree_layout.borrow();
+        // We can unwrap here because `update_layout` always leaves a layout
+        let lines = &layout.as_ref().unwrap().lines;
+        let selected_nodes = self.highlighted_selection();
+
+        /// A cheeky macro to draw a line to the terminal
+        macro_rules! draw_line {
+            ($line: expr, $row: expr, $effect: expr) => {{
+                for (span_col, string, attr, node, _) in $line {
+                    let attr = self.highlight(*attr, *node, &selected_nodes);
+                    let attr = attr.effect(attr.effect | $effect);
+                    term.print_with_attr($row, col + span_col, string, attr)
+                        .unwrap();
+                }
+            }};
+        }
+
+        // Draw the pinned lines, underlining the last one to separate the header from the rest of
+        // the tree
+        for (i, line_index) in pinned.iter().enumerate() {
+            let effect = if i + 1 == pinned.len() {
+                Effect::UNDERLINE
+            } else {
+                Effect::empty()
+            };
+            draw_line!(&lines[*line_index], row + i, effect);
+        }
+        // Draw the visible lines underneath the header
+        for i in pinned.len()..view_height {
+            if let Some(line) = lines.get(self.scroll + i) {
+                draw_line!(line, row + i, Effect::empty());
+            }
+        }
+    }
+
+    /// Returns the lines which are pinned to the top of the tree view (which is `view_height` rows
+    /// tall), because they belong to ancestors of the cursor that have been scrolled off the top
+    fn pinned_lines(&self, view_height: usize) -> Vec<usize> {
+        let mut ancestor_lines = viewport::node_start_lines(
+            self.tree.root(),
+            self.tree.cursor_path(),
+            &self.format_style,
+            &self.folded,
+        );
+        ancestor_lines.pop();
+        viewport::sticky_lines(&ancestor_lines, self.scroll, view_height / 2)
+    }
+
+    /// Moves the cursor to the node drawn at a given row and column of the tree view (which is
+    /// `view_height` rows tall), because it was clicked on.  Clicks below the end of the tree are
+    /// ignored.
+    fn click(&mut self, row: usize, col: usize, view_height: usize) {
+        if row >= view_height
+            || self.prompt.is_some()
+            || self.show_help
+            || self.violation_list.is_some()
+        {
+            return;
+        }
+        // The pinned lines are drawn over the top rows of the view (see `render_tree`)
+        let pinned = self.pinned_lines(view_height);
+        let line = match pinned.get(row) {
+            Some(line) => *line,
+            None => self.scroll + row,
+        };
+        // The clicked line is on the screen, so it is part of the layout that was last drawn
+        self.update_layout((self.scroll + view_height, self.scroll, pinned));
+        let layout = self.tree_layout.borrow();
+        // We can unwrap here because `update_layout` always leaves a layout
+        let path = match layout.as_ref().unwrap().path_at(line, col) {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        drop(layout);
+        self.command.clear();
+        self.selection_anchor = None;
+        // We can unwrap here because the path was found by drawing the tree
+        self.tree.set_cursor_path(path).unwrap();
+    }
+
+    /// Scrolls the tree view down by `lines` (or up, if `lines` is negative) without moving the
+    /// cursor.  The view stops when the last line of the tree is at the top of the screen.
+    fn scroll_view(&mut self, lines: isize) {
+        let last_line = self.tree.root().size(&self.format_style).lines();
+        self.scroll = self.scroll.saturating_add_signed(lines).min(last_line);
+    }
+
+    /// Returns `true` if the tree has changed since it was last laid out, so that the cached
+    /// layout can't be drawn again (even if the view hasn't scrolled).  Commands which only move
+    /// the cursor don't change the layout, because the cursor is highlighted as the tree is drawn.
+    fn needs_full_render(&self) -> bool {
+        match &*self.tree_layout.borrow() {
+            Some(layout) => self.render_dirty.get() || !std::ptr::eq(layout.root, self.tree.root()),
+            None => true,
+        }
+    }
+
+    /// Lays out the lines of the tree in `window` (see [`TreeLayout::window`]), unless they are
+    /// already laid out and the tree hasn't changed since
+    fn update_layout(&self, window: (usize, usize, Vec<usize>)) {
+        let is_cached = !self.needs_full_render()
+            && self.tree_layout.borrow().as_ref().map(|l| &l.window) == Some(&window);
+        if is_cached {
+            return;
+        }
+        let (num_lines, scroll, pinned) = &window;
+        let lines = self.layout_unhighlighted(*num_lines, *scroll, pinned);
+        *self.tree_layout.borrow_mut() = Some(TreeLayout {
+            root: self.tree.root(),
+            window,
+            lines,
+        });
+        self.render_dirty.set(false);
+    }
+
+    /// Draws the (cached) status line across the whole width of the screen on a given row
+    fn render_status(&self, term: &Term, row: usize, width: usize) {
+        let text = format!("{:width$}", self.status_line.text(), width = width);
+        term.print_with_attr(row, 0, &text, Attr::default().effect(Effect::REVERSE))
+            .unwrap();
+    }
+
+    /* ===== MAIN FUNCTIONS ===== */
+
+    /// Update the terminal UI display
+    fn update_display(&self, term: &Term) {
+        // Put the terminal size into some convenient variables
+        let (width, height) = term.term_size().unwrap();
+        // The bottom two rows hold the status line and the bottom bar.  A terminal can be shorter
+        // than that, in which case there is no room for the rows that don't fit.
+        let view_height = height.saturating_sub(2);
+        // Clear the terminal
+        term.clear().unwrap();
+
+        /* RENDER MAIN TEXT VIEW */
+
+        if self.show_help {
+            // The help screen covers both the tree and the log
+            for (row, line) in help::render_help(&self.keymap)
+                .lines()
+                .take(view_height)
+                .enumerate()
+            {
+                term.print(row, 0, line).unwrap();
+            }
+        } else if let Some(violations) = &self.violation_list {
+            for (row, line) in violations.iter().take(view_height).enumerate() {
+                term.print(row, 0, line).unwrap();
+            }
+        } else if view_height > 0 {
+            self.render_tree(term, 0, 0, view_height);
+
+            /* RENDER LOG SECTION */
+
+            self.command_log.render(term, 0, width / 2);
+        }
+
+        /* RENDER STATUS LINE */
+
+        if let Some(row) = height.checked_sub(2) {
+            self.render_status(term, row, width);
+        }
+
+        /* RENDER BOTTOM BAR */
+
+        if let Some(row) = height.checked_sub(1) {
+            // Add either the open prompt or the `Press 'Q' to exit.` message
+            if let Some((prompt, text)) = &self.prompt {
+                term.print(row, 0, &format!("{}{}", prompt.message(), text))
+                    .unwrap();
+            } else if let Some(status) = &self.status {
+                term.print(row, 0, status).unwrap();
+            } else {
+                term.print(
+                    row,
+                    0,
+                    &format!("Press 'Q' to exit.  {}", self.tree.breadcrumb()),
+                )
+                .unwrap();
+            }
+            // Draw the current command buffer
+            let command_col = width.saturating_sub(5 + self.command.chars().count());
+            term.print(row, command_col, &self.command).unwrap();
+        }
+
+        /* UPDATE THE TERMINAL SCREEN */
+
+        term.present().unwrap();
+    }
+
+    /// Consumes a [`char`] and adds it to the command buffer.  If the command buffer contains a
+    /// valid command, then execute that command.  This returns `true` if the command 'Quit' was
+    /// executed, otherwise `false` is returned.
+    fn consume_command_char(&mut self, c: char) -> bool {
+        // Whilst recording a macro, the record key on its own stops the recording instead of
+        // starting a new one
+        if self.command.is_empty()
+            && self.recording.is_some()
+            && self.keymap.get(c) == Some(&Command::RecordMacro)
+        {
+            self.stop_recording();
+            return false;
+        }
+        // Add the new keypress to the command
+        self.command.push(c);
+        self.run_command(true)
+    }
+
+    /// Returns `true` if the keys typed so far are the start of a multi-key sequence, in which
+    /// case the editor is waiting for either the next key or the sequence timeout
+    fn is_waiting_for_sequence(&self) -> bool {
+        // A replace sequence which is the start of a longer one (see `parse_replace_seq`) also
+        // waits for the timeout, since it is a complete command without it
+        self.prompt.is_none()
+            && (self.keymap.is_sequence_prefix(split_count(&self.command).1)
+                || (self.parse_command(true).is_none() && self.parse_command(false).is_some()))
+    }
+
+    /// Parses the keys typed so far with [`parse_command`], using the sequences that the cursor
+    /// can be replaced with
+    fn parse_command(&self, wait_for_sequences: bool) -> Option<(usize, Action)> {
+        let replace_seqs: Vec<String> = self.tree.cursor().replace_seqs().collect();
+        parse_command(
+            &self.keymap,
+            &replace_seqs,
+            &self.command,
+            wait_for_sequences,
+        )
+    }
+
+    /// Parses the keys typed so far, and runs them if they make a complete command.  If
+    /// `wait_for_sequences` is `false`, keys which could be the start of a multi-key sequence are
+    /// run as they are (this happens when the user stops typing for the sequence timeout).  This
+    /// returns `true` if the editor should quit.
+    fn run_command(&mut self, wait_for_sequences: bool) -> bool {
+        let mut should_quit = false;
+        // Attempt to parse the command, and take action if the command is
+        // complete
+        if let Some((count, action)) = self.parse_command(wait_for_sequences) {
+            // Clear the command box before executing the command, because replaying a macro will
+            // type more commands
+            let command = std::mem::take(&mut self.command);
+            // Only cursor movements keep the selection, because other commands might change
+            // which nodes are the cursor's siblings
+            let keeps_selection = matches!(action, Action::MoveCursor(_) | Action::Visual);
+            let only_moves_cursor = action.only_moves_cursor();
+            // Respond to the action.  Commands which can't be repeated ignore the count.
+            match action {
+                Action::Undefined => {
+                    fail!(self, warn, "'{}' is not a command.", command);
+                }
+                Action::Quit => {
+                    // Break the mainloop to quit
+                    if self.try_quit() {
+                        log::trace!("Recieved command 'Quit', so exiting mainloop");
+                        should_quit = true;
+                    }
+                }
+                Action::MoveCursor(direction) => {
+                    self.move_cursor(direction, count);
+                }
+                Action::Replace(seq) => {
+                    self.replace_cursor(seq);
+                }
+                Action::InsertChild(c) => {
+                    self.insert_child(c);
+                }
+                Action::InsertBefore(c) => {
+                    self.insert_next_to_cursor(c, Side::Prev);
+                }
+                Action::InsertAfter(c) => {
+                    self.insert_next_to_cursor(c, Side::Next);
+                }
+                Action::Undo => {
+                    self.undo(count);
+                }
+                Action::Redo => {
+                    self.redo(count);
+                }
+                Action::GenerateSamples(c) => {
+                    self.generate_samples(c);
+                }
+                Action::CheckRoundTrip => {
+                    self.check_round_trip();
+                }
+                Action::Yank => {
+                    self.yank();
+                }
+                Action::Duplicate => {
+                    self.duplicate_cursor(count);
+                }
+                Action::Paste => {
+                    self.paste();
+                }
+                Action::Explode => {
+                    self.explode();
+                }
+                Action::Merge => {
+                    self.merge();
+                }
+                Action::Delete => {
+                    self.delete_cursor(count);
+                }
+                Action::Rename => {
+                    self.start_rename();
+                }
+                Action::MoveNode(side) => {
+                    self.move_node(side, count);
+                }
+                Action::ArenaStats => {
+                    self.log_arena_stats();
+                }
+                Action::ToggleFold => {
+                    self.toggle_fold();
+                }
+                Action::FoldAtDepth => {
+                    self.fold_at_depth(count);
+                }
+                Action::UnfoldAll => {
+                    self.folded.clear();
+                    log::debug!("Tree view:\n{}", self.tree_view());
+                }
+                Action::RecordMacro(register) => {
+                    self.start_recording(register);
+                }
+                Action::ReplayMacro(register) => {
+                    should_quit = self.replay_macro(register, count);
+                }
+                Action::SetMark(mark) => {
+                    self.set_mark(mark);
+                }
+                Action::JumpToMark(mark) => {
+                    self.jump_to_mark(mark);
+                }
+                Action::JumpToViolation(side) => {
+                    self.jump_to_violation(side, count);
+                }
+                Action::Visual => {
+                    self.toggle_visual();
+                }
+                Action::Search => {
+                    self.prompt = Some((Prompt::Search, String::new()));
+                }
+                Action::SearchAgain(side) => {
+                    self.search_again(side, count);
+                }
+                Action::CommandLine => {
+                    self.prompt = Some((Prompt::CommandLine, String::new()));
+                }
+                Action::CycleFormat => {
+                    for _ in 0..count {
+                        Node::cycle_format_style(&mut self.format_style);
+                    }
+                }
+                Action::JumpToBoundary => {
+                    self.jump_to_boundary(count);
+                }
+                Action::TimeTravel(side) => {
+                    self.time_travel(side, count);
+                }
+                Action::MoveToRoot => {
+                    self.move_to_root();
+                }
+                Action::JumpToMatch => {
+                    self.tree.jump_to_match();
+                }
+                Action::Help => {
+                    self.show_help = true;
+                }
+                Action::Write => {
+                    self.write(None);
+                }
+                Action::Convert(seq) => {
+                    if let Err(e) = self.tree.convert_cursor(&seq) {
+                        fail!(self, warn, "{}", e);
+                    }
+                }
+                Action::Sort(descending) => {
+                    if let Err(e) = self.tree.sort_cursor(descending) {
+                        fail!(self, warn, "{}", e);
+                    }
+                }
+            }
+            if !keeps_selection {
+                self.selection_anchor = None;
+            }
+            if !only_moves_cursor {
+                self.render_dirty.set(true);
+            }
+            // Add the command to the command log
+            self.command_log.push(command, &self.keymap);
+        }
+        should_quit
+    }
+
+    /// Consumes a keypress whilst the user is typing into a [`Prompt`].  `Enter` submits the
+    /// typed text, and `ESC` cancels the prompt.  This returns `true` if the editor should quit.
+    fn consume_prompt_key(&mut self, key: Key) -> bool {
+        // We can unwrap here because this is only called when there is a prompt
+        let (prompt, text) = self.prompt.as_mut().unwrap();
+        if let Prompt::ConfirmReplace(seq, _) = prompt {
+            let seq = std::mem::take(seq);
+            self.prompt = None;
+            self.confirm_replace(&seq, key);
+            return false;
+        }
+        match key {
+            Key::Char(c) => text.push(c),
+            Key::Backspace => {
+                text.pop();
+            }
+            Key::Enter => {
+                let prompt = prompt.clone();
+                let text = std::mem::take(text);
+                self.prompt = None;
+                // The command line can change the theme or format style, which changes how the
+                // tree is drawn
+                self.render_dirty.set(true);
+                match prompt {
+                    Prompt::InsertKeyedChild => self.insert_keyed_child(text),
+                    Prompt::InsertKeyedSibling(side) => self.insert_keyed_sibling(text, side),
+                    Prompt::RenameKey => self.rename_cursor(text),
+                    Prompt::Search => self.search(text),
+                    Prompt::CommandLine => return self.run_command_line(text),
+                    // Answered as soon as any key is pressed (see above)
+                    Prompt::ConfirmReplace(..) => unreachable!(),
+                }
+            }
+            Key::ESC => self.prompt = None,
+            _ => {}
+        }
+        false
+    }
+
+    /// Consumes a single keypress, either from the user or from a macro that is being replayed.
+    /// This returns `true` if the editor should quit.
+    fn consume_key(&mut self, key: Key) -> bool {
+        let was_recording = self.recording.is_some();
+        // Status messages only stay until the next key is pressed
+        self.status = None;
+        let should_quit = match key {
+            // Any key dismisses the help screen or the list of violations, without doing
+            // anything else
+            _ if self.show_help => {
+                self.show_help = false;
+                false
+            }
+            _ if self.violation_list.is_some() => {
+                self.violation_list = None;
+                false
+            }
+            _ if self.prompt.is_some() => self.consume_prompt_key(key),
+            Key::Char(c) => self.consume_command_char(c),
+            Key::ESC => {
+                self.command.clear();
+                self.selection_anchor = None;
+                false
+            }
+            // `<C-r>` redoes a change, like in Vim
+            Key::Ctrl('r') => {
+                self.command.clear();
+                self.redo(1);
+                false
+            }
+            _ => false,
+        };
+        // Add the key to the macro being recorded.  The keys which start and stop the recording
+        // aren't part of the macro, and nor are the keys typed by replaying other macros.
+        if was_recording && self.replaying.is_empty() {
+            if let Some((_, keys)) = &mut self.recording {
+                keys.push(key);
+            }
+        }
+        self.update_marks();
+        self.unfold_cursor_ancestors();
+        should_quit
+    }
+
+    fn mainloop(&mut self, term: &Term) {
+        log::trace!("Starting mainloop");
+        let mut mouse = self.mouse;
+        // Sit in the infinte mainloop
+        loop {
+            /* RESPOND TO THE USER'S INPUT */
+            // If the user might be part way through a multi-key sequence, only wait for the next
+            // key until the sequence times out
+            let event = if self.is_waiting_for_sequence() {
+                term.peek_event(self.sequence_timeout)
+            } else {
+                term.poll_event()
+            };
+            // Scrolling with the mouse wheel moves the view away from the cursor, so the view only
+            // follows the cursor again after the next input
+            let mut follow_cursor = true;
+            // `self.consume_key` and `self.run_command` return `true` if the editor should quit
+            let should_quit = match event {
+                Ok(Event::Key(Key::SingleClick(MouseButton::Left, row, col))) => {
+                    let view_height = term.term_size().unwrap().1.saturating_sub(2);
+                    self.click(row as usize, col as usize, view_height);
+                    false
+                }
+                Ok(Event::Key(Key::WheelUp(_, _, lines))) => {
+                    self.scroll_view(-(lines as isize));
+                    follow_cursor = false;
+                    false
+                }
+                Ok(Event::Key(Key::WheelDown(_, _, lines))) => {
+                    self.scroll_view(lines as isize);
+                    follow_cursor = false;
+                    false
+                }
+                Ok(Event::Key(key)) => self.consume_key(key),
+                Ok(_) => false,
+                // tuikit reports that no event arrived before the timeout as a plain string error
+                Err(e) if e.to_string() == "timeout" => {
+                    let should_quit = self.run_command(false);
+                    self.update_marks();
+                    should_quit
+                }
+                Err(_) => break,
+            };
+            if should_quit {
+                break;
+            }
+            // Turn mouse reporting on or off if `:set mouse` has changed it
+            if self.mouse != mouse {
+                mouse = self.mouse;
+                if mouse {
+                    term.enable_mouse_support().unwrap();
+                } else {
+                    term.disable_mouse_support().unwrap();
+                }
+            }
+
+            // Make sure that the logger isn't taller than the screen
+            let height = term.term_size().unwrap().1;
+            self.command_log.set_max_entries(height.min(10));
+            // Make sure that the cursor is on the screen
+            if follow_cursor {
+                self.scroll_to_cursor(height.saturating_sub(2));
+            }
+            self.status_line.update(self.tree, &self.format_style);
+
+            // Update the screen after every input.  The tree is only laid out again if it has
+            // changed or the view has scrolled (see `Editor::needs_full_render`).
+            self.update_display(term);
+        }
+    }
+
+    /// Start the editor and enter the mainloop
+    pub fn run(mut self) {
+        let term = Term::with_options(TermOptions::default().mouse_enabled(self.mouse)).unwrap();
+        // Start the mainloop
+        self.mainloop(&term);
+        log::trace!("Making the cursor reappear.");
+        // Show the cursor before closing so that the cursor isn't permanently disabled
+        // (see issue https://github.com/lotabout/tuikit/issues/28)
+        term.show_cursor(true).unwrap();
+        term.present().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        clipboard::ClipboardKind, config::EditorConfig, parse_command, theme::Theme, Action,
+        Editor, Key, KeyMap,
+    };
+    use crate::arena::Arena;
+    use crate::ast::{
+        cursor_path::CursorPath,
+        display_token::Indent,
+        json::{CompactFormat, JSONFormat, PrettyFormat, JSON},
+        test_json::TestJSON,
+        toml::{TOMLFormat, TOML},
+        Ast,
+    };
+    use crate::editable_tree::{Direction, Side, DAG};
+    use tuikit::attr::{Attr, Color, Effect};
+    use unicode_width::UnicodeWidthStr;
+
+    /// A line of the tree's text with the cursor and selection highlighted, stored as a list of
+    /// spans along with the column that each span starts at and the attributes it is drawn with
+    type Line = Vec<(usize, String, Attr)>;
+
+    impl<'arena, Node: Ast<'arena> + 'arena> Editor<'arena, Node> {
+        /// Lay out the text of the tree like [`Editor::layout_unhighlighted`], and then highlight
+        /// the cursor and selection like [`Editor::render_tree`] does
+        fn layout_tree(&self, num_lines: usize, scroll: usize, pinned: &[usize]) -> Vec<Line> {
+            let selected_nodes = self.highlighted_selection();
+            self.layout_unhighlighted(num_lines, scroll, pinned)
+                .into_iter()
+                .map(|line| {
+                    line.into_iter()
+                        .map(|(col, s, attr, node, _)| {
+                            (col, s, self.highlight(attr, node, &selected_nodes))
+                        })
+                        .collect()
+                })
+                .collect()
+        }
+    }
 
     #[test]
     fn parse_command_complete() {
         let keymap = super::default_keymap();
-        for (command, expected_effect) in &[
-            ("q", Action::Quit),
-            ("x", Action::Undefined),
-            ("pajlbsi", Action::MoveCursor(Direction::Up)),
-            ("Pxx", Action::Undefined),
-            ("Qsx", Action::Undefined),
-            ("ra", Action::Replace('a')),
-            ("rg", Action::Replace('g')),
-            ("oX", Action::InsertChild('X')),
-            ("oP", Action::InsertChild('P')),
+        for (command, expected_count, expected_effect) in &[
+            ("Q", 1, Action::Quit),
+            ("x", 1, Action::Undefined),
+            ("pajlbsi", 1, Action::MoveCursor(Direction::Up)),
+            ("Xxx", 1, Action::Undefined),
+            ("Zsx", 1, Action::Undefined),
+            ("ra", 1, Action::Replace("a".to_string())),
+            ("rg", 1, Action::Replace("g".to_string())),
+            ("oX", 1, Action::InsertChild('X')),
+            ("oP", 1, Action::InsertChild('P')),
+            ("3d", 3, Action::Delete),
+            ("2D", 2, Action::Duplicate),
+            ("12j", 12, Action::MoveCursor(Direction::Next)),
+            ("2r3", 2, Action::Replace("3".to_string())),
+            ("3x", 3, Action::Undefined),
+            ("sa", 1, Action::Sort(false)),
+            ("sd", 1, Action::Sort(true)),
+            ("sx", 1, Action::Undefined),
+            ("To", 1, Action::Convert("o".to_string())),
+            ("2g-", 2, Action::TimeTravel(Side::Prev)),
+            ("g+", 1, Action::TimeTravel(Side::Next)),
+            ("gx", 1, Action::Undefined),
+            ("za", 1, Action::ToggleFold),
+            ("2zM", 2, Action::FoldAtDepth),
+            ("zR", 1, Action::UnfoldAll),
+            ("zx", 1, Action::Undefined),
+            ("gg", 1, Action::MoveToRoot),
+            ("B", 1, Action::JumpToMatch),
+            ("]v", 1, Action::JumpToViolation(Side::Next)),
+            ("2[v", 2, Action::JumpToViolation(Side::Prev)),
+            ("?", 1, Action::Help),
+            ("ma", 1, Action::SetMark('a')),
+            ("qa", 1, Action::RecordMacro('a')),
+            ("'a", 1, Action::JumpToMark('a')),
+            ("3gg", 3, Action::MoveToRoot),
+            // A count of 0 behaves like 1
+            ("0j", 1, Action::MoveCursor(Direction::Next)),
+            ("00d", 1, Action::Delete),
+            ("03d", 3, Action::Delete),
+            (
+                "99999999999999999999999k",
+                usize::MAX,
+                Action::MoveCursor(Direction::Prev),
+            ),
         ] {
             assert_eq!(
-                parse_command(&keymap, *command),
-                Some(expected_effect.clone())
+                parse_command(&keymap, &[], command, true),
+                Some((*expected_count, expected_effect.clone()))
             );
         }
     }
@@ -646,8 +2873,1551 @@ mod tests {
     #[test]
     fn parse_command_incomplete() {
         let keymap = super::default_keymap();
-        for command in &["", "r", "o"] {
-            assert_eq!(parse_command(&keymap, *command), None);
+        for command in &["", "r", "o", "3", "10", "0", "2r", "s", "g", "2g"] {
+            assert_eq!(parse_command(&keymap, &[], command, true), None);
+        }
+    }
+
+    #[test]
+    fn parse_replace_seqs() {
+        let keymap = super::default_keymap();
+        let seqs: Vec<String> = ["n", "t", "tr", "ts", "xy"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let parse = |command: &str, wait_for_sequences: bool| {
+            parse_command(&keymap, &seqs, command, wait_for_sequences).map(|(_, a)| a)
+        };
+        let replace = |seq: &str| Some(Action::Replace(seq.to_string()));
+        // Unambiguous sequences are complete as soon as they are typed
+        assert_eq!(parse("rn", true), replace("n"));
+        assert_eq!(parse("rtr", true), replace("tr"));
+        assert_eq!(parse("Tts", true), Some(Action::Convert("ts".to_string())));
+        // Chars which can't start any sequence are complete, so that the command can fail
+        assert_eq!(parse("rq", true), replace("q"));
+        assert_eq!(parse("rtq", true), replace("tq"));
+        // A sequence which starts a longer one waits for the sequence timeout...
+        assert_eq!(parse("rt", true), None);
+        assert_eq!(parse("rt", false), replace("t"));
+        // ... but the start of a sequence which isn't a sequence itself waits for more keys
+        assert_eq!(parse("rx", true), None);
+        assert_eq!(parse("rx", false), None);
+    }
+
+    /// Creates a [`DAG`] containing `[true, false, null, true, false]`, with the cursor on the
+    /// first `false`
+    fn sample_tree<'arena>(arena: &'arena Arena<JSON<'arena>>) -> DAG<'arena, JSON<'arena>> {
+        let root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::False,
+            TestJSON::Null,
+            TestJSON::True,
+            TestJSON::False,
+        ])
+        .add_to_arena(arena);
+        let mut tree = DAG::new(arena, root);
+        tree.move_cursor(Direction::Down);
+        tree.move_cursor(Direction::Next);
+        tree
+    }
+
+    #[test]
+    fn count_prefix() {
+        for (keys, expected_text, expected_cursor_name) in &[
+            // Counts are applied to deletion as a single edit ...
+            ("3d", "[true, false]", "true"),
+            ("d", "[true, null, true, false]", "true"),
+            // ... and are clamped to the number of siblings that exist
+            ("9d", "[true]", "true"),
+            // Movement is repeated, and stops at the first failure
+            ("2j", "[true, false, null, true, false]", "true"),
+            ("9j", "[true, false, null, true, false]", "false"),
+            ("2J", "[true, null, true, false, false]", "false"),
+            // Undo is repeated as well, which restores the cursor to the root
+            ("dd2u", "[true, false, null, true, false]", "array"),
+            // The count is reset after every command
+            ("2jd", "[true, false, null, false]", "null"),
+            // Commands that can't be repeated ignore the count
+            ("3rn", "[true, null, null, true, false]", "null"),
+        ] {
+            let arena = Arena::new();
+            let mut tree = sample_tree(&arena);
+            let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+            for c in keys.chars() {
+                assert!(!editor.consume_command_char(c));
+            }
+            assert!(editor.command.is_empty());
+            assert_eq!(editor.tree.to_text(&JSONFormat::COMPACT), *expected_text);
+            assert_eq!(editor.tree.cursor().display_name(), *expected_cursor_name);
+        }
+    }
+
+    #[test]
+    fn macros() {
+        for (keys, expected_text, expected_cursor_name) in &[
+            // Recording a macro also executes its commands
+            ("qartjq", "[true, true, null, true, false]", "null"),
+            ("qartjq@a", "[true, true, true, true, false]", "true"),
+            // `@@` replays the last macro that was replayed
+            ("qartjq@a@@", "[true, true, true, true, false]", "false"),
+            // Replaying stops at the first failure, which here is moving past the last child
+            ("qartjq9@a", "[true, true, true, true, true]", "true"),
+            // Every edit in a macro is undone separately
+            ("qartjq@au", "[true, true, null, true, false]", "true"),
+            // A macro which replays itself stops instead of looping
+            ("qa@arnjq@a@a", "[true, null, null, true, false]", "null"),
+            // Replaying an empty register does nothing
+            ("@b@@", "[true, false, null, true, false]", "false"),
+            // Recording into a register overwrites its old macro
+            ("qarnqqartq@a", "[true, true, null, true, false]", "true"),
+        ] {
+            let arena = Arena::new();
+            let mut tree = sample_tree(&arena);
+            let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+            for c in keys.chars() {
+                assert!(!editor.consume_key(Key::Char(c)));
+            }
+            assert!(editor.command.is_empty());
+            assert!(editor.recording.is_none());
+            assert_eq!(editor.tree.to_text(&JSONFormat::COMPACT), *expected_text);
+            assert_eq!(editor.tree.cursor().display_name(), *expected_cursor_name);
+        }
+    }
+
+    #[test]
+    fn replay_delete_and_move() {
+        let arena = Arena::new();
+        let mut tree = sample_tree(&arena);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        // Record a macro which moves to the next sibling and deletes it
+        assert!(!type_keys(&mut editor, "qajdq"));
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            "[true, false, true, false]"
+        );
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::from_vec(vec![1]));
+        // Replaying it once repeats both commands from the new cursor position
+        assert!(!type_keys(&mut editor, "@a"));
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            "[true, false, false]"
+        );
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::from_vec(vec![1]));
+        assert_eq!(editor.status, None);
+        // The second repetition can't move past the last child, so it stops without deleting it
+        assert!(!type_keys(&mut editor, "3@a"));
+        assert_eq!(editor.tree.to_text(&JSONFormat::COMPACT), "[true, false]");
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::from_vec(vec![1]));
+        assert_eq!(
+            editor.status.as_deref(),
+            Some("Stopped replaying macro 'a' because a command failed.")
+        );
+    }
+
+    #[test]
+    fn visual_mode() {
+        for (keys, expected_text, expected_cursor_name) in &[
+            // Selections can extend in either direction from where they started
+            ("vjjd", "[true, false]", "true"),
+            ("vkd", "[null, true, false]", "array"),
+            // The count of `d` is ignored when there is a selection
+            ("vj9d", "[true, true, false]", "true"),
+            // Leaving visual mode (with `v` or ESC) goes back to deleting just the cursor
+            ("vjvd", "[true, false, true, false]", "false"),
+            ("vj\x1bd", "[true, false, true, false]", "false"),
+            // The selection can't move to a different parent
+            ("vpd", "[true, null, true, false]", "true"),
+            // Yanked selections are pasted in the same order
+            (
+                "vjyjjP",
+                "[true, false, null, true, false, false, null]",
+                "null",
+            ),
+            // ... and are undone together
+            ("vjyjjPu", "[true, false, null, true, false]", "array"),
+            // The selection ends after any other command
+            ("vjyd", "[true, false, true, false]", "false"),
+        ] {
+            let arena = Arena::new();
+            let mut tree = sample_tree(&arena);
+            let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+            for c in keys.chars() {
+                let key = if c == '\x1b' { Key::ESC } else { Key::Char(c) };
+                assert!(!editor.consume_key(key));
+            }
+            assert_eq!(editor.selection(), None);
+            assert_eq!(editor.tree.to_text(&JSONFormat::COMPACT), *expected_text);
+            assert_eq!(editor.tree.cursor().display_name(), *expected_cursor_name);
+        }
+        // The root can't be selected
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![]).add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        editor.consume_key(Key::Char('v'));
+        assert_eq!(editor.selection(), None);
+    }
+
+    #[test]
+    fn visual_delete() {
+        let arena = Arena::new();
+        let mut tree = sample_tree(&arena);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        // Extending the selection past the first sibling leaves it at the first sibling
+        assert!(!type_keys(&mut editor, "vkkk"));
+        assert_eq!(editor.selection(), Some(0..2));
+        // Select `false` and `null`, and delete them.  The cursor moves to the previous sibling.
+        assert!(!type_keys(&mut editor, "\x1bjvjd"));
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            "[true, true, false]"
+        );
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::from_vec(vec![0]));
+        // The whole selection is deleted in one undoable step
+        assert!(!type_keys(&mut editor, "u"));
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            "[true, false, null, true, false]"
+        );
+    }
+
+    #[test]
+    fn search() {
+        for (keys, expected_cursor_path) in &[
+            ("/true\n", vec![3]),
+            ("/true\nn", vec![0]),
+            ("/true\nN", vec![0]),
+            ("/true\n2n", vec![3]),
+            ("/e\n2n", vec![0]),
+            // Failed searches leave the cursor where it is
+            ("/nothing\n", vec![1]),
+            ("n", vec![1]),
+        ] {
+            let arena = Arena::new();
+            let mut tree = sample_tree(&arena);
+            let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+            for c in keys.chars() {
+                let key = if c == '\n' { Key::Enter } else { Key::Char(c) };
+                assert!(!editor.consume_key(key));
+            }
+            assert_eq!(
+                editor.tree.cursor_path(),
+                &CursorPath::from_vec(expected_cursor_path.clone())
+            );
+        }
+    }
+
+    #[test]
+    fn search_single_match() {
+        // [true, {"a": false}, null]
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::Object(vec![("a".to_string(), TestJSON::False)]),
+            TestJSON::Null,
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        // Searching is case-insensitive, and finds the only object
+        type_keys(&mut editor, "/OBJECT\n");
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::from_vec(vec![1]));
+        assert!(!editor.command_failed);
+        // With only one match, `n` wraps all the way around to the same node
+        for keys in &["n", "2n", "N"] {
+            type_keys(&mut editor, keys);
+            assert_eq!(editor.tree.cursor_path(), &CursorPath::from_vec(vec![1]));
+            assert!(!editor.command_failed);
+        }
+        // Searching again starts from the cursor, so moving away and pressing `n` comes back
+        type_keys(&mut editor, "jn");
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::from_vec(vec![1]));
+    }
+
+    /// Types some keys into the editor (where `'\n'` presses enter, `'\u{8}'` presses backspace
+    /// and `'\x1b'` presses escape), returning `true` if the last key quit the editor
+    fn type_keys<'arena>(editor: &mut Editor<'arena, JSON<'arena>>, keys: &str) -> bool {
+        let mut should_quit = false;
+        for c in keys.chars() {
+            let key = match c {
+                '\n' => Key::Enter,
+                '\u{8}' => Key::Backspace,
+                '\x1b' => Key::ESC,
+                _ => Key::Char(c),
+            };
+            should_quit = editor.consume_key(key);
+        }
+        should_quit
+    }
+
+    /// Returns the text of each line that the editor would draw for the tree
+    fn rendered_lines<'arena>(editor: &Editor<'arena, JSON<'arena>>) -> Vec<String> {
+        editor
+            .layout_tree(usize::MAX, 0, &[])
+            .iter()
+            .map(line_text)
+            .collect()
+    }
+
+    #[test]
+    fn theme() {
+        let arena = Arena::new();
+        let root = JSON::from_text(r#"[{"a": null}, "b"]"#, &arena).unwrap();
+        let mut tree = DAG::new(&arena, root);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        assert!(!type_keys(&mut editor, ":set theme light\n"));
+        assert_eq!(editor.theme, Theme::LIGHT);
+        // Every span is coloured by the kind of node that it belongs to
+        fn spans<'arena>(editor: &Editor<'arena, JSON<'arena>>) -> Vec<(String, Attr)> {
+            let lines = editor.layout_tree(usize::MAX, 0, &[]);
+            lines[0].iter().map(|(_, s, a)| (s.clone(), *a)).collect()
+        }
+        let container = Attr::default().fg(Color::BLUE);
+        let cursor = container.effect(Effect::REVERSE);
+        assert_eq!(
+            spans(&editor),
+            vec![
+                ("[".to_string(), cursor),
+                ("{".to_string(), container),
+                (r#""a""#.to_string(), Attr::default().fg(Color::MAGENTA)),
+                (": ".to_string(), Attr::default().fg(Color::LIGHT_BLACK)),
+                ("null".to_string(), Attr::default().fg(Color::RED)),
+                ("}".to_string(), container),
+                (",".to_string(), cursor),
+                (r#""b""#.to_string(), Attr::default().fg(Color::GREEN)),
+                ("]".to_string(), cursor),
+            ]
+        );
+        // Without colours, only the cursor stands out
+        assert!(!type_keys(&mut editor, ":set theme monochrome\n"));
+        assert!(spans(&editor)
+            .iter()
+            .all(|(_, attr)| attr.fg == Color::Default));
+        assert!(!type_keys(&mut editor, ":set theme solarized\n"));
+        assert_eq!(
+            editor.status.as_deref(),
+            Some("Expected 'dark', 'light' or 'monochrome', found 'solarized'.")
+        );
+    }
+
+    #[test]
+    fn substitute() {
+        let arena = Arena::new();
+        let mut tree = sample_tree(&arena);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        assert!(!type_keys(&mut editor, ":%s/true/\"yes\"\n"));
+        assert_eq!(editor.status.as_deref(), Some("Replaced 2 nodes"));
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            r#"["yes", false, null, "yes", false]"#
+        );
+        // The replacement is undone in one step
+        assert!(!type_keys(&mut editor, "u"));
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            "[true, false, null, true, false]"
+        );
+        assert!(!type_keys(&mut editor, ":%s/[]/null\n"));
+        assert_eq!(
+            editor.status.as_deref(),
+            Some("There are no nodes matching '[]'.")
+        );
+        assert!(!type_keys(&mut editor, ":%s/true/tru\n"));
+        assert!(editor.command_failed);
+        assert!(editor
+            .status
+            .as_deref()
+            .unwrap()
+            .starts_with("Couldn't parse substitution: "));
+    }
+
+    #[test]
+    fn confirm_replace() {
+        let arena = Arena::new();
+        let root = JSON::from_text(r#"[[true, false], {"a": null}]"#, &arena).unwrap();
+        let mut tree = DAG::new(&arena, root);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        // Replacing the inner array with `true` would discard its children, so asks first
+        assert!(!type_keys(&mut editor, "crt"));
+        assert_eq!(
+            editor.prompt,
+            Some((
+                super::Prompt::ConfirmReplace("t".to_string(), 2),
+                String::new()
+            ))
+        );
+        assert_eq!(
+            editor.prompt.as_ref().unwrap().0.message(),
+            "Replace and discard 2 children? (y/n) "
+        );
+        // The converted node is kept until the prompt is answered, rather than converted again
+        assert_eq!(editor.pending_replacement, Some(JSON::True));
+        // Any key other than `y` leaves the tree alone
+        assert!(!type_keys(&mut editor, "n"));
+        assert_eq!(editor.prompt, None);
+        assert_eq!(editor.pending_replacement, None);
+        assert_eq!(editor.status.as_deref(), Some("Replace cancelled"));
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            r#"[[true, false], {"a": null}]"#
+        );
+        assert!(!type_keys(&mut editor, "rty"));
+        assert_eq!(editor.pending_replacement, None);
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            r#"[true, {"a": null}]"#
+        );
+        // Replacements which keep every child don't ask
+        assert!(!type_keys(&mut editor, "jra"));
+        assert_eq!(editor.prompt, None);
+        assert_eq!(editor.tree.to_text(&JSONFormat::COMPACT), "[true, [null]]");
+        assert!(!type_keys(&mut editor, "krf"));
+        assert_eq!(editor.prompt, None);
+        assert_eq!(editor.tree.to_text(&JSONFormat::COMPACT), "[false, [null]]");
+    }
+
+    #[test]
+    fn two_char_replace_seqs() {
+        let arena = Arena::new();
+        let mut tree = sample_tree(&arena);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        // `[]` only replaces the cursor once both of its chars have been typed
+        assert!(!type_keys(&mut editor, "r["));
+        assert_eq!(editor.command, "r[");
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            "[true, false, null, true, false]"
+        );
+        assert!(!type_keys(&mut editor, "]"));
+        assert!(editor.command.is_empty());
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            "[true, [], null, true, false]"
+        );
+        assert!(!type_keys(&mut editor, "jr{}jr\"\""));
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            r#"[true, [], {}, "", false]"#
+        );
+        // Single-char sequences still work alongside them
+        assert!(!type_keys(&mut editor, "jrn"));
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            r#"[true, [], {}, "", null]"#
+        );
+    }
+
+    #[test]
+    fn marks() {
+        let arena = Arena::new();
+        let mut tree = sample_tree(&arena);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        // Mark the second `true`, then delete the `null` before it
+        assert!(!type_keys(&mut editor, "jjmakd"));
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            "[true, false, true, false]"
+        );
+        // The mark follows its node to its new index
+        assert!(!type_keys(&mut editor, "gg'a"));
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::from_vec(vec![2]));
+        // Deleting the marked node removes the mark
+        assert!(!type_keys(&mut editor, "d'a"));
+        assert!(editor.command_failed);
+        assert_eq!(
+            editor.status.as_deref(),
+            Some("Mark 'a' isn't set, or its node has been removed.")
+        );
+        assert!(!type_keys(&mut editor, "'b"));
+        assert!(editor.command_failed);
+    }
+
+    /// Returns the text of a line laid out by [`Editor::layout_tree`]
+    fn line_text(line: &Line) -> String {
+        let mut text = String::new();
+        for (col, span, _) in line {
+            text += &" ".repeat(col - text.width());
+            text += span;
+        }
+        text
+    }
+
+    /// An array of `len` arrays of 3 values, which is made of `4 * len + 1` nodes
+    fn nested_arrays<'arena>(
+        arena: &'arena Arena<JSON<'arena>>,
+        len: usize,
+    ) -> DAG<'arena, JSON<'arena>> {
+        let inner = || TestJSON::Array(vec![TestJSON::True, TestJSON::Null, TestJSON::False]);
+        let root = TestJSON::Array((0..len).map(|_| inner()).collect()).add_to_arena(arena);
+        DAG::new(arena, root)
+    }
+
+    #[test]
+    fn wide_characters() {
+        let arena = Arena::new();
+        let root = TestJSON::Object(vec![
+            ("日本語".to_string(), TestJSON::True),
+            ("abc".to_string(), TestJSON::False),
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        let editor = Editor::new(&mut tree, JSONFormat::PRETTY, super::default_keymap());
+        // Each of the three wide characters takes up two columns, so the value after the key
+        // starts 6 columns later than its `char` count would suggest
+        let lines = editor.layout_tree(usize::MAX, 0, &[]);
+        let columns = |line: usize| -> Vec<usize> { lines[line].iter().map(|s| s.0).collect() };
+        assert_eq!(columns(1), vec![4, 12, 14, 18]);
+        assert_eq!(columns(2), vec![4, 9, 11]);
+        assert_eq!(
+            rendered_lines(&editor),
+            vec!["{", r#"    "日本語": true,"#, r#"    "abc": false"#, "}"]
+        );
+    }
+
+    #[test]
+    fn layout_window() {
+        let arena = Arena::new();
+        let mut tree = nested_arrays(&arena, 20);
+        let editor = Editor::new(&mut tree, JSONFormat::PRETTY, super::default_keymap());
+        let full = rendered_lines(&editor);
+        assert_eq!(full.len(), 20 * 5 + 2);
+        // Only the lines in the window are laid out, and they match the full layout
+        let window = editor.layout_tree(30, 20, &[0]);
+        assert_eq!(window.len(), 30);
+        for (i, line) in window.iter().enumerate() {
+            let expected = if i == 0 || i >= 20 { &full[i] } else { "" };
+            assert_eq!(line_text(line), expected);
+        }
+        // The nodes before the window are skipped rather than laid out, but everything after them
+        // is still drawn in the same place, including when the layout depends on the column
+        let root = JSON::from_text(
+            r#"{"a": [true, {"b": [null, []], "cc": "x\ny"}], "d": {"e": [false]}, "f": null}"#,
+            &arena,
+        )
+        .unwrap();
+        let mut tree = DAG::new(&arena, root);
+        let mut editor = Editor::new(&mut tree, JSONFormat::PRETTY, super::default_keymap());
+        for format in &[
+            JSONFormat::PRETTY,
+            JSONFormat::pretty_fill(16),
+            JSONFormat::Pretty(PrettyFormat {
+                align_values: true,
+                trailing_commas: true,
+                ..PrettyFormat::fill(20)
+            }),
+        ] {
+            editor.format_style = *format;
+            let full = rendered_lines(&editor);
+            for scroll in 0..full.len() {
+                let window: Vec<String> = editor
+                    .layout_tree(usize::MAX, scroll, &[])
+                    .iter()
+                    .map(line_text)
+                    .collect();
+                assert_eq!(
+                    window[scroll..],
+                    full[scroll..],
+                    "{:?} from {}",
+                    format,
+                    scroll
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn render_cache() {
+        let arena = Arena::new();
+        let mut tree = sample_tree(&arena);
+        let mut editor = Editor::new(&mut tree, JSONFormat::PRETTY, super::default_keymap());
+        let window = (usize::MAX, 0, vec![]);
+        assert!(editor.needs_full_render());
+        editor.update_layout(window.clone());
+        assert!(!editor.needs_full_render());
+        // Moving the cursor keeps the cached layout, and the cursor is highlighted when the cached
+        // lines are drawn
+        assert!(!type_keys(&mut editor, "j"));
+        assert!(!editor.needs_full_render());
+        let highlighted: Vec<Line> = {
+            let selected_nodes = editor.highlighted_selection();
+            let layout = editor.tree_layout.borrow();
+            let lines = &layout.as_ref().unwrap().lines;
+            lines
+                .iter()
+                .map(|line| {
+                    line.iter()
+                        .map(|(col, s, attr, node, _)| {
+                            (
+                                *col,
+                                s.clone(),
+                                editor.highlight(*attr, *node, &selected_nodes),
+                            )
+                        })
+                        .collect()
+                })
+                .collect()
+        };
+        assert_eq!(highlighted, editor.layout_tree(usize::MAX, 0, &[]));
+        // Replacing a node changes the tree, so it has to be laid out again
+        assert!(!type_keys(&mut editor, "rn"));
+        assert!(editor.needs_full_render());
+        editor.update_layout(window);
+        assert!(!editor.needs_full_render());
+        assert_eq!(
+            editor.tree_layout.borrow().as_ref().unwrap().lines[3][0].1,
+            "null"
+        );
+        // Changing the theme doesn't change the tree, but does change its colours
+        assert!(!type_keys(&mut editor, ":set theme light\n"));
+        assert!(editor.needs_full_render());
+    }
+
+    /// Checks that laying out one screen of a 10k node tree is much faster than laying out the
+    /// whole tree, wherever the screen is.  Run with `cargo test --release bench_layout --
+    /// --ignored`.
+    #[test]
+    #[ignore]
+    fn bench_layout() {
+        let arena = Arena::new();
+        let mut tree = nested_arrays(&arena, 2500);
+        assert_eq!(tree.root().node_count(), 10001);
+        let editor = Editor::new(&mut tree, JSONFormat::PRETTY, super::default_keymap());
+        let iterations = 100;
+        let time = |num_lines: usize, scroll: usize| {
+            let begin = std::time::Instant::now();
+            for _ in 0..iterations {
+                editor.layout_tree(num_lines, scroll, &[]);
+            }
+            begin.elapsed() / iterations
+        };
+        let full = time(usize::MAX, 0);
+        // The tree is 12502 lines long
+        for scroll in [0, 6250, 12450] {
+            let window = time(scroll + 50, scroll);
+            assert!(
+                window * 4 < full,
+                "Laying out 50 lines from line {} took {:?}, but the whole tree took {:?}",
+                scroll,
+                window,
+                full
+            );
+        }
+    }
+
+    #[test]
+    fn cycle_format() {
+        let arena = Arena::new();
+        let mut tree = sample_tree(&arena);
+        let mut editor = Editor::new(&mut tree, JSONFormat::PRETTY, super::default_keymap());
+        assert_eq!(rendered_lines(&editor).len(), 7);
+        // `F` switches to the compact layout, which puts everything on one line
+        assert!(!editor.consume_command_char('F'));
+        assert_eq!(editor.format_style, JSONFormat::COMPACT);
+        assert_eq!(
+            rendered_lines(&editor),
+            vec!["[true, false, null, true, false]"]
+        );
+        // Pressing it again switches back, keeping `sort_keys` but resetting the options which
+        // only the compact formatter has
+        editor.format_style = JSONFormat::Compact(CompactFormat {
+            minified: true,
+            sort_keys: true,
+        });
+        assert!(!editor.consume_command_char('F'));
+        assert_eq!(
+            editor.format_style,
+            JSONFormat::Pretty(PrettyFormat {
+                sort_keys: true,
+                ..PrettyFormat::DEFAULT
+            })
+        );
+        assert_eq!(
+            rendered_lines(&editor),
+            vec![
+                "[",
+                "    true,",
+                "    false,",
+                "    null,",
+                "    true,",
+                "    false",
+                "]"
+            ]
+        );
+    }
+
+    #[test]
+    fn with_config() {
+        let arena = Arena::new();
+        let mut tree = sample_tree(&arena);
+        let path = std::env::temp_dir().join(format!("sapling-config-{}.json", std::process::id()));
+        let config = EditorConfig {
+            format: JSONFormat::Pretty(PrettyFormat {
+                indent: Indent::Spaces(2),
+                ..PrettyFormat::DEFAULT
+            }),
+            keymap: KeyMap::from_config_str("x = \"delete\"").unwrap().0,
+            path: Some(path.clone()),
+            trailing_newline: true,
+            sequence_timeout: std::time::Duration::from_millis(500),
+            theme: Theme::MONOCHROME,
+            mouse: false,
+            clipboard: ClipboardKind::InMemory,
+        };
+        let mut editor = Editor::with_config(&mut tree, config);
+        // The tree is rendered in the configured format
+        assert_eq!(
+            rendered_lines(&editor),
+            vec!["[", "  true,", "  false,", "  null,", "  true,", "  false", "]"]
+        );
+        // The configured keymap, path and trailing newline are used too
+        assert!(!type_keys(&mut editor, "x:w\n"));
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "[\n  true,\n  null,\n  true,\n  false\n]\n"
+        );
+        std::fs::remove_file(&path).unwrap();
+        // The default config uses the pretty format and the default keymap
+        let default_config: EditorConfig<JSONFormat> = EditorConfig::default();
+        assert_eq!(default_config.format, JSONFormat::PRETTY);
+        assert_eq!(default_config.keymap, super::default_keymap());
+        assert_eq!(default_config.path, None);
+        assert!(!default_config.trailing_newline);
+        assert_eq!(default_config.clipboard, ClipboardKind::System);
+        assert_eq!(
+            default_config.sequence_timeout,
+            std::time::Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn write_file() {
+        let arena = Arena::new();
+        let mut tree = sample_tree(&arena);
+        let mut editor = Editor::new(&mut tree, JSONFormat::PRETTY, super::default_keymap());
+        let path = std::env::temp_dir().join(format!("sapling-write-{}.json", std::process::id()));
+        // `:w <path>` writes the tree in the current format
+        assert!(!type_keys(&mut editor, &format!(":w {}\n", path.display())));
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            editor.tree.to_text(&JSONFormat::PRETTY)
+        );
+        assert_eq!(editor.status, Some(format!("Wrote {}", path.display())));
+        // `:w` on its own re-saves to the same file
+        assert!(!type_keys(&mut editor, "2d:w\n"));
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            editor.tree.to_text(&JSONFormat::PRETTY)
+        );
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            "[true, true, false]"
+        );
+        std::fs::remove_file(&path).unwrap();
+        // I/O errors are shown in the status bar, and don't change the remembered path
+        let bad_path = path.join("not-a-directory.json");
+        assert!(!type_keys(
+            &mut editor,
+            &format!(":wq {}\n", bad_path.display())
+        ));
+        assert!(editor
+            .status
+            .as_deref()
+            .unwrap()
+            .starts_with(&format!("Couldn't write {}: ", bad_path.display())));
+        assert_eq!(editor.path, Some(path));
+    }
+
+    #[test]
+    fn help() {
+        let arena = Arena::new();
+        let mut tree = sample_tree(&arena);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        assert!(!type_keys(&mut editor, "?"));
+        assert!(editor.show_help);
+        // The key which dismisses the help screen doesn't do anything else
+        assert!(!type_keys(&mut editor, "Q"));
+        assert!(!editor.show_help);
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::from_vec(vec![1]));
+        assert!(type_keys(&mut editor, "Q"));
+    }
+
+    #[test]
+    fn unsaved_changes() {
+        let arena = Arena::new();
+        let mut tree = sample_tree(&arena);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        // Quitting is refused once the tree has been edited
+        assert!(!type_keys(&mut editor, "d"));
+        assert!(editor.is_modified());
+        assert!(!type_keys(&mut editor, "Q"));
+        assert!(!type_keys(&mut editor, ":q\n"));
+        assert_eq!(
+            editor.status.as_deref(),
+            Some("There are unsaved changes (use :q! to quit anyway, or :wq to save and quit).")
+        );
+        // Undoing back to the saved state means there's nothing to lose
+        assert!(!type_keys(&mut editor, "u"));
+        assert!(!editor.is_modified());
+        assert!(!type_keys(&mut editor, "d"));
+        assert!(type_keys(&mut editor, ":q!\n"));
+        // Writing or opening a file makes the tree unmodified
+        let path =
+            std::env::temp_dir().join(format!("sapling-unsaved-{}.json", std::process::id()));
+        editor.write(Some(path.clone()));
+        assert!(!editor.is_modified());
+        assert!(!type_keys(&mut editor, "d"));
+        let edited = editor.tree.root();
+        // Opening a file would lose the edit, so it is refused unless forced
+        assert!(!type_keys(&mut editor, &format!(":e {}\n", path.display())));
+        assert_eq!(
+            editor.status.as_deref(),
+            Some("No write since last change (add ! to override).")
+        );
+        assert!(editor.is_modified());
+        assert!(std::ptr::eq(editor.tree.root(), edited));
+        assert!(!type_keys(
+            &mut editor,
+            &format!(":e! {}\n", path.display())
+        ));
+        assert!(!editor.is_modified());
+        assert!(!std::ptr::eq(editor.tree.root(), edited));
+        assert!(type_keys(&mut editor, "Q"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn export_dot() {
+        let arena = Arena::new();
+        let mut tree = sample_tree(&arena);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        let path = std::env::temp_dir().join(format!("sapling-export-{}.dot", std::process::id()));
+        assert!(!type_keys(
+            &mut editor,
+            &format!(":export dot {}\n", path.display())
+        ));
+        assert_eq!(editor.status, Some(format!("Exported {}", path.display())));
+        let dot = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(dot, editor.tree.root().to_dot());
+        assert!(dot.starts_with("digraph {\n    n0 [label=\"array\"];\n"));
+        std::fs::remove_file(&path).unwrap();
+        // Exporting doesn't change the path that `:w` writes to
+        assert_eq!(editor.path, None);
+    }
+
+    #[test]
+    fn prune() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::Array(vec![]),
+            TestJSON::Object(vec![("a".to_string(), TestJSON::Object(vec![]))]),
+            TestJSON::True,
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        assert!(!type_keys(&mut editor, ":prune\n"));
+        assert_eq!(editor.tree.to_text(&JSONFormat::COMPACT), "[true]");
+        assert_eq!(editor.status.as_deref(), Some("Removed 3 empty nodes"));
+        assert!(!type_keys(&mut editor, ":prune\n"));
+        assert_eq!(
+            editor.status.as_deref(),
+            Some("There are no empty containers to remove.")
+        );
+        assert!(!type_keys(&mut editor, "u"));
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            r#"[[], {"a": {}}, true]"#
+        );
+    }
+
+    #[test]
+    fn validate() {
+        let arena = Arena::new();
+        let mut tree = sample_tree(&arena);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        assert!(!type_keys(&mut editor, "]v"));
+        assert_eq!(
+            editor.status.as_deref(),
+            Some("No schema has been loaded (use ':validate <path>').")
+        );
+        let path = std::env::temp_dir().join(format!("sapling-schema-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"items": {"type": "boolean"}, "maxItems": "4"}"#).unwrap();
+        assert!(!type_keys(
+            &mut editor,
+            &format!(":validate {}\n", path.display())
+        ));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            editor.status.as_deref(),
+            Some("Found 2 violations of the schema.")
+        );
+        assert_eq!(
+            editor.violation_list,
+            Some(vec![
+                "$: Expected at most 4 items, found 5.".to_string(),
+                "$[2]: Expected boolean, found null.".to_string(),
+            ])
+        );
+        // Any key hides the list
+        assert!(!type_keys(&mut editor, "\x1b"));
+        assert_eq!(editor.violation_list, None);
+        // Jumping between violations wraps around the ends of the document
+        assert!(!type_keys(&mut editor, "]v"));
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::from_vec(vec![2]));
+        assert_eq!(
+            editor.status.as_deref(),
+            Some("Violation 2 of 2: Expected boolean, found null.")
+        );
+        assert!(!type_keys(&mut editor, "]v"));
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::root());
+        assert!(!type_keys(&mut editor, "[v"));
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::from_vec(vec![2]));
+        // Violations are found again after every edit
+        assert!(!type_keys(&mut editor, "rf]v"));
+        assert_eq!(
+            editor.status.as_deref(),
+            Some("Violation 1 of 1: Expected at most 4 items, found 5.")
+        );
+        assert!(!type_keys(&mut editor, "cd]v"));
+        assert!(editor.command_failed);
+        assert_eq!(
+            editor.status.as_deref(),
+            Some("The tree matches the schema.")
+        );
+    }
+
+    #[test]
+    fn stats() {
+        let arena = Arena::new();
+        let mut tree = sample_tree(&arena);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        assert!(!type_keys(&mut editor, ":stats\n"));
+        assert_eq!(
+            editor.status.as_deref(),
+            Some("6 nodes, max depth 2: 1 array, 4 booleans, 1 null")
+        );
+    }
+
+    #[test]
+    fn lint() {
+        let arena = Arena::new();
+        let root = JSON::from_text(r#"{"a": true, "b": null, "a": false}"#, &arena).unwrap();
+        let mut tree = DAG::new(&arena, root);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        // Both copies of a duplicated key are highlighted, along with their fields' colons
+        let lines = editor.layout_tree(usize::MAX, 0, &[]);
+        let error = Attr::default()
+            .fg(Color::LIGHT_RED)
+            .effect(Effect::UNDERLINE);
+        let attrs: Vec<(&str, Attr)> = lines[0].iter().map(|(_, s, a)| (s.as_str(), *a)).collect();
+        assert_eq!(attrs[1], (r#""a""#, error));
+        assert_eq!(attrs[2], (": ", error));
+        assert_eq!(
+            attrs[5],
+            (r#""b""#, Attr::default().fg(Color::LIGHT_MAGENTA))
+        );
+        assert_eq!(attrs[9], (r#""a""#, error));
+        assert!(!type_keys(&mut editor, ":lint\n"));
+        assert_eq!(editor.status.as_deref(), Some("Found 2 problems."));
+        assert_eq!(
+            editor.violation_list,
+            Some(vec![
+                r#"$.a (field 0): Duplicate key "a"."#.to_string(),
+                r#"$.a (field 2): Duplicate key "a"."#.to_string(),
+            ])
+        );
+        // Duplicated keys can be jumped between without a schema
+        assert!(!type_keys(&mut editor, "\x1b]v]v"));
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::from_vec(vec![2]));
+        assert_eq!(
+            editor.status.as_deref(),
+            Some(r#"Violation 2 of 2: Duplicate key "a"."#)
+        );
+        // Renaming one of the keys fixes the problem
+        assert!(!type_keys(&mut editor, "e\u{8}c\n:lint\n"));
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            r#"{"a": true, "b": null, "c": false}"#
+        );
+        assert_eq!(editor.status.as_deref(), Some("No problems found."));
+        assert_eq!(editor.violation_list, None);
+    }
+
+    #[test]
+    fn goto() {
+        let arena = Arena::new();
+        let mut tree = sample_tree(&arena);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        assert!(!type_keys(&mut editor, ":goto $[3]\n"));
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::from_vec(vec![3]));
+        // Paths which don't exist leave the cursor where it is
+        assert!(!type_keys(&mut editor, ":goto $[5]\n"));
+        assert!(editor.command_failed);
+        assert_eq!(
+            editor.status.as_deref(),
+            Some("Index 5 is out of range, because $ only has 5 children.")
+        );
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::from_vec(vec![3]));
+    }
+
+    #[test]
+    fn open_file() {
+        let arena = Arena::new();
+        let mut tree = sample_tree(&arena);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        let path = std::env::temp_dir().join(format!("sapling-open-{}.json", std::process::id()));
+        // Files which can't be read leave the tree untouched
+        assert!(!type_keys(&mut editor, &format!(":e {}\n", path.display())));
+        assert!(editor
+            .status
+            .as_deref()
+            .unwrap()
+            .starts_with(&format!("Couldn't read {}: ", path.display())));
+        // As do files which can't be parsed
+        std::fs::write(&path, "[true, nul]").unwrap();
+        assert!(!type_keys(&mut editor, &format!(":e {}\n", path.display())));
+        assert_eq!(
+            editor.status,
+            Some(format!(
+                "Couldn't parse {}: Unexpected char ']' at line 1, column 11.",
+                path.display()
+            ))
+        );
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            "[true, false, null, true, false]"
+        );
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::from_vec(vec![1]));
+        // Valid files replace the tree, and move the cursor to the root
+        std::fs::write(&path, r#"{"a": [true, null], "b": {}}"#).unwrap();
+        assert!(!type_keys(&mut editor, &format!(":e {}\n", path.display())));
+        let expected = TestJSON::Object(vec![
+            (
+                "a".to_string(),
+                TestJSON::Array(vec![TestJSON::True, TestJSON::Null]),
+            ),
+            ("b".to_string(), TestJSON::Object(vec![])),
+        ])
+        .add_to_arena(&arena);
+        assert_eq!(editor.tree.root(), expected);
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::root());
+        assert_eq!(editor.path, Some(path.clone()));
+        // The old document can't be reached with undo
+        assert!(!type_keys(&mut editor, "u"));
+        assert_eq!(editor.tree.root(), expected);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn command_line() {
+        let arena = Arena::new();
+        let mut tree = sample_tree(&arena);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        // Format options can be set
+        assert!(!type_keys(
+            &mut editor,
+            ":set format pretty\n:set indent tab\n"
+        ));
+        assert_eq!(
+            editor.format_style,
+            JSONFormat::Pretty(PrettyFormat {
+                indent: Indent::Tab,
+                ..PrettyFormat::DEFAULT
+            })
+        );
+        // Errors are shown in the status bar
+        assert!(!type_keys(&mut editor, ":frobnicate\n"));
+        assert_eq!(
+            editor.status.as_deref(),
+            Some("Unknown command 'frobnicate'.")
+        );
+        assert!(!type_keys(&mut editor, ":set indent lots\n"));
+        assert_eq!(
+            editor.status.as_deref(),
+            Some("Expected a number of spaces or 'tab', found 'lots'.")
+        );
+        assert!(!type_keys(&mut editor, ":w\n"));
+        assert_eq!(editor.status.as_deref(), Some("No file name given."));
+        // The status disappears after the next key
+        assert!(!type_keys(&mut editor, "j"));
+        assert_eq!(editor.status, None);
+        // Writing remembers the path, so `:wq` writes to the same file
+        let path = std::env::temp_dir().join(format!("sapling-test-{}.json", std::process::id()));
+        let command = format!(":w {}\n", path.display());
+        assert!(!type_keys(&mut editor, &command));
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "[\n\ttrue,\n\tfalse,\n\tnull,\n\ttrue,\n\tfalse\n]"
+        );
+        // `F` cycles between the layouts, and saving uses the current layout
+        assert!(!type_keys(&mut editor, "F"));
+        assert_eq!(editor.format_style, JSONFormat::COMPACT);
+        assert!(!type_keys(&mut editor, "3F"));
+        assert_eq!(editor.format_style, JSONFormat::PRETTY);
+        assert!(type_keys(&mut editor, ":set format compact\n:wq\n"));
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "[true, false, null, true, false]"
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn jump_to_boundary() {
+        for (keys, expected_path) in &[
+            ("%", vec![0]),
+            ("%%", vec![2]),
+            ("%%%", vec![]),
+            ("%%%%", vec![0]),
+            ("2%", vec![2]),
+            // A leaf jumps around its parent
+            ("c%", vec![2]),
+            ("cjcj%", vec![1, 0]),
+            ("cjj%", vec![]),
+            // A container starts a new cycle around itself
+            ("cj%", vec![1, 0]),
+            ("cj%%", vec![1, 2]),
+            ("cj%%%", vec![1]),
+            ("cj%%%%", vec![1, 0]),
+            // Moving out of the container starts a new cycle
+            ("cj%%%k%", vec![2]),
+        ] {
+            // [true, [null, false, true], null]
+            let arena = Arena::new();
+            let root = TestJSON::Array(vec![
+                TestJSON::True,
+                TestJSON::Array(vec![TestJSON::Null, TestJSON::False, TestJSON::True]),
+                TestJSON::Null,
+            ])
+            .add_to_arena(&arena);
+            let mut tree = DAG::new(&arena, root);
+            let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+            for c in keys.chars() {
+                assert!(!editor.consume_command_char(c));
+            }
+            assert_eq!(
+                editor.tree.cursor_path(),
+                &CursorPath::from_vec(expected_path.clone()),
+                "after typing {:?}",
+                keys
+            );
+        }
+    }
+
+    #[test]
+    fn toggle_fold() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::Array(vec![TestJSON::Null, TestJSON::False]),
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        let unfolded = editor.tree_view();
+        assert!(!type_keys(&mut editor, "cjza"));
+        assert_eq!(editor.tree_view(), "array\n  true\n  array (+2)");
+        // The text of a folded node is replaced by a placeholder
+        assert_eq!(rendered_lines(&editor), vec!["[true, array (+2)]"]);
+        // Folding is a view setting, so it doesn't change the tree
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            "[true, [null, false]]"
+        );
+        // The cursor can't move into a folded node
+        assert!(!type_keys(&mut editor, "c"));
+        assert!(editor.command_failed);
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::from_vec(vec![1]));
+        // Toggling again unfolds the node
+        assert!(!type_keys(&mut editor, "za"));
+        assert_eq!(editor.tree_view(), unfolded);
+        assert_eq!(rendered_lines(&editor), vec!["[true, [null, false]]"]);
+        // Every node at a given depth can be folded at once, and then unfolded again
+        assert!(!type_keys(&mut editor, "zM"));
+        assert_eq!(editor.tree_view(), "array\n  true\n  array (+2)");
+        assert!(!type_keys(&mut editor, "zR"));
+        assert_eq!(editor.tree_view(), unfolded);
+        // Jumping into a folded node unfolds it, so the cursor isn't hidden
+        assert!(!type_keys(&mut editor, "ggcjcjma"));
+        for (keys, expected_path) in &[
+            ("/false\n", vec![1, 1]),
+            (":goto $[1][1]\n", vec![1, 1]),
+            ("'a", vec![1, 1]),
+            ("cj%", vec![1, 0]),
+            ("cjB", vec![1, 1]),
+        ] {
+            assert!(!type_keys(&mut editor, "ggcjzagg"));
+            assert_eq!(rendered_lines(&editor), vec!["[true, array (+2)]"]);
+            assert!(!type_keys(&mut editor, keys));
+            assert_eq!(
+                editor.tree.cursor_path(),
+                &CursorPath::from_vec(expected_path.clone()),
+                "after typing {:?}",
+                keys
+            );
+            assert_eq!(rendered_lines(&editor), vec!["[true, [null, false]]"]);
+        }
+    }
+
+    #[test]
+    fn insert_siblings() {
+        let arena = Arena::new();
+        let mut tree = sample_tree(&arena);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        // Insert before the first element, and after the last one
+        assert!(!type_keys(&mut editor, "kin"));
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::from_vec(vec![0]));
+        assert!(!type_keys(&mut editor, "5jao"));
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::from_vec(vec![6]));
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            "[null, true, false, null, true, false, {}]"
+        );
+        // The root has no siblings
+        assert!(!type_keys(&mut editor, "ggat"));
+        assert!(editor.command_failed);
+        assert_eq!(editor.tree.cursor().children().len(), 7);
+        // A new field in an object is given a key by typing it into a prompt
+        assert!(!type_keys(&mut editor, "lrooifoo\nii"));
+        assert_eq!(
+            editor.prompt,
+            Some((super::Prompt::InsertKeyedSibling(Side::Prev), String::new()))
+        );
+        assert!(!type_keys(&mut editor, "bar\n"));
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::from_vec(vec![0, 0]));
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            r#"[{"bar": {}, "foo": {}}, true, false, null, true, false, {}]"#
+        );
+    }
+
+    #[test]
+    fn rename() {
+        let arena = Arena::new();
+        let root = JSON::from_text(r#"{"a": [true], "b": null}"#, &arena).unwrap();
+        let mut tree = DAG::new(&arena, root);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        fn keys(object: &JSON) -> Vec<String> {
+            let fields = object.children();
+            fields
+                .iter()
+                .map(|f| f.key().unwrap().to_string())
+                .collect()
+        }
+        // The prompt starts with the old key, which can be edited
+        assert!(!type_keys(&mut editor, "ce"));
+        assert_eq!(
+            editor.prompt,
+            Some((super::Prompt::RenameKey, "a".to_string()))
+        );
+        assert!(!type_keys(&mut editor, "\u{8}xs\n"));
+        assert_eq!(keys(editor.tree.root()), vec!["xs", "b"]);
+        assert_eq!(editor.prompt, None);
+        // The value and position of the field are unchanged
+        assert!(std::ptr::eq(
+            editor.tree.cursor().children()[1],
+            root.children()[0].children()[1]
+        ));
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::from_vec(vec![0]));
+        // Renaming to a sibling's key shows an error, and keeps the typed key in the prompt
+        assert!(!type_keys(&mut editor, "e\u{8}\u{8}b\n"));
+        assert_eq!(keys(editor.tree.root()), vec!["xs", "b"]);
+        assert_eq!(
+            editor.status.as_deref(),
+            Some(r#"Cannot rename to "b", because object already contains it."#)
+        );
+        assert_eq!(
+            editor.prompt,
+            Some((super::Prompt::RenameKey, "b".to_string()))
+        );
+        assert!(!type_keys(&mut editor, "2\n"));
+        assert_eq!(keys(editor.tree.root()), vec!["b2", "b"]);
+    }
+
+    #[test]
+    fn key_sequences() {
+        let arena = Arena::new();
+        let mut tree = sample_tree(&arena);
+        let (keymap, _) = KeyMap::from_config_str("dd = \"move to root\"").unwrap();
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, keymap);
+        // `gg` is a sequence, and `g` followed by anything else is still time travel
+        assert!(!type_keys(&mut editor, "g"));
+        assert!(editor.is_waiting_for_sequence());
+        assert!(!type_keys(&mut editor, "g"));
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::root());
+        assert!(!type_keys(&mut editor, "cjg-"));
+        assert_eq!(editor.command, "");
+        assert!(!editor.is_waiting_for_sequence());
+        // `d` on its own could be the start of `dd`, so it waits for the next key...
+        assert!(!type_keys(&mut editor, "2d"));
+        assert_eq!(editor.command, "2d");
+        assert!(editor.is_waiting_for_sequence());
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            "[true, false, null, true, false]"
+        );
+        // ...until the sequence times out, when it deletes as usual
+        assert!(!editor.run_command(false));
+        assert_eq!(editor.command, "");
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            "[true, true, false]"
+        );
+        assert!(!type_keys(&mut editor, "dd"));
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::root());
+    }
+
+    #[test]
+    fn leader_sequences() {
+        let arena = Arena::new();
+        let mut tree = sample_tree(&arena);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        let path = std::env::temp_dir().join(format!("sapling-leader-{}.json", std::process::id()));
+        editor.path = Some(path.clone());
+        // The leader on its own waits for the rest of the sequence...
+        assert!(!type_keys(&mut editor, " "));
+        assert_eq!(editor.command, " ");
+        assert!(editor.is_waiting_for_sequence());
+        // ...and `<leader>w` writes the file
+        assert!(!type_keys(&mut editor, "w"));
+        assert_eq!(editor.command, "");
+        assert!(!editor.is_waiting_for_sequence());
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "[true, false, null, true, false]"
+        );
+        std::fs::remove_file(&path).unwrap();
+        // A leader which times out isn't a command, so is dropped
+        assert!(!type_keys(&mut editor, "2 "));
+        assert!(editor.is_waiting_for_sequence());
+        assert!(!editor.run_command(false));
+        assert_eq!(editor.command, "");
+        assert!(editor.command_failed);
+        // A sequence that doesn't match any binding is dropped as soon as it can't match
+        editor.command_failed = false;
+        assert!(!type_keys(&mut editor, " d"));
+        assert_eq!(editor.command, "");
+        assert!(editor.command_failed);
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            "[true, false, null, true, false]"
+        );
+        // ESC also drops a sequence part way through
+        assert!(!type_keys(&mut editor, " \x1bd"));
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            "[true, null, true, false]"
+        );
+    }
+
+    #[test]
+    fn layout_paths() {
+        // [
+        //     {"a": true},
+        //     [
+        //         null
+        //     ]
+        // ]
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::Object(vec![("a".to_string(), TestJSON::True)]),
+            TestJSON::Array(vec![TestJSON::Null]),
+        ])
+        .add_to_arena(&arena);
+        let mut pretty = PrettyFormat::DEFAULT;
+        pretty.options.inline_single_field_objects = true;
+        let format = JSONFormat::from(pretty);
+        // Lays out every line of a tree, and returns the path at each of `positions`
+        fn paths_at<'arena>(
+            root: &'arena JSON<'arena>,
+            format: JSONFormat,
+            positions: &[(usize, usize)],
+        ) -> Vec<Option<CursorPath>> {
+            let arena = Arena::new();
+            let mut tree = DAG::new(&arena, root);
+            let editor = Editor::new(&mut tree, format, super::default_keymap());
+            editor.update_layout((usize::MAX, 0, vec![]));
+            let layout = editor.tree_layout.borrow();
+            let layout = layout.as_ref().unwrap();
+            (positions.iter())
+                .map(|(line, col)| layout.path_at(*line, *col).cloned())
+                .collect()
+        }
+        let cases = [
+            (0, 0, vec![]),
+            // Past the end of a line picks the last text on it
+            (0, 10, vec![]),
+            // Indentation picks the first text on the line
+            (1, 0, vec![0]),
+            (1, 4, vec![0]),
+            (1, 5, vec![0, 0, 0]),
+            (1, 8, vec![0, 0]),
+            (1, 10, vec![0, 0, 1]),
+            (1, 13, vec![0, 0, 1]),
+            (1, 14, vec![0]),
+            // The comma belongs to the root array
+            (1, 15, vec![]),
+            (1, 16, vec![]),
+            (2, 4, vec![1]),
+            (3, 0, vec![1, 0]),
+            (4, 4, vec![1]),
+            (5, 0, vec![]),
+        ];
+        let positions: Vec<(usize, usize)> = cases.iter().map(|(l, c, _)| (*l, *c)).collect();
+        let expected: Vec<Option<CursorPath>> = (cases.iter())
+            .map(|(_, _, path)| Some(CursorPath::from_vec(path.clone())))
+            .collect();
+        assert_eq!(paths_at(root, format, &positions), expected);
+        // There's nothing below the last line
+        assert_eq!(paths_at(root, format, &[(6, 0)]), vec![None]);
+        assert_eq!(paths_at(root, JSONFormat::COMPACT, &[(1, 0)]), vec![None]);
+        // Nodes can be shared, but each copy still has its own path
+        let shared = TestJSON::Array(vec![TestJSON::True]).add_to_arena(&arena);
+        let root = arena.alloc(JSON::Array(vec![shared, shared]));
+        assert_eq!(
+            paths_at(root, JSONFormat::PRETTY, &[(2, 8), (5, 8)]),
+            vec![
+                Some(CursorPath::from_vec(vec![0, 0])),
+                Some(CursorPath::from_vec(vec![1, 0]))
+            ]
+        );
+        // ...even if they are reordered by sorting the keys of an object
+        let root = TestJSON::Object(vec![
+            ("b".to_string(), TestJSON::Null),
+            ("a".to_string(), TestJSON::Null),
+            ("c".to_string(), TestJSON::Null),
+        ])
+        .add_to_arena(&arena);
+        let value = root.children()[0].children()[1];
+        let field = |i: usize| {
+            let key = root.children()[i].children()[0];
+            arena.alloc(JSON::Field([key, value]))
+        };
+        let b = field(0);
+        let root = arena.alloc(JSON::Object(vec![b, field(1), b, field(2)]));
+        // {"a": null, "b": null, "b": null, "c": null}
+        let positions = [(0, 1), (0, 6), (0, 12), (0, 23), (0, 34)];
+        assert_eq!(
+            paths_at(
+                root,
+                JSONFormat::Compact(CompactFormat {
+                    sort_keys: true,
+                    ..CompactFormat::DEFAULT
+                }),
+                &positions
+            ),
+            [vec![1, 0], vec![1, 1], vec![0, 0], vec![2, 0], vec![3, 0]]
+                .iter()
+                .map(|p| Some(CursorPath::from_vec(p.clone())))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn mouse() {
+        // [
+        //     [
+        //         true,
+        //         null,
+        //         false
+        //     ],
+        //     [
+        //         ...
+        //     ]
+        // ]
+        let arena = Arena::new();
+        let mut tree = nested_arrays(&arena, 2);
+        let mut editor = Editor::new(&mut tree, JSONFormat::PRETTY, super::default_keymap());
+        let path = |p: &[usize]| CursorPath::from_vec(p.to_vec());
+        // Clicking on a node moves the cursor to it, and cancels any partly typed command
+        assert!(!type_keys(&mut editor, "2"));
+        editor.click(3, 10, 20);
+        assert_eq!(editor.tree.cursor_path(), &path(&[0, 1]));
+        assert_eq!(editor.command, "");
+        // Clicks below the tree or outside the tree view are ignored
+        editor.click(15, 0, 20);
+        editor.click(3, 10, 3);
+        assert_eq!(editor.tree.cursor_path(), &path(&[0, 1]));
+        // The indentation before a node belongs to the first node on the line
+        editor.click(6, 0, 20);
+        assert_eq!(editor.tree.cursor_path(), &path(&[1]));
+        // Scrolling doesn't move the cursor, and stops at the last line of the tree
+        editor.scroll_view(5);
+        assert_eq!(editor.scroll, 5);
+        assert_eq!(editor.tree.cursor_path(), &path(&[1]));
+        // The root's line is pinned over the first row, so the rows below it are shifted
+        editor.click(0, 0, 20);
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::root());
+        editor.click(2, 8, 20);
+        assert_eq!(editor.tree.cursor_path(), &path(&[1, 0]));
+        editor.scroll_view(100);
+        assert_eq!(editor.scroll, 11);
+        editor.scroll_view(-100);
+        assert_eq!(editor.scroll, 0);
+        // Mouse reporting can be turned off
+        assert!(editor.mouse);
+        assert!(!type_keys(&mut editor, ":set mouse false\n"));
+        assert!(!editor.mouse);
+        assert!(!type_keys(&mut editor, ":set mouse maybe\n"));
+        assert_eq!(
+            editor.status.as_deref(),
+            Some("Expected 'true' or 'false', found 'maybe'.")
+        );
+    }
+
+    #[test]
+    fn move_node() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::Array(vec![TestJSON::Null]),
+            TestJSON::False,
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        let mut editor = Editor::new(&mut tree, JSONFormat::COMPACT, super::default_keymap());
+        let moved = root.children()[1];
+        // Move the middle element up, and check that the cursor stays on it
+        assert!(!type_keys(&mut editor, "cjK"));
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            "[[null], true, false]"
+        );
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::from_vec(vec![0]));
+        // The siblings are reordered, not copied
+        assert!(std::ptr::eq(editor.tree.cursor(), moved));
+        // Then move it down twice, past its original position
+        assert!(!type_keys(&mut editor, "2J"));
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            "[true, false, [null]]"
+        );
+        assert_eq!(editor.tree.cursor_path(), &CursorPath::from_vec(vec![2]));
+        assert!(std::ptr::eq(editor.tree.cursor(), moved));
+        // At the end of the siblings, moving further leaves the tree alone
+        assert!(!type_keys(&mut editor, "J"));
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            "[true, false, [null]]"
+        );
+        // Every move is a separate edit
+        assert!(!type_keys(&mut editor, "uu"));
+        assert_eq!(
+            editor.tree.to_text(&JSONFormat::COMPACT),
+            "[[null], true, false]"
+        );
+    }
+
+    #[test]
+    fn toml_backend() {
+        // The editor should work with any `Ast`, not just JSON
+        let arena = Arena::new();
+        let root = TOML::from_text("a = true\nb = [false]", &arena).unwrap();
+        let mut tree = DAG::new(&arena, root);
+        let mut editor = Editor::new(&mut tree, TOMLFormat, super::default_keymap());
+        for c in "cjcjcrsaa".chars() {
+            assert!(!editor.consume_command_char(c));
         }
+        assert_eq!(editor.tree.to_text(&TOMLFormat), "a = true\nb = [\"\", []]");
     }
 }
@@ -0,0 +1,130 @@
+//! Access to the clipboard, so that yanked nodes can be pasted into other programs and vice versa.
+//!
+//! Which clipboard is used is decided by the [`ClipboardKind`] in the editor's
+//! [config](super::config::EditorConfig::clipboard).  The system clipboard is only available if
+//! Sapling is built with the `clipboard` feature.  Otherwise (or if there is no system clipboard,
+//! e.g. when running headless) [`Clipboard`] behaves as though it is always empty, and yank and
+//! paste only use the editor's internal register.
+
+/// Which clipboard an editor copies yanked nodes to
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ClipboardKind {
+    /// The system clipboard, which is shared with other programs
+    System,
+    /// A clipboard which only exists inside the editor, so that nothing outside it is changed
+    /// (e.g. when running tests)
+    InMemory,
+}
+
+/// Where a [`Clipboard`] stores its text
+enum Backend {
+    /// There is no clipboard, so nothing can be put on it
+    Unavailable,
+    /// A connection to the system clipboard
+    #[cfg(feature = "clipboard")]
+    System(arboard::Clipboard),
+    /// The text on an in-memory clipboard, if any
+    InMemory(Option<String>),
+}
+
+/// A handle to a clipboard, which remembers the last text that Sapling put on it
+pub struct Clipboard {
+    backend: Backend,
+    /// The text most recently put on the clipboard by [`set_text`](Clipboard::set_text)
+    last_set: Option<String>,
+}
+
+impl Clipboard {
+    /// Opens a clipboard of a given kind.  If that is the system clipboard and it can't be opened,
+    /// this falls back to no clipboard.
+    pub fn new(kind: ClipboardKind) -> Self {
+        let backend = match kind {
+            ClipboardKind::System => Self::system_backend(),
+            ClipboardKind::InMemory => Backend::InMemory(None),
+        };
+        Clipboard {
+            backend,
+            last_set: None,
+        }
+    }
+
+    #[cfg(feature = "clipboard")]
+    fn system_backend() -> Backend {
+        match arboard::Clipboard::new() {
+            Ok(clipboard) => Backend::System(clipboard),
+            Err(e) => {
+                log::warn!("Couldn't open the system clipboard: {}", e);
+                Backend::Unavailable
+            }
+        }
+    }
+
+    #[cfg(not(feature = "clipboard"))]
+    fn system_backend() -> Backend {
+        Backend::Unavailable
+    }
+
+    /// Puts some text on the clipboard, returning an error message if there is no clipboard
+    pub fn set_text(&mut self, text: String) -> Result<(), String> {
+        match &mut self.backend {
+            Backend::Unavailable if cfg!(feature = "clipboard") => {
+                return Err("No system clipboard is available.".to_string())
+            }
+            Backend::Unavailable => {
+                return Err("Sapling was built without clipboard support.".to_string())
+            }
+            #[cfg(feature = "clipboard")]
+            Backend::System(clipboard) => clipboard.set_text(&text).map_err(|e| e.to_string())?,
+            Backend::InMemory(contents) => *contents = Some(text.clone()),
+        }
+        self.last_set = Some(text);
+        Ok(())
+    }
+
+    /// Returns the text on the clipboard, but only if it was put there by another program (i.e.
+    /// it isn't the text that was last [set](Clipboard::set_text)).  Returns [`None`] if there is
+    /// no clipboard or it doesn't contain text.
+    pub fn external_text(&mut self) -> Option<String> {
+        let text = match &mut self.backend {
+            Backend::Unavailable => None,
+            #[cfg(feature = "clipboard")]
+            Backend::System(clipboard) => clipboard.get_text().ok(),
+            Backend::InMemory(contents) => contents.clone(),
+        }?;
+        if self.last_set.as_ref() == Some(&text) {
+            None
+        } else {
+            Some(text)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backend, Clipboard, ClipboardKind};
+
+    #[test]
+    #[cfg(not(feature = "clipboard"))]
+    fn no_clipboard() {
+        // Without the `clipboard` feature, the system clipboard is never available and always
+        // empty
+        let mut clipboard = Clipboard::new(ClipboardKind::System);
+        assert_eq!(
+            clipboard.set_text("[true]".to_string()),
+            Err("Sapling was built without clipboard support.".to_string())
+        );
+        assert_eq!(clipboard.external_text(), None);
+    }
+
+    #[test]
+    fn in_memory() {
+        let mut clipboard = Clipboard::new(ClipboardKind::InMemory);
+        assert_eq!(clipboard.external_text(), None);
+        // Text which Sapling put on the clipboard itself isn't external
+        assert_eq!(clipboard.set_text("[true]".to_string()), Ok(()));
+        assert_eq!(clipboard.external_text(), None);
+        // ...but text put there by 'another program' is
+        clipboard.backend = Backend::InMemory(Some("[null]".to_string()));
+        assert_eq!(clipboard.external_text(), Some("[null]".to_string()));
+    }
+}
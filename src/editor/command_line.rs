@@ -0,0 +1,295 @@
+//! Code for parsing the commands that can be typed into the command line (which is opened with
+//! `:`, like in Vim).
+//!
+//! To add a new command, add a variant to [`CommandLine`] and an entry to [`COMMANDS`] which
+//! builds that variant from the command's arguments.
+
+use std::path::PathBuf;
+
+/// A command typed into the command line
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum CommandLine {
+    /// Write the tree to a file, or to the file that was last written if no path is given
+    Write(Option<PathBuf>),
+    /// Quit Sapling, unless the tree has changes which haven't been written
+    Quit,
+    /// Quit Sapling, throwing away any changes which haven't been written
+    ForceQuit,
+    /// Write the tree to a file (like [`Write`](CommandLine::Write)), then quit
+    WriteQuit(Option<PathBuf>),
+    /// Replace the tree with the contents of a file, unless the tree has changes which haven't
+    /// been written
+    Edit(PathBuf),
+    /// Replace the tree with the contents of a file, throwing away any changes which haven't been
+    /// written
+    ForceEdit(PathBuf),
+    /// Regenerate the tree by writing it in the current format and parsing it again
+    Reflow,
+    /// Remove every empty container from the tree
+    Prune,
+    /// Write a Graphviz graph of the tree to a file
+    ExportDot(PathBuf),
+    /// Change an option of the format style
+    Set { option: String, value: String },
+    /// Replace every node whose text is `from` with the node whose text is `to`
+    Substitute { from: String, to: String },
+    /// Check the tree against the schema in a file
+    Validate(PathBuf),
+    /// Move the cursor to the node addressed by a JSONPath expression
+    Goto(String),
+    /// List the likely mistakes in the tree
+    Lint,
+    /// Show how many nodes of each kind the tree contains
+    Stats,
+}
+
+/// The specification of one command that can be typed into the command line
+struct CommandSpec {
+    /// The names that can be typed to run this command
+    names: &'static [&'static str],
+    /// Builds the command from its arguments, or returns an error message if the arguments aren't
+    /// valid
+    parse: fn(&[&str]) -> Result<CommandLine, String>,
+}
+
+/// Every command that can be typed into the command line
+const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        names: &["w", "write"],
+        parse: |args| optional_path(args).map(CommandLine::Write),
+    },
+    CommandSpec {
+        names: &["q", "quit"],
+        parse: |args| no_args(args).map(|()| CommandLine::Quit),
+    },
+    CommandSpec {
+        names: &["q!", "quit!"],
+        parse: |args| no_args(args).map(|()| CommandLine::ForceQuit),
+    },
+    CommandSpec {
+        names: &["wq", "x"],
+        parse: |args| optional_path(args).map(CommandLine::WriteQuit),
+    },
+    CommandSpec {
+        names: &["e", "edit"],
+        parse: |args| match args {
+            [path] => Ok(CommandLine::Edit(PathBuf::from(path))),
+            _ => Err("Expected ':e <path>'.".to_string()),
+        },
+    },
+    CommandSpec {
+        names: &["e!", "edit!"],
+        parse: |args| match args {
+            [path] => Ok(CommandLine::ForceEdit(PathBuf::from(path))),
+            _ => Err("Expected ':e! <path>'.".to_string()),
+        },
+    },
+    CommandSpec {
+        names: &["reflow"],
+        parse: |args| no_args(args).map(|()| CommandLine::Reflow),
+    },
+    CommandSpec {
+        names: &["prune"],
+        parse: |args| no_args(args).map(|()| CommandLine::Prune),
+    },
+    CommandSpec {
+        names: &["export"],
+        parse: |args| match args {
+            ["dot", path] => Ok(CommandLine::ExportDot(PathBuf::from(path))),
+            [format, _] => Err(format!("Unknown export format '{}'.", format)),
+            _ => Err("Expected ':export <format> <path>'.".to_string()),
+        },
+    },
+    CommandSpec {
+        names: &["set"],
+        parse: |args| match args {
+            [option, value] => Ok(CommandLine::Set {
+                option: option.to_string(),
+                value: value.to_string(),
+            }),
+            _ => Err("Expected ':set <option> <value>'.".to_string()),
+        },
+    },
+    CommandSpec {
+        names: &["validate"],
+        parse: |args| match args {
+            [path] => Ok(CommandLine::Validate(PathBuf::from(path))),
+            _ => Err("Expected ':validate <path>'.".to_string()),
+        },
+    },
+    CommandSpec {
+        names: &["lint"],
+        parse: |args| no_args(args).map(|()| CommandLine::Lint),
+    },
+    CommandSpec {
+        names: &["stats"],
+        parse: |args| no_args(args).map(|()| CommandLine::Stats),
+    },
+];
+
+/// Parses the arguments of a command that takes no arguments
+fn no_args(args: &[&str]) -> Result<(), String> {
+    match args {
+        [] => Ok(()),
+        _ => Err(format!("Unexpected arguments '{}'.", args.join(" "))),
+    }
+}
+
+/// Parses the arguments of a command that takes an optional path
+fn optional_path(args: &[&str]) -> Result<Option<PathBuf>, String> {
+    match args {
+        [] => Ok(None),
+        [path] => Ok(Some(PathBuf::from(path))),
+        _ => Err("Expected at most one path.".to_string()),
+    }
+}
+
+/// Parses the text typed into the command line (without the leading `:`), returning an error
+/// message if it isn't a valid command
+pub fn parse(text: &str) -> Result<CommandLine, String> {
+    // The arguments of `%s` and `goto` can contain spaces, so they can't be split into words like
+    // the other commands
+    let text = text.trim();
+    if let Some(rest) = text.strip_prefix("%s") {
+        return parse_substitute(rest);
+    }
+    if let Some(rest) = text.strip_prefix("goto") {
+        if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+            return parse_goto(rest);
+        }
+    }
+    let mut words = text.split_whitespace();
+    let name = words
+        .next()
+        .ok_or_else(|| "No command given.".to_string())?;
+    let args: Vec<&str> = words.collect();
+    let spec = COMMANDS
+        .iter()
+        .find(|spec| spec.names.contains(&name))
+        .ok_or_else(|| format!("Unknown command '{}'.", name))?;
+    (spec.parse)(&args)
+}
+
+/// Parses the arguments of `:%s/<from>/<to>/`, where the `/`s can be any punctuation character
+/// (like in Vim) and the last one is optional
+fn parse_substitute(text: &str) -> Result<CommandLine, String> {
+    let error = || "Expected ':%s/<from>/<to>/'.".to_string();
+    let delimiter = text
+        .chars()
+        .next()
+        .filter(char::is_ascii_punctuation)
+        .ok_or_else(error)?;
+    let parts: Vec<&str> = text[1..].split(delimiter).collect();
+    match parts.as_slice() {
+        [from, to] | [from, to, ""] if !from.is_empty() && !to.is_empty() => {
+            Ok(CommandLine::Substitute {
+                from: from.to_string(),
+                to: to.to_string(),
+            })
+        }
+        _ => Err(error()),
+    }
+}
+
+/// Parses the argument of `:goto <JSONPath>`, which is the rest of the line so that quoted keys
+/// keep their whitespace
+fn parse_goto(text: &str) -> Result<CommandLine, String> {
+    match text.trim() {
+        "" => Err("Expected ':goto <JSONPath>'.".to_string()),
+        path => Ok(CommandLine::Goto(path.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, CommandLine};
+    use std::path::PathBuf;
+
+    #[test]
+    fn commands() {
+        for (text, expected_command) in &[
+            ("w", CommandLine::Write(None)),
+            (
+                "write out.json",
+                CommandLine::Write(Some(PathBuf::from("out.json"))),
+            ),
+            ("  q  ", CommandLine::Quit),
+            ("q!", CommandLine::ForceQuit),
+            ("x", CommandLine::WriteQuit(None)),
+            ("e in.json", CommandLine::Edit(PathBuf::from("in.json"))),
+            (
+                "edit! in.json",
+                CommandLine::ForceEdit(PathBuf::from("in.json")),
+            ),
+            ("reflow", CommandLine::Reflow),
+            ("prune", CommandLine::Prune),
+            ("lint", CommandLine::Lint),
+            ("stats", CommandLine::Stats),
+            ("goto $.foo[2]", CommandLine::Goto("$.foo[2]".to_string())),
+            (
+                r#"goto $["a b"]"#,
+                CommandLine::Goto(r#"$["a b"]"#.to_string()),
+            ),
+            (
+                "goto  $[\"a  \tb\"] ",
+                CommandLine::Goto("$[\"a  \tb\"]".to_string()),
+            ),
+            (
+                "validate schema.json",
+                CommandLine::Validate(PathBuf::from("schema.json")),
+            ),
+            (
+                "export dot tree.dot",
+                CommandLine::ExportDot(PathBuf::from("tree.dot")),
+            ),
+            (
+                "set indent 2",
+                CommandLine::Set {
+                    option: "indent".to_string(),
+                    value: "2".to_string(),
+                },
+            ),
+            (
+                "%s/true/false",
+                CommandLine::Substitute {
+                    from: "true".to_string(),
+                    to: "false".to_string(),
+                },
+            ),
+            (
+                r#"%s|"a b"|[1, "/"]|"#,
+                CommandLine::Substitute {
+                    from: r#""a b""#.to_string(),
+                    to: r#"[1, "/"]"#.to_string(),
+                },
+            ),
+        ] {
+            assert_eq!(parse(text).as_ref(), Ok(expected_command));
+        }
+    }
+
+    #[test]
+    fn errors() {
+        for (text, expected_error) in &[
+            ("", "No command given."),
+            ("frobnicate", "Unknown command 'frobnicate'."),
+            ("q now", "Unexpected arguments 'now'."),
+            ("w a b", "Expected at most one path."),
+            ("e", "Expected ':e <path>'."),
+            ("e! a b", "Expected ':e! <path>'."),
+            ("reflow now", "Unexpected arguments 'now'."),
+            ("stats all", "Unexpected arguments 'all'."),
+            ("export dot", "Expected ':export <format> <path>'."),
+            ("export svg tree.svg", "Unknown export format 'svg'."),
+            ("set indent", "Expected ':set <option> <value>'."),
+            ("validate", "Expected ':validate <path>'."),
+            ("goto", "Expected ':goto <JSONPath>'."),
+            ("gotox $", "Unknown command 'gotox'."),
+            ("%s/true", "Expected ':%s/<from>/<to>/'."),
+            ("%s/true/false/x", "Expected ':%s/<from>/<to>/'."),
+            ("%strue", "Expected ':%s/<from>/<to>/'."),
+        ] {
+            assert_eq!(parse(text), Err(expected_error.to_string()));
+        }
+    }
+}
@@ -0,0 +1,398 @@
+//! Code for configuring the editor: the [`EditorConfig`] that an editor is started with, and
+//! reading keybindings from files.
+//!
+//! A keybinding file consists of lines of the form `<key> = "<command>"`, where `<key>` is a
+//! single character or a sequence of characters like `gg` (which must be quoted if it isn't
+//! alphanumeric, like `"<" = "undo"`), and `<command>` is the
+//! [summary string](super::Command::summary_string) of a command.  Blank lines and comments
+//! starting with `#` are ignored.  This is a subset of TOML, so these files can be called
+//! `keys.toml` and get syntax highlighting in other editors.
+//!
+//! Inside a key, `<Space>` stands for the space bar and `<leader>` for the leader key, which is
+//! space unless the file sets it with a `leader = "<key>"` line (so `"<leader>w" = "write"`
+//! binds space then `w` by default).
+
+use super::clipboard::ClipboardKind;
+use super::theme::Theme;
+use super::{Command, KeyMap};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How long the editor waits for the next key of a sequence by default (see
+/// [`EditorConfig::sequence_timeout`]).  This is the same as Vim's default `timeoutlen`.
+pub const DEFAULT_SEQUENCE_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// The key that starts leader sequences (like `<leader>w`) unless a keybinding file sets another
+pub const DEFAULT_LEADER: char = ' ';
+
+/// The name of the line in a keybinding file which sets the leader key, rather than binding a key
+const LEADER_OPTION: &str = "leader";
+
+/// The options that an [`Editor`](super::Editor) is started with (see
+/// [`Editor::with_config`](super::Editor::with_config)).  `Format` is the format style of the AST
+/// being edited.
+#[derive(Debug, Clone)]
+pub struct EditorConfig<Format> {
+    /// The format style that the tree is displayed and written in
+    pub format: Format,
+    /// The keys bound to each command
+    pub keymap: KeyMap,
+    /// The file that `:w` writes to if it isn't given a path
+    pub path: Option<PathBuf>,
+    /// If `true`, a newline is added to the end of every file that is written
+    pub trailing_newline: bool,
+    /// If the keys typed so far are the start of a multi-key sequence (like `g` for `gg`) but
+    /// would also make a complete command on their own, how long to wait for the next key before
+    /// running them as they are
+    pub sequence_timeout: Duration,
+    /// The colours that the tree is drawn with
+    pub theme: Theme,
+    /// If `true`, clicking on a node moves the cursor to it and the mouse wheel scrolls the tree.
+    /// This can be turned off with `:set mouse false`, e.g. so that the terminal can select text.
+    pub mouse: bool,
+    /// The clipboard that yanked nodes are copied to, and which can be pasted from
+    pub clipboard: ClipboardKind,
+}
+
+impl<Format: Default> Default for EditorConfig<Format> {
+    fn default() -> Self {
+        EditorConfig {
+            format: Format::default(),
+            keymap: super::default_keymap(),
+            path: None,
+            trailing_newline: false,
+            sequence_timeout: DEFAULT_SEQUENCE_TIMEOUT,
+            theme: Theme::default(),
+            mouse: true,
+            clipboard: ClipboardKind::System,
+        }
+    }
+}
+
+/// The ways that reading a config file can fail
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file couldn't be read
+    Io(std::io::Error),
+    /// A line of the file isn't of the form `<key> = "<command>"`
+    Syntax { line: usize },
+    /// A sequence of keys was bound which is empty, or which starts with a digit (so would be read
+    /// as a count)
+    InvalidKey { line: usize, key: String },
+    /// A key was bound to a command name which doesn't exist.  This is only a warning (see
+    /// [`parse_bindings`]).
+    UnknownCommand { line: usize, name: String },
+    /// The same key was bound more than once in the same file
+    ConflictingBinding { line: usize, key: String },
+    /// An option was given a value which it can't have (e.g. an unknown colour in a theme file)
+    InvalidValue { line: usize, message: String },
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "Couldn't read config file: {}", e),
+            ConfigError::Syntax { line } => {
+                write!(f, "Expected `<key> = \"<value>\"` on line {}.", line)
+            }
+            ConfigError::InvalidKey { line, key } if key.is_empty() => {
+                write!(f, "Empty key sequence on line {}.", line)
+            }
+            ConfigError::InvalidKey { line, key } => write!(
+                f,
+                "Key sequence {:?} on line {} shouldn't start with a digit.",
+                key, line
+            ),
+            ConfigError::UnknownCommand { line, name } => {
+                write!(f, "Unknown command {:?} on line {}.", name, line)
+            }
+            ConfigError::ConflictingBinding { line, key } => {
+                write!(f, "Key {:?} on line {} is already bound.", key, line)
+            }
+            ConfigError::InvalidValue { line, message } => {
+                write!(f, "{} on line {}.", message, line)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(e: std::io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+/// The `(keys, command)` pairs read from a keybinding file, the leader key that it sets (if any),
+/// and any warnings
+pub type Bindings = (Vec<(String, Command)>, Option<char>, Vec<ConfigError>);
+
+/// Parses the contents of a keybinding file into a list of `(keys, command)` pairs, in the order
+/// that they appear in the file.  The keys are returned as written, so still contain key names
+/// like `<leader>` (see [`expand_key_names`]).  A binding to an unknown command (e.g. one from a
+/// newer version of Sapling) doesn't stop the rest of the file from being used, so it is skipped
+/// and returned in the list of warnings instead.
+pub fn parse_bindings(text: &str) -> Result<Bindings, ConfigError> {
+    let mut bindings: Vec<(String, Command)> = Vec::new();
+    let mut leader = None;
+    let mut leader_line = 0;
+    let mut warnings = Vec::new();
+    for (line_index, line_text) in text.lines().enumerate() {
+        // Line numbers are 1-indexed, to match other text editors
+        let line = line_index + 1;
+        let (key, name) = match parse_line(line_text) {
+            Ok(Some(binding)) => binding,
+            Ok(None) => continue,
+            Err(()) => return Err(ConfigError::Syntax { line }),
+        };
+        if key == LEADER_OPTION {
+            if leader.is_some() {
+                return Err(ConfigError::ConflictingBinding { line, key });
+            }
+            // The leader can't be a digit, because then every leader sequence would be a count
+            let expanded = expand_key_names(&name, DEFAULT_LEADER);
+            let mut chars = expanded.chars();
+            leader = match (chars.next(), chars.next()) {
+                (Some(c), None) if !c.is_ascii_digit() => Some(c),
+                _ => {
+                    return Err(ConfigError::InvalidValue {
+                        line,
+                        message: format!("Leader {:?} isn't a single non-digit key", name),
+                    })
+                }
+            };
+            leader_line = line;
+            continue;
+        }
+        // An empty key can't be pressed, and digits at the start of a command are its count, so
+        // sequences can't start with one
+        if key.is_empty()
+            || (key.chars().count() > 1 && key.starts_with(|c: char| c.is_ascii_digit()))
+        {
+            return Err(ConfigError::InvalidKey { line, key });
+        }
+        let command = match Command::from_summary_string(&name) {
+            Some(command) => command,
+            None => {
+                warnings.push(ConfigError::UnknownCommand { line, name });
+                continue;
+            }
+        };
+        if bindings.iter().any(|(k, _)| *k == key) {
+            return Err(ConfigError::ConflictingBinding { line, key });
+        }
+        bindings.push((key, command));
+    }
+    // A leader which is also bound on its own would run that command as soon as it is pressed, so
+    // none of the leader sequences could be typed
+    if let Some(leader) = leader {
+        let command = bindings
+            .iter()
+            .find(|(keys, _)| expand_key_names(keys, leader) == leader.to_string())
+            .map(|(_, command)| command.clone())
+            .or_else(|| super::default_keymap().get(leader).cloned());
+        if let Some(command) = command {
+            return Err(ConfigError::InvalidValue {
+                line: leader_line,
+                message: format!(
+                    "Leader {:?} is already bound to {:?}",
+                    leader,
+                    command.summary_string()
+                ),
+            });
+        }
+    }
+    Ok((bindings, leader, warnings))
+}
+
+/// Replaces the names of keys in a key sequence from a keybinding file with the keys themselves:
+/// `<leader>` becomes the given leader key, and `<Space>` becomes a space
+pub fn expand_key_names(keys: &str, leader: char) -> String {
+    keys.replace("<leader>", &leader.to_string())
+        .replace("<Space>", " ")
+}
+
+/// Parses a single line of a keybinding or theme file, returning the key and the (unquoted)
+/// value, or [`None`] if the line is blank or only contains a comment
+pub(super) fn parse_line(line: &str) -> Result<Option<(String, String)>, ()> {
+    let rest = line.trim_start();
+    if rest.is_empty() || rest.starts_with('#') {
+        return Ok(None);
+    }
+    // Read the key, which is either quoted or bare.  Only a quoted key can be empty, so that the
+    // caller can say why an empty key isn't allowed.
+    let (key, rest) = if rest.starts_with('"') {
+        parse_quoted(rest)?
+    } else {
+        let end = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(rest.len());
+        if end == 0 {
+            return Err(());
+        }
+        (rest[..end].to_string(), &rest[end..])
+    };
+    // Read the `=`
+    let rest = rest.trim_start().strip_prefix('=').ok_or(())?.trim_start();
+    // Read the command name, which must be quoted
+    let (name, rest) = parse_quoted(rest)?;
+    // The only thing allowed after the value is a comment
+    let rest = rest.trim_start();
+    if rest.is_empty() || rest.starts_with('#') {
+        Ok(Some((key, name)))
+    } else {
+        Err(())
+    }
+}
+
+/// Parses a double-quoted string from the start of `text`, returning its (unescaped) contents
+/// and the text after the closing quote
+fn parse_quoted(text: &str) -> Result<(String, &str), ()> {
+    let mut contents = String::new();
+    let mut chars = text.strip_prefix('"').ok_or(())?.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            // The `+ 2` skips the opening and closing quotes
+            '"' => return Ok((contents, &text[i + 2..])),
+            '\\' => match chars.next() {
+                Some((_, '"')) => contents.push('"'),
+                Some((_, '\\')) => contents.push('\\'),
+                _ => return Err(()),
+            },
+            _ => contents.push(c),
+        }
+    }
+    // We ran out of chars without finding the closing quote
+    Err(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConfigError;
+    use crate::editable_tree::{Direction, Side};
+    use crate::editor::{Command, KeyMap};
+
+    #[test]
+    fn remap() {
+        let (keymap, warnings) = KeyMap::from_config_str(
+            r#"
+# Use vim-style hjkl movement
+h = "move to parent"
+l = "move to first child"
+"<" = "move node up"   # Quoted keys can be any character
+">" = "move node down"
+"\"" = "yank"
+dd = "delete"       # Keys can also be sequences
+"#,
+        )
+        .unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(keymap.get_sequence("dd"), Some(&Command::Delete));
+        assert_eq!(keymap.get_sequence("gg"), Some(&Command::MoveToRoot));
+        assert!(keymap.is_sequence_prefix("d"));
+        assert!(!keymap.is_sequence_prefix("dd"));
+        for (key, command) in &[
+            ('h', Command::MoveCursor(Direction::Up)),
+            ('l', Command::MoveCursor(Direction::Down)),
+            ('<', Command::MoveNode(Side::Prev)),
+            ('>', Command::MoveNode(Side::Next)),
+            ('"', Command::Yank),
+            // Unspecified keys fall back to the default keymap
+            ('Q', Command::Quit),
+            ('c', Command::MoveCursor(Direction::Down)),
+        ] {
+            assert_eq!(keymap.get(*key), Some(command));
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let error = |config: &str| KeyMap::from_config_str(config).unwrap_err();
+        assert!(matches!(
+            error("x = \"delete\"\nx = \"undo\""),
+            ConfigError::ConflictingBinding { line: 2, ref key } if key == "x"
+        ));
+        assert!(matches!(
+            error("2x = \"delete\""),
+            ConfigError::InvalidKey { line: 1, ref key } if key == "2x"
+        ));
+        assert!(matches!(
+            error("\"\" = \"delete\""),
+            ConfigError::InvalidKey { line: 1, ref key } if key.is_empty()
+        ));
+        for config in &[
+            "x",
+            "x = delete",
+            "x = \"delete",
+            "= \"delete\"",
+            "x = \"a\" b",
+        ] {
+            assert!(matches!(error(config), ConfigError::Syntax { line: 1 }));
+        }
+    }
+
+    #[test]
+    fn leader() {
+        // The default leader is space
+        let (keymap, _) = KeyMap::from_config_str("\"<leader>d\" = \"delete\"").unwrap();
+        assert_eq!(keymap.get_sequence(" d"), Some(&Command::Delete));
+        assert_eq!(keymap.get_sequence(" w"), Some(&Command::Write));
+        assert!(keymap.is_sequence_prefix(" "));
+        // Setting the leader moves every leader sequence, even ones earlier in the file
+        let (keymap, warnings) = KeyMap::from_config_str(
+            r#"
+"<leader>d" = "delete"
+leader = ","
+"<Space>" = "undo"
+"<Space><leader>" = "yank"
+"#,
+        )
+        .unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(keymap.get_sequence(",d"), Some(&Command::Delete));
+        assert_eq!(keymap.get_sequence(",w"), Some(&Command::Write));
+        assert_eq!(keymap.get_sequence(" w"), None);
+        assert_eq!(keymap.get_sequence(" ,"), Some(&Command::Yank));
+        assert_eq!(keymap.get(' '), Some(&Command::Undo));
+        // The leader must be set once, to a single key which isn't a digit
+        let error = |config: &str| KeyMap::from_config_str(config).unwrap_err();
+        for config in &["leader = \"ab\"", "leader = \"\"", "leader = \"3\""] {
+            assert!(matches!(
+                error(config),
+                ConfigError::InvalidValue { line: 1, .. }
+            ));
+        }
+        assert!(matches!(
+            error("leader = \",\"\nleader = \"<Space>\""),
+            ConfigError::ConflictingBinding { line: 2, ref key } if key == "leader"
+        ));
+        // ...and can't already be bound on its own, either by default or by the file
+        assert_eq!(
+            error("\n\"<leader>w\" = \"write\"\nleader = \"d\"").to_string(),
+            r#"Leader 'd' is already bound to "delete" on line 3."#
+        );
+        assert_eq!(
+            error("leader = \",\"\n\"<leader>\" = \"undo\"").to_string(),
+            r#"Leader ',' is already bound to "undo" on line 1."#
+        );
+    }
+
+    #[test]
+    fn unknown_commands() {
+        // Bindings to unknown commands are skipped with a warning, but the rest of the file is
+        // still used
+        let (keymap, warnings) =
+            KeyMap::from_config_str("x = \"delete\"\n\nq = \"frobnicate\"\nq = \"undo\"").unwrap();
+        assert!(matches!(
+            warnings.as_slice(),
+            [ConfigError::UnknownCommand { line: 3, ref name }] if name == "frobnicate"
+        ));
+        assert_eq!(
+            warnings[0].to_string(),
+            r#"Unknown command "frobnicate" on line 3."#
+        );
+        assert_eq!(keymap.get('x'), Some(&Command::Delete));
+        assert_eq!(keymap.get('q'), Some(&Command::Undo));
+    }
+}
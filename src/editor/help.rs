@@ -0,0 +1,98 @@
+//! The help screen, which is shown when `?` is pressed and lists every key binding in the active
+//! keymap.
+
+use super::KeyMap;
+
+/// The number of columns that the bindings are split into
+const NUM_COLUMNS: usize = 2;
+/// The spaces between two columns of bindings
+const COLUMN_GAP: &str = "    ";
+
+/// Builds the text of the help screen for a keymap.  Every binding (including multi-key
+/// sequences) is listed as its keys followed by the
+/// [summary string](super::Command::summary_string) of its command, with spaces (like the default
+/// leader key) shown as `<Space>`.  The bindings are sorted by their keys, and laid out in columns
+/// which are read top to bottom.
+pub fn render_help(keymap: &KeyMap) -> String {
+    let mut bindings: Vec<(String, &'static str)> = keymap
+        .bindings
+        .iter()
+        .map(|(key, command)| (key.to_string(), command.summary_string()))
+        .chain(
+            keymap
+                .sequences
+                .iter()
+                .map(|(keys, command)| (keys.clone(), command.summary_string())),
+        )
+        .map(|(keys, summary)| (keys.replace(' ', "<Space>"), summary))
+        .collect();
+    bindings.sort();
+    let key_width = bindings
+        .iter()
+        .map(|(keys, _)| keys.chars().count())
+        .max()
+        .unwrap_or(0);
+    let entries: Vec<String> = bindings
+        .iter()
+        .map(|(keys, summary)| format!("{:>width$}  {}", keys, summary, width = key_width))
+        .collect();
+    // Split the entries into columns, and pad every column to the width of its longest entry
+    let num_rows = entries.len().div_ceil(NUM_COLUMNS);
+    let columns: Vec<&[String]> = entries.chunks(num_rows.max(1)).collect();
+    let column_widths: Vec<usize> = columns
+        .iter()
+        .map(|c| c.iter().map(|e| e.chars().count()).max().unwrap_or(0))
+        .collect();
+    let mut lines = Vec::with_capacity(num_rows);
+    for row in 0..num_rows {
+        let mut line = String::new();
+        for (column, width) in columns.iter().zip(&column_widths) {
+            if let Some(entry) = column.get(row) {
+                line.push_str(&format!("{:width$}{}", entry, COLUMN_GAP, width = width));
+            }
+        }
+        lines.push(line.trim_end().to_string());
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_help;
+    use crate::editable_tree::Direction;
+    use crate::editor::{Command, KeyMap};
+
+    #[test]
+    fn help_text() {
+        let keymap = KeyMap {
+            bindings: hmap::hmap! {
+                'q' => Command::Quit,
+                'j' => Command::MoveCursor(Direction::Next),
+                'd' => Command::Delete,
+                '?' => Command::Help,
+                'u' => Command::Undo
+            },
+            sequences: hmap::hmap! {
+                "gg".to_string() => Command::MoveToRoot,
+                " w".to_string() => Command::Write
+            },
+        };
+        assert_eq!(
+            render_help(&keymap),
+            "<Space>w  write                  j  move to next sibling
+       ?  help                   q  quit
+       d  delete                 u  undo
+      gg  move to root"
+        );
+        // An empty keymap has no help
+        let empty = KeyMap {
+            bindings: std::collections::HashMap::new(),
+            sequences: std::collections::HashMap::new(),
+        };
+        assert_eq!(render_help(&empty), "");
+        // The two commands which jump around a container say how they differ
+        let help = render_help(&crate::editor::default_keymap());
+        assert!(help.contains("%  jump between first/last child"));
+        assert!(help.contains("B  jump to last descendant/parent"));
+    }
+}
@@ -0,0 +1,119 @@
+//! Finding likely mistakes in a tree, which are highlighted in the tree view and listed by
+//! `:lint`.
+//!
+//! At the moment the only mistake that is found is a duplicated key: a node with more than one
+//! child with the same [`key`](Ast::key), like a JSON object with two fields called `"a"`.  This
+//! is allowed by the grammar, but most programs only read one of the values and silently ignore
+//! the others.
+
+use crate::ast::cursor_path::CursorPath;
+use crate::ast::Ast;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// Finds the nodes with duplicated keys in trees.  The results for every subtree are cached by
+/// the address of its root, which is always correct because nodes are immutable and an address is
+/// only reused after [`Arena::gc`](crate::arena::Arena::gc) (so the cache mustn't be kept across a
+/// collection).  An edit only creates new nodes along the path from the root to the node that changed, so
+/// after an edit only those nodes have to be checked again.
+#[derive(Debug)]
+pub struct DuplicateKeys<Node> {
+    cache: RefCell<HashMap<*const Node, Rc<Vec<CursorPath>>>>,
+}
+
+impl<Node> DuplicateKeys<Node> {
+    /// Creates an empty cache
+    pub fn new() -> Self {
+        DuplicateKeys {
+            cache: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the paths (relative to `node`) of every node in the subtree under `node` whose key
+    /// is shared with one of its siblings, in document order
+    pub fn find<'arena>(&self, node: &'arena Node) -> Rc<Vec<CursorPath>>
+    where
+        Node: Ast<'arena>,
+    {
+        if let Some(paths) = self.cache.borrow().get(&(node as *const Node)) {
+            return paths.clone();
+        }
+        let children = node.children();
+        // Find the keys which are used more than once, then every child with one of those keys
+        let mut seen_keys = HashSet::new();
+        let duplicated_keys: HashSet<&str> = children
+            .iter()
+            .filter_map(|c| c.key())
+            .filter(|key| !seen_keys.insert(*key))
+            .collect();
+        let mut paths = Vec::new();
+        for (index, child) in children.iter().enumerate() {
+            if child.key().is_some_and(|key| duplicated_keys.contains(key)) {
+                paths.push(CursorPath::from_vec(vec![index]));
+            }
+            for child_path in self.find(*child).iter() {
+                let mut path = CursorPath::from_vec(vec![index]);
+                for i in child_path.iter() {
+                    path.push(*i);
+                }
+                paths.push(path);
+            }
+        }
+        let paths = Rc::new(paths);
+        self.cache.borrow_mut().insert(node, paths.clone());
+        paths
+    }
+
+    /// Returns the addresses of every node in the tree under `root` whose key is shared with one
+    /// of its siblings
+    pub fn node_set<'arena>(&self, root: &'arena Node) -> HashSet<*const Node>
+    where
+        Node: Ast<'arena>,
+    {
+        self.find(root)
+            .iter()
+            .map(|path| path.cursor(root) as *const Node)
+            .collect()
+    }
+}
+
+impl<Node> Default for DuplicateKeys<Node> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DuplicateKeys;
+    use crate::arena::Arena;
+    use crate::ast::{json::JSON, Ast};
+
+    #[test]
+    fn duplicate_keys() {
+        let arena = Arena::new();
+        let root = JSON::from_text(
+            r#"[{"a": true, "b": {"c": null, "c": [], "d": true}, "a": false}, {"a": "a"}]"#,
+            &arena,
+        )
+        .unwrap();
+        let duplicate_keys = DuplicateKeys::new();
+        let paths: Vec<Vec<usize>> = duplicate_keys
+            .find(root)
+            .iter()
+            .map(|path| path.iter().copied().collect())
+            .collect();
+        assert_eq!(
+            paths,
+            vec![vec![0, 0], vec![0, 1, 1, 0], vec![0, 1, 1, 1], vec![0, 2]]
+        );
+        // Every subtree is cached, so a tree which shares subtrees only checks its new nodes
+        let object = root.children()[1];
+        assert!(duplicate_keys.find(object).is_empty());
+        let num_cached = duplicate_keys.cache.borrow().len();
+        let new_root = arena.alloc(JSON::Array(vec![object, root.children()[0]]));
+        duplicate_keys.find(new_root);
+        assert_eq!(duplicate_keys.cache.borrow().len(), num_cached + 1);
+    }
+}
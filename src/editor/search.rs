@@ -0,0 +1,125 @@
+//! Code for finding the nodes that match a search query.
+
+use crate::ast::{cursor_path::CursorPath, Ast};
+use crate::editable_tree::{Side, DAG};
+
+/// A search query typed by the user
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Query {
+    /// The text that a node's display name or key must contain.  If the query isn't case
+    /// sensitive, this is lower case.
+    text: String,
+    case_sensitive: bool,
+}
+
+impl Query {
+    /// Builds a [`Query`] from the text typed by the user.  Queries are case-insensitive, unless
+    /// they start with `\C` (like in Vim).
+    pub fn new(text: &str) -> Query {
+        match text.strip_prefix("\\C") {
+            Some(text) => Query {
+                text: text.to_string(),
+                case_sensitive: true,
+            },
+            None => Query {
+                text: text.to_lowercase(),
+                case_sensitive: false,
+            },
+        }
+    }
+
+    /// Returns the text that this query is searching for
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns `true` if the [`display_name`](Ast::display_name) or [`key`](Ast::key) of `node`
+    /// contains this query
+    pub fn matches<'arena, Node: Ast<'arena>>(&self, node: &Node) -> bool {
+        let contains = |s: &str| {
+            if self.case_sensitive {
+                s.contains(&self.text)
+            } else {
+                s.to_lowercase().contains(&self.text)
+            }
+        };
+        contains(&node.display_name()) || node.key().is_some_and(contains)
+    }
+}
+
+/// Returns the path to the closest node after (or before, if `side` is [`Side::Prev`]) the cursor
+/// in document order which matches `query`.  The search wraps around the ends of the document,
+/// so the cursor is only returned if it's the only match.  Returns [`None`] if no nodes match.
+pub fn find_match<'arena, Node: Ast<'arena>>(
+    tree: &DAG<'arena, Node>,
+    query: &Query,
+    side: Side,
+) -> Option<CursorPath> {
+    let paths = tree.paths_in_order();
+    // The cursor is always in the tree, so we can unwrap
+    let cursor_index = paths.iter().position(|p| p == tree.cursor_path()).unwrap();
+    // Start from the node after the cursor, wrap around and finish at the cursor
+    let mut candidates: Vec<&CursorPath> = paths[cursor_index + 1..]
+        .iter()
+        .chain(paths[..=cursor_index].iter())
+        .collect();
+    if side == Side::Prev {
+        // Reversing puts the cursor first, so rotate it back to the end
+        candidates.reverse();
+        candidates.rotate_left(1);
+    }
+    candidates
+        .into_iter()
+        .find(|path| {
+            // We can unwrap because every path came from the tree
+            query.matches(path.node_iter(tree.root()).last().unwrap())
+        })
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_match, Query};
+    use crate::arena::Arena;
+    use crate::ast::{cursor_path::CursorPath, test_json::TestJSON};
+    use crate::editable_tree::{Side, DAG};
+
+    #[test]
+    fn find() {
+        // [true, {"Foo": false, "bar": "food"}, true]
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::Object(vec![
+                ("Foo".to_string(), TestJSON::False),
+                ("bar".to_string(), TestJSON::Str("food".to_string())),
+            ]),
+            TestJSON::True,
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        let path = |v: &[usize]| CursorPath::from_vec(v.to_vec());
+        for (cursor, query, side, expected_match) in &[
+            // Searches are case-insensitive, and match keys as well as display names
+            (vec![], "foo", Side::Next, Some(vec![1, 0])),
+            (vec![1, 0], "foo", Side::Next, Some(vec![1, 0, 0])),
+            (vec![1, 0, 0], "foo", Side::Next, Some(vec![1, 1, 1])),
+            (vec![1, 1, 1], "FOO", Side::Prev, Some(vec![1, 0, 0])),
+            // `\C` makes the search case-sensitive
+            (vec![], "\\Cfoo", Side::Next, Some(vec![1, 1, 1])),
+            // Searches wrap around the ends of the document
+            (vec![2], "true", Side::Next, Some(vec![0])),
+            (vec![0], "true", Side::Prev, Some(vec![2])),
+            // If the cursor is the only match, the cursor is returned
+            (vec![1, 0, 1], "false", Side::Next, Some(vec![1, 0, 1])),
+            (vec![1, 0, 1], "false", Side::Prev, Some(vec![1, 0, 1])),
+            (vec![0], "null", Side::Next, None),
+        ] {
+            tree.set_cursor_path(path(cursor)).unwrap();
+            assert_eq!(
+                find_match(&tree, &Query::new(query), *side),
+                expected_match.as_ref().map(|m| path(m))
+            );
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! Statistics about the shape of a tree, which are shown by `:stats`.
+
+use crate::ast::{Ast, SyntaxClass};
+use std::collections::BTreeMap;
+
+/// The number of nodes of each kind in a tree, along with its total size and depth
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of nodes of each kind (see [`kind`]), sorted by the name of the kind
+    pub kinds: BTreeMap<String, usize>,
+    /// The total number of nodes in the tree
+    pub num_nodes: usize,
+    /// The number of nodes on the longest path from the root to a leaf (see [`Ast::depth`])
+    pub max_depth: usize,
+}
+
+impl Stats {
+    /// Counts the nodes in the tree under `root`
+    pub fn new<'arena, Node: Ast<'arena>>(root: &'arena Node) -> Self {
+        let mut stats = Stats {
+            kinds: BTreeMap::new(),
+            num_nodes: 0,
+            max_depth: 0,
+        };
+        // Walk the tree with an explicit stack, so that very deep trees can't overflow the call
+        // stack.  Each node is stored with its parent (which decides the kind of some nodes, like
+        // JSON keys) and its depth.
+        let mut stack: Vec<(&'arena Node, Option<&'arena Node>, usize)> = vec![(root, None, 1)];
+        while let Some((node, parent, depth)) = stack.pop() {
+            *stats.kinds.entry(kind(node, parent)).or_insert(0) += 1;
+            stats.num_nodes += 1;
+            stats.max_depth = stats.max_depth.max(depth);
+            stack.extend(node.children().iter().map(|c| (*c, Some(node), depth + 1)));
+        }
+        stats
+    }
+}
+
+impl std::fmt::Display for Stats {
+    /// Summarises the stats on one line, e.g. `5 nodes, max depth 3: 1 array, 2 booleans, ...`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} nodes, max depth {}:", self.num_nodes, self.max_depth)?;
+        for (i, (kind, count)) in self.kinds.iter().enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            let plural = if *count == 1 { "" } else { "s" };
+            write!(f, "{}{} {}{}", separator, count, kind, plural)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the name of the kind of a node.  Leaves are grouped by their [`SyntaxClass`] (so every
+/// JSON string is a `string`), but nodes which hold other nodes are named by their
+/// [`display_name`](Ast::display_name) (like `array` or `object`), since those are the kinds that
+/// give a tree its shape.
+fn kind<'arena, Node: Ast<'arena>>(node: &'arena Node, parent: Option<&'arena Node>) -> String {
+    match node.syntax_class(parent) {
+        SyntaxClass::Container | SyntaxClass::Punctuation => node.display_name(),
+        class => class.name().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Stats;
+    use crate::arena::Arena;
+    use crate::ast::test_json::TestJSON;
+
+    #[test]
+    fn stats() {
+        // [true, {"a": null, "b": ["c", false]}, []]
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::Object(vec![
+                ("a".to_string(), TestJSON::Null),
+                (
+                    "b".to_string(),
+                    TestJSON::Array(vec![TestJSON::Str("c".to_string()), TestJSON::False]),
+                ),
+            ]),
+            TestJSON::Array(vec![]),
+        ])
+        .add_to_arena(&arena);
+        let stats = Stats::new(root);
+        let counts: Vec<(&str, usize)> = stats
+            .kinds
+            .iter()
+            .map(|(kind, count)| (kind.as_str(), *count))
+            .collect();
+        assert_eq!(
+            counts,
+            vec![
+                ("array", 3),
+                ("boolean", 2),
+                ("field", 2),
+                ("key", 2),
+                ("null", 1),
+                ("object", 1),
+                ("string", 1),
+            ]
+        );
+        assert_eq!(stats.num_nodes, 12);
+        // root > object > field "b" > array > "c"
+        assert_eq!(stats.max_depth, 5);
+        assert_eq!(stats.max_depth, crate::ast::Ast::depth(root));
+        assert_eq!(
+            stats.to_string(),
+            "12 nodes, max depth 5: 3 arrays, 2 booleans, 2 fields, 2 keys, 1 null, 1 object, \
+             1 string"
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! The status line, which is drawn just above the bottom bar and summarises the state of the
+//! editor: the format style, the size of the tree and the location of the cursor.
+
+use crate::ast::cursor_path::CursorPath;
+use crate::ast::Ast;
+use crate::editable_tree::DAG;
+
+/// Builds the text of the status line for a given tree and format style, e.g.
+/// `pretty | 6 nodes | $[2].value`.  The cursor's location is a [path
+/// expression](crate::ast::extension::LanguageExtension::path_expr) if the AST has them, and a
+/// [breadcrumb](DAG::breadcrumb) if it doesn't.
+pub fn build_status<'arena, Node: Ast<'arena>>(
+    tree: &DAG<'arena, Node>,
+    format_style: &Node::FormatStyle,
+) -> String {
+    let location = match tree.root().extension() {
+        Some(extension) => extension.path_expr(tree.cursor_path()),
+        None => tree.breadcrumb(),
+    };
+    format!(
+        "{} | {} nodes | {}",
+        Node::format_style_name(format_style),
+        tree.root().node_count(),
+        location
+    )
+}
+
+/// A cached copy of the status line.  Counting the nodes in the tree means walking the whole
+/// tree, so the text is only rebuilt when the state that it shows has changed.
+#[derive(Debug, Clone)]
+pub struct StatusLine<Node> {
+    /// The root, cursor path and format style name that `text` was built from
+    state: Option<(*const Node, CursorPath, String)>,
+    /// The cached text of the status line
+    text: String,
+}
+
+impl<Node> StatusLine<Node> {
+    /// Creates an empty status line, which will be built on the first call to
+    /// [`update`](StatusLine::update)
+    pub fn new() -> Self {
+        StatusLine {
+            state: None,
+            text: String::new(),
+        }
+    }
+
+    /// Returns the current text of the status line
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl<'arena, Node: Ast<'arena>> StatusLine<Node> {
+    /// Rebuilds the status line if the tree, cursor or format style have changed since the last
+    /// update.  Returns `true` if the text was rebuilt.
+    pub fn update(&mut self, tree: &DAG<'arena, Node>, format_style: &Node::FormatStyle) -> bool {
+        // Nodes are immutable, so if the root is the same node then so is the rest of the tree
+        let state = (
+            tree.root() as *const Node,
+            tree.cursor_path().clone(),
+            Node::format_style_name(format_style),
+        );
+        if self.state.as_ref() == Some(&state) {
+            return false;
+        }
+        self.text = build_status(tree, format_style);
+        self.state = Some(state);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_status, StatusLine};
+    use crate::arena::Arena;
+    use crate::ast::{json::JSONFormat, test_json::TestJSON};
+    use crate::editable_tree::{Direction, DAG};
+
+    #[test]
+    fn status() {
+        // [true, false, {"value": true}]
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::False,
+            TestJSON::Object(vec![("value".to_string(), TestJSON::True)]),
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root);
+        assert_eq!(
+            build_status(&tree, &JSONFormat::PRETTY),
+            "pretty | 7 nodes | $"
+        );
+        tree.move_cursor(Direction::Down);
+        tree.move_cursor(Direction::Next);
+        tree.move_cursor(Direction::Next);
+        tree.move_cursor(Direction::Down);
+        assert_eq!(
+            build_status(&tree, &JSONFormat::COMPACT),
+            "compact | 7 nodes | $[2].value"
+        );
+
+        // The cached status line is only rebuilt when something changes
+        let mut status_line = StatusLine::new();
+        assert!(status_line.update(&tree, &JSONFormat::COMPACT));
+        assert!(!status_line.update(&tree, &JSONFormat::COMPACT));
+        assert!(status_line.update(&tree, &JSONFormat::PRETTY));
+        tree.move_cursor(Direction::Up);
+        assert!(status_line.update(&tree, &JSONFormat::PRETTY));
+        tree.delete_cursor(1).unwrap();
+        assert!(status_line.update(&tree, &JSONFormat::PRETTY));
+        assert_eq!(status_line.text(), "pretty | 3 nodes | $[1]");
+    }
+}
@@ -0,0 +1,304 @@
+//! The colours that the tree view is drawn with.
+//!
+//! Every node is coloured by its [`SyntaxClass`], using the colours of the editor's [`Theme`].  A
+//! theme can be one of the built-in themes (see [`Theme::builtin`]), or read from a theme file.
+//! Like a keybinding file (see the [`config`](super::config) module), a theme file consists of
+//! lines of the form `<option> = "<value>"`, e.g.
+//! ```toml
+//! base = "light"     # Start from a built-in theme
+//! key = "magenta"    # and then override some of its colours
+//! string = "#00af5f"
+//! ```
+//! Colours are either the name of a terminal colour (like `red` or `light_blue`), `default` for
+//! the terminal's own colour, a number from 0 to 255, or an RGB colour like `#ff8000`.
+
+use super::config::{self, ConfigError};
+use crate::ast::SyntaxClass;
+use std::path::Path;
+use tuikit::attr::{Attr, Color, Effect};
+
+/// The colours used to draw each [`SyntaxClass`] in the tree view
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Theme {
+    pub container: Color,
+    pub key: Color,
+    pub boolean: Color,
+    pub null: Color,
+    pub number: Color,
+    pub string: Color,
+    pub punctuation: Color,
+    pub other: Color,
+    /// The colour of text that is part of a likely mistake (see the [`lint`](super::lint)
+    /// module), which is also underlined
+    pub error: Color,
+    /// The background of nodes which are selected in visual mode.  If this is
+    /// [`Color::Default`], selected nodes are underlined instead.
+    pub selection: Color,
+}
+
+/// The names of the terminal colours, in the order of their ANSI values
+const COLOR_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+impl Theme {
+    /// Bright colours, for terminals with a dark background
+    pub const DARK: Theme = Theme {
+        container: Color::LIGHT_BLUE,
+        key: Color::LIGHT_MAGENTA,
+        boolean: Color::LIGHT_YELLOW,
+        null: Color::LIGHT_RED,
+        number: Color::LIGHT_CYAN,
+        string: Color::LIGHT_GREEN,
+        punctuation: Color::WHITE,
+        other: Color::LIGHT_WHITE,
+        error: Color::LIGHT_RED,
+        selection: Color::LIGHT_BLACK,
+    };
+    /// Dim colours, for terminals with a light background
+    pub const LIGHT: Theme = Theme {
+        container: Color::BLUE,
+        key: Color::MAGENTA,
+        boolean: Color::YELLOW,
+        null: Color::RED,
+        number: Color::CYAN,
+        string: Color::GREEN,
+        punctuation: Color::LIGHT_BLACK,
+        other: Color::BLACK,
+        error: Color::RED,
+        selection: Color::WHITE,
+    };
+    /// No colours at all, for terminals which don't support them.  The cursor is still shown in
+    /// reverse video, and the selection is underlined.
+    pub const MONOCHROME: Theme = Theme {
+        container: Color::Default,
+        key: Color::Default,
+        boolean: Color::Default,
+        null: Color::Default,
+        number: Color::Default,
+        string: Color::Default,
+        punctuation: Color::Default,
+        other: Color::Default,
+        error: Color::Default,
+        selection: Color::Default,
+    };
+
+    /// Returns the built-in theme with a given name (`dark`, `light` or `monochrome`), or
+    /// [`None`] if there is no such theme
+    pub fn builtin(name: &str) -> Option<Theme> {
+        match name {
+            "dark" => Some(Theme::DARK),
+            "light" => Some(Theme::LIGHT),
+            "monochrome" => Some(Theme::MONOCHROME),
+            _ => None,
+        }
+    }
+
+    /// Returns the colour that nodes of a given [`SyntaxClass`] are drawn with
+    pub fn color(&self, class: SyntaxClass) -> Color {
+        match class {
+            SyntaxClass::Container => self.container,
+            SyntaxClass::Key => self.key,
+            SyntaxClass::Boolean => self.boolean,
+            SyntaxClass::Null => self.null,
+            SyntaxClass::Number => self.number,
+            SyntaxClass::String => self.string,
+            SyntaxClass::Punctuation => self.punctuation,
+            SyntaxClass::Other => self.other,
+        }
+    }
+
+    /// Returns the attributes that the text of a node should be drawn with
+    pub fn attr(&self, class: SyntaxClass, is_cursor: bool, is_selected: bool) -> Attr {
+        self.highlight(
+            Attr::default().fg(self.color(class)),
+            is_cursor,
+            is_selected,
+        )
+    }
+
+    /// Returns `attr` changed to show whether its text is part of the cursor or the selection.
+    /// The cursor and selection only add to the attributes of the text, so this can be applied
+    /// to text which has already been coloured (and cached) without knowing where the cursor is.
+    pub fn highlight(&self, attr: Attr, is_cursor: bool, is_selected: bool) -> Attr {
+        if is_cursor {
+            attr.effect(Effect::REVERSE)
+        } else if !is_selected {
+            attr
+        } else if self.selection == Color::Default {
+            attr.effect(Effect::UNDERLINE)
+        } else {
+            attr.bg(self.selection)
+        }
+    }
+
+    /// Returns `attr` changed to show that its text is part of a likely mistake
+    pub fn error_attr(&self, attr: Attr) -> Attr {
+        Attr {
+            fg: self.error,
+            effect: attr.effect | Effect::UNDERLINE,
+            ..attr
+        }
+    }
+
+    /// Sets one option of this theme: either `base` (which replaces every colour with those of a
+    /// [built-in theme](Theme::builtin)) or the name of a colour
+    pub fn set_option(&mut self, option: &str, value: &str) -> Result<(), String> {
+        if option == "base" {
+            *self = Theme::builtin(value).ok_or_else(|| format!("Unknown theme {:?}", value))?;
+            return Ok(());
+        }
+        let color = match option {
+            "container" => &mut self.container,
+            "key" => &mut self.key,
+            "boolean" => &mut self.boolean,
+            "null" => &mut self.null,
+            "number" => &mut self.number,
+            "string" => &mut self.string,
+            "punctuation" => &mut self.punctuation,
+            "other" => &mut self.other,
+            "error" => &mut self.error,
+            "selection" => &mut self.selection,
+            _ => return Err(format!("Unknown theme option {:?}", option)),
+        };
+        *color = parse_color(value).ok_or_else(|| format!("Unknown color {:?}", value))?;
+        Ok(())
+    }
+
+    /// Reads a theme file (see the [module-level documentation](self)).  Any colours which the
+    /// file doesn't set are taken from [`Theme::DARK`].
+    pub fn from_config(path: &Path) -> Result<Theme, ConfigError> {
+        Self::from_config_str(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parses the contents of a theme file (see [`Theme::from_config`])
+    pub fn from_config_str(text: &str) -> Result<Theme, ConfigError> {
+        let mut theme = Theme::default();
+        for (line_index, line_text) in text.lines().enumerate() {
+            // Line numbers are 1-indexed, to match other text editors
+            let line = line_index + 1;
+            match config::parse_line(line_text) {
+                Ok(Some((option, value))) => theme
+                    .set_option(&option, &value)
+                    .map_err(|message| ConfigError::InvalidValue { line, message })?,
+                Ok(None) => {}
+                Err(()) => return Err(ConfigError::Syntax { line }),
+            }
+        }
+        Ok(theme)
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::DARK
+    }
+}
+
+/// Parses the name of a colour (see the [module-level documentation](self))
+fn parse_color(name: &str) -> Option<Color> {
+    if name == "default" {
+        return Some(Color::Default);
+    }
+    if let Some(hex) = name.strip_prefix('#') {
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        return Some(Color::Rgb(component(0)?, component(2)?, component(4)?));
+    }
+    if let Ok(value) = name.parse::<u8>() {
+        return Some(Color::AnsiValue(value));
+    }
+    let (base_name, offset) = match name.strip_prefix("light_") {
+        Some(base_name) => (base_name, 8),
+        None => (name, 0),
+    };
+    let index = COLOR_NAMES.iter().position(|n| *n == base_name)?;
+    Some(Color::AnsiValue(index as u8 + offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_color, Theme};
+    use crate::ast::SyntaxClass;
+    use crate::editor::config::ConfigError;
+    use tuikit::attr::{Attr, Color, Effect};
+
+    #[test]
+    fn colors() {
+        for (name, expected_color) in &[
+            ("default", Some(Color::Default)),
+            ("red", Some(Color::RED)),
+            ("light_cyan", Some(Color::LIGHT_CYAN)),
+            ("208", Some(Color::AnsiValue(208))),
+            ("#FF8000", Some(Color::Rgb(255, 128, 0))),
+            ("#ff80", None),
+            ("light_default", None),
+            ("pink", None),
+            ("256", None),
+        ] {
+            assert_eq!(parse_color(name), *expected_color, "parsing {:?}", name);
+        }
+    }
+
+    #[test]
+    fn theme_file() {
+        let theme = Theme::from_config_str(
+            r#"
+base = "light"
+key = "light_red"   # Override a single colour
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            theme,
+            Theme {
+                key: Color::LIGHT_RED,
+                ..Theme::LIGHT
+            }
+        );
+        // Options which aren't set come from the default theme
+        assert_eq!(Theme::from_config_str("").unwrap(), Theme::DARK);
+        let error = |text: &str| Theme::from_config_str(text).unwrap_err().to_string();
+        assert_eq!(
+            error("key = red"),
+            "Expected `<key> = \"<value>\"` on line 1."
+        );
+        assert_eq!(
+            error("\nkey = \"pink\""),
+            r#"Unknown color "pink" on line 2."#
+        );
+        assert_eq!(
+            error(r#"base = "solarized""#),
+            r#"Unknown theme "solarized" on line 1."#
+        );
+        assert!(matches!(
+            Theme::from_config_str(r#"keys = "red""#),
+            Err(ConfigError::InvalidValue { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn monochrome() {
+        // Without colours, the cursor and selection are still visible
+        let theme = Theme::MONOCHROME;
+        assert_eq!(
+            theme.attr(SyntaxClass::Key, true, false),
+            Attr::default().effect(Effect::REVERSE)
+        );
+        assert_eq!(
+            theme.attr(SyntaxClass::Key, false, true),
+            Attr::default().effect(Effect::UNDERLINE)
+        );
+        assert_eq!(theme.attr(SyntaxClass::Key, false, false), Attr::default());
+        assert_eq!(
+            theme.error_attr(theme.attr(SyntaxClass::Key, true, false)),
+            Attr::default().effect(Effect::REVERSE | Effect::UNDERLINE)
+        );
+        assert_eq!(
+            Theme::DARK.attr(SyntaxClass::Null, false, true),
+            Attr::default().fg(Color::LIGHT_RED).bg(Color::LIGHT_BLACK)
+        );
+    }
+}
@@ -0,0 +1,190 @@
+//! Utilities for deciding which lines of the rendered tree should be visible on the screen.
+
+use crate::ast::cursor_path::CursorPath;
+use crate::ast::display_token::DisplayToken;
+use crate::ast::Ast;
+use std::collections::HashSet;
+
+/// Returns the line on which each node along a [`CursorPath`] starts, when the tree is rendered in
+/// a given format.  The first value corresponds to the root, and the last value corresponds to
+/// the node under the cursor.  A node is considered to start on the line containing the first
+/// piece of text that belongs to that node (so a field starts on the line containing its key).
+/// The nodes in `folded` are drawn as placeholders (see
+/// [`with_folded`](crate::ast::display_token::DisplayTokenIter::with_folded)).
+pub fn node_start_lines<'arena, Node: Ast<'arena>>(
+    root: &'arena Node,
+    path: &CursorPath,
+    format_style: &Node::FormatStyle,
+    folded: &HashSet<*const Node>,
+) -> Vec<usize> {
+    let path_nodes: Vec<&'arena Node> = path.node_iter(root).collect();
+    let mut start_lines: Vec<Option<usize>> = vec![None; path_nodes.len()];
+    let mut line = 0;
+    for (node, tok) in root.display_token_iter(format_style).with_folded(folded) {
+        match tok {
+            DisplayToken::Newline => line += 1,
+            DisplayToken::Text(_) => {
+                for (path_node, start_line) in path_nodes.iter().zip(start_lines.iter_mut()) {
+                    if start_line.is_none() && std::ptr::eq(*path_node, node) {
+                        *start_line = Some(line);
+                    }
+                }
+                // Once every node has been found, the rest of the tree doesn't need to be laid out
+                if start_lines.iter().all(Option::is_some) {
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    // Any node which doesn't render any text of its own is considered to start on the line of its
+    // closest ancestor
+    let mut last_line = 0;
+    start_lines
+        .into_iter()
+        .map(|l| {
+            last_line = l.unwrap_or(last_line);
+            last_line
+        })
+        .collect()
+}
+
+/// Given the start lines of the ancestors of the cursor (root first, as generated by
+/// [`node_start_lines`]), returns the lines that should be pinned to the top of the screen when the
+/// first visible line is `scroll`.  The pinned lines are drawn over the top rows of the view, so
+/// an ancestor is pinned if it would otherwise be scrolled off the screen or hidden behind the
+/// ancestors that are already pinned.  At most `max_pinned` lines will be pinned.
+pub fn sticky_lines(ancestor_lines: &[usize], scroll: usize, max_pinned: usize) -> Vec<usize> {
+    let mut pinned: Vec<usize> = Vec::new();
+    for &line in ancestor_lines {
+        // Nested ancestors can start on the same line, in which case we only pin that line once
+        if pinned.last() == Some(&line) {
+            continue;
+        }
+        if pinned.len() >= max_pinned || line >= scroll + pinned.len() {
+            break;
+        }
+        pinned.push(line);
+    }
+    pinned
+}
+
+/// Returns the new value of `scroll` required to make the cursor's line visible, whilst moving
+/// the view as little as possible.  The cursor must not be hidden behind any of the sticky header
+/// lines.
+pub fn scroll_to_cursor(
+    scroll: usize,
+    ancestor_lines: &[usize],
+    cursor_line: usize,
+    view_height: usize,
+    max_pinned: usize,
+) -> usize {
+    let mut scroll = scroll;
+    // Scroll down so that the cursor isn't below the bottom of the screen
+    if cursor_line >= scroll + view_height {
+        scroll = cursor_line + 1 - view_height;
+    }
+    // Scroll up until the cursor isn't hidden by the header (or off the top of the screen)
+    while scroll > 0
+        && cursor_line < scroll + sticky_lines(ancestor_lines, scroll, max_pinned).len()
+    {
+        scroll -= 1;
+    }
+    scroll
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{node_start_lines, scroll_to_cursor, sticky_lines};
+    use crate::arena::Arena;
+    use crate::ast::cursor_path::CursorPath;
+    use crate::ast::{json::JSONFormat, test_json::TestJSON, Ast};
+    use std::collections::HashSet;
+
+    #[test]
+    fn start_lines() {
+        // [                    0
+        //     {                1
+        //         "foos": [    2
+        //             false,   3
+        //             true,    4
+        //             false    5
+        //         ],           6
+        //         "bar": false 7
+        //     },               8
+        //     true             9
+        // ]                    10
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::Object(vec![
+                (
+                    "foos".to_string(),
+                    TestJSON::Array(vec![TestJSON::False, TestJSON::True, TestJSON::False]),
+                ),
+                ("bar".to_string(), TestJSON::False),
+            ]),
+            TestJSON::True,
+        ])
+        .add_to_arena(&arena);
+        for (path, expected_lines) in &[
+            (vec![], vec![0]),
+            (vec![1], vec![0, 9]),
+            (vec![0, 0, 1, 2], vec![0, 1, 2, 2, 5]),
+            (vec![0, 1, 1], vec![0, 1, 7, 7]),
+        ] {
+            let path = CursorPath::from_vec(path.clone());
+            assert_eq!(
+                node_start_lines(root, &path, &JSONFormat::PRETTY, &HashSet::new()),
+                *expected_lines
+            );
+        }
+        // Folding the object draws it on one line, and nodes inside it start on that line
+        let folded: HashSet<*const _> = vec![root.children()[0] as *const _].into_iter().collect();
+        for (path, expected_lines) in &[(vec![1], vec![0, 2]), (vec![0, 0, 1], vec![0, 1, 1, 1])] {
+            let path = CursorPath::from_vec(path.clone());
+            assert_eq!(
+                node_start_lines(root, &path, &JSONFormat::PRETTY, &folded),
+                *expected_lines
+            );
+        }
+        // In compact mode, everything is on the first line
+        let path = CursorPath::from_vec(vec![0, 0, 1, 2]);
+        assert_eq!(
+            node_start_lines(root, &path, &JSONFormat::COMPACT, &HashSet::new()),
+            vec![0; 5]
+        );
+    }
+
+    #[test]
+    fn sticky() {
+        // The ancestors of some node, which start on lines 0, 3 and 7
+        let ancestors = [0, 3, 7];
+        for (scroll, expected_pinned) in &[
+            (0, vec![]),
+            (1, vec![0]),
+            (2, vec![0]),
+            // Line 3 would now be hidden behind the pinned line 0
+            (3, vec![0, 3]),
+            (5, vec![0, 3]),
+            (6, vec![0, 3, 7]),
+            (20, vec![0, 3, 7]),
+        ] {
+            assert_eq!(sticky_lines(&ancestors, *scroll, 10), *expected_pinned);
+        }
+        // Ancestors which start on the same line only get pinned once
+        assert_eq!(sticky_lines(&[0, 1, 2, 2], 5, 10), vec![0, 1, 2]);
+        // The number of pinned lines can be limited
+        assert_eq!(sticky_lines(&ancestors, 6, 2), vec![0, 3]);
+    }
+
+    #[test]
+    fn scroll() {
+        let ancestors = [0, 1, 2, 2];
+        // The cursor is already on the screen, so no scrolling is needed
+        assert_eq!(scroll_to_cursor(0, &ancestors, 5, 10, 10), 0);
+        // The cursor is below the screen, so scroll down until it's on the last line
+        assert_eq!(scroll_to_cursor(0, &ancestors, 15, 10, 10), 6);
+        // The cursor is hidden behind the three pinned lines, so scroll up until it isn't
+        assert_eq!(scroll_to_cursor(4, &ancestors, 5, 10, 10), 2);
+    }
+}
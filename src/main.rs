@@ -4,10 +4,11 @@ pub mod editable_tree;
 pub mod editor;
 
 use crate::arena::Arena;
-use crate::ast::json::JSONFormat;
 use crate::ast::test_json::TestJSON;
 use crate::editable_tree::DAG;
-use crate::editor::Editor;
+use crate::editor::config::EditorConfig;
+use crate::editor::theme::Theme;
+use crate::editor::{Editor, KeyMap};
 
 fn main() {
     // Initialise the logging and startup
@@ -25,7 +26,46 @@ fn main() {
     ])
     .add_to_arena(&arena);
 
+    // Load the user's keybindings, if they have any
+    let keymap = match std::env::var_os("HOME")
+        .map(|home| std::path::Path::new(&home).join(".config/sapling/keys.toml"))
+        .filter(|path| path.exists())
+    {
+        Some(path) => {
+            log::info!("Loading keybindings from {:?}", path);
+            let (keymap, warnings) = KeyMap::from_config(&path).unwrap_or_else(|e| {
+                eprintln!("Error loading keybindings from {:?}: {}", path, e);
+                std::process::exit(1);
+            });
+            for warning in warnings {
+                eprintln!("Warning: skipping binding in {:?}: {}", path, warning);
+            }
+            keymap
+        }
+        None => editor::default_keymap(),
+    };
+
+    // Load the user's colour theme, if they have one
+    let theme = match std::env::var_os("HOME")
+        .map(|home| std::path::Path::new(&home).join(".config/sapling/theme.toml"))
+        .filter(|path| path.exists())
+    {
+        Some(path) => {
+            log::info!("Loading theme from {:?}", path);
+            Theme::from_config(&path).unwrap_or_else(|e| {
+                eprintln!("Error loading theme from {:?}: {}", path, e);
+                std::process::exit(1);
+            })
+        }
+        None => Theme::default(),
+    };
+
     let mut tree = DAG::new(&arena, root);
-    let editor = Editor::new(&mut tree, JSONFormat::Pretty, editor::default_keymap());
+    let config = EditorConfig {
+        keymap,
+        theme,
+        ..EditorConfig::default()
+    };
+    let editor = Editor::with_config(&mut tree, config);
     editor.run();
 }